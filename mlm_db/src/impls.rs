@@ -6,15 +6,16 @@ pub mod old_categories;
 pub mod series;
 pub mod size;
 
-use std::{fmt, str::FromStr};
+use std::{cmp::Ordering, fmt, str::FromStr};
 
 use matchr::score;
+use mlm_parse::TitleRules;
 use serde::{Deserialize, Deserializer};
 use time::UtcDateTime;
 
 use crate::{
-    Event, EventType, ListItem, OldDbMainCat, Series, Timestamp, Torrent, TorrentCost, TorrentMeta,
-    TorrentStatus, Uuid, VipStatus,
+    Event, EventType, ListItem, OldDbMainCat, SearchHistory, SelectedTorrent, Series, SeriesEntry,
+    Timestamp, Torrent, TorrentCost, TorrentMeta, TorrentStatus, Uuid, VerifyOutcome, VipStatus,
 };
 
 pub fn parse<'de, T, D>(deserializer: D) -> Result<T, D::Error>
@@ -48,6 +49,32 @@ where
 }
 
 impl Torrent {
+    /// A torrent known by its info hash, that is not in a library yet. Set the
+    /// other fields with struct update syntax
+    pub fn new(id: String, meta: TorrentMeta, rules: &TitleRules) -> Self {
+        Self {
+            id,
+            id_is_hash: true,
+            mam_id: meta.mam_id,
+            abs_id: None,
+            goodreads_id: None,
+            library_path: None,
+            library_files: vec![],
+            linker: None,
+            category: None,
+            selected_audio_format: None,
+            selected_ebook_format: None,
+            title_search: rules.normalize_title(&meta.title),
+            meta,
+            created_at: Timestamp::now(),
+            replaced_with: None,
+            request_matadata_update: false,
+            library_mismatch: None,
+            client_status: None,
+            note: None,
+        }
+    }
+
     pub fn matches(&self, other: &Torrent) -> bool {
         // if self.hash == other.hash { return true };
         if self.title_search != other.title_search {
@@ -57,6 +84,17 @@ impl Torrent {
     }
 }
 
+impl SelectedTorrent {
+    /// Order in which queued torrents are grabbed, highest priority first and
+    /// then oldest first
+    pub fn queue_cmp(&self, other: &SelectedTorrent) -> Ordering {
+        other
+            .priority
+            .cmp(&self.priority)
+            .then(self.created_at.cmp(&other.created_at))
+    }
+}
+
 impl Event {
     pub fn new(torrent_id: Option<String>, mam_id: Option<u64>, event: EventType) -> Self {
         Self {
@@ -69,6 +107,22 @@ impl Event {
     }
 }
 
+impl SearchHistory {
+    pub fn new(query: String, sort: String, uploader: Option<u64>) -> Self {
+        Self {
+            id: Uuid::new(),
+            created_at: Timestamp::now(),
+            query,
+            sort,
+            uploader,
+        }
+    }
+
+    pub fn same_search(&self, other: &SearchHistory) -> bool {
+        self.query == other.query && self.sort == other.sort && self.uploader == other.uploader
+    }
+}
+
 impl ListItem {
     pub fn want_audio(&self) -> bool {
         if self.marked_done_at.is_some() {
@@ -111,10 +165,13 @@ impl ListItem {
     }
 
     pub fn matches(&self, meta: &TorrentMeta) -> bool {
-        if score(&self.title, &meta.title) < 80 {
+        if score(&self.title, &meta.title) < 80 && !self.in_omnibus(meta) {
             return false;
         }
+        self.author_matches(meta)
+    }
 
+    fn author_matches(&self, meta: &TorrentMeta) -> bool {
         let authors = self
             .authors
             .iter()
@@ -123,9 +180,54 @@ impl ListItem {
 
         meta.authors
             .iter()
+            .chain(meta.original_authors.iter().flatten())
             .map(|a| a.to_lowercase())
             .any(|a| authors.iter().any(|b| score(b, &a) > 90))
     }
+
+    /// Checks if the torrent is an omnibus whose series range covers this item
+    pub fn in_omnibus(&self, meta: &TorrentMeta) -> bool {
+        self.series.iter().any(|(name, num)| {
+            let name = name.to_lowercase();
+            meta.series.iter().any(|s| {
+                score(&name, &s.name.to_lowercase()) > 90
+                    && s.entries
+                        .0
+                        .iter()
+                        .any(|e| matches!(e, SeriesEntry::Range(_, _)) && e.contains(*num as f32))
+            })
+        })
+    }
+
+    /// Checks if the torrent is a collection that contains this item, either
+    /// by its position in a series or by its title being part of the torrent
+    /// title, like "The Dune Trilogy: Dune, Dune Messiah, Children of Dune"
+    pub fn in_collection(&self, meta: &TorrentMeta) -> bool {
+        if !meta.is_collection() {
+            return false;
+        }
+        let in_series = self.series.iter().any(|(name, num)| {
+            let name = name.to_lowercase();
+            meta.series.iter().any(|s| {
+                score(&name, &s.name.to_lowercase()) > 90 && s.entries.contains(*num as f32)
+            })
+        });
+        (in_series || contains_words(&meta.title, &self.title)) && self.author_matches(meta)
+    }
+}
+
+/// Checks if `needle` is in `haystack` as whole words, ignoring case
+fn contains_words(haystack: &str, needle: &str) -> bool {
+    let haystack = haystack.to_lowercase();
+    let needle = needle.trim().to_lowercase();
+    if needle.is_empty() {
+        return false;
+    }
+    haystack.match_indices(&needle).any(|(i, _)| {
+        let before = haystack[..i].chars().next_back();
+        let after = haystack[i + needle.len()..].chars().next();
+        before.is_none_or(|c| !c.is_alphanumeric()) && after.is_none_or(|c| !c.is_alphanumeric())
+    })
 }
 
 impl VipStatus {
@@ -161,6 +263,17 @@ impl fmt::Display for VipStatus {
     }
 }
 
+impl fmt::Display for VerifyOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyOutcome::Complete => write!(f, "data is complete, started seeding"),
+            VerifyOutcome::NoData => write!(f, "no existing data, started downloading"),
+            VerifyOutcome::Mismatch => write!(f, "existing data does not match, left stopped"),
+            VerifyOutcome::TimedOut => write!(f, "checking timed out, left stopped"),
+        }
+    }
+}
+
 pub fn format_serie(series: &Series) -> String {
     if series.entries.0.is_empty() {
         series.name.clone()
@@ -40,6 +40,16 @@ impl Flags {
         (is_hide, field)
     }
 
+    /// The `browseFlagsHideVsShow` and `browseFlags` parameters of a MaM search
+    pub fn as_search_params(&self) -> (Option<u8>, Vec<u8>) {
+        let (is_hide, field) = self.as_search_bitfield();
+        if field.is_empty() {
+            (None, field)
+        } else {
+            (Some(if is_hide { 0 } else { 1 }), field)
+        }
+    }
+
     pub fn as_bitfield(&self) -> u8 {
         let mut field = 0;
         if self.crude_language.unwrap_or_default() {
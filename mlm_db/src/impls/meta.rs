@@ -1,12 +1,25 @@
 use std::fmt;
 
 use itertools::Itertools as _;
+use once_cell::sync::Lazy;
+use regex::Regex;
 
 use crate::{
-    Flags, MediaType, MetadataSource, OldCategory, TorrentMeta, TorrentMetaDiff, TorrentMetaField,
-    VipStatus, impls::format_serie,
+    Flags, MediaType, MetadataSource, OldCategory, SeriesEntry, TorrentMeta, TorrentMetaDiff,
+    TorrentMetaField, VipStatus, impls::format_serie,
 };
 
+/// Longest value kept for a field in an update event, so a changed
+/// description doesn't bloat the event log
+const MAX_DIFF_VALUE_CHARS: usize = 300;
+
+static COLLECTION_TITLE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\b(?:collection|omnibus|anthology|box(?:ed)?\s*set|complete series|trilogy|books\s*\d+\s*(?:-|–|&|,|and|to)\s*\d+)\b",
+    )
+    .unwrap()
+});
+
 impl TorrentMeta {
     pub fn matches(&self, other: &TorrentMeta) -> bool {
         self.media_type.matches(other.media_type)
@@ -23,6 +36,27 @@ impl TorrentMeta {
                 || self.narrators.iter().any(|a| other.narrators.contains(a)))
     }
 
+    /// Anthologies, box sets and other torrents with more than one book, by
+    /// their title or by listing several books of a series
+    pub fn is_collection(&self) -> bool {
+        COLLECTION_TITLE.is_match(&self.title)
+            || self.series.iter().any(|s| {
+                s.entries.0.len() > 1
+                    || s.entries
+                        .0
+                        .iter()
+                        .any(|e| matches!(e, SeriesEntry::Range(_, _)))
+            })
+    }
+
+    /// Keeps the ids from `old` that this metadata is missing, they may have
+    /// been looked up somewhere else than this metadata came from
+    pub fn keep_ids(&mut self, old: &TorrentMeta) {
+        for (kind, id) in &old.ids {
+            self.ids.entry(kind.clone()).or_insert_with(|| id.clone());
+        }
+    }
+
     pub fn cat_name(&self) -> &str {
         match self.cat {
             Some(OldCategory::Audio(cat)) => cat.to_str(),
@@ -198,10 +232,42 @@ impl TorrentMeta {
                 to: other.source.to_string(),
             });
         }
+        if self.description != other.description {
+            diff.push(TorrentMetaDiff {
+                field: TorrentMetaField::Description,
+                from: truncate_diff_value(self.description.as_deref().unwrap_or_default()),
+                to: truncate_diff_value(other.description.as_deref().unwrap_or_default()),
+            });
+        }
+        if self.ids != other.ids {
+            diff.push(TorrentMetaDiff {
+                field: TorrentMetaField::Ids,
+                from: self.ids.iter().map(|(k, v)| format!("{k}: {v}")).join(", "),
+                to: other
+                    .ids
+                    .iter()
+                    .map(|(k, v)| format!("{k}: {v}"))
+                    .join(", "),
+            });
+        }
+        if self.dramatized != other.dramatized {
+            diff.push(TorrentMetaDiff {
+                field: TorrentMetaField::Dramatized,
+                from: self.dramatized.to_string(),
+                to: other.dramatized.to_string(),
+            });
+        }
         diff
     }
 }
 
+fn truncate_diff_value(value: &str) -> String {
+    match value.char_indices().nth(MAX_DIFF_VALUE_CHARS) {
+        Some((end, _)) => format!("{}…", value[..end].trim_end()),
+        None => value.to_string(),
+    }
+}
+
 impl MediaType {
     pub fn matches(&self, other: MediaType) -> bool {
         match (*self, other) {
@@ -234,6 +300,9 @@ impl std::fmt::Display for TorrentMetaField {
             TorrentMetaField::Narrators => write!(f, "narrators"),
             TorrentMetaField::Series => write!(f, "series"),
             TorrentMetaField::Source => write!(f, "source"),
+            TorrentMetaField::Description => write!(f, "description"),
+            TorrentMetaField::Ids => write!(f, "ids"),
+            TorrentMetaField::Dramatized => write!(f, "dramatized"),
         }
     }
 }
@@ -243,6 +312,7 @@ impl fmt::Display for MetadataSource {
         match self {
             MetadataSource::Mam => write!(f, "MaM"),
             MetadataSource::Manual => write!(f, "Manual"),
+            MetadataSource::File => write!(f, "File"),
         }
     }
 }
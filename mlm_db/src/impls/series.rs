@@ -1,11 +1,13 @@
 use std::{cmp::Ordering, str::FromStr};
 
+use mlm_parse::TitleRules;
 use nom::{
     Finish, IResult, Parser,
     branch::alt,
+    bytes::complete::tag_no_case,
     character::{complete::char, digit1, multispace0},
     combinator::{complete, map, map_res, opt, recognize},
-    multi::separated_list0,
+    multi::{separated_list0, separated_list1},
     sequence::delimited,
 };
 
@@ -20,6 +22,30 @@ impl TryFrom<(String, String)> for Series {
     }
 }
 
+/// Finds the book numbers in omnibus titles like "The Expanse, Books 1-3" or
+/// "Vol. 1–2", for torrents where MaM doesn't list them in the series info.
+/// The title is cleaned up like the title of the metadata first, and a number
+/// found by a `series_extract_patterns` rule wins over the built-in parsing.
+pub fn parse_series_from_title(title: &str, rules: &TitleRules) -> Option<SeriesEntries> {
+    let title = rules.strip_title(title);
+    if let Some(found) = rules.extract_series(&title)
+        && let Ok(entries) = series_entries(&found.number)
+        && !entries.is_empty()
+    {
+        return Some(SeriesEntries::new(entries));
+    }
+    let mut prev = None;
+    for (i, c) in title.char_indices() {
+        if prev.is_none_or(|p: char| !p.is_alphanumeric())
+            && let Ok((_, entries)) = title_series_entries(&title[i..])
+        {
+            return Some(SeriesEntries::new(entries));
+        }
+        prev = Some(c);
+    }
+    None
+}
+
 impl SeriesEntries {
     pub fn contains(&self, num: f32) -> bool {
         self.0.iter().any(|s| s.contains(num))
@@ -127,7 +153,13 @@ fn series_part(input: &str) -> IResult<&str, SeriesEntry> {
 
 fn series_range(input: &str) -> IResult<&str, SeriesEntry> {
     map(
-        (float, multispace0(), char('-'), multispace0(), float),
+        (
+            float,
+            multispace0(),
+            alt((char('-'), char('–'))),
+            multispace0(),
+            float,
+        ),
         |(start, _, _, _, end)| SeriesEntry::Range(start, end),
     )
     .parse_complete(input)
@@ -154,8 +186,33 @@ fn series_entries(input: &str) -> Result<Vec<SeriesEntry>, nom::error::Error<&st
     .map(|(_, entries)| entries)
 }
 
+fn title_series_entries(input: &str) -> IResult<&str, Vec<SeriesEntry>> {
+    map(
+        (
+            alt((
+                tag_no_case("books"),
+                tag_no_case("book"),
+                tag_no_case("volumes"),
+                tag_no_case("volume"),
+                tag_no_case("vols"),
+                tag_no_case("vol"),
+            )),
+            opt(char('.')),
+            multispace0(),
+            separated_list1(
+                delimited(multispace0(), char(','), multispace0()),
+                series_entry,
+            ),
+        ),
+        |(_, _, _, entries)| entries,
+    )
+    .parse_complete(input)
+}
+
 #[cfg(test)]
 mod tests {
+    use mlm_parse::TitlePatterns;
+
     use super::*;
 
     #[test]
@@ -197,6 +254,7 @@ mod tests {
             series_range(".5 - 32."),
             Ok(("", SeriesEntry::Range(0.5, 32.)))
         );
+        assert_eq!(series_range("1–3"), Ok(("", SeriesEntry::Range(1.0, 3.0))));
     }
 
     #[test]
@@ -247,4 +305,91 @@ mod tests {
             ])
         );
     }
+
+    #[test]
+    fn test_parse_series_from_title() {
+        let rules = TitleRules::default();
+        assert_eq!(
+            parse_series_from_title("The Expanse: Book 1-3", &rules),
+            Some(SeriesEntries::new(vec![SeriesEntry::Range(1.0, 3.0)]))
+        );
+        assert_eq!(
+            parse_series_from_title("Red Rising Trilogy (Books 1–3)", &rules),
+            Some(SeriesEntries::new(vec![SeriesEntry::Range(1.0, 3.0)]))
+        );
+        assert_eq!(
+            parse_series_from_title("Spice and Wolf, Vol. 1-2", &rules),
+            Some(SeriesEntries::new(vec![SeriesEntry::Range(1.0, 2.0)]))
+        );
+        assert_eq!(
+            parse_series_from_title("Discworld Books 1, 3, Mort", &rules),
+            Some(SeriesEntries::new(vec![
+                SeriesEntry::Num(1.0),
+                SeriesEntry::Num(3.0)
+            ]))
+        );
+        assert_eq!(parse_series_from_title("The Book of Dust", &rules), None);
+        assert_eq!(parse_series_from_title("Volcano 2", &rules), None);
+        assert_eq!(parse_series_from_title("Notebook 3", &rules), None);
+    }
+
+    #[test]
+    fn test_novella_entries() {
+        let series = Series::try_from(("The Expanse".to_string(), "2.5".to_string())).unwrap();
+        assert_eq!(
+            series.entries,
+            SeriesEntries::new(vec![SeriesEntry::Num(2.5)])
+        );
+        assert_eq!(series.entries.to_string(), "2.5");
+        assert!(series.entries.contains(2.5));
+        assert!(!series.entries.contains(2.0));
+
+        let mut entries = vec![
+            SeriesEntry::Num(3.0),
+            SeriesEntry::Num(2.5),
+            SeriesEntry::Num(2.0),
+        ];
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![
+                SeriesEntry::Num(2.0),
+                SeriesEntry::Num(2.5),
+                SeriesEntry::Num(3.0)
+            ]
+        );
+        let rules = TitleRules::default();
+        assert_eq!(
+            parse_series_from_title("The Expanse, Books 2.5-3", &rules),
+            Some(SeriesEntries::new(vec![SeriesEntry::Range(2.5, 3.0)]))
+        );
+    }
+
+    #[test]
+    fn test_parse_series_from_title_with_patterns() {
+        let mut patterns = TitlePatterns::default();
+        patterns.add_strip(r"(?i)\s*\(books? \d+\)$").unwrap();
+        patterns
+            .add_series(
+                r"^(?P<series>.+) Omnibus (?P<number>[\d-]+):",
+                "series",
+                "number",
+            )
+            .unwrap();
+        let rules = TitleRules::default().with_patterns(patterns);
+
+        // The marker the user strips would otherwise be read as the book
+        assert_eq!(
+            parse_series_from_title("Dune Messiah (Book 2)", &TitleRules::default()),
+            Some(SeriesEntries::new(vec![SeriesEntry::Num(2.0)]))
+        );
+        assert_eq!(
+            parse_series_from_title("Dune Messiah (Book 2)", &rules),
+            None
+        );
+        assert_eq!(
+            parse_series_from_title("Wheel of Time Omnibus 1-3: The Beginning", &rules),
+            Some(SeriesEntries::new(vec![SeriesEntry::Range(1.0, 3.0)]))
+        );
+    }
 }
@@ -9,22 +9,28 @@ impl Size {
     pub fn unit(self) -> u64 {
         if self.bytes() > 0 { 1 } else { 0 }
     }
+
+    /// The `unit` to search MaM with for a size range. Sizes are always sent
+    /// in bytes, so this is 1 when either end of the range is set
+    pub fn range_unit(min: Size, max: Size) -> u64 {
+        min.unit().max(max.unit())
+    }
 }
 
 impl std::fmt::Display for Size {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut value = self.bytes() as f64;
         let mut unit = "B";
-        if value > 1024_f64.powf(4.0) {
+        if value >= 1024_f64.powf(4.0) {
             value /= 1024_f64.powf(4.0);
             unit = "TiB";
-        } else if value > 1024_f64.powf(3.0) {
+        } else if value >= 1024_f64.powf(3.0) {
             value /= 1024_f64.powf(3.0);
             unit = "GiB";
-        } else if value > 1024_f64.powf(2.0) {
+        } else if value >= 1024_f64.powf(2.0) {
             value /= 1024_f64.powf(2.0);
             unit = "MiB";
-        } else if value > 1024.0 {
+        } else if value >= 1024.0 {
             value /= 1024.0;
             unit = "KiB";
         }
@@ -33,31 +39,47 @@ impl std::fmt::Display for Size {
     }
 }
 
-pub static SIZE_PATTERN: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"^((?:\d{1,3},)?\d{1,6}(?:\.\d{1,3})?) ([kKMGT]?)(i)?B$").unwrap());
+/// A number with an optional unit, like "100 MiB", "1.5gb" or "1,016.2 KiB".
+/// A comma followed by three digits separates thousands, any other comma is a
+/// decimal comma
+pub static SIZE_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)^\s*(\d+(?:,\d{3})*(?:[.,]\d+)?)\s*([kmgt]?)(i?)(b?)\s*$").unwrap()
+});
 
 impl FromStr for Size {
     type Err = String;
 
     fn from_str(value: &str) -> Result<Self, Self::Err> {
-        if let Some((Some(value), Some(unit), i)) = SIZE_PATTERN
-            .captures(value)
-            .map(|c| (c.get(1), c.get(2), c.get(3)))
-        {
-            let value: f64 = value.as_str().replace(",", "").parse().unwrap();
-            let base: u64 = if i.is_some() { 1024 } else { 1000 };
-            let multiplier = match unit.as_str() {
-                "" => 1,
-                "k" | "K" => base,
-                "M" => base.pow(2),
-                "G" => base.pow(3),
-                "T" => base.pow(4),
-                _ => unreachable!("unknown unit: {}", unit.as_str()),
-            } as f64;
-            Ok(Size::from_bytes((value * multiplier).round() as u64))
-        } else {
-            Err(format!("invalid size value {value}"))
+        let invalid = || {
+            format!(
+                "invalid size {value:?}, expected a number with a unit like \"100 MiB\" or \"1.5 GB\""
+            )
+        };
+        let captures = SIZE_PATTERN.captures(value).ok_or_else(invalid)?;
+        let number = &captures[1];
+        let prefix = captures[2].to_ascii_lowercase();
+        let binary = !captures[3].is_empty();
+        if binary && prefix.is_empty() {
+            return Err(invalid());
         }
+        // "1,5" is a decimal comma, "1,500" and "1,016.2" separate thousands
+        let number = match number.rsplit_once(',') {
+            Some((_, decimals)) if decimals.len() != 3 && !number.contains('.') => {
+                number.replace(',', ".")
+            }
+            _ => number.replace(',', ""),
+        };
+        let number: f64 = number.parse().map_err(|_| invalid())?;
+        let base: u64 = if binary { 1024 } else { 1000 };
+        let multiplier = match prefix.as_str() {
+            "" => 1,
+            "k" => base,
+            "m" => base.pow(2),
+            "g" => base.pow(3),
+            "t" => base.pow(4),
+            _ => unreachable!("unknown unit: {prefix}"),
+        } as f64;
+        Ok(Size::from_bytes((number * multiplier).round() as u64))
     }
 }
 
@@ -88,4 +110,67 @@ mod tests {
             "1.43 GiB"
         );
     }
+
+    #[test]
+    fn test_size_units() {
+        let cases = [
+            ("100 MiB", 100 * 1024 * 1024),
+            ("100mib", 100 * 1024 * 1024),
+            ("100 MB", 100_000_000),
+            ("100mb", 100_000_000),
+            ("100 m", 100_000_000),
+            ("1.5 GiB", 1_610_612_736),
+            ("1,5 GiB", 1_610_612_736),
+            ("1,5 GB", 1_500_000_000),
+            ("1.5gb", 1_500_000_000),
+            ("2 TiB", 2 * 1024_u64.pow(4)),
+            ("0.5 kB", 500),
+            ("512 KiB", 512 * 1024),
+            ("1,500 MB", 1_500_000_000),
+            ("1,016.2 KiB", 1_040_589),
+            (" 42 B ", 42),
+            ("42", 42),
+            ("0", 0),
+        ];
+        for (input, bytes) in cases {
+            assert_eq!(
+                Size::from_str(input),
+                Ok(Size::from_bytes(bytes)),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_size_invalid() {
+        for input in [
+            "", "MiB", "100 MiBs", "100 XB", "100 iB", "-1 MB", "1.5.5 GB", "1,5,5 GB", "ten MB",
+        ] {
+            let err = Size::from_str(input).unwrap_err();
+            assert!(err.contains(&format!("{input:?}")), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_size_round_trip() {
+        for input in [
+            "100 MiB", "1.5 GiB", "512 KiB", "2 TiB", "42 B", "1 MiB", "0 B",
+        ] {
+            let size = Size::from_str(input).unwrap();
+            assert_eq!(size.to_string(), input);
+            assert_eq!(Size::from_str(&size.to_string()), Ok(size));
+        }
+        let size = Size::from_str("1,016.2 KiB").unwrap();
+        assert_eq!(Size::from_str(&size.to_string()), Ok(size));
+    }
+
+    #[test]
+    fn test_range_unit() {
+        let none = Size::from_bytes(0);
+        let some = Size::from_str("10 MiB").unwrap();
+        assert_eq!(Size::range_unit(none, none), 0);
+        assert_eq!(Size::range_unit(some, none), 1);
+        assert_eq!(Size::range_unit(none, some), 1);
+        assert_eq!(Size::range_unit(some, some), 1);
+    }
 }
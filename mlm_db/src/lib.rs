@@ -16,11 +16,12 @@ mod v14;
 mod v15;
 mod v16;
 mod v17;
+mod v18;
 
 use std::collections::HashMap;
 
 use anyhow::Result;
-use mlm_parse::normalize_title;
+use mlm_parse::TitleRules;
 use native_db::Models;
 use native_db::transaction::RwTransaction;
 use native_db::{Database, ToInput, db_type};
@@ -33,6 +34,16 @@ pub static MODELS: Lazy<Models> = Lazy::new(|| {
     let mut models = Models::new();
     models.define::<v01::Config>().unwrap();
 
+    models.define::<v18::Torrent>().unwrap();
+    models.define::<v18::SelectedTorrent>().unwrap();
+    models.define::<v18::DuplicateTorrent>().unwrap();
+    models.define::<v18::ErroredTorrent>().unwrap();
+    models.define::<v18::Event>().unwrap();
+    models.define::<v18::List>().unwrap();
+    models.define::<v18::SearchHistory>().unwrap();
+    models.define::<v18::TaskError>().unwrap();
+    models.define::<v18::ListItem>().unwrap();
+
     models.define::<v17::Torrent>().unwrap();
     models.define::<v17::SelectedTorrent>().unwrap();
     models.define::<v17::DuplicateTorrent>().unwrap();
@@ -130,27 +141,32 @@ pub static MODELS: Lazy<Models> = Lazy::new(|| {
 });
 
 pub type Config = v01::Config;
-pub type Torrent = v17::Torrent;
-pub type TorrentKey = v17::TorrentKey;
-pub type SelectedTorrent = v17::SelectedTorrent;
-pub type SelectedTorrentKey = v17::SelectedTorrentKey;
-pub type DuplicateTorrent = v17::DuplicateTorrent;
-pub type ErroredTorrent = v17::ErroredTorrent;
-pub type ErroredTorrentKey = v17::ErroredTorrentKey;
+pub type Torrent = v18::Torrent;
+pub type TorrentKey = v18::TorrentKey;
+pub type SelectedTorrent = v18::SelectedTorrent;
+pub type SelectedTorrentKey = v18::SelectedTorrentKey;
+pub type DuplicateTorrent = v18::DuplicateTorrent;
+pub type ErroredTorrent = v18::ErroredTorrent;
+pub type ErroredTorrentKey = v18::ErroredTorrentKey;
 pub type ErroredTorrentId = v11::ErroredTorrentId;
-pub type Event = v17::Event;
-pub type EventKey = v17::EventKey;
-pub type EventType = v17::EventType;
-pub type List = v05::List;
-pub type ListKey = v05::ListKey;
-pub type ListItem = v05::ListItem;
-pub type ListItemKey = v05::ListItemKey;
+pub type Event = v18::Event;
+pub type EventKey = v18::EventKey;
+pub type EventType = v18::EventType;
+pub type VerifyOutcome = v18::VerifyOutcome;
+pub type SearchHistory = v18::SearchHistory;
+pub type SearchHistoryKey = v18::SearchHistoryKey;
+pub type TaskError = v18::TaskError;
+pub type List = v18::List;
+pub type ListKey = v18::ListKey;
+pub type ListItem = v18::ListItem;
+pub type ListItemKey = v18::ListItemKey;
+pub type ListItemCandidate = v18::ListItemCandidate;
 pub type ListItemTorrent = v04::ListItemTorrent;
-pub type TorrentMeta = v17::TorrentMeta;
-pub type TorrentMetaDiff = v17::TorrentMetaDiff;
-pub type TorrentMetaField = v17::TorrentMetaField;
+pub type TorrentMeta = v18::TorrentMeta;
+pub type TorrentMetaDiff = v18::TorrentMetaDiff;
+pub type TorrentMetaField = v18::TorrentMetaField;
 pub type VipStatus = v11::VipStatus;
-pub type MetadataSource = v10::MetadataSource;
+pub type MetadataSource = v18::MetadataSource;
 pub type OldDbMainCat = v01::MainCat;
 pub type MainCat = v12::MainCat;
 pub type Uuid = v03::Uuid;
@@ -163,7 +179,7 @@ pub type FlagBits = v08::FlagBits;
 pub type Size = v03::Size;
 pub type TorrentCost = v04::TorrentCost;
 pub type TorrentStatus = v04::TorrentStatus;
-pub type LibraryMismatch = v08::LibraryMismatch;
+pub type LibraryMismatch = v18::LibraryMismatch;
 pub type ClientStatus = v08::ClientStatus;
 pub type AudiobookCategory = v06::AudiobookCategory;
 pub type EbookCategory = v06::EbookCategory;
@@ -173,6 +189,11 @@ pub type OldCategory = v16::OldCategory;
 pub type MediaType = v13::MediaType;
 pub type Category = v15::Category;
 
+/// Key of the ISBN in [`TorrentMeta::ids`]
+pub const ISBN_ID: &str = "isbn";
+/// Key of the Amazon ASIN in [`TorrentMeta::ids`]
+pub const ASIN_ID: &str = "asin";
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum OldMainCat {
     Audio,
@@ -237,7 +258,7 @@ where
 }
 
 #[instrument(skip_all)]
-pub fn update_search_title(db: &Database<'_>) -> Result<()> {
+pub fn update_search_title(db: &Database<'_>, rules: &TitleRules) -> Result<()> {
     let rw = db.rw_transaction()?;
 
     info!("Update search title started");
@@ -247,7 +268,7 @@ pub fn update_search_title(db: &Database<'_>) -> Result<()> {
         .all()?
         .collect::<Result<Vec<_>, _>>()?;
     for mut torrent in torrents {
-        torrent.title_search = normalize_title(&torrent.meta.title);
+        torrent.title_search = rules.normalize_title(&torrent.meta.title);
         rw.upsert(torrent)?;
     }
     rw.commit()?;
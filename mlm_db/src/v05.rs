@@ -1,4 +1,4 @@
-use super::{v01, v03, v04, v06};
+use super::{v01, v03, v04, v06, v18};
 use native_db::{ToKey, native_db};
 use native_model::{Model, native_model};
 use serde::{Deserialize, Serialize};
@@ -96,6 +96,17 @@ impl From<v04::List> for List {
     }
 }
 
+impl From<v18::List> for List {
+    fn from(t: v18::List) -> Self {
+        Self {
+            id: t.id,
+            title: t.title,
+            updated_at: t.updated_at,
+            build_date: t.build_date,
+        }
+    }
+}
+
 impl From<v04::ListItem> for ListItem {
     fn from(t: v04::ListItem) -> Self {
         Self {
@@ -122,6 +133,28 @@ impl From<v04::ListItem> for ListItem {
     }
 }
 
+impl From<v18::ListItem> for ListItem {
+    fn from(t: v18::ListItem) -> Self {
+        Self {
+            guid: t.guid,
+            list_id: t.list_id,
+            title: t.title,
+            authors: t.authors,
+            series: t.series,
+            cover_url: t.cover_url,
+            book_url: t.book_url,
+            isbn: t.isbn,
+            prefer_format: t.prefer_format,
+            allow_audio: t.allow_audio,
+            audio_torrent: t.audio_torrent,
+            allow_ebook: t.allow_ebook,
+            ebook_torrent: t.ebook_torrent,
+            created_at: t.created_at,
+            marked_done_at: t.marked_done_at,
+        }
+    }
+}
+
 impl From<v06::Torrent> for Torrent {
     fn from(t: v06::Torrent) -> Self {
         Self {
@@ -1,4 +1,4 @@
-use super::{v03, v04, v08, v09, v10, v11, v12, v13, v15, v16};
+use super::{v03, v04, v08, v09, v10, v11, v12, v13, v15, v16, v18};
 use mlm_parse::{normalize_title, parse_edition};
 use native_db::{ToKey, native_db};
 use native_model::{Model, native_model};
@@ -348,3 +348,206 @@ impl From<v12::TorrentMetaField> for TorrentMetaField {
         }
     }
 }
+
+impl From<v18::Event> for Event {
+    fn from(t: v18::Event) -> Self {
+        Self {
+            id: t.id,
+            torrent_id: t.torrent_id,
+            mam_id: t.mam_id,
+            created_at: t.created_at,
+            event: t.event.into(),
+        }
+    }
+}
+
+impl From<v18::EventType> for EventType {
+    fn from(t: v18::EventType) -> Self {
+        match t {
+            v18::EventType::Grabbed {
+                grabber,
+                cost,
+                wedged,
+            } => Self::Grabbed {
+                grabber,
+                cost,
+                wedged,
+            },
+            v18::EventType::Linked {
+                linker,
+                library_path,
+            } => Self::Linked {
+                linker,
+                library_path,
+            },
+            v18::EventType::Cleaned {
+                library_path,
+                files,
+            } => Self::Cleaned {
+                library_path,
+                files,
+            },
+            v18::EventType::Updated { fields } => Self::Updated {
+                fields: fields
+                    .into_iter()
+                    .filter(|f| {
+                        !matches!(
+                            f.field,
+                            v18::TorrentMetaField::Description
+                                | v18::TorrentMetaField::Ids
+                                | v18::TorrentMetaField::Dramatized
+                        )
+                    })
+                    .map(Into::into)
+                    .collect(),
+            },
+            v18::EventType::RemovedFromMam => Self::RemovedFromMam,
+            v18::EventType::Selected { .. } => Self::Updated { fields: vec![] },
+            v18::EventType::AbsUpdated { .. } => Self::Updated { fields: vec![] },
+            v18::EventType::FilesMissing { .. } => Self::Updated { fields: vec![] },
+            v18::EventType::HookFailed { .. } => Self::Updated { fields: vec![] },
+            v18::EventType::Converted { .. } => Self::Updated { fields: vec![] },
+            v18::EventType::Verified { .. } => Self::Updated { fields: vec![] },
+        }
+    }
+}
+
+impl From<v18::TorrentMetaDiff> for TorrentMetaDiff {
+    fn from(value: v18::TorrentMetaDiff) -> Self {
+        Self {
+            field: value.field.into(),
+            from: value.from,
+            to: value.to,
+        }
+    }
+}
+
+impl From<v18::TorrentMetaField> for TorrentMetaField {
+    fn from(value: v18::TorrentMetaField) -> Self {
+        match value {
+            v18::TorrentMetaField::MamId => TorrentMetaField::MamId,
+            v18::TorrentMetaField::Vip => TorrentMetaField::Vip,
+            v18::TorrentMetaField::Cat => TorrentMetaField::Cat,
+            v18::TorrentMetaField::MediaType => TorrentMetaField::MediaType,
+            v18::TorrentMetaField::MainCat => TorrentMetaField::MainCat,
+            v18::TorrentMetaField::Categories => TorrentMetaField::Categories,
+            v18::TorrentMetaField::Language => TorrentMetaField::Language,
+            v18::TorrentMetaField::Flags => TorrentMetaField::Flags,
+            v18::TorrentMetaField::Filetypes => TorrentMetaField::Filetypes,
+            v18::TorrentMetaField::Size => TorrentMetaField::Size,
+            v18::TorrentMetaField::Title => TorrentMetaField::Title,
+            v18::TorrentMetaField::Edition => TorrentMetaField::Edition,
+            v18::TorrentMetaField::Authors => TorrentMetaField::Authors,
+            v18::TorrentMetaField::Narrators => TorrentMetaField::Narrators,
+            v18::TorrentMetaField::Series => TorrentMetaField::Series,
+            v18::TorrentMetaField::Source => TorrentMetaField::Source,
+            v18::TorrentMetaField::Description
+            | v18::TorrentMetaField::Ids
+            | v18::TorrentMetaField::Dramatized => unimplemented!(),
+        }
+    }
+}
+
+impl From<v18::Torrent> for Torrent {
+    fn from(t: v18::Torrent) -> Self {
+        Self {
+            id: t.id,
+            id_is_hash: t.id_is_hash,
+            mam_id: t.mam_id,
+            abs_id: t.abs_id,
+            goodreads_id: t.goodreads_id,
+            library_path: t.library_path,
+            library_files: t.library_files,
+            linker: t.linker,
+            category: t.category,
+            selected_audio_format: t.selected_audio_format,
+            selected_ebook_format: t.selected_ebook_format,
+            title_search: t.title_search,
+            meta: t.meta.into(),
+            created_at: t.created_at,
+            replaced_with: t.replaced_with,
+            request_matadata_update: t.request_matadata_update,
+            library_mismatch: t.library_mismatch.and_then(|m| match m {
+                v18::LibraryMismatch::NewLibraryDir(path) => {
+                    Some(v08::LibraryMismatch::NewLibraryDir(path))
+                }
+                v18::LibraryMismatch::NewPath(path) => Some(v08::LibraryMismatch::NewPath(path)),
+                v18::LibraryMismatch::NoLibrary => Some(v08::LibraryMismatch::NoLibrary),
+                v18::LibraryMismatch::MissingFiles(_) => None,
+            }),
+            client_status: t.client_status,
+        }
+    }
+}
+
+impl From<v18::SelectedTorrent> for SelectedTorrent {
+    fn from(t: v18::SelectedTorrent) -> Self {
+        Self {
+            mam_id: t.mam_id,
+            goodreads_id: t.goodreads_id,
+            hash: t.hash,
+            dl_link: t.dl_link,
+            unsat_buffer: t.unsat_buffer,
+            wedge_buffer: t.wedge_buffer,
+            cost: t.cost,
+            category: t.category,
+            tags: t.tags,
+            title_search: t.title_search,
+            meta: t.meta.into(),
+            grabber: t.grabber,
+            created_at: t.created_at,
+            started_at: t.started_at,
+            removed_at: t.removed_at,
+        }
+    }
+}
+
+impl From<v18::DuplicateTorrent> for DuplicateTorrent {
+    fn from(t: v18::DuplicateTorrent) -> Self {
+        Self {
+            mam_id: t.mam_id,
+            dl_link: t.dl_link,
+            title_search: t.title_search,
+            meta: t.meta.into(),
+            created_at: t.created_at,
+            duplicate_of: t.duplicate_of,
+        }
+    }
+}
+
+impl From<v18::ErroredTorrent> for ErroredTorrent {
+    fn from(t: v18::ErroredTorrent) -> Self {
+        Self {
+            id: t.id,
+            title: t.title,
+            error: t.error,
+            meta: t.meta.map(|t| t.into()),
+            created_at: t.created_at,
+        }
+    }
+}
+
+impl From<v18::TorrentMeta> for TorrentMeta {
+    fn from(t: v18::TorrentMeta) -> Self {
+        Self {
+            mam_id: t.mam_id,
+            vip_status: t.vip_status,
+            cat: t.cat,
+            media_type: t.media_type,
+            main_cat: t.main_cat,
+            categories: t.categories,
+            language: t.language,
+            flags: t.flags,
+            filetypes: t.filetypes,
+            num_files: t.num_files,
+            size: t.size,
+            title: t.title,
+            edition: t.edition,
+            authors: t.authors,
+            narrators: t.narrators,
+            series: t.series,
+            source: t.source,
+            uploaded_at: t.uploaded_at,
+        }
+    }
+}
@@ -0,0 +1,591 @@
+use super::{v01, v03, v04, v05, v08, v09, v10, v11, v12, v13, v15, v16, v17};
+use native_db::{ToKey, native_db};
+use native_model::{Model, native_model};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[native_model(id = 2, version = 18, from = v17::Torrent)]
+#[native_db(export_keys = true)]
+pub struct Torrent {
+    #[primary_key]
+    pub id: String,
+    pub id_is_hash: bool,
+    #[secondary_key(unique)]
+    pub mam_id: u64,
+    pub abs_id: Option<String>,
+    pub goodreads_id: Option<u64>,
+    pub library_path: Option<PathBuf>,
+    pub library_files: Vec<PathBuf>,
+    pub linker: Option<String>,
+    pub category: Option<String>,
+    pub selected_audio_format: Option<String>,
+    pub selected_ebook_format: Option<String>,
+    #[secondary_key]
+    pub title_search: String,
+    pub meta: TorrentMeta,
+    #[secondary_key]
+    pub created_at: v03::Timestamp,
+    pub replaced_with: Option<(String, v03::Timestamp)>,
+    pub request_matadata_update: bool,
+    pub library_mismatch: Option<LibraryMismatch>,
+    pub client_status: Option<v08::ClientStatus>,
+    /// Free text note, kept when the metadata is updated
+    pub note: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum LibraryMismatch {
+    NewLibraryDir(PathBuf),
+    NewPath(PathBuf),
+    NoLibrary,
+    MissingFiles(Vec<PathBuf>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[native_model(id = 3, version = 18, from = v17::SelectedTorrent)]
+#[native_db(export_keys = true)]
+pub struct SelectedTorrent {
+    #[primary_key]
+    pub mam_id: u64,
+    pub goodreads_id: Option<u64>,
+    #[secondary_key(unique, optional)]
+    pub hash: Option<String>,
+    pub dl_link: String,
+    pub unsat_buffer: Option<u64>,
+    pub wedge_buffer: Option<u64>,
+    pub cost: v04::TorrentCost,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    #[secondary_key]
+    pub title_search: String,
+    pub meta: TorrentMeta,
+    pub grabber: Option<String>,
+    /// The `[[mam]]` account that selected the torrent, `None` for the main one
+    pub account: Option<String>,
+    /// Higher priority torrents are grabbed first
+    pub priority: i32,
+    /// Free text note, carried over to the torrent when it is linked
+    pub note: Option<String>,
+    pub created_at: v03::Timestamp,
+    pub started_at: Option<v03::Timestamp>,
+    pub removed_at: Option<v03::Timestamp>,
+    /// When `recheck_seeders_before_grab` last found too few seeders, cleared
+    /// once it has enough again
+    pub grab_skipped_at: Option<v03::Timestamp>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[native_model(id = 4, version = 18, from = v17::DuplicateTorrent)]
+#[native_db]
+pub struct DuplicateTorrent {
+    #[primary_key]
+    pub mam_id: u64,
+    pub dl_link: Option<String>,
+    #[secondary_key]
+    pub title_search: String,
+    pub meta: TorrentMeta,
+    pub created_at: v03::Timestamp,
+    pub duplicate_of: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[native_model(id = 5, version = 18, from = v17::ErroredTorrent)]
+#[native_db(export_keys = true)]
+pub struct ErroredTorrent {
+    #[primary_key]
+    pub id: v11::ErroredTorrentId,
+    pub title: String,
+    pub error: String,
+    pub meta: Option<TorrentMeta>,
+    #[secondary_key]
+    pub created_at: v03::Timestamp,
+    /// Number of times in a row this step has failed or waited for the torrent
+    pub attempts: u64,
+    /// Number of times in a row this step has failed for the torrent, counted
+    /// against `max_attempts`
+    pub failures: u64,
+    /// When the step may run again for the torrent, None once it has given up
+    /// and only retries when asked to from the errors page
+    pub next_retry_at: Option<v03::Timestamp>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TorrentMeta {
+    pub mam_id: u64,
+    pub vip_status: Option<v11::VipStatus>,
+    pub cat: Option<v16::OldCategory>,
+    pub media_type: v13::MediaType,
+    pub main_cat: Option<v12::MainCat>,
+    pub categories: Vec<v15::Category>,
+    pub language: Option<v03::Language>,
+    pub flags: Option<v08::FlagBits>,
+    pub filetypes: Vec<String>,
+    pub num_files: u64,
+    pub size: v03::Size,
+    pub title: String,
+    pub edition: Option<(String, u64)>,
+    pub authors: Vec<String>,
+    /// The authors as written on MaM, if `author_aliases` replaced any of them
+    pub original_authors: Option<Vec<String>>,
+    pub narrators: Vec<String>,
+    pub series: Vec<v09::Series>,
+    pub description: Option<String>,
+    /// Standard ids of the book, like isbn and asin, by kind
+    pub ids: BTreeMap<String, String>,
+    /// A dramatized or full cast recording rather than a single narrator
+    pub dramatized: bool,
+    pub source: MetadataSource,
+    pub uploaded_at: v03::Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum MetadataSource {
+    Mam,
+    Manual,
+    /// Imported from an existing library, like Audiobookshelf, without a MaM
+    /// torrent
+    File,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[native_model(id = 6, version = 18, from = v17::Event)]
+#[native_db(export_keys = true)]
+pub struct Event {
+    #[primary_key]
+    pub id: v03::Uuid,
+    #[secondary_key]
+    pub torrent_id: Option<String>,
+    #[secondary_key]
+    pub mam_id: Option<u64>,
+    #[secondary_key]
+    pub created_at: v03::Timestamp,
+    pub event: EventType,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[native_model(id = 7, version = 18, from = v05::List)]
+#[native_db(export_keys = true)]
+pub struct List {
+    #[primary_key]
+    pub id: String,
+    #[secondary_key]
+    pub title: String,
+    pub updated_at: Option<v03::Timestamp>,
+    pub build_date: Option<v03::Timestamp>,
+    /// Cache validators from the last time the list was fetched
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[native_model(id = 8, version = 18, from = v05::ListItem)]
+#[native_db(export_keys = true)]
+pub struct ListItem {
+    #[primary_key]
+    pub guid: (String, String),
+    #[secondary_key]
+    pub list_id: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub series: Vec<(String, f64)>,
+    pub cover_url: String,
+    pub book_url: Option<String>,
+    pub isbn: Option<u64>,
+    pub prefer_format: Option<v01::MainCat>,
+    pub allow_audio: bool,
+    pub audio_torrent: Option<v04::ListItemTorrent>,
+    pub allow_ebook: bool,
+    pub ebook_torrent: Option<v04::ListItemTorrent>,
+    #[secondary_key]
+    pub created_at: v03::Timestamp,
+    pub marked_done_at: Option<v03::Timestamp>,
+    /// The best search results when none matched well enough to be grabbed,
+    /// for picking one by hand
+    pub candidates: Vec<ListItemCandidate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ListItemCandidate {
+    pub mam_id: u64,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub media_type: v13::MediaType,
+    pub filetypes: Vec<String>,
+    pub score: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[native_model(id = 9, version = 18)]
+#[native_db(export_keys = true)]
+pub struct SearchHistory {
+    #[primary_key]
+    pub id: v03::Uuid,
+    #[secondary_key]
+    pub created_at: v03::Timestamp,
+    pub query: String,
+    pub sort: String,
+    pub uploader: Option<u64>,
+}
+
+/// The last time a background task failed, kept so it is still known after
+/// a restart
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[native_model(id = 10, version = 18)]
+#[native_db(export_keys = true)]
+pub struct TaskError {
+    /// The task, keyed like paused tasks, e.g. "autograb:Freeleech" or "linker"
+    #[primary_key]
+    pub task: String,
+    pub created_at: v03::Timestamp,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum EventType {
+    Selected {
+        grabber: Option<String>,
+        cost: Option<v04::TorrentCost>,
+    },
+    Grabbed {
+        grabber: Option<String>,
+        cost: Option<v04::TorrentCost>,
+        wedged: bool,
+    },
+    Linked {
+        linker: Option<String>,
+        library_path: PathBuf,
+    },
+    Cleaned {
+        library_path: PathBuf,
+        files: Vec<PathBuf>,
+    },
+    Updated {
+        fields: Vec<TorrentMetaDiff>,
+    },
+    RemovedFromMam,
+    AbsUpdated {
+        abs_id: String,
+        fields: Vec<String>,
+    },
+    FilesMissing {
+        library_path: PathBuf,
+        files: Vec<PathBuf>,
+    },
+    HookFailed {
+        hook: String,
+        status: Option<i32>,
+        output: String,
+    },
+    Converted {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Verified {
+        outcome: VerifyOutcome,
+    },
+    IdsAdded {
+        ids: BTreeMap<String, String>,
+    },
+    /// A selected torrent was left for later as it had too few seeders when
+    /// it was about to be grabbed
+    GrabSkipped {
+        seeders: u64,
+        min_seeders: u64,
+    },
+    /// A selected torrent was unselected as it was not grabbed within
+    /// `selected_max_age_days`
+    SelectionExpired {
+        max_age_days: u64,
+    },
+    /// A torrent was not linked as its library requires metadata it is missing
+    MetadataPending {
+        missing: Vec<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TorrentMetaDiff {
+    pub field: TorrentMetaField,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum TorrentMetaField {
+    MamId,
+    Vip,
+    Cat,
+    MediaType,
+    MainCat,
+    Categories,
+    Language,
+    Flags,
+    Filetypes,
+    Size,
+    Title,
+    Edition,
+    Authors,
+    Narrators,
+    Series,
+    Source,
+    Description,
+    Ids,
+    Dramatized,
+}
+
+/// What happened when a torrent was checked before starting it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyOutcome {
+    /// All existing data matched and the torrent was started to seed
+    Complete,
+    /// There was no existing data and the torrent was started to download
+    NoData,
+    /// Only part of the existing data matched and the torrent was left stopped
+    Mismatch,
+    /// Checking did not finish in time and the torrent was left stopped
+    TimedOut,
+}
+
+impl From<v17::Torrent> for Torrent {
+    fn from(t: v17::Torrent) -> Self {
+        Self {
+            id: t.id,
+            id_is_hash: t.id_is_hash,
+            mam_id: t.mam_id,
+            abs_id: t.abs_id,
+            goodreads_id: t.goodreads_id,
+            library_path: t.library_path,
+            library_files: t.library_files,
+            linker: t.linker,
+            category: t.category,
+            selected_audio_format: t.selected_audio_format,
+            selected_ebook_format: t.selected_ebook_format,
+            title_search: t.title_search,
+            meta: t.meta.into(),
+            created_at: t.created_at,
+            replaced_with: t.replaced_with,
+            request_matadata_update: t.request_matadata_update,
+            library_mismatch: t.library_mismatch.map(Into::into),
+            client_status: t.client_status,
+            note: None,
+        }
+    }
+}
+
+impl From<v08::LibraryMismatch> for LibraryMismatch {
+    fn from(t: v08::LibraryMismatch) -> Self {
+        match t {
+            v08::LibraryMismatch::NewLibraryDir(path) => Self::NewLibraryDir(path),
+            v08::LibraryMismatch::NewPath(path) => Self::NewPath(path),
+            v08::LibraryMismatch::NoLibrary => Self::NoLibrary,
+        }
+    }
+}
+
+impl From<v17::SelectedTorrent> for SelectedTorrent {
+    fn from(t: v17::SelectedTorrent) -> Self {
+        Self {
+            mam_id: t.mam_id,
+            goodreads_id: t.goodreads_id,
+            hash: t.hash,
+            dl_link: t.dl_link,
+            unsat_buffer: t.unsat_buffer,
+            wedge_buffer: t.wedge_buffer,
+            cost: t.cost,
+            category: t.category,
+            tags: t.tags,
+            title_search: t.title_search,
+            meta: t.meta.into(),
+            grabber: t.grabber,
+            account: None,
+            priority: 0,
+            note: None,
+            created_at: t.created_at,
+            started_at: t.started_at,
+            removed_at: t.removed_at,
+            grab_skipped_at: None,
+        }
+    }
+}
+
+impl From<v05::List> for List {
+    fn from(t: v05::List) -> Self {
+        Self {
+            id: t.id,
+            title: t.title,
+            updated_at: t.updated_at,
+            build_date: t.build_date,
+            etag: None,
+            last_modified: None,
+        }
+    }
+}
+
+impl From<v05::ListItem> for ListItem {
+    fn from(t: v05::ListItem) -> Self {
+        Self {
+            guid: t.guid,
+            list_id: t.list_id,
+            title: t.title,
+            authors: t.authors,
+            series: t.series,
+            cover_url: t.cover_url,
+            book_url: t.book_url,
+            isbn: t.isbn,
+            prefer_format: t.prefer_format,
+            allow_audio: t.allow_audio,
+            audio_torrent: t.audio_torrent,
+            allow_ebook: t.allow_ebook,
+            ebook_torrent: t.ebook_torrent,
+            created_at: t.created_at,
+            marked_done_at: t.marked_done_at,
+            candidates: vec![],
+        }
+    }
+}
+
+impl From<v17::DuplicateTorrent> for DuplicateTorrent {
+    fn from(t: v17::DuplicateTorrent) -> Self {
+        Self {
+            mam_id: t.mam_id,
+            dl_link: t.dl_link,
+            title_search: t.title_search,
+            meta: t.meta.into(),
+            created_at: t.created_at,
+            duplicate_of: t.duplicate_of,
+        }
+    }
+}
+
+impl From<v17::ErroredTorrent> for ErroredTorrent {
+    fn from(t: v17::ErroredTorrent) -> Self {
+        Self {
+            id: t.id,
+            title: t.title,
+            error: t.error,
+            meta: t.meta.map(|t| t.into()),
+            created_at: t.created_at,
+            attempts: 1,
+            failures: 1,
+            next_retry_at: Some(t.created_at),
+        }
+    }
+}
+
+impl From<v17::TorrentMeta> for TorrentMeta {
+    fn from(t: v17::TorrentMeta) -> Self {
+        Self {
+            mam_id: t.mam_id,
+            vip_status: t.vip_status,
+            cat: t.cat,
+            media_type: t.media_type,
+            main_cat: t.main_cat,
+            categories: t.categories,
+            language: t.language,
+            flags: t.flags,
+            filetypes: t.filetypes,
+            num_files: t.num_files,
+            size: t.size,
+            title: t.title,
+            edition: t.edition,
+            authors: t.authors,
+            original_authors: None,
+            narrators: t.narrators,
+            series: t.series,
+            description: None,
+            ids: BTreeMap::new(),
+            dramatized: false,
+            source: t.source.into(),
+            uploaded_at: t.uploaded_at,
+        }
+    }
+}
+
+impl From<v10::MetadataSource> for MetadataSource {
+    fn from(t: v10::MetadataSource) -> Self {
+        match t {
+            v10::MetadataSource::Mam => Self::Mam,
+            v10::MetadataSource::Manual => Self::Manual,
+        }
+    }
+}
+
+impl From<v17::Event> for Event {
+    fn from(t: v17::Event) -> Self {
+        Self {
+            id: t.id,
+            torrent_id: t.torrent_id,
+            mam_id: t.mam_id,
+            created_at: t.created_at,
+            event: t.event.into(),
+        }
+    }
+}
+
+impl From<v17::EventType> for EventType {
+    fn from(t: v17::EventType) -> Self {
+        match t {
+            v17::EventType::Grabbed {
+                grabber,
+                cost,
+                wedged,
+            } => Self::Grabbed {
+                grabber,
+                cost,
+                wedged,
+            },
+            v17::EventType::Linked {
+                linker,
+                library_path,
+            } => Self::Linked {
+                linker,
+                library_path,
+            },
+            v17::EventType::Cleaned {
+                library_path,
+                files,
+            } => Self::Cleaned {
+                library_path,
+                files,
+            },
+            v17::EventType::Updated { fields } => Self::Updated {
+                fields: fields.into_iter().map(Into::into).collect(),
+            },
+            v17::EventType::RemovedFromMam => Self::RemovedFromMam,
+        }
+    }
+}
+
+impl From<v17::TorrentMetaDiff> for TorrentMetaDiff {
+    fn from(value: v17::TorrentMetaDiff) -> Self {
+        Self {
+            field: value.field.into(),
+            from: value.from,
+            to: value.to,
+        }
+    }
+}
+
+impl From<v17::TorrentMetaField> for TorrentMetaField {
+    fn from(value: v17::TorrentMetaField) -> Self {
+        match value {
+            v17::TorrentMetaField::MamId => TorrentMetaField::MamId,
+            v17::TorrentMetaField::Vip => TorrentMetaField::Vip,
+            v17::TorrentMetaField::Cat => TorrentMetaField::Cat,
+            v17::TorrentMetaField::MediaType => TorrentMetaField::MediaType,
+            v17::TorrentMetaField::MainCat => TorrentMetaField::MainCat,
+            v17::TorrentMetaField::Categories => TorrentMetaField::Categories,
+            v17::TorrentMetaField::Language => TorrentMetaField::Language,
+            v17::TorrentMetaField::Flags => TorrentMetaField::Flags,
+            v17::TorrentMetaField::Filetypes => TorrentMetaField::Filetypes,
+            v17::TorrentMetaField::Size => TorrentMetaField::Size,
+            v17::TorrentMetaField::Title => TorrentMetaField::Title,
+            v17::TorrentMetaField::Edition => TorrentMetaField::Edition,
+            v17::TorrentMetaField::Authors => TorrentMetaField::Authors,
+            v17::TorrentMetaField::Narrators => TorrentMetaField::Narrators,
+            v17::TorrentMetaField::Series => TorrentMetaField::Series,
+            v17::TorrentMetaField::Source => TorrentMetaField::Source,
+        }
+    }
+}
@@ -18,6 +18,7 @@ use tracing::{debug, error, info, trace, warn};
 use crate::{
     // config::{Config, SnatchlistType},
     enums::SnatchlistType,
+    http::client_builder,
     search::{MaMTorrent, SearchError, SearchFields, SearchQuery, SearchResult, Tor},
     user_data::UserResponse,
     user_torrent::UserDetailsTorrentResponse,
@@ -64,6 +65,8 @@ pub enum WedgeBuyError {
 }
 
 pub struct MaM<'a> {
+    /// Name of the `[[mam]]` account, `None` for the main `mam_id`
+    pub account: Option<String>,
     jar: Arc<CookieStoreRwLock>,
     client: reqwest::Client,
     db: Arc<Database<'a>>,
@@ -72,12 +75,31 @@ pub struct MaM<'a> {
 
 impl<'a> MaM<'a> {
     pub async fn new(mam_id: &str, db: Arc<Database<'a>>) -> Result<MaM<'a>> {
+        Self::connect(None, mam_id, db).await
+    }
+
+    /// A session for a named account, its mam_id is stored separately from the
+    /// main one
+    pub async fn new_account(
+        account: &str,
+        mam_id: &str,
+        db: Arc<Database<'a>>,
+    ) -> Result<MaM<'a>> {
+        Self::connect(Some(account.to_owned()), mam_id, db).await
+    }
+
+    async fn connect(
+        account: Option<String>,
+        mam_id: &str,
+        db: Arc<Database<'a>>,
+    ) -> Result<MaM<'a>> {
         let jar: CookieStoreRwLock = Default::default();
         let url = "https://www.myanonamouse.net/json".parse::<Url>().unwrap();
 
+        let key = stored_key(account.as_deref());
         let stored_mam_id = db
             .r_transaction()
-            .and_then(|r| r.get().primary::<mlm_db::Config>("mam_id"))
+            .and_then(|r| r.get().primary::<mlm_db::Config>(key))
             .ok()
             .flatten()
             .map(|c| c.value);
@@ -95,13 +117,13 @@ impl<'a> MaM<'a> {
             .store_response_cookies([cookie].into_iter(), &url);
 
         let jar = Arc::new(jar);
-        let client = reqwest::Client::builder()
+        let client = client_builder()
             .cookie_provider(jar.clone())
-            .user_agent("MLM")
             .timeout(Duration::from_secs(20))
             .build()?;
 
         let mam = MaM {
+            account,
             jar,
             client,
             db,
@@ -347,7 +369,7 @@ impl<'a> MaM<'a> {
         };
         let ok = rw
             .upsert(mlm_db::Config {
-                key: "mam_id".to_string(),
+                key: stored_key(self.account.as_deref()),
                 value,
             })
             .and_then(|_| rw.commit())
@@ -358,3 +380,11 @@ impl<'a> MaM<'a> {
         }
     }
 }
+
+/// The db key the rotated mam_id of an account is stored under
+fn stored_key(account: Option<&str>) -> String {
+    match account {
+        Some(account) => format!("mam_id:{account}"),
+        None => "mam_id".to_string(),
+    }
+}
@@ -61,7 +61,7 @@ impl SearchIn {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum SearchKind {
     /// Last update had 1+ seeders
     #[serde(rename = "active")]
@@ -1,5 +1,6 @@
 pub mod api;
 pub mod enums;
+pub mod http;
 pub mod meta;
 pub mod search;
 pub mod serde;
@@ -1,6 +1,8 @@
+use std::{collections::HashMap, sync::OnceLock};
+
 use anyhow::{Error, Result};
-use mlm_db::{MediaType, OldCategory, TorrentMeta};
-use mlm_parse::{SERIES_CLEANUP, TITLE_CLEANUP, clean_name, clean_value, parse_edition};
+use mlm_db::{MediaType, OldCategory, Series, TorrentMeta};
+use mlm_parse::{SERIES_CLEANUP, TitleRules, clean_name, clean_value, parse_dramatized};
 
 #[derive(thiserror::Error, Debug)]
 pub enum MetaError {
@@ -24,7 +26,23 @@ pub enum MetaError {
     Other(#[from] Error),
 }
 
-pub fn clean_meta(mut meta: TorrentMeta, tags: &str) -> Result<TorrentMeta> {
+/// Canonical author names, keyed on the lowercased alias
+static AUTHOR_ALIASES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Sets the alias to canonical name mapping `clean_meta` applies to authors,
+/// should be called once at startup
+pub fn set_author_aliases<'a>(aliases: impl IntoIterator<Item = (&'a String, &'a String)>) {
+    AUTHOR_ALIASES
+        .set(
+            aliases
+                .into_iter()
+                .map(|(alias, canonical)| (alias.to_lowercase(), canonical.clone()))
+                .collect(),
+        )
+        .ok();
+}
+
+pub fn clean_meta(mut meta: TorrentMeta, tags: &str, rules: &TitleRules) -> Result<TorrentMeta> {
     // A large amount of audiobook torrents have been incorrectly set to ebook
     if meta.media_type == MediaType::Ebook
         && let Some(OldCategory::Audio(_)) = meta.cat
@@ -43,7 +61,7 @@ pub fn clean_meta(mut meta: TorrentMeta, tags: &str) -> Result<TorrentMeta> {
             .to_string();
     }
 
-    let (title, edition) = parse_edition(&meta.title, tags);
+    let (title, edition) = rules.parse_edition(&meta.title, tags);
     meta.title = title;
     meta.edition = edition;
 
@@ -67,10 +85,99 @@ pub fn clean_meta(mut meta: TorrentMeta, tags: &str) -> Result<TorrentMeta> {
         }
     }
 
-    meta.title = TITLE_CLEANUP
-        .replace_all(&meta.title, "")
-        .trim()
-        .to_string();
+    meta.title = rules.strip_title(&meta.title);
+    (meta.title, meta.dramatized) = parse_dramatized(&meta.title, tags);
+    if meta.series.is_empty()
+        && let Some(found) = rules.extract_series(&meta.title)
+        && let Ok(series) = Series::try_from((found.name, found.number))
+    {
+        meta.title = found.title;
+        meta.series.push(series);
+    }
+
+    // Done last so the title is cleaned up with the author as written on MaM
+    if let Some(aliases) = AUTHOR_ALIASES.get() {
+        alias_authors(&mut meta, aliases);
+    }
 
     Ok(meta)
 }
+
+/// Replaces aliased authors with their canonical name, keeping the authors as
+/// they were in `original_authors`
+fn alias_authors(meta: &mut TorrentMeta, aliases: &HashMap<String, String>) {
+    if !meta
+        .authors
+        .iter()
+        .any(|author| aliases.contains_key(&author.to_lowercase()))
+    {
+        return;
+    }
+    let original = meta.authors.clone();
+    let mut authors: Vec<String> = Vec::with_capacity(original.len());
+    for author in &original {
+        let author = aliases
+            .get(&author.to_lowercase())
+            .unwrap_or(author)
+            .clone();
+        if !authors.contains(&author) {
+            authors.push(author);
+        }
+    }
+    meta.authors = authors;
+    meta.original_authors = Some(original);
+}
+
+#[cfg(test)]
+mod tests {
+    use mlm_db::{MetadataSource, Size, Timestamp};
+
+    use super::*;
+
+    #[test]
+    fn test_alias_authors() {
+        let aliases = HashMap::from([("robert galbraith".to_string(), "J.K. Rowling".to_string())]);
+        let mut meta = TorrentMeta {
+            mam_id: 1,
+            vip_status: None,
+            cat: None,
+            media_type: MediaType::Audiobook,
+            main_cat: None,
+            categories: vec![],
+            language: None,
+            flags: None,
+            filetypes: vec![],
+            num_files: 1,
+            size: Size::from_bytes(100),
+            title: "The Cuckoo's Calling".to_string(),
+            edition: None,
+            authors: vec!["Robert Galbraith".to_string(), "J.K. Rowling".to_string()],
+            original_authors: None,
+            narrators: vec![],
+            series: vec![],
+            description: None,
+            ids: Default::default(),
+            dramatized: false,
+            source: MetadataSource::Mam,
+            uploaded_at: Timestamp::now(),
+        };
+        let unaliased = TorrentMeta {
+            authors: vec!["Someone Else".to_string()],
+            ..meta.clone()
+        };
+
+        alias_authors(&mut meta, &aliases);
+        assert_eq!(meta.authors, vec!["J.K. Rowling".to_string()]);
+        assert_eq!(
+            meta.original_authors,
+            Some(vec![
+                "Robert Galbraith".to_string(),
+                "J.K. Rowling".to_string()
+            ])
+        );
+
+        let mut meta = unaliased.clone();
+        alias_authors(&mut meta, &aliases);
+        assert_eq!(meta, unaliased);
+    }
+}
@@ -2,9 +2,11 @@ use std::collections::BTreeMap;
 
 use anyhow::Result;
 use mlm_db::{
-    Category, FlagBits, Language, MainCat, MediaType, MetadataSource, OldCategory, Series,
-    SeriesEntries, Timestamp, TorrentMeta, VipStatus,
+    ASIN_ID, Category, FlagBits, ISBN_ID, Language, MainCat, MediaType, MetadataSource,
+    OldCategory, Series, SeriesEntries, Timestamp, TorrentMeta, VipStatus,
+    impls::series::parse_series_from_title,
 };
+use mlm_parse::TitleRules;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use time::UtcDateTime;
@@ -284,10 +286,10 @@ pub struct MediaInfoMenu {
 }
 
 impl MaMTorrent {
-    pub fn as_meta(&self) -> Result<TorrentMeta, MetaError> {
+    pub fn as_meta(&self, rules: &TitleRules) -> Result<TorrentMeta, MetaError> {
         let authors = self.author_info.values().cloned().collect();
         let narrators = self.narrator_info.values().cloned().collect();
-        let series = self
+        let mut series = self
             .series_info
             .values()
             .map(|series| {
@@ -312,6 +314,13 @@ impl MaMTorrent {
                 })
             })
             .collect::<Result<Vec<_>>>()?;
+        // Omnibus uploads often only carry the book range in the title
+        if let [series] = series.as_mut_slice()
+            && series.entries.0.is_empty()
+            && let Some(entries) = parse_series_from_title(&self.title, rules)
+        {
+            series.entries = entries;
+        }
 
         let media_type = MediaType::from_id(self.mediatype)
             .or_else(|| MediaType::from_main_cat_id(self.main_cat))
@@ -372,15 +381,35 @@ impl MaMTorrent {
                 title: self.title.clone(),
                 edition: None,
                 authors,
+                original_authors: None,
                 narrators,
                 series,
+                description: self.description.clone(),
+                ids: self.ids(),
+                dramatized: false,
                 source: MetadataSource::Mam,
                 uploaded_at,
             },
             &self.tags,
+            rules,
         )?)
     }
 
+    /// The ISBN or ASIN in the isbn field, MaM keeps ASINs there prefixed
+    /// with "ASIN:"
+    pub fn ids(&self) -> BTreeMap<String, String> {
+        let mut ids = BTreeMap::new();
+        let isbn = self.isbn.as_deref().unwrap_or_default().trim();
+        if let Some(asin) = isbn.strip_prefix("ASIN:") {
+            if !asin.trim().is_empty() {
+                ids.insert(ASIN_ID.to_string(), asin.trim().to_string());
+            }
+        } else if !isbn.is_empty() {
+            ids.insert(ISBN_ID.to_string(), isbn.to_string());
+        }
+        ids
+    }
+
     pub fn is_free(&self) -> bool {
         self.free || self.personal_freeleech || self.fl_vip
     }
@@ -9,6 +9,8 @@ pub struct UserResponse {
     pub seedbonus: i64,
     pub wedges: u64,
     pub unsat: Unsats,
+    #[serde(default)]
+    pub vip_until: Option<String>,
     // pub classname: UserClass,
     // pub connectable: String,
     // pub country_code: Option<String>,
@@ -45,7 +47,6 @@ pub struct UserResponse {
     // pub uploaded: String,
     // pub username: String,
     // pub v6_connectable: bool,
-    // pub vip_until: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -4,7 +4,7 @@ use mlm_db::{
     Category, FlagBits, MediaType, MetadataSource, OldCategory, Series, SeriesEntries, Timestamp,
     TorrentMeta, VipStatus,
 };
-use mlm_parse::clean_value;
+use mlm_parse::{TitleRules, clean_value};
 use serde::{Deserialize, Serialize};
 use time::UtcDateTime;
 use tracing::warn;
@@ -123,7 +123,7 @@ pub struct UserDetailsTorrent {
 }
 
 impl UserDetailsTorrent {
-    pub fn as_meta(&self) -> Result<TorrentMeta, MetaError> {
+    pub fn as_meta(&self, rules: &TitleRules) -> Result<TorrentMeta, MetaError> {
         let authors = self
             .author
             .iter()
@@ -205,13 +205,18 @@ impl UserDetailsTorrent {
                 title: clean_value(&self.title)?,
                 edition: None,
                 authors,
+                original_authors: None,
                 narrators,
                 series,
+                description: None,
+                ids: Default::default(),
+                dramatized: false,
                 source: MetadataSource::Mam,
                 // TODO: Currently added isn't returned
                 uploaded_at: Timestamp::from(UtcDateTime::UNIX_EPOCH),
             },
             &clean_value(&self.tags)?,
+            rules,
         )?)
     }
 }
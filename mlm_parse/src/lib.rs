@@ -1,4 +1,6 @@
-use anyhow::Result;
+use std::{borrow::Cow, fmt, str::FromStr};
+
+use anyhow::{Context as _, Result, bail};
 use htmlentity::entity::{self, ICodedDataTrait as _};
 use once_cell::sync::Lazy;
 use regex::{Captures, Match, Regex};
@@ -8,10 +10,339 @@ pub fn clean_value(value: &str) -> Result<String> {
     entity::decode(value.as_bytes()).to_string()
 }
 
+/// The language of the leading articles and edition words removed from titles
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TitleLocale {
+    #[default]
+    English,
+    German,
+    Swedish,
+    French,
+    Spanish,
+}
+
+impl TitleLocale {
+    /// Articles stripped from the start of titles, after transliteration to
+    /// ASCII
+    fn articles(self) -> &'static [&'static str] {
+        match self {
+            TitleLocale::English => &["the", "a", "an"],
+            TitleLocale::German => &["der", "die", "das", "ein", "eine"],
+            TitleLocale::Swedish => &["den", "det", "de", "en", "ett"],
+            TitleLocale::French => &["le", "la", "les", "un", "une"],
+            TitleLocale::Spanish => &["el", "la", "los", "las", "un", "una"],
+        }
+    }
+
+    /// Words for edition recognized next to the English ones, since tags are
+    /// usually in English whatever the language of the book
+    fn edition_words(self) -> &'static [&'static str] {
+        match self {
+            TitleLocale::English => &[],
+            TitleLocale::German => &["Auflage", "Ausgabe"],
+            TitleLocale::Swedish => &["upplaga", "upplagan", "utgåva", "utgåvan"],
+            TitleLocale::French => &["édition"],
+            TitleLocale::Spanish => &["edición"],
+        }
+    }
+
+    /// The number of an edition written as an ordinal word
+    fn ordinal(self, word: &str) -> Option<u64> {
+        let ordinals: &[&str] = match self {
+            TitleLocale::English => &[],
+            TitleLocale::German => &["erste", "zweite", "dritte", "vierte", "fünfte"],
+            TitleLocale::Swedish => &["första", "andra", "tredje", "fjärde", "femte"],
+            TitleLocale::French => &[
+                "première",
+                "deuxième",
+                "troisième",
+                "quatrième",
+                "cinquième",
+            ],
+            TitleLocale::Spanish => &["primera", "segunda", "tercera", "cuarta", "quinta"],
+        };
+        ordinals
+            .iter()
+            .position(|o| word.starts_with(o))
+            .map(|i| i as u64 + 1)
+    }
+}
+
+impl FromStr for TitleLocale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "en" | "english" => Ok(TitleLocale::English),
+            "de" | "german" => Ok(TitleLocale::German),
+            "sv" | "swedish" => Ok(TitleLocale::Swedish),
+            "fr" | "french" => Ok(TitleLocale::French),
+            "es" | "spanish" => Ok(TitleLocale::Spanish),
+            _ => Err(format!("Unknown title locale: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for TitleLocale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TitleLocale::English => write!(f, "en"),
+            TitleLocale::German => write!(f, "de"),
+            TitleLocale::Swedish => write!(f, "sv"),
+            TitleLocale::French => write!(f, "fr"),
+            TitleLocale::Spanish => write!(f, "es"),
+        }
+    }
+}
+
+/// The title cleanup regexes for one locale, with the title patterns from the
+/// config
+#[derive(Debug)]
+pub struct TitleRules {
+    locale: TitleLocale,
+    search_title_cleanup: Regex,
+    edition: Regex,
+    edition_start: Regex,
+    patterns: TitlePatterns,
+}
+
+impl TitleRules {
+    pub fn new(locale: TitleLocale) -> Self {
+        let articles = locale.articles().join("|");
+        let edition = ["Edition", r"ed\."]
+            .iter()
+            .chain(locale.edition_words())
+            .copied()
+            .collect::<Vec<_>>()
+            .join("|");
+        let search_title_cleanup = Regex::new(&format!(r"(?i)^(?:{articles})\s+|[^\w ]")).unwrap();
+        let edition_regex = Regex::new(&format!(r"(?i)^(.*?)(?:(?:(?:\s*[-–.:;|,]\s*)((\w+?)\s+(?:[a-z]+\s+)*(?:{edition}))|(?:\s*[-–.:;|,]\s*)?(?:\s*[(\[]\s*)((\w*?)\s+(?:[a-z]+\s+)*(?:{edition}))(?:\s*[)\]]\s*))(?:\s*[-:;,]\s*)?(.*?)|\s+((\d+\w*?)\s+(?:{edition})))$")).unwrap();
+        let edition_start = Regex::new(&format!(r"(?i)((\d+(?:st|nd|rd|th)|first|second|third|fifth|sixth|seventh|eight|ninth|tenth|new|revised|updated)\s+(?:[a-z']+\s+)*(?:{edition})|(\w+?)\s+(?:{edition}))")).unwrap();
+        Self {
+            locale,
+            search_title_cleanup,
+            edition: edition_regex,
+            edition_start,
+            patterns: TitlePatterns::default(),
+        }
+    }
+
+    pub fn with_patterns(self, patterns: TitlePatterns) -> Self {
+        Self { patterns, ..self }
+    }
+
+    /// The user `title_strip_patterns` are removed first, so titles that only
+    /// differ in those markers are searched as the same title
+    pub fn normalize_title(&self, value: &str) -> String {
+        let value = self.patterns.strip_user(value);
+        let title = unidecode(&value).to_lowercase().replace(" & ", " and ");
+        let title = self.search_title_cleanup.replace_all(&title, "");
+        SEARCH_TITLE_VOLUME.replace_all(&title, "").to_string()
+    }
+
+    pub fn parse_edition(&self, title: &str, tags: &str) -> (String, Option<(String, u64)>) {
+        if let Some(captures) = self.edition.captures(title)
+            && let Some(edition) = self.parse_normal_edition_match(&captures)
+        {
+            let mut title_str = captures.get(1).unwrap().as_str().to_string();
+            if let Some(subtitle_match) = captures.get(6) {
+                let subtitle_str = subtitle_match.as_str();
+                if !subtitle_str.is_empty() {
+                    title_str.push_str(&format!(": {}", subtitle_str.trim()));
+                }
+            }
+            return (title_str, Some(edition));
+        }
+
+        if let Some(captures) = self.edition.captures(tags)
+            && let Some(edition) = self.parse_normal_edition_match(&captures)
+        {
+            return (title.to_string(), Some(edition));
+        }
+        if let Some(captures) = self.edition_start.captures(tags)
+            && let Some(edition) = self.parse_start_edition_match(&captures)
+        {
+            return (title.to_string(), Some(edition));
+        }
+
+        (title.to_string(), None)
+    }
+
+    fn parse_normal_edition_match(&self, captures: &Captures) -> Option<(String, u64)> {
+        let edition_match = captures
+            .get(2)
+            .or_else(|| captures.get(4))
+            .or_else(|| captures.get(7))?;
+
+        let edition_number = captures
+            .get(3)
+            .or_else(|| captures.get(5))
+            .or_else(|| captures.get(8))?;
+
+        self.parse_edition_match(edition_match, edition_number)
+    }
+
+    fn parse_start_edition_match(&self, captures: &Captures) -> Option<(String, u64)> {
+        let edition_match = captures.get(1)?;
+        let edition_number = captures.get(2).or_else(|| captures.get(3))?;
+
+        self.parse_edition_match(edition_match, edition_number)
+    }
+
+    fn parse_edition_match(
+        &self,
+        edition_match: Match,
+        edition_number: Match,
+    ) -> Option<(String, u64)> {
+        let edition_number = match edition_number.as_str().to_lowercase().as_str() {
+            "first" | "1st" => 1,
+            "second" | "2nd" => 2,
+            "third" | "3rd" => 3,
+            "fourth" | "4th" => 4,
+            "fifth" | "5th" => 5,
+            "sixth" | "6th" => 6,
+            "seventh" | "7th" => 7,
+            "eighth" | "8th" => 8,
+            "ninth" | "9th" => 9,
+            "tenth" | "10th" => 10,
+            n if n.ends_with("th") => n[..n.len() - 2].parse().unwrap_or(0),
+            n => self
+                .locale
+                .ordinal(n)
+                .unwrap_or_else(|| n.parse().unwrap_or(0)),
+        };
+        let mut edition_str = edition_match.as_str().to_string();
+
+        let mut first_letter = true;
+        for (i, c) in edition_str.clone().char_indices() {
+            if first_letter
+                && !c.is_uppercase()
+                && let Some(char) = edition_str.get_mut(i..=i)
+            {
+                char.make_ascii_uppercase();
+            }
+            first_letter = c == ' ';
+        }
+        if edition_str.ends_with("Ed.") {
+            edition_str.replace_range((edition_str.len() - 3).., "Edition");
+        }
+
+        Some((edition_str, edition_number))
+    }
+
+    /// Removes format markers and the like from a title, with the built-in
+    /// cleanup and then the user `title_strip_patterns`
+    pub fn strip_title(&self, title: &str) -> String {
+        self.patterns.strip_title(title)
+    }
+
+    /// Finds a series in a title with the user `series_extract_patterns`
+    pub fn extract_series(&self, title: &str) -> Option<TitleSeries> {
+        self.patterns.extract_series(title)
+    }
+}
+
+impl Default for TitleRules {
+    fn default() -> Self {
+        Self::new(TitleLocale::default())
+    }
+}
+
+static DEFAULT_TITLE_RULES: Lazy<TitleRules> = Lazy::new(TitleRules::default);
+
+/// `TitleRules::normalize_title` with the default locale, for code without a
+/// config like the database migrations
 pub fn normalize_title(value: &str) -> String {
-    let title = unidecode(value).to_lowercase().replace(" & ", " and ");
-    let title = SEARCH_TITLE_CLEANUP.replace_all(&title, "");
-    SEARCH_TITLE_VOLUME.replace_all(&title, "").to_string()
+    DEFAULT_TITLE_RULES.normalize_title(value)
+}
+
+/// A user rule that moves a series and its number out of a title
+#[derive(Debug)]
+struct SeriesPattern {
+    regex: Regex,
+    series_group: String,
+    number_group: String,
+}
+
+/// A series found in a title, with the title it is removed from
+#[derive(Debug, PartialEq)]
+pub struct TitleSeries {
+    pub title: String,
+    pub name: String,
+    pub number: String,
+}
+
+/// Title rules from the config, applied after the built-in ones
+#[derive(Debug, Default)]
+pub struct TitlePatterns {
+    strip: Vec<Regex>,
+    series: Vec<SeriesPattern>,
+}
+
+impl TitlePatterns {
+    /// Adds a regex whose matches are removed from titles
+    pub fn add_strip(&mut self, pattern: &str) -> Result<()> {
+        let regex = Regex::new(pattern).with_context(|| format!("invalid regex {pattern:?}"))?;
+        self.strip.push(regex);
+        Ok(())
+    }
+
+    /// Adds a regex that finds a series in titles, with the names of the
+    /// capture groups holding the series name and number
+    pub fn add_series(
+        &mut self,
+        pattern: &str,
+        series_group: &str,
+        number_group: &str,
+    ) -> Result<()> {
+        let regex = Regex::new(pattern).with_context(|| format!("invalid regex {pattern:?}"))?;
+        for group in [series_group, number_group] {
+            if !regex.capture_names().any(|name| name == Some(group)) {
+                bail!("regex {pattern:?} has no capture group named {group:?}");
+            }
+        }
+        self.series.push(SeriesPattern {
+            regex,
+            series_group: series_group.to_string(),
+            number_group: number_group.to_string(),
+        });
+        Ok(())
+    }
+
+    pub fn strip_title(&self, title: &str) -> String {
+        let title = TITLE_CLEANUP.replace_all(title, "");
+        self.strip_user(title.trim()).into_owned()
+    }
+
+    /// Only the user patterns of `strip_title`
+    fn strip_user<'a>(&self, title: &'a str) -> Cow<'a, str> {
+        let mut title = Cow::Borrowed(title);
+        for regex in &self.strip {
+            title = Cow::Owned(regex.replace_all(&title, "").trim().to_string());
+        }
+        title
+    }
+
+    /// The series of the first pattern that matches, the whole match is
+    /// removed from the title
+    pub fn extract_series(&self, title: &str) -> Option<TitleSeries> {
+        self.series.iter().find_map(|pattern| {
+            let captures = pattern.regex.captures(title)?;
+            let name = captures.name(&pattern.series_group)?.as_str().trim();
+            let number = captures.name(&pattern.number_group)?.as_str().trim();
+            let matched = captures.get(0)?;
+            let rest = format!("{}{}", &title[..matched.start()], &title[matched.end()..]);
+            let rest = rest.trim().trim_matches([',', ':', ';', '-', '–']).trim();
+            if name.is_empty() || number.is_empty() || rest.is_empty() {
+                return None;
+            }
+            Some(TitleSeries {
+                title: rest.to_string(),
+                name: name.to_string(),
+                number: number.to_string(),
+            })
+        })
+    }
 }
 
 pub fn clean_name(name: &mut String) -> Result<()> {
@@ -59,14 +390,6 @@ pub fn clean_name(name: &mut String) -> Result<()> {
     Ok(())
 }
 
-static EDITION_REGEX: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"(?i)^(.*?)(?:(?:(?:\s*[-–.:;|,]\s*)((\w+?)\s+(?:[a-z]+\s+)*(?:Edition|ed\.))|(?:\s*[-–.:;|,]\s*)?(?:\s*[(\[]\s*)((\w*?)\s+(?:[a-z]+\s+)*(?:Edition|ed\.))(?:\s*[)\]]\s*))(?:\s*[-:;,]\s*)?(.*?)|\s+((\d+\w*?)\s+(?:Edition|ed\.)))$").unwrap()
-});
-
-static EDITION_START_REGEX: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"(?i)((\d+(?:st|nd|rd|th)|first|second|third|fifth|sixth|seventh|eight|ninth|tenth|new|revised|updated)\s+(?:[a-z']+\s+)*(?:Edition|ed\.)|(\w+?)\s+(?:Edition|ed\.))").unwrap()
-});
-
 pub static TITLE_CLEANUP: Lazy<Regex> = Lazy::new(|| {
     Regex::new(
         r"(?i)(?:: A (?:Novel|Memoir)$)|(?:: An? (?:\w+ )(?:Fantasy Adventure)$)|(?:: An? (?:\w+ ){1,3}(?:Romance)$)|(?:\s*-\s*\d+(?:\.| - )epub$)|(?:\s*[\(\[]\.?(?:digital|light novel|epub|pdf|cbz|cbr|mp3|m4b|tpb|fixed|unabridged)[\)\]])*",
@@ -74,95 +397,38 @@ pub static TITLE_CLEANUP: Lazy<Regex> = Lazy::new(|| {
     .unwrap()
 });
 
-static SEARCH_TITLE_CLEANUP: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"(?i)^(?:the|a|an)\s+|[^\w ]").unwrap());
-
 static SEARCH_TITLE_VOLUME: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"(?i)(?:volume|vol\.)").unwrap());
 
 pub static SERIES_CLEANUP: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"(?i)(?:\s*\((?:digital|light novel)\))*").unwrap());
 
+/// `TitleRules::parse_edition` with the default locale
 pub fn parse_edition(title: &str, tags: &str) -> (String, Option<(String, u64)>) {
-    if let Some(captures) = EDITION_REGEX.captures(title)
-        && let Some(edition) = parse_normal_edition_match(&captures)
-    {
-        let mut title_str = captures.get(1).unwrap().as_str().to_string();
-        if let Some(subtitle_match) = captures.get(6) {
-            let subtitle_str = subtitle_match.as_str();
-            if !subtitle_str.is_empty() {
-                title_str.push_str(&format!(": {}", subtitle_str.trim()));
-            }
-        }
-        return (title_str, Some(edition));
-    }
-
-    if let Some(captures) = EDITION_REGEX.captures(tags)
-        && let Some(edition) = parse_normal_edition_match(&captures)
-    {
-        return (title.to_string(), Some(edition));
-    }
-    if let Some(captures) = EDITION_START_REGEX.captures(tags)
-        && let Some(edition) = parse_start_edition_match(&captures)
-    {
-        return (title.to_string(), Some(edition));
-    }
-
-    (title.to_string(), None)
+    DEFAULT_TITLE_RULES.parse_edition(title, tags)
 }
 
-fn parse_normal_edition_match(captures: &Captures) -> Option<(String, u64)> {
-    let edition_match = captures
-        .get(2)
-        .or_else(|| captures.get(4))
-        .or_else(|| captures.get(7))?;
-
-    let edition_number = captures
-        .get(3)
-        .or_else(|| captures.get(5))
-        .or_else(|| captures.get(8))?;
+static DRAMATIZED_TITLE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\s*[\(\[](?:an? )?(?:dramati[sz]ed(?: adaptation)?|dramati[sz]ation|full[- ]cast(?: (?:dramati[sz]ation|production|audio drama|edition))?)[\)\]]",
+    )
+    .unwrap()
+});
 
-    parse_edition_match(edition_match, edition_number)
-}
-fn parse_start_edition_match(captures: &Captures) -> Option<(String, u64)> {
-    let edition_match = captures.get(1)?;
-    let edition_number = captures.get(2).or_else(|| captures.get(3))?;
+static DRAMATIZED: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b(?:dramati[sz](?:ed|ation)|full[- ]cast)\b").unwrap());
 
-    parse_edition_match(edition_match, edition_number)
+/// Strips a "[Dramatized Adaptation]" or "(Full Cast)" marker from the title,
+/// returning the title and if the torrent is a dramatization by its title or tags
+pub fn parse_dramatized(title: &str, tags: &str) -> (String, bool) {
+    let dramatized = is_dramatized(title, tags);
+    let title = DRAMATIZED_TITLE.replace_all(title, "").trim().to_string();
+    (title, dramatized)
 }
 
-fn parse_edition_match(edition_match: Match, edition_number: Match) -> Option<(String, u64)> {
-    let edition_number = match edition_number.as_str().to_lowercase().as_str() {
-        "first" | "1st" => 1,
-        "second" | "2nd" => 2,
-        "third" | "3rd" => 3,
-        "fourth" | "4th" => 4,
-        "fifth" | "5th" => 5,
-        "sixth" | "6th" => 6,
-        "seventh" | "7th" => 7,
-        "eighth" | "8th" => 8,
-        "ninth" | "9th" => 9,
-        "tenth" | "10th" => 10,
-        n if n.ends_with("th") => n[..n.len() - 2].parse().unwrap_or(0),
-        n => n.parse().unwrap_or(0),
-    };
-    let mut edition_str = edition_match.as_str().to_string();
-
-    let mut first_letter = true;
-    for (i, c) in edition_str.clone().char_indices() {
-        if first_letter
-            && !c.is_uppercase()
-            && let Some(char) = edition_str.get_mut(i..=i)
-        {
-            char.make_ascii_uppercase();
-        }
-        first_letter = c == ' ';
-    }
-    if edition_str.ends_with("Ed.") {
-        edition_str.replace_range((edition_str.len() - 3).., "Edition");
-    }
-
-    Some((edition_str, edition_number))
+/// If the title or tags of a torrent mention it being dramatized or full cast
+pub fn is_dramatized(title: &str, tags: &str) -> bool {
+    DRAMATIZED.is_match(title) || DRAMATIZED.is_match(tags)
 }
 
 #[cfg(test)]
@@ -291,4 +557,136 @@ mod tests {
         assert_eq!(parsed_title, "Title");
         assert_eq!(parsed_edition, Some(("3rd Edition".to_string(), 3)));
     }
+
+    #[test]
+    fn test_title_patterns() {
+        let title = "Discworld 3: Equal Rites (Audible Original)";
+        assert_eq!(TitlePatterns::default().extract_series(title), None);
+
+        let mut patterns = TitlePatterns::default();
+        patterns.add_strip(r"(?i)\s*\(audible original\)").unwrap();
+        patterns
+            .add_series(
+                r"^(?P<series>Discworld) (?P<number>\d+):",
+                "series",
+                "number",
+            )
+            .unwrap();
+        let title = patterns.strip_title(title);
+        assert_eq!(title, "Discworld 3: Equal Rites");
+        assert_eq!(
+            patterns.extract_series(&title),
+            Some(TitleSeries {
+                title: "Equal Rites".to_string(),
+                name: "Discworld".to_string(),
+                number: "3".to_string(),
+            })
+        );
+        assert_eq!(patterns.extract_series("Mort"), None);
+
+        let err = patterns.add_strip("(unclosed").unwrap_err();
+        assert!(format!("{err:#}").contains("invalid regex \"(unclosed\""));
+        let err = patterns
+            .add_series(r"(?P<name>.+) #(?P<number>\d+)", "series", "number")
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("no capture group named \"series\"")
+        );
+    }
+
+    #[test]
+    fn test_normalize_title_with_patterns() {
+        let title = "The Hobbit - A Gripping Fantasy";
+        let rules = TitleRules::default();
+        assert_eq!(rules.normalize_title(title), "hobbit  a gripping fantasy");
+
+        let mut patterns = TitlePatterns::default();
+        patterns.add_strip(r"(?i)\s*- a gripping \w+$").unwrap();
+        let rules = TitleRules::default().with_patterns(patterns);
+        assert_eq!(rules.normalize_title(title), "hobbit");
+        assert_eq!(rules.strip_title(title), "The Hobbit");
+    }
+
+    #[test]
+    fn test_parse_dramatized() {
+        assert_eq!(
+            parse_dramatized("Leviathan Wakes [Dramatized Adaptation]", ""),
+            ("Leviathan Wakes".to_string(), true)
+        );
+        assert_eq!(
+            parse_dramatized("Dune (Full-Cast Production)", ""),
+            ("Dune".to_string(), true)
+        );
+        assert_eq!(
+            parse_dramatized("Dune", "Unabridged, full cast"),
+            ("Dune".to_string(), true)
+        );
+        assert_eq!(
+            parse_dramatized("The Dramatist", "Unabridged"),
+            ("The Dramatist".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn test_normalize_title_english() {
+        let rules = TitleRules::new(TitleLocale::English);
+        assert_eq!(rules.normalize_title("The Hobbit"), "hobbit");
+        assert_eq!(rules.normalize_title("An Echo"), "echo");
+        assert_eq!(rules.normalize_title("Die Hard"), "die hard");
+    }
+
+    #[test]
+    fn test_normalize_title_german() {
+        let rules = TitleRules::new(TitleLocale::German);
+        assert_eq!(rules.normalize_title("Der Zauberberg"), "zauberberg");
+        assert_eq!(rules.normalize_title("Die Verwandlung"), "verwandlung");
+        assert_eq!(rules.normalize_title("Das Parfum"), "parfum");
+        assert_eq!(
+            rules.normalize_title("Eine Frage der Zeit"),
+            "frage der zeit"
+        );
+        assert_eq!(rules.normalize_title("The Hobbit"), "the hobbit");
+    }
+
+    #[test]
+    fn test_normalize_title_swedish() {
+        let rules = TitleRules::new(TitleLocale::Swedish);
+        assert_eq!(
+            rules.normalize_title("En man som heter Ove"),
+            "man som heter ove"
+        );
+        assert_eq!(rules.normalize_title("Ett öga rött"), "oga rott");
+        assert_eq!(
+            rules.normalize_title("Den allvarsamma leken"),
+            "allvarsamma leken"
+        );
+        assert_eq!(rules.normalize_title("Det"), "det");
+        assert_eq!(
+            rules.normalize_title("A Man Called Ove"),
+            "a man called ove"
+        );
+    }
+
+    #[test]
+    fn test_parse_edition_locale() {
+        let rules = TitleRules::new(TitleLocale::Swedish);
+        let (parsed_title, parsed_edition) = rules.parse_edition("Titel (Andra utgåvan)", "Tags");
+        assert_eq!(parsed_title, "Titel");
+        assert_eq!(parsed_edition, Some(("Andra Utgåvan".to_string(), 2)));
+
+        let (parsed_title, parsed_edition) = rules.parse_edition("Title (2nd edition)", "Tags");
+        assert_eq!(parsed_title, "Title");
+        assert_eq!(parsed_edition, Some(("2nd Edition".to_string(), 2)));
+
+        let rules = TitleRules::new(TitleLocale::German);
+        let (parsed_title, parsed_edition) = rules.parse_edition("Titel, zweite Auflage", "Tags");
+        assert_eq!(parsed_title, "Titel");
+        assert_eq!(parsed_edition, Some(("Zweite Auflage".to_string(), 2)));
+
+        let rules = TitleRules::new(TitleLocale::English);
+        let (parsed_title, parsed_edition) = rules.parse_edition("Titel, zweite Auflage", "Tags");
+        assert_eq!(parsed_title, "Titel, zweite Auflage");
+        assert_eq!(parsed_edition, None);
+    }
 }
@@ -157,3 +157,49 @@ document.body.addEventListener('change', e => {
                 }
         }
 })
+
+/** @type {number | undefined} */
+let globalSearchTimeout
+const globalSearchGroups = { library: 'Library', selected: 'Selected', lists: 'Lists', mam: 'MaM' }
+
+document.body.addEventListener('input', e => {
+        if (!(e.target instanceof HTMLInputElement)) return
+        const form = e.target.closest('.global_search')
+        if (!form) return
+        const results = form.querySelector('.global_search_results')
+        const q = e.target.value.trim()
+        clearTimeout(globalSearchTimeout)
+        if (!q) {
+                results.replaceChildren()
+                return
+        }
+        globalSearchTimeout = setTimeout(async () => {
+                const r = await fetch(`/search/global?q=${encodeURIComponent(q)}`)
+                // A newer search has started while this one ran
+                if (!r.ok || e.target.value.trim() !== q) return
+                const groups = await r.json()
+                const children = []
+                for (const [key, name] of Object.entries(globalSearchGroups)) {
+                        const group = groups[key]
+                        if (!group.count && !group.error) continue
+                        const heading = document.createElement('h4')
+                        heading.textContent = `${name} (${group.count})`
+                        children.push(heading)
+                        if (group.error) {
+                                const error = document.createElement('div')
+                                error.className = 'warn'
+                                error.textContent = group.error
+                                children.push(error)
+                        }
+                        for (const result of group.results) {
+                                const link = document.createElement('a')
+                                link.href = result.url
+                                link.textContent = result.authors.length
+                                        ? `${result.title} by ${result.authors.join(', ')}`
+                                        : result.title
+                                children.push(link)
+                        }
+                }
+                results.replaceChildren(...children)
+        }, 300)
+})
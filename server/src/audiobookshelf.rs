@@ -1,16 +1,27 @@
 use std::{collections::BTreeSet, path::PathBuf, sync::Arc};
 
 use anyhow::Result;
-use axum::http::HeaderMap;
-use mlm_db::{DatabaseExt as _, Flags, Torrent, TorrentMeta, impls::format_serie};
-use mlm_mam::search::MaMTorrent;
+use mlm_db::{
+    ASIN_ID, DatabaseExt as _, Event, EventType, Flags, ISBN_ID, MediaType, MetadataSource, Series,
+    SeriesEntries, Size, Timestamp, Torrent, TorrentMeta, impls::format_serie,
+};
+use mlm_mam::{http, search::MaMTorrent};
+use mlm_parse::TitleRules;
 use native_db::Database;
-use reqwest::{Url, header::AUTHORIZATION};
+use reqwest::{Client, IntoUrl, Method, RequestBuilder, Url};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tracing::{debug, error, instrument, trace};
+use time::UtcDateTime;
+use tracing::{debug, error, info, instrument, trace, warn};
+use uuid::Uuid;
 
-use crate::config::AudiobookShelfConfig;
+use crate::{audit::dir_files, config::AudiobookShelfConfig, logging::write_event};
+
+/// Books imported from ABS have no MaM torrent, so they get made up MaM ids from
+/// here up, far above any real one
+pub const IMPORTED_MAM_ID_START: u64 = 1 << 48;
+
+const ABS_PAGE_SIZE: usize = 100;
 
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
 pub struct LibrariesResponse {
@@ -363,6 +374,12 @@ pub struct LibraryItemMinified {
     pub rel_path: String,
     #[serde(rename = "isFile")]
     pub is_file: bool,
+    #[serde(default, rename = "mediaType")]
+    pub media_type: String,
+    #[serde(default)]
+    pub media: Option<MediaMinified>,
+    #[serde(default)]
+    pub size: Option<u64>,
     // #[serde(rename = "mtimeMs")]
     // pub mtime_ms: i64,
     // #[serde(rename = "ctimeMs")]
@@ -385,6 +402,33 @@ pub struct LibraryItemMinified {
     // pub size: i64,
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct LibraryItemsResponse {
+    pub results: Vec<LibraryItemMinified>,
+    pub total: usize,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct MediaMinified {
+    pub metadata: MetadataMinified,
+    #[serde(default, rename = "numAudioFiles")]
+    pub num_audio_files: u64,
+    #[serde(default, rename = "ebookFormat", alias = "ebookFileFormat")]
+    pub ebook_format: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct MetadataMinified {
+    pub title: Option<String>,
+    #[serde(rename = "authorName")]
+    pub author_name: Option<String>,
+    #[serde(rename = "narratorName")]
+    pub narrator_name: Option<String>,
+    #[serde(rename = "seriesName")]
+    pub series_name: Option<String>,
+    pub language: Option<String>,
+}
+
 // #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
 // pub struct MediaMinified {
 //     pub metadata: MetadataSmall,
@@ -470,6 +514,35 @@ pub struct SeriesUpdate<'a> {
     pub sequence: Option<String>,
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct ItemResponse {
+    pub id: String,
+    pub media: ItemMedia,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct ItemMedia {
+    pub metadata: ItemMetadata,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct ItemMetadata {
+    #[serde(default)]
+    pub narrators: Vec<String>,
+    #[serde(default)]
+    pub series: Vec<ItemSeries>,
+    #[serde(default)]
+    pub genres: Vec<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct ItemSeries {
+    pub name: String,
+    pub sequence: Option<String>,
+}
+
 // #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
 // pub struct SeriesItem {
 //     pub id: String,
@@ -482,7 +555,7 @@ pub async fn match_torrents_to_abs(
     config: &AudiobookShelfConfig,
     db: Arc<Database<'_>>,
 ) -> Result<()> {
-    let abs = Abs::new(config)?;
+    let abs = Abs::new(config, http::client());
     let torrents = db.r_transaction()?.scan().primary::<Torrent>()?;
     let torrents = torrents.all()?.filter(|t| {
         t.as_ref()
@@ -502,34 +575,197 @@ pub async fn match_torrents_to_abs(
             "Matched ABS entry with torrent {} {}",
             torrent.meta.mam_id, torrent.meta.title
         );
-        torrent.abs_id = Some(book.id);
-        let (_guard, rw) = db.rw_async().await?;
-        rw.upsert(torrent)?;
-        rw.commit()?;
+        torrent.abs_id = Some(book.id.clone());
+        let torrent_id = torrent.id.clone();
+        let mam_id = torrent.meta.mam_id;
+        {
+            let (_guard, rw) = db.rw_async().await?;
+            rw.upsert(torrent.clone())?;
+            rw.commit()?;
+        }
+
+        if config.write_metadata {
+            let fields = match abs
+                .write_metadata(&book.id, &torrent.meta, config.overwrite)
+                .await
+            {
+                Ok(fields) => fields,
+                Err(err) => {
+                    warn!("Failed writing metadata to ABS item {}: {err:?}", book.id);
+                    continue;
+                }
+            };
+            if !fields.is_empty() {
+                write_event(
+                    &db,
+                    Event::new(
+                        Some(torrent_id),
+                        Some(mam_id),
+                        EventType::AbsUpdated {
+                            abs_id: book.id,
+                            fields,
+                        },
+                    ),
+                )
+                .await;
+            }
+        }
     }
 
     Ok(())
 }
 
+/// Adds the books in ABS that MLM doesn't know about yet as torrents with the
+/// metadata from ABS, so they count as in the library and are not grabbed again.
+/// Returns the number of imported books.
+#[instrument(skip_all)]
+pub async fn import_abs_library(
+    config: &AudiobookShelfConfig,
+    db: &Database<'_>,
+    rules: &TitleRules,
+) -> Result<usize> {
+    let abs = Abs::new(config, http::client());
+    let (known_ids, known_paths, mut next_mam_id) = {
+        let r = db.r_transaction()?;
+        let mut known_ids = BTreeSet::new();
+        let mut known_paths = BTreeSet::new();
+        let mut next_mam_id = IMPORTED_MAM_ID_START;
+        for torrent in r.scan().primary::<Torrent>()?.all()? {
+            let torrent = torrent?;
+            known_ids.extend(torrent.abs_id);
+            known_paths.extend(torrent.library_path);
+            next_mam_id = next_mam_id.max(torrent.mam_id + 1);
+        }
+        (known_ids, known_paths, next_mam_id)
+    };
+
+    let mut imported = 0;
+    for library in abs.libraries().await? {
+        for item in abs.library_items(&library.id).await? {
+            let path = PathBuf::from(&item.path);
+            if known_ids.contains(&item.id) || known_paths.contains(&path) {
+                continue;
+            }
+            let Some(meta) = imported_meta(&item, next_mam_id, rules) else {
+                trace!("Skipping ABS item {} without book metadata", item.id);
+                continue;
+            };
+            info!("Importing \"{}\" from ABS", meta.title);
+            // Tracked like linked files, so the untracked files scan and the
+            // audit know about them
+            let library_files = dir_files(&path).unwrap_or_else(|err| {
+                warn!("Could not list the files of {path:?}: {err}");
+                vec![]
+            });
+            let (_guard, rw) = db.rw_async().await?;
+            rw.insert(Torrent {
+                id_is_hash: false,
+                abs_id: Some(item.id.clone()),
+                library_path: Some(path),
+                library_files,
+                ..Torrent::new(Uuid::new_v4().to_string(), meta, rules)
+            })?;
+            rw.commit()?;
+            next_mam_id += 1;
+            imported += 1;
+        }
+    }
+
+    Ok(imported)
+}
+
+/// Minimal metadata for a book imported from ABS, `None` for podcasts and items
+/// without a title
+fn imported_meta(
+    item: &LibraryItemMinified,
+    mam_id: u64,
+    rules: &TitleRules,
+) -> Option<TorrentMeta> {
+    if item.media_type != "book" {
+        return None;
+    }
+    let media = item.media.as_ref()?;
+    let metadata = &media.metadata;
+    let (title, edition) = rules.parse_edition(metadata.title.as_deref()?, "");
+    let names = |names: &Option<String>| {
+        names
+            .iter()
+            .flat_map(|names| names.split(", "))
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    let series = names(&metadata.series_name)
+        .into_iter()
+        .map(|series| match series.rsplit_once(" #") {
+            Some((name, num)) => Series::try_from((name.to_string(), num.to_string()))
+                .unwrap_or_else(|_| Series {
+                    name: series.clone(),
+                    entries: SeriesEntries::new(vec![]),
+                }),
+            None => Series {
+                name: series,
+                entries: SeriesEntries::new(vec![]),
+            },
+        })
+        .collect();
+    let media_type = if media.num_audio_files > 0 {
+        MediaType::Audiobook
+    } else {
+        MediaType::Ebook
+    };
+    Some(TorrentMeta {
+        mam_id,
+        vip_status: None,
+        cat: None,
+        media_type,
+        main_cat: None,
+        categories: vec![],
+        language: metadata
+            .language
+            .as_deref()
+            .and_then(|language| language.parse().ok()),
+        flags: None,
+        filetypes: media
+            .ebook_format
+            .iter()
+            .filter(|_| media_type == MediaType::Ebook)
+            .cloned()
+            .collect(),
+        num_files: media.num_audio_files,
+        size: Size::from_bytes(item.size.unwrap_or_default()),
+        title,
+        edition,
+        authors: names(&metadata.author_name),
+        original_authors: None,
+        narrators: names(&metadata.narrator_name),
+        series,
+        description: None,
+        ids: Default::default(),
+        dramatized: false,
+        source: MetadataSource::File,
+        uploaded_at: Timestamp(UtcDateTime::UNIX_EPOCH),
+    })
+}
+
 pub struct Abs {
     base_url: String,
-    client: reqwest::Client,
+    token: String,
+    client: Client,
 }
 
 impl Abs {
-    pub fn new(config: &AudiobookShelfConfig) -> Result<Abs> {
-        // let mut headers = Default::default();
-        let mut headers = HeaderMap::new();
-        headers.insert(AUTHORIZATION, format!("Bearer {}", config.token).parse()?);
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .user_agent("MLM")
-            .build()?;
-
-        Ok(Abs {
+    pub fn new(config: &AudiobookShelfConfig, client: Client) -> Abs {
+        Abs {
             base_url: config.url.to_owned(),
+            token: config.token.clone(),
             client,
-        })
+        }
+    }
+
+    fn request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
+        self.client.request(method, url).bearer_auth(&self.token)
     }
 
     pub async fn get_book(&self, torrent: &Torrent) -> Result<Option<LibraryItemMinified>> {
@@ -539,15 +775,7 @@ impl Abs {
         let Some(first_author) = torrent.meta.authors.first() else {
             return Ok(None);
         };
-        let resp: LibrariesResponse = self
-            .client
-            .get(format!("{}/api/libraries", self.base_url))
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await?;
-        let libraries = resp.libraries.into_iter().filter(|l| {
+        let libraries = self.libraries().await?.into_iter().filter(|l| {
             l.folders
                 .iter()
                 .any(|f| library_path.starts_with(&f.full_path))
@@ -559,8 +787,7 @@ impl Abs {
                 .unwrap();
             url.query_pairs_mut().append_pair("q", first_author);
             let resp = self
-                .client
-                .get(url)
+                .request(Method::GET, url)
                 .send()
                 .await?
                 .error_for_status()?
@@ -577,8 +804,7 @@ impl Abs {
                     .parse()
                     .unwrap();
                 let resp = self
-                    .client
-                    .get(url)
+                    .request(Method::GET, url)
                     .send()
                     .await?
                     .error_for_status()?
@@ -605,6 +831,49 @@ impl Abs {
         Ok(None)
     }
 
+    pub async fn libraries(&self) -> Result<Vec<Library>> {
+        let resp: LibrariesResponse = self
+            .request(Method::GET, format!("{}/api/libraries", self.base_url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(resp.libraries)
+    }
+
+    /// Every item in a library, fetched a page at a time
+    pub async fn library_items(&self, library_id: &str) -> Result<Vec<LibraryItemMinified>> {
+        let mut items = vec![];
+        for page in 0.. {
+            let mut url: Url = format!("{}/api/libraries/{library_id}/items", self.base_url)
+                .parse()
+                .unwrap();
+            url.query_pairs_mut()
+                .append_pair("minified", "1")
+                .append_pair("limit", &ABS_PAGE_SIZE.to_string())
+                .append_pair("page", &page.to_string());
+            let resp = self
+                .request(Method::GET, url)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+
+            let resp: LibraryItemsResponse = serde_json::from_str(&resp).map_err(|err| {
+                error!("Error parsing ABS response: {err}\nResponse: {resp}");
+                err
+            })?;
+            let last = resp.results.is_empty();
+            items.extend(resp.results);
+            if last || items.len() >= resp.total {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
     pub async fn update_book(
         &self,
         id: &str,
@@ -614,60 +883,108 @@ impl Abs {
         let (title, subtitle) = parse_titles(meta);
         let (isbn, asin) = parse_isbn(mam_torrent);
 
-        self.client
-            .patch(format!("{}/api/items/{id}/media", self.base_url))
-            .header("Content-Type", "application/json")
-            .body(serde_json::to_string(&MediaUpdate {
-                metadata: MetadataUpdate {
-                    title,
-                    subtitle,
-                    authors: meta
-                        .authors
-                        .iter()
-                        .map(|name| AuthorUpdate { name })
-                        .collect(),
-                    series: meta
-                        .series
-                        .iter()
-                        .map(|series| SeriesUpdate {
-                            name: &series.name,
-                            sequence: if series.entries.0.is_empty() {
-                                None
-                            } else {
-                                Some(format!("{}", series.entries))
-                            },
-                        })
-                        .collect(),
-                    narrators: meta.narrators.iter().map(|name| name.as_str()).collect(),
-                    description: mam_torrent.description.as_deref(),
-                    isbn,
-                    asin,
-                    genres: meta
-                        .cat
-                        .as_ref()
-                        .map(|c| c.as_str())
-                        .into_iter()
-                        .chain(meta.categories.iter().map(|c| c.as_str()))
-                        .collect::<BTreeSet<_>>(),
-                    language: meta.language.map(|l| l.to_str()),
-                    explicit: meta
-                        .flags
-                        .is_some_and(|f| Flags::from_bitfield(f.0).explicit == Some(true)),
-                    abridged: meta
-                        .flags
-                        .is_some_and(|f| Flags::from_bitfield(f.0).abridged == Some(true)),
-                },
-            })?)
+        self.request(
+            Method::PATCH,
+            format!("{}/api/items/{id}/media", self.base_url),
+        )
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_string(&MediaUpdate {
+            metadata: MetadataUpdate {
+                title,
+                subtitle,
+                authors: meta
+                    .authors
+                    .iter()
+                    .map(|name| AuthorUpdate { name })
+                    .collect(),
+                series: meta
+                    .series
+                    .iter()
+                    .map(|series| SeriesUpdate {
+                        name: &series.name,
+                        sequence: if series.entries.0.is_empty() {
+                            None
+                        } else {
+                            Some(format!("{}", series.entries))
+                        },
+                    })
+                    .collect(),
+                narrators: meta.narrators.iter().map(|name| name.as_str()).collect(),
+                description: mam_torrent.description.as_deref(),
+                isbn,
+                asin,
+                genres: meta
+                    .cat
+                    .as_ref()
+                    .map(|c| c.as_str())
+                    .into_iter()
+                    .chain(meta.categories.iter().map(|c| c.as_str()))
+                    .collect::<BTreeSet<_>>(),
+                language: meta.language.map(|l| l.to_str()),
+                explicit: meta
+                    .flags
+                    .is_some_and(|f| Flags::from_bitfield(f.0).explicit == Some(true)),
+                abridged: meta
+                    .flags
+                    .is_some_and(|f| Flags::from_bitfield(f.0).abridged == Some(true)),
+            },
+        })?)
+        .send()
+        .await?
+        .error_for_status()?;
+
+        Ok(())
+    }
+
+    pub async fn get_item(&self, id: &str) -> Result<ItemResponse> {
+        let resp = self
+            .request(Method::GET, format!("{}/api/items/{id}", self.base_url))
             .send()
             .await?
-            .error_for_status()?;
+            .error_for_status()?
+            .text()
+            .await?;
 
-        Ok(())
+        let resp = serde_json::from_str(&resp).map_err(|err| {
+            error!("Error parsing ABS response: {err}\nResponse: {resp}");
+            err
+        })?;
+
+        Ok(resp)
+    }
+
+    /// Writes series, narrators and genres from MLM to an ABS item. Only fields
+    /// that are empty in ABS are written unless `overwrite` is set.
+    /// Returns the names of the fields that were written.
+    pub async fn write_metadata(
+        &self,
+        id: &str,
+        meta: &TorrentMeta,
+        overwrite: bool,
+    ) -> Result<Vec<String>> {
+        let item = self.get_item(id).await?;
+        let (metadata, fields) = missing_metadata(&item.media.metadata, meta, overwrite);
+        if fields.is_empty() {
+            trace!("ABS item {id} has no missing metadata");
+            return Ok(fields);
+        }
+
+        debug!("Writing {fields:?} to ABS item {id}");
+        self.request(
+            Method::PATCH,
+            format!("{}/api/items/{id}/media", self.base_url),
+        )
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_string(&json!({ "metadata": metadata }))?)
+        .send()
+        .await?
+        .error_for_status()?;
+
+        Ok(fields)
     }
 
     pub async fn delete_book(&self, id: &str) -> Result<()> {
-        self.client
-            .delete(format!("{}/api/items/{id}", self.base_url))
+        self.request(Method::DELETE, format!("{}/api/items/{id}", self.base_url))
             .send()
             .await?
             .error_for_status()?;
@@ -676,9 +993,61 @@ impl Abs {
     }
 }
 
-pub fn create_metadata(mam_torrent: &MaMTorrent, meta: &TorrentMeta) -> serde_json::Value {
+fn missing_metadata(
+    existing: &ItemMetadata,
+    meta: &TorrentMeta,
+    overwrite: bool,
+) -> (serde_json::Map<String, serde_json::Value>, Vec<String>) {
+    let mut metadata = serde_json::Map::new();
+    if !meta.series.is_empty() && (overwrite || existing.series.is_empty()) {
+        metadata.insert(
+            "series".to_string(),
+            json!(
+                meta.series
+                    .iter()
+                    .map(|series| SeriesUpdate {
+                        name: &series.name,
+                        sequence: if series.entries.0.is_empty() {
+                            None
+                        } else {
+                            Some(series.entries.to_string())
+                        },
+                    })
+                    .collect::<Vec<_>>()
+            ),
+        );
+    }
+    if !meta.narrators.is_empty() && (overwrite || existing.narrators.is_empty()) {
+        metadata.insert("narrators".to_string(), json!(meta.narrators));
+    }
+    let genres = meta
+        .cat
+        .as_ref()
+        .map(|c| c.as_str())
+        .into_iter()
+        .chain(meta.categories.iter().map(|c| c.as_str()))
+        .collect::<BTreeSet<_>>();
+    if !genres.is_empty() && (overwrite || existing.genres.is_empty()) {
+        metadata.insert("genres".to_string(), json!(genres));
+    }
+    let fields = metadata.keys().cloned().collect();
+    (metadata, fields)
+}
+
+pub fn create_metadata(mam_torrent: Option<&MaMTorrent>, meta: &TorrentMeta) -> serde_json::Value {
     let (title, subtitle) = parse_titles(meta);
-    let (isbn, asin) = parse_isbn(mam_torrent);
+    // Torrents imported without a MaM torrent only have what was imported
+    let (isbn, asin, description) = match mam_torrent {
+        Some(mam_torrent) => {
+            let (isbn, asin) = parse_isbn(mam_torrent);
+            (isbn, asin, mam_torrent.description.as_deref())
+        }
+        None => (
+            meta.ids.get(ISBN_ID).map(String::as_str),
+            meta.ids.get(ASIN_ID).map(String::as_str),
+            meta.description.as_deref(),
+        ),
+    };
     let flags = Flags::from_bitfield(meta.flags.map_or(0, |f| f.0));
 
     let metadata = json!({
@@ -687,7 +1056,7 @@ pub fn create_metadata(mam_torrent: &MaMTorrent, meta: &TorrentMeta) -> serde_js
         "series": &meta.series.iter().map(format_serie).collect::<Vec<_>>(),
         "title": title,
         "subtitle": subtitle,
-        "description": mam_torrent.description,
+        "description": description,
         "isbn": isbn,
         "asin": asin,
         "tags": if flags.lgbt == Some(true) { Some(vec!["LGBT"]) } else { None },
@@ -729,3 +1098,99 @@ fn parse_isbn(mam_torrent: &MaMTorrent) -> (Option<&str>, Option<&str>) {
 
     (isbn, asin)
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_abs_uses_given_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+            let mut buf = [0; 4096];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap().to_lowercase()
+        });
+
+        let config: AudiobookShelfConfig =
+            toml::from_str(&format!("url = \"{url}\"\ntoken = \"secret\"")).unwrap();
+        let client = http::client_builder()
+            .user_agent("MLM test")
+            .build()
+            .unwrap();
+        Abs::new(&config, client).delete_book("li_1").await.unwrap();
+
+        let request = handle.await.unwrap();
+        assert!(request.starts_with("delete /api/items/li_1 "));
+        assert!(request.contains("authorization: bearer secret\r\n"));
+        assert!(request.contains("user-agent: mlm test\r\n"));
+    }
+
+    #[test]
+    fn test_imported_meta() {
+        let item: LibraryItemMinified = serde_json::from_str(
+            r#"{
+                "id": "li_1",
+                "ino": "1",
+                "libraryId": "lib_1",
+                "folderId": "fol_1",
+                "path": "/audiobooks/Author/Title",
+                "relPath": "Author/Title",
+                "isFile": false,
+                "mediaType": "book",
+                "size": 1048576,
+                "media": {
+                    "metadata": {
+                        "title": "Title, 2nd Edition",
+                        "authorName": "Author One, Author Two",
+                        "narratorName": "Narrator",
+                        "seriesName": "Series #3, Other Series",
+                        "language": "English"
+                    },
+                    "numAudioFiles": 2,
+                    "ebookFormat": null
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let rules = TitleRules::default();
+        let meta = imported_meta(&item, IMPORTED_MAM_ID_START, &rules).unwrap();
+        assert_eq!(meta.mam_id, IMPORTED_MAM_ID_START);
+        assert_eq!(meta.title, "Title");
+        assert_eq!(meta.edition, Some(("2nd Edition".to_string(), 2)));
+        assert_eq!(meta.authors, vec!["Author One", "Author Two"]);
+        assert_eq!(meta.narrators, vec!["Narrator"]);
+        assert_eq!(
+            meta.series.iter().map(format_serie).collect::<Vec<_>>(),
+            vec!["Series #3", "Other Series"]
+        );
+        assert_eq!(meta.media_type, MediaType::Audiobook);
+        assert_eq!(meta.source, MetadataSource::File);
+        assert!(meta.filetypes.is_empty());
+
+        let podcast = LibraryItemMinified {
+            media_type: "podcast".to_string(),
+            ..item
+        };
+        assert!(imported_meta(&podcast, IMPORTED_MAM_ID_START, &rules).is_none());
+    }
+}
@@ -0,0 +1,595 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Result, bail};
+use mlm_db::{DatabaseExt as _, Event, EventType, LibraryMismatch, Size, Torrent, TorrentMeta};
+use native_db::Database;
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+use crate::{
+    audiobookshelf::IMPORTED_MAM_ID_START, config::Config, linker::file_size, logging::write_event,
+    qbittorrent::file_meta,
+};
+
+#[instrument(skip_all)]
+pub async fn run_library_audit(config: Arc<Config>, db: Arc<Database<'_>>) -> Result<()> {
+    let torrents = db
+        .r_transaction()?
+        .scan()
+        .primary::<Torrent>()?
+        .all()?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // A library dir that is gone is most likely an unmounted or sleeping NAS,
+    // reporting every book in it as missing would only bury real problems
+    let offline_dirs = config
+        .libraries
+        .iter()
+        .map(|library| library.library_dir())
+        .filter(|dir| !dir.is_dir())
+        .collect::<Vec<_>>();
+    for dir in &offline_dirs {
+        warn!("Library dir {dir:?} is not available, skipping audit of it");
+    }
+
+    for mut torrent in torrents {
+        let Some(library_path) = torrent.library_path.clone() else {
+            continue;
+        };
+        if offline_dirs.iter().any(|dir| library_path.starts_with(dir)) {
+            continue;
+        }
+        let missing = missing_files(&library_path, &torrent.library_files);
+        let known_missing = match &torrent.library_mismatch {
+            Some(LibraryMismatch::MissingFiles(files)) => Some(files),
+            _ => None,
+        };
+        if missing.is_empty() {
+            if known_missing.is_none() {
+                continue;
+            }
+            debug!(
+                "Files for {} are back in {library_path:?}",
+                torrent.meta.title
+            );
+            torrent.library_mismatch = None;
+        } else {
+            if known_missing == Some(&missing) {
+                continue;
+            }
+            warn!(
+                "{} files for {} are missing from {library_path:?}",
+                missing.len(),
+                torrent.meta.title
+            );
+            torrent.library_mismatch = Some(LibraryMismatch::MissingFiles(missing.clone()));
+            write_event(
+                &db,
+                Event::new(
+                    Some(torrent.id.clone()),
+                    Some(torrent.mam_id),
+                    EventType::FilesMissing {
+                        library_path,
+                        files: missing,
+                    },
+                ),
+            )
+            .await;
+        }
+        let (_guard, rw) = db.rw_async().await?;
+        rw.upsert(torrent)?;
+        rw.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Files in a library dir that no linked torrent has
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UntrackedFiles {
+    pub dir: PathBuf,
+    /// Relative to `dir`
+    pub files: Vec<PathBuf>,
+    pub size: u64,
+    /// Nothing in the dir is tracked, so it can be removed as a whole
+    pub orphaned: bool,
+}
+
+/// Walks every library dir for files that were added by hand or left behind
+/// after a book was moved. Symlinks are listed but never followed, so the
+/// walk stays inside the library
+pub fn find_untracked_files(
+    config: &Config,
+    db: &Database<'_>,
+) -> Result<(Vec<UntrackedFiles>, Vec<Torrent>)> {
+    let torrents = db
+        .r_transaction()?
+        .scan()
+        .primary::<Torrent>()?
+        .all()?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut tracked = HashSet::new();
+    // Books whose files are not known, like ones imported from ABS from a
+    // folder MLM could not read, are left alone as a whole
+    let mut unknown_files = vec![];
+    for torrent in &torrents {
+        let Some(library_path) = &torrent.library_path else {
+            continue;
+        };
+        if torrent.library_files.is_empty() {
+            unknown_files.push(library_path);
+            continue;
+        }
+        tracked.extend(torrent.library_files.iter().map(|f| library_path.join(f)));
+        // Written next to the linked files by the linker
+        tracked.insert(library_path.join("metadata.json"));
+        tracked.insert(library_path.join("chapters.txt"));
+    }
+
+    let mut untracked = vec![];
+    let dirs = library_dirs(config);
+    for dir in &dirs {
+        if !dir.is_dir() {
+            warn!("Library dir {dir:?} is not available, skipping scan of it");
+            continue;
+        }
+        // Another library inside this one is scanned on its own
+        let skip = dirs
+            .iter()
+            .filter(|d| *d != dir && d.starts_with(dir))
+            .chain(unknown_files.iter().copied())
+            .collect::<Vec<_>>();
+        untracked.extend(scan_dir(dir, &tracked, &skip, true)?.1);
+    }
+    Ok((untracked, torrents))
+}
+
+fn library_dirs(config: &Config) -> Vec<PathBuf> {
+    let mut dirs = config
+        .libraries
+        .iter()
+        .map(|library| library.library_dir().clone())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+/// Returns whether anything in the dir is tracked and the untracked files in it
+fn scan_dir(
+    dir: &Path,
+    tracked: &HashSet<PathBuf>,
+    skip: &[&PathBuf],
+    root: bool,
+) -> io::Result<(bool, Vec<UntrackedFiles>)> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    let mut has_tracked = false;
+    let mut files = vec![];
+    let mut size = 0;
+    let mut found = vec![];
+    for path in entries {
+        if skip.contains(&&path) {
+            has_tracked = true;
+            continue;
+        }
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            let (sub_tracked, sub_found) = scan_dir(&path, tracked, skip, false)?;
+            has_tracked |= sub_tracked;
+            found.extend(sub_found);
+        } else if tracked.contains(&path) {
+            has_tracked = true;
+        } else {
+            size += file_size(&metadata);
+            files.push(PathBuf::from(path.file_name().unwrap_or_default()));
+        }
+    }
+
+    if !has_tracked && !root {
+        for sub in found {
+            let sub_dir = sub.dir.strip_prefix(dir).unwrap_or(&sub.dir);
+            files.extend(sub.files.iter().map(|f| sub_dir.join(f)));
+            size += sub.size;
+        }
+        files.sort();
+        let orphan = UntrackedFiles {
+            dir: dir.to_path_buf(),
+            files,
+            size,
+            orphaned: true,
+        };
+        return Ok((false, vec![orphan]));
+    }
+    if !files.is_empty() {
+        found.insert(
+            0,
+            UntrackedFiles {
+                dir: dir.to_path_buf(),
+                files,
+                size,
+                orphaned: false,
+            },
+        );
+    }
+    Ok((true, found))
+}
+
+/// Deletes untracked files, and orphaned dirs as a whole. Only dirs the scan
+/// still finds are touched
+pub fn delete_untracked_files(config: &Config, db: &Database<'_>, dirs: &[PathBuf]) -> Result<()> {
+    let (untracked, _) = find_untracked_files(config, db)?;
+    for found in untracked.iter().filter(|u| dirs.contains(&u.dir)) {
+        if found.orphaned {
+            info!("Deleting orphaned dir {:?}", found.dir);
+            fs::remove_dir_all(&found.dir)?;
+        } else {
+            for file in &found.files {
+                info!("Deleting untracked file {:?}", found.dir.join(file));
+                fs::remove_file(found.dir.join(file))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A torrent whose linked files are gone but are all in the orphaned dir, as
+/// when a book was moved or renamed by hand
+pub fn moved_torrent<'a>(orphan: &UntrackedFiles, torrents: &'a [Torrent]) -> Option<&'a Torrent> {
+    if !orphan.orphaned {
+        return None;
+    }
+    torrents.iter().find(|torrent| {
+        let Some(library_path) = &torrent.library_path else {
+            return false;
+        };
+        !torrent.library_files.is_empty()
+            && torrent
+                .library_files
+                .iter()
+                .all(|f| orphan.files.contains(f))
+            && !missing_files(library_path, &torrent.library_files).is_empty()
+    })
+}
+
+/// Points the torrents that were moved into the selected orphaned dirs at
+/// their new place, and imports the other orphaned dirs as books without a
+/// MaM torrent
+pub async fn import_untracked_files(
+    config: &Config,
+    db: &Database<'_>,
+    dirs: &[PathBuf],
+) -> Result<()> {
+    let (untracked, torrents) = find_untracked_files(config, db)?;
+    let mut next_mam_id = torrents
+        .iter()
+        .map(|torrent| torrent.mam_id + 1)
+        .fold(IMPORTED_MAM_ID_START, u64::max);
+    for orphan in untracked.iter().filter(|u| dirs.contains(&u.dir)) {
+        let Some(torrent) = moved_torrent(orphan, &torrents) else {
+            import_orphan(config, db, orphan, next_mam_id).await?;
+            next_mam_id += 1;
+            continue;
+        };
+        let mut torrent = torrent.clone();
+        info!(
+            "Torrent {} was moved to {:?}",
+            torrent.meta.title, orphan.dir
+        );
+        torrent.library_path = Some(orphan.dir.clone());
+        torrent.library_mismatch = None;
+        {
+            let (_guard, rw) = db.rw_async().await?;
+            rw.upsert(torrent.clone())?;
+            rw.commit()?;
+        }
+        write_event(
+            db,
+            Event::new(
+                Some(torrent.id.clone()),
+                Some(torrent.mam_id),
+                EventType::Linked {
+                    linker: torrent.linker.clone(),
+                    library_path: orphan.dir.clone(),
+                },
+            ),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// Adds an orphaned dir as a book with metadata from its path, like
+/// "Author/Title" or "Author - Title"
+async fn import_orphan(
+    config: &Config,
+    db: &Database<'_>,
+    orphan: &UntrackedFiles,
+    mam_id: u64,
+) -> Result<()> {
+    if !orphan.orphaned {
+        bail!(
+            "{:?} has linked files in it and can not be imported",
+            orphan.dir
+        );
+    }
+    let Some(meta) = orphan_meta(config, orphan, mam_id) else {
+        bail!("{:?} has no audio or ebook files to import", orphan.dir);
+    };
+    info!("Importing {:?} as \"{}\"", orphan.dir, meta.title);
+    let (_guard, rw) = db.rw_async().await?;
+    rw.insert(Torrent {
+        id_is_hash: false,
+        library_path: Some(orphan.dir.clone()),
+        library_files: orphan.files.clone(),
+        ..Torrent::new(Uuid::new_v4().to_string(), meta, config.title_rules())
+    })?;
+    rw.commit()?;
+    Ok(())
+}
+
+fn orphan_meta(config: &Config, orphan: &UntrackedFiles, mam_id: u64) -> Option<TorrentMeta> {
+    let library_dir = library_dirs(config)
+        .into_iter()
+        .filter(|dir| orphan.dir.starts_with(dir))
+        .max_by_key(|dir| dir.components().count())?;
+    let relative = orphan.dir.strip_prefix(&library_dir).ok()?;
+    let mut components = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned());
+    let first = components.next()?;
+    let name = match components.next_back() {
+        Some(title) => format!("{first} - {title}"),
+        None => first,
+    };
+    let files = orphan
+        .files
+        .iter()
+        .map(|file| file.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    let mut meta = file_meta(config, &name, &files, mam_id)?;
+    meta.size = Size::from_bytes(orphan.size);
+    Some(meta)
+}
+
+/// Every file below the dir, relative to it, for books MLM did not link itself.
+/// Symlinks are listed but never followed
+pub fn dir_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = PathBuf::from(path.file_name().unwrap_or_default());
+        if fs::symlink_metadata(&path)?.is_dir() {
+            files.extend(dir_files(&path)?.into_iter().map(|f| name.join(f)));
+        } else {
+            files.push(name);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Linked files that no longer exist, or are empty after a failed copy
+fn missing_files(library_path: &Path, files: &[PathBuf]) -> Vec<PathBuf> {
+    files
+        .iter()
+        .filter(|file| !fs::metadata(library_path.join(file)).is_ok_and(|m| file_size(&m) > 0))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use mlm_db::{MediaType, MetadataSource};
+
+    use super::*;
+    use crate::test_helpers::{library_torrent, template_meta};
+
+    #[test]
+    fn test_missing_files() {
+        let dir = env::temp_dir().join(format!("mlm-audit-{}", std::process::id()));
+        fs::create_dir_all(dir.join("Disc 1")).unwrap();
+        fs::write(dir.join("Disc 1/01.mp3"), b"audio").unwrap();
+        fs::write(dir.join("empty.mp3"), b"").unwrap();
+
+        let files = vec![
+            PathBuf::from("Disc 1/01.mp3"),
+            PathBuf::from("Disc 1/02.mp3"),
+            PathBuf::from("empty.mp3"),
+        ];
+        assert_eq!(
+            missing_files(&dir, &files),
+            vec![PathBuf::from("Disc 1/02.mp3"), PathBuf::from("empty.mp3")]
+        );
+        assert!(missing_files(&dir, &files[..1]).is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_dir_files() {
+        let dir = env::temp_dir().join(format!("mlm-dir-files-{}", std::process::id()));
+        fs::create_dir_all(dir.join("Disc 1")).unwrap();
+        fs::write(dir.join("Disc 1/01.mp3"), b"audio").unwrap();
+        fs::write(dir.join("cover.jpg"), b"image").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(env::temp_dir(), dir.join("outside")).unwrap();
+
+        let mut expected = vec![PathBuf::from("Disc 1/01.mp3"), PathBuf::from("cover.jpg")];
+        #[cfg(unix)]
+        expected.push(PathBuf::from("outside"));
+        assert_eq!(dir_files(&dir).unwrap(), expected);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_scan_untracked_files() {
+        let dir = env::temp_dir().join(format!("mlm-untracked-{}", std::process::id()));
+        let book = dir.join("Author/Book");
+        fs::create_dir_all(&book).unwrap();
+        fs::create_dir_all(dir.join("Other/Old")).unwrap();
+        fs::create_dir_all(dir.join("Empty")).unwrap();
+        fs::write(book.join("01.mp3"), b"audio").unwrap();
+        fs::write(book.join("metadata.json"), b"{}").unwrap();
+        fs::write(book.join("cover.jpg"), b"image").unwrap();
+        fs::write(dir.join("Other/Old/01.mp3"), b"old audio").unwrap();
+        fs::write(dir.join("loose.txt"), b"notes").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(env::temp_dir(), book.join("outside")).unwrap();
+
+        let tracked = HashSet::from([book.join("01.mp3"), book.join("metadata.json")]);
+        let (has_tracked, untracked) = scan_dir(&dir, &tracked, &[], true).unwrap();
+        assert!(has_tracked);
+        let mut book_files = vec![PathBuf::from("cover.jpg")];
+        #[cfg(unix)]
+        book_files.push(PathBuf::from("outside"));
+        assert_eq!(
+            untracked,
+            vec![
+                UntrackedFiles {
+                    dir: dir.clone(),
+                    files: vec![PathBuf::from("loose.txt")],
+                    size: 5,
+                    orphaned: false,
+                },
+                UntrackedFiles {
+                    size: untracked[1].size,
+                    dir: book.clone(),
+                    files: book_files,
+                    orphaned: false,
+                },
+                UntrackedFiles {
+                    dir: dir.join("Empty"),
+                    files: vec![],
+                    size: 0,
+                    orphaned: true,
+                },
+                UntrackedFiles {
+                    dir: dir.join("Other"),
+                    files: vec![PathBuf::from("Old/01.mp3")],
+                    size: 9,
+                    orphaned: true,
+                },
+            ]
+        );
+
+        // A library inside the scanned one is left alone
+        let skip = dir.join("Other");
+        let (_, untracked) = scan_dir(&dir, &tracked, &[&skip], true).unwrap();
+        assert!(untracked.iter().all(|u| !u.dir.starts_with(&skip)));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_untracked_skips_unknown_files() {
+        let dir = env::temp_dir().join(format!("mlm-unknown-files-{}", std::process::id()));
+        let imported = dir.join("Author/Imported");
+        fs::create_dir_all(&imported).unwrap();
+        fs::write(imported.join("01.mp3"), b"audio").unwrap();
+        fs::create_dir_all(dir.join("Author/Orphan")).unwrap();
+        fs::write(dir.join("Author/Orphan/01.mp3"), b"audio").unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            mam_id = "abc"
+
+            [[library]]
+            download_dir = "/downloads"
+            library_dir = {:?}
+            "#,
+            dir
+        ))
+        .unwrap();
+        let db = native_db::Builder::new()
+            .create_in_memory(&mlm_db::MODELS)
+            .unwrap();
+        {
+            let (_guard, rw) = db.rw_async().await.unwrap();
+            rw.insert(Torrent {
+                library_path: Some(imported.clone()),
+                ..library_torrent("imported", template_meta())
+            })
+            .unwrap();
+            rw.commit().unwrap();
+        }
+
+        let (untracked, _) = find_untracked_files(&config, &db).unwrap();
+        let dirs = untracked.iter().map(|u| u.dir.clone()).collect::<Vec<_>>();
+        assert_eq!(dirs, vec![dir.join("Author/Orphan")]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_orphan_meta() {
+        let config: Config = toml::from_str(
+            r#"
+            mam_id = "abc"
+
+            [[library]]
+            download_dir = "/downloads"
+            library_dir = "/library"
+            "#,
+        )
+        .unwrap();
+        let orphan = |dir: &str, files: &[&str]| UntrackedFiles {
+            dir: PathBuf::from(dir),
+            files: files.iter().map(PathBuf::from).collect(),
+            size: 1024,
+            orphaned: true,
+        };
+
+        let meta = orphan_meta(
+            &config,
+            &orphan(
+                "/library/Andy Weir/Project Hail Mary",
+                &["01.mp3", "02.mp3"],
+            ),
+            IMPORTED_MAM_ID_START,
+        )
+        .unwrap();
+        assert_eq!(meta.title, "Project Hail Mary");
+        assert_eq!(meta.authors, vec!["Andy Weir"]);
+        assert_eq!(meta.size, Size::from_bytes(1024));
+        assert_eq!(meta.source, MetadataSource::File);
+
+        let meta = orphan_meta(
+            &config,
+            &orphan("/library/J.R.R. Tolkien - The Hobbit", &["The Hobbit.epub"]),
+            IMPORTED_MAM_ID_START,
+        )
+        .unwrap();
+        assert_eq!(meta.title, "The Hobbit");
+        assert_eq!(meta.authors, vec!["J.R.R. Tolkien"]);
+        assert_eq!(meta.media_type, MediaType::Ebook);
+
+        assert!(
+            orphan_meta(
+                &config,
+                &orphan("/library/Some Album", &["cover.jpg"]),
+                IMPORTED_MAM_ID_START
+            )
+            .is_none()
+        );
+        assert!(
+            orphan_meta(
+                &config,
+                &orphan("/elsewhere/Book", &["01.mp3"]),
+                IMPORTED_MAM_ID_START
+            )
+            .is_none()
+        );
+    }
+}
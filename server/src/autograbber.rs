@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeSet,
     fs::File,
     io::{BufWriter, Write as _},
     ops::RangeInclusive,
@@ -10,18 +11,19 @@ use anyhow::{Context, Error, Result};
 use itertools::Itertools as _;
 use lava_torrent::torrent::v1::Torrent;
 use mlm_db::{
-    ClientStatus, DatabaseExt as _, DuplicateTorrent, Event, EventType, MetadataSource,
-    SelectedTorrent, Timestamp, TorrentCost, TorrentKey, TorrentMeta, VipStatus,
+    ClientStatus, DatabaseExt as _, DuplicateTorrent, Event, EventKey, EventType, MetadataSource,
+    SelectedTorrent, Size, Timestamp, TorrentCost, TorrentKey, TorrentMeta, VipStatus,
 };
 use mlm_mam::{
     api::MaM,
-    enums::{SearchKind, SearchTarget},
+    enums::{SearchIn, SearchKind, SearchTarget},
+    http,
     meta::MetaError,
     search::{MaMTorrent, SearchFields, SearchQuery, SearchResult, Tor},
     serde::DATE_FORMAT,
 };
-use mlm_parse::normalize_title;
 use native_db::{Database, db_type, transaction::RwTransaction};
+use time::OffsetDateTime;
 use tokio::{
     fs,
     sync::{MutexGuard, watch::Sender},
@@ -34,6 +36,7 @@ use crate::{
     audiobookshelf::{self as abs, Abs},
     config::{Config, Cost, SortBy, TorrentFilter, TorrentSearch, Type},
     logging::write_event,
+    stats::Stats,
     torrent_downloader::get_mam_torrent_file,
 };
 
@@ -45,6 +48,7 @@ pub async fn run_autograbber(
     db: Arc<Database<'_>>,
     mam: Arc<MaM<'_>>,
     autograb_trigger: Sender<()>,
+    stats: &Stats,
     index: usize,
     autograb_config: Arc<TorrentSearch>,
 ) -> Result<()> {
@@ -63,7 +67,9 @@ pub async fn run_autograbber(
         name, user_info.unsat
     );
 
-    let unsat_buffer = autograb_config.unsat_buffer.unwrap_or(config.unsat_buffer);
+    let unsat_buffer = autograb_config
+        .unsat_buffer
+        .unwrap_or(config.unsat_buffer.min());
     let mut max_torrents = max_torrents.saturating_sub(unsat_buffer);
 
     if max_torrents > 0
@@ -82,6 +88,20 @@ pub async fn run_autograbber(
         max_torrents = max_torrents.min(max_active_downloads.saturating_sub(downloading_torrents));
     }
 
+    let mut size_budget = match autograb_config.max_total_size {
+        Some(max_total_size) => {
+            let since = autograb_config
+                .max_total_size_days
+                .map(|days| OffsetDateTime::now_utc() - time::Duration::days(days as i64));
+            let used = grabbed_size(&db, &name, since)?;
+            Some(SizeBudget::new(max_total_size, used))
+        }
+        None => None,
+    };
+    if size_budget.is_some_and(|b| b.is_exhausted()) {
+        max_torrents = 0;
+    }
+
     if max_torrents > 0
         || autograb_config.cost == Cost::MetadataOnly
         || autograb_config.cost == Cost::MetadataOnlyAdd
@@ -96,12 +116,36 @@ pub async fn run_autograbber(
             },
             &mam,
             max_torrents,
+            size_budget.as_mut(),
         )
         .await
         .context("search_torrents")?;
     }
 
-    if !config.qbittorrent.is_empty() {
+    let stopped = size_budget.filter(|b| b.is_exhausted()).map(|_| {
+        let max_total_size = autograb_config.max_total_size.unwrap_or_default();
+        match autograb_config.max_total_size_days {
+            Some(days) => {
+                format!("Reached max_total_size of {max_total_size} for the last {days} days")
+            }
+            None => format!("Reached max_total_size of {max_total_size}"),
+        }
+    });
+    if let Some(stopped) = &stopped {
+        info!("autograbber {name}: {stopped}");
+    }
+    stats
+        .update(|stats| match stopped {
+            Some(stopped) => {
+                stats.autograbber_stopped.insert(index, stopped);
+            }
+            None => {
+                stats.autograbber_stopped.remove(&index);
+            }
+        })
+        .await;
+
+    if config.has_download_client() {
         autograb_trigger.send(())?;
     }
 
@@ -116,40 +160,46 @@ pub async fn search_and_select_torrents(
     fields: SearchFields,
     mam: &MaM<'_>,
     max_torrents: u64,
+    mut size_budget: Option<&mut SizeBudget>,
 ) -> Result<u64> {
     let torrents = search_torrents(torrent_search, fields, mam)
         .await
-        .context("search_torrents")?;
+        .context("search_torrents")?
+        .collect::<Vec<_>>();
 
     if torrent_search.mark_removed {
-        let torrents = torrents.collect::<Vec<_>>();
         mark_removed_torrents(db, mam, &torrents)
             .await
             .context("mark_removed_torrents")?;
-
-        return select_torrents(
-            config,
-            db,
-            mam,
-            torrents.into_iter(),
-            &torrent_search.filter,
-            torrent_search.cost,
-            torrent_search.unsat_buffer,
-            torrent_search.wedge_buffer,
-            torrent_search.category.clone(),
-            torrent_search.dry_run,
-            max_torrents,
-            None,
-        )
-        .await
-        .context("select_torrents");
     }
 
-    select_torrents(
+    let series_candidates = if torrent_search.grab_full_series && !torrent_search.dry_run {
+        let r = db.r_transaction()?;
+        torrents
+            .iter()
+            .filter(|t| {
+                r.get()
+                    .primary::<SelectedTorrent>(t.id)
+                    .ok()
+                    .flatten()
+                    .is_none()
+                    && r.get()
+                        .secondary::<mlm_db::Torrent>(TorrentKey::mam_id, t.id)
+                        .ok()
+                        .flatten()
+                        .is_none()
+            })
+            .map(|t| t.id)
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+
+    let selected_torrents = select_torrents(
         config,
         db,
         mam,
-        torrents,
+        torrents.into_iter(),
         &torrent_search.filter,
         torrent_search.cost,
         torrent_search.unsat_buffer,
@@ -158,41 +208,129 @@ pub async fn search_and_select_torrents(
         torrent_search.dry_run,
         max_torrents,
         None,
+        size_budget.as_deref_mut(),
+    )
+    .await
+    .context("select_torrents")?;
+
+    if series_candidates.is_empty() {
+        return Ok(selected_torrents);
+    }
+
+    // Only expand series for torrents that were selected in this run, anything
+    // that was already selected or in the library has had its series expanded
+    let series = {
+        let r = db.r_transaction()?;
+        let mut series = BTreeSet::new();
+        for mam_id in series_candidates {
+            if let Some(selected) = r.get().primary::<SelectedTorrent>(mam_id)? {
+                series.extend(selected.meta.series.into_iter().map(|s| s.name));
+            }
+        }
+        series
+    };
+    let max_series_torrents = max_series_grabs(torrent_search, max_torrents, selected_torrents);
+
+    let series_torrents = select_series_torrents(
+        config,
+        db,
+        torrent_search,
+        fields,
+        mam,
+        series,
+        max_series_torrents,
+        size_budget,
     )
     .await
-    .context("select_torrents")
+    .context("select_series_torrents")?;
+
+    Ok(selected_torrents + series_torrents)
+}
+
+/// How many more torrents grabbing the rest of the series may add, after
+/// `selected_torrents` of the `max_torrents` this run may grab
+fn max_series_grabs(
+    torrent_search: &TorrentSearch,
+    max_torrents: u64,
+    selected_torrents: u64,
+) -> u64 {
+    max_torrents
+        .saturating_sub(selected_torrents)
+        .min(torrent_search.max_series_grabs.unwrap_or(10))
+}
+
+#[instrument(skip_all)]
+async fn select_series_torrents(
+    config: &Config,
+    db: &Database<'_>,
+    torrent_search: &TorrentSearch,
+    fields: SearchFields,
+    mam: &impl MaMSession,
+    series: BTreeSet<String>,
+    max_torrents: u64,
+    mut size_budget: Option<&mut SizeBudget>,
+) -> Result<u64> {
+    let mut selected_torrents = 0;
+    for series_name in series {
+        let remaining = max_torrents.saturating_sub(selected_torrents);
+        if remaining == 0 {
+            debug!("Reached series grab limit, skipping series \"{series_name}\"");
+            break;
+        }
+        if size_budget.as_ref().is_some_and(|b| b.is_exhausted()) {
+            debug!("Reached max_total_size, skipping series \"{series_name}\"");
+            break;
+        }
+        let series_search = TorrentSearch {
+            kind: Type::New,
+            query: Some(series_name.clone()),
+            search_in: vec![SearchIn::Series],
+            sort_by: None,
+            max_pages: Some(1),
+            mark_removed: false,
+            grab_full_series: false,
+            ..torrent_search.clone()
+        };
+        let torrents = search_torrents(&series_search, fields, mam)
+            .await
+            .context("search_torrents")?
+            .filter(|t| {
+                t.as_meta(config.title_rules()).is_ok_and(|meta| {
+                    meta.series
+                        .iter()
+                        .any(|s| s.name.eq_ignore_ascii_case(&series_name))
+                })
+            });
+        debug!("Grabbing rest of series \"{series_name}\"");
+        selected_torrents += select_torrents(
+            config,
+            db,
+            mam,
+            torrents,
+            &torrent_search.filter,
+            torrent_search.cost,
+            torrent_search.unsat_buffer,
+            torrent_search.wedge_buffer,
+            torrent_search.category.clone(),
+            torrent_search.dry_run,
+            remaining,
+            None,
+            size_budget.as_deref_mut(),
+        )
+        .await
+        .context("select_torrents")?;
+        sleep(Duration::from_millis(400)).await;
+    }
+
+    Ok(selected_torrents)
 }
 
 #[instrument(skip_all)]
 pub async fn search_torrents(
     torrent_search: &TorrentSearch,
     fields: SearchFields,
-    mam: &MaM<'_>,
+    mam: &impl MaMSession,
 ) -> Result<impl Iterator<Item = MaMTorrent>> {
-    let target = match torrent_search.kind {
-        Type::Bookmarks => Some(SearchTarget::Bookmarks),
-        Type::Mine => Some(SearchTarget::Mine),
-        Type::Uploader(id) => Some(SearchTarget::Uploader(id)),
-        _ => None,
-    };
-    let kind = match (torrent_search.kind, torrent_search.cost) {
-        (Type::Freeleech, _) => Some(SearchKind::Freeleech),
-        (_, Cost::Free) => Some(SearchKind::Free),
-        _ => None,
-    };
-    let sort_type = torrent_search
-        .sort_by
-        .map(|sort_by| match sort_by {
-            SortBy::LowSeeders => "seedersAsc",
-            SortBy::LowSnatches => "snatchedAsc",
-            SortBy::OldestFirst => "dateAsc",
-            SortBy::Random => "random",
-        })
-        .unwrap_or(match torrent_search.kind {
-            Type::New => "dateDesc",
-            _ => "",
-        });
-    let (flags_is_hide, flags) = torrent_search.filter.flags.as_search_bitfield();
     let max_pages = torrent_search
         .max_pages
         .unwrap_or(match torrent_search.kind {
@@ -202,57 +340,9 @@ pub async fn search_torrents(
 
     let mut results: Option<SearchResult> = None;
     for page in 1.. {
+        let start_number = results.as_ref().map_or(0, |r| r.data.len() as u64);
         let mut page_results = mam
-            .search(&SearchQuery {
-                fields,
-                perpage: 100,
-                tor: Tor {
-                    start_number: results.as_ref().map_or(0, |r| r.data.len() as u64),
-                    target,
-                    kind,
-                    text: torrent_search.query.clone().unwrap_or_default(),
-                    srch_in: torrent_search.search_in.clone(),
-                    main_cat: torrent_search.filter.categories.get_main_cats(),
-                    cat: torrent_search.filter.categories.get_cats(),
-                    browse_lang: torrent_search
-                        .filter
-                        .languages
-                        .iter()
-                        .map(|l| l.to_id())
-                        .collect(),
-                    browse_flags_hide_vs_show: if flags.is_empty() {
-                        None
-                    } else {
-                        Some(if flags_is_hide { 0 } else { 1 })
-                    },
-                    browse_flags: flags.clone(),
-                    start_date: torrent_search
-                        .filter
-                        .uploaded_after
-                        .map_or_else(|| Ok(String::new()), |d| d.format(&DATE_FORMAT))?,
-                    end_date: torrent_search
-                        .filter
-                        .uploaded_before
-                        .map_or_else(|| Ok(String::new()), |d| d.format(&DATE_FORMAT))?,
-                    min_size: torrent_search.filter.min_size.bytes(),
-                    max_size: torrent_search.filter.max_size.bytes(),
-                    unit: torrent_search
-                        .filter
-                        .min_size
-                        .unit()
-                        .max(torrent_search.filter.max_size.unit()),
-                    min_seeders: torrent_search.filter.min_seeders,
-                    max_seeders: torrent_search.filter.max_seeders,
-                    min_leechers: torrent_search.filter.min_leechers,
-                    max_leechers: torrent_search.filter.max_leechers,
-                    min_snatched: torrent_search.filter.min_snatched,
-                    max_snatched: torrent_search.filter.max_snatched,
-                    sort_type: sort_type.to_string(),
-                    ..Default::default()
-                },
-
-                ..Default::default()
-            })
+            .search(&search_query(torrent_search, fields, start_number)?)
             .await
             .context("search")?;
 
@@ -295,11 +385,89 @@ pub async fn search_torrents(
         .unwrap()
         .data
         .into_iter()
-        .filter(|t| torrent_search.filter.matches(t));
+        .filter(|t| torrent_search.filter.matches(t) && torrent_search.cost.matches(t));
 
     Ok(torrents)
 }
 
+/// Builds the MaM search for an autograb. Everything MaM can filter on, like
+/// categories, languages and content flags, is sent along so hidden torrents
+/// don't use up pages of the result
+fn search_query(
+    torrent_search: &TorrentSearch,
+    fields: SearchFields,
+    start_number: u64,
+) -> Result<SearchQuery> {
+    let target = match torrent_search.kind {
+        Type::Bookmarks => Some(SearchTarget::Bookmarks),
+        Type::Mine => Some(SearchTarget::Mine),
+        Type::Uploader(id) => Some(SearchTarget::Uploader(id)),
+        _ => None,
+    };
+    let kind = match (torrent_search.kind, torrent_search.cost) {
+        (Type::Freeleech, _) => Some(SearchKind::Freeleech),
+        (_, cost) => cost.search_kind(),
+    };
+    let sort_type = torrent_search
+        .sort_by
+        .map(|sort_by| match sort_by {
+            SortBy::LowSeeders => "seedersAsc",
+            SortBy::LowSnatches => "snatchedAsc",
+            SortBy::OldestFirst => "dateAsc",
+            SortBy::Random => "random",
+        })
+        .unwrap_or(match torrent_search.kind {
+            Type::New => "dateDesc",
+            _ => "",
+        });
+    let (browse_flags_hide_vs_show, browse_flags) = torrent_search.filter.flags.as_search_params();
+
+    Ok(SearchQuery {
+        fields,
+        perpage: 100,
+        tor: Tor {
+            start_number,
+            target,
+            kind,
+            text: torrent_search.query.clone().unwrap_or_default(),
+            srch_in: torrent_search.search_in.clone(),
+            main_cat: torrent_search.filter.categories.get_main_cats(),
+            cat: torrent_search.filter.categories.get_cats(),
+            browse_lang: torrent_search
+                .filter
+                .languages
+                .iter()
+                .map(|l| l.to_id())
+                .collect(),
+            browse_flags_hide_vs_show,
+            browse_flags,
+            start_date: torrent_search
+                .filter
+                .uploaded_after
+                .map_or_else(|| Ok(String::new()), |d| d.format(&DATE_FORMAT))?,
+            end_date: torrent_search
+                .filter
+                .uploaded_before
+                .map_or_else(|| Ok(String::new()), |d| d.format(&DATE_FORMAT))?,
+            min_size: torrent_search.filter.min_size.bytes(),
+            max_size: torrent_search.filter.max_size.bytes(),
+            unit: Size::range_unit(
+                torrent_search.filter.min_size,
+                torrent_search.filter.max_size,
+            ),
+            min_seeders: torrent_search.filter.min_seeders,
+            max_seeders: torrent_search.filter.max_seeders,
+            min_leechers: torrent_search.filter.min_leechers,
+            max_leechers: torrent_search.filter.max_leechers,
+            min_snatched: torrent_search.filter.min_snatched,
+            max_snatched: torrent_search.filter.max_snatched,
+            sort_type: sort_type.to_string(),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
 #[instrument(skip_all)]
 pub async fn mark_removed_torrents(
     db: &Database<'_>,
@@ -339,7 +507,7 @@ pub async fn mark_removed_torrents(
 pub async fn select_torrents<T: Iterator<Item = MaMTorrent>>(
     config: &Config,
     db: &Database<'_>,
-    mam: &MaM<'_>,
+    mam: &impl MaMSession,
     torrents: T,
     grabber: &TorrentFilter,
     cost: Cost,
@@ -349,6 +517,7 @@ pub async fn select_torrents<T: Iterator<Item = MaMTorrent>>(
     dry_run: bool,
     max_torrents: u64,
     goodreads_id: Option<u64>,
+    mut size_budget: Option<&mut SizeBudget>,
 ) -> Result<u64> {
     let mut selected_torrents = 0;
     'torrent: for torrent in torrents {
@@ -357,7 +526,7 @@ pub async fn select_torrents<T: Iterator<Item = MaMTorrent>>(
             continue;
         }
 
-        let meta = match torrent.as_meta() {
+        let meta = match torrent.as_meta(config.title_rules()) {
             Ok(it) => it,
             Err(err) => match err {
                 MetaError::UnknownMediaType(_) => {
@@ -424,7 +593,7 @@ pub async fn select_torrents<T: Iterator<Item = MaMTorrent>>(
         }
         if cost == Cost::MetadataOnlyAdd {
             let mam_id = meta.mam_id;
-            add_metadata_only_torrent(rw_opt.unwrap(), torrent, meta)
+            add_metadata_only_torrent(config, rw_opt.unwrap(), torrent, meta)
                 .await
                 .or_else(|err| {
                     let err = err.downcast::<db_type::Error>()?;
@@ -440,7 +609,11 @@ pub async fn select_torrents<T: Iterator<Item = MaMTorrent>>(
         if cost == Cost::MetadataOnly {
             continue 'torrent;
         }
-        let title_search = normalize_title(&meta.title);
+        if !cost.matches(&torrent) {
+            trace!("Torrent {} does not match cost {:?}", torrent.id, cost);
+            continue 'torrent;
+        }
+        let title_search = config.title_rules().normalize_title(&meta.title);
         let preferred_types = config.preferred_types(&meta.media_type);
         let preference = preferred_types
             .iter()
@@ -472,10 +645,7 @@ pub async fn select_torrents<T: Iterator<Item = MaMTorrent>>(
                     old.title_search, old.meta.filetypes
                 );
                 if meta.matches(&old.meta) {
-                    let old_preference = preferred_types
-                        .iter()
-                        .position(|t| old.meta.filetypes.contains(t));
-                    if old_preference <= preference {
+                    if !is_upgrade(config, &old.meta, &meta, preference) {
                         if let Err(err) =
                             add_duplicate_torrent(rw, None, torrent.dl.clone(), title_search, meta)
                         {
@@ -536,10 +706,7 @@ pub async fn select_torrents<T: Iterator<Item = MaMTorrent>>(
                     old.title_search, old.meta.filetypes
                 );
                 if meta.matches(&old.meta) {
-                    let old_preference = preferred_types
-                        .iter()
-                        .position(|t| old.meta.filetypes.contains(t));
-                    if old_preference <= preference {
+                    if !is_upgrade(config, &old.meta, &meta, preference) {
                         if let Err(err) = add_duplicate_torrent(
                             rw,
                             Some(old.id),
@@ -564,6 +731,26 @@ pub async fn select_torrents<T: Iterator<Item = MaMTorrent>>(
                 }
             }
         }
+        let type_max_torrents = config
+            .unsat_buffer
+            .max_torrents(max_torrents, meta.media_type);
+        if unsat_buffer.is_none() && selected_torrents >= type_max_torrents {
+            trace!(
+                "Skipping torrent {} as the unsat buffer for {} is reached",
+                torrent.id,
+                meta.media_type.as_str()
+            );
+            continue;
+        }
+        if let Some(budget) = size_budget.as_deref_mut()
+            && !budget.take(meta.size)
+        {
+            trace!(
+                "Stopping at torrent {} of {} as it does not fit in max_total_size",
+                torrent.id, meta.size
+            );
+            break;
+        }
         let tags: Vec<_> = config
             .tags
             .iter()
@@ -608,9 +795,13 @@ pub async fn select_torrents<T: Iterator<Item = MaMTorrent>>(
                 title_search,
                 meta,
                 grabber: grabber.name.clone(),
+                account: mam.account(),
+                priority: 0,
+                note: None,
                 created_at: Timestamp::now(),
                 started_at: None,
                 removed_at: None,
+                grab_skipped_at: None,
             })?;
             rw_opt.unwrap().1.commit()?;
             if selected_torrents >= max_torrents {
@@ -622,8 +813,110 @@ pub async fn select_torrents<T: Iterator<Item = MaMTorrent>>(
     Ok(selected_torrents)
 }
 
+/// What selecting torrents needs from a MaM session
+pub trait MaMSession {
+    fn account(&self) -> Option<String>;
+    async fn torrent_hash(&self, dl_link: &str) -> Result<String>;
+    async fn search(&self, query: &SearchQuery) -> Result<SearchResult>;
+}
+
+impl MaMSession for MaM<'_> {
+    fn account(&self) -> Option<String> {
+        self.account.clone()
+    }
+
+    async fn torrent_hash(&self, dl_link: &str) -> Result<String> {
+        get_mam_torrent_hash(self, dl_link).await
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<SearchResult> {
+        MaM::search(self, query).await
+    }
+}
+
+/// What is left of an autograb's `max_total_size`
+#[derive(Clone, Copy, Debug)]
+pub struct SizeBudget {
+    remaining: u64,
+}
+
+impl SizeBudget {
+    pub fn new(max_total_size: Size, used: u64) -> Self {
+        Self {
+            remaining: max_total_size.bytes().saturating_sub(used),
+        }
+    }
+
+    /// Reserves room for a torrent. Returns false and stops the budget from
+    /// handing out more room if it does not fit
+    pub fn take(&mut self, size: Size) -> bool {
+        if size.bytes() > self.remaining {
+            self.remaining = 0;
+            return false;
+        }
+        self.remaining -= size.bytes();
+        true
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+/// Total size of torrents selected or grabbed by an autograb since the given time
+fn grabbed_size(db: &Database<'_>, grabber: &str, since: Option<OffsetDateTime>) -> Result<u64> {
+    let since = since.map(Timestamp::from);
+    let r = db.r_transaction()?;
+    let mut counted = BTreeSet::new();
+    let mut size = 0;
+
+    // Selected torrents are removed once linked, the grabbed events cover those
+    for selected in r.scan().primary::<SelectedTorrent>()?.all()? {
+        let selected = selected?;
+        if selected.grabber.as_deref() == Some(grabber)
+            && since.is_none_or(|since| selected.created_at >= since)
+            && counted.insert(selected.mam_id)
+        {
+            size += selected.meta.size.bytes();
+        }
+    }
+    for event in r
+        .scan()
+        .secondary::<Event>(EventKey::created_at)?
+        .all()?
+        .rev()
+    {
+        let event = event?;
+        if since.is_some_and(|since| event.created_at < since) {
+            break;
+        }
+        let EventType::Grabbed {
+            grabber: Some(event_grabber),
+            ..
+        } = &event.event
+        else {
+            continue;
+        };
+        let Some(mam_id) = event.mam_id else {
+            continue;
+        };
+        if event_grabber != grabber || !counted.insert(mam_id) {
+            continue;
+        }
+        if let Some(torrent) = r
+            .get()
+            .secondary::<mlm_db::Torrent>(TorrentKey::mam_id, mam_id)?
+        {
+            size += torrent.meta.size.bytes();
+        }
+    }
+
+    Ok(size)
+}
+
 #[instrument(skip_all)]
 pub async fn add_metadata_only_torrent(
+    config: &Config,
     (_guard, rw): (MutexGuard<'_, ()>, RwTransaction<'_>),
     torrent: MaMTorrent,
     meta: TorrentMeta,
@@ -649,13 +942,14 @@ pub async fn add_metadata_only_torrent(
             category: None,
             selected_audio_format: None,
             selected_ebook_format: None,
-            title_search: normalize_title(&meta.title),
+            title_search: config.title_rules().normalize_title(&meta.title),
             meta,
             created_at: Timestamp::now(),
             replaced_with: None,
             request_matadata_update: false,
             library_mismatch: None,
             client_status: None,
+            note: None,
         })?;
         rw.commit()?;
     }
@@ -670,10 +964,24 @@ pub async fn update_torrent_meta(
     (guard, rw): (MutexGuard<'_, ()>, RwTransaction<'_>),
     mam_torrent: &MaMTorrent,
     mut torrent: mlm_db::Torrent,
-    meta: TorrentMeta,
+    mut meta: TorrentMeta,
     allow_non_mam: bool,
     linker_is_owner: bool,
 ) -> Result<()> {
+    // Not all MaM responses include the description
+    if meta.description.is_none() {
+        meta.description = torrent.meta.description.clone();
+    }
+    meta.keep_ids(&torrent.meta);
+    // The torrent may have been read before the transaction, keep a note
+    // that was set since
+    if let Some(current) = rw.get().primary::<mlm_db::Torrent>(torrent.id.clone())? {
+        torrent.note = current.note;
+    }
+    // The fields only updated silently below are still shown in the event
+    // when something else changed too
+    let original_meta = torrent.meta.clone();
+
     if !allow_non_mam && torrent.meta.source != MetadataSource::Mam {
         // Update VIP status and uploaded_at still
         if torrent.meta.vip_status != meta.vip_status
@@ -705,11 +1013,17 @@ pub async fn update_torrent_meta(
         }
     }
 
-    // Check uploaded_at and num_files
-    if torrent.meta.uploaded_at != meta.uploaded_at || torrent.meta.num_files != meta.num_files {
+    // Check uploaded_at, num_files, description and ids
+    if torrent.meta.uploaded_at != meta.uploaded_at
+        || torrent.meta.num_files != meta.num_files
+        || torrent.meta.description != meta.description
+        || torrent.meta.ids != meta.ids
+    {
         torrent.meta.uploaded_at = meta.uploaded_at;
         torrent.meta.num_files = meta.num_files;
-        // If uploaded_at or num_files was the only change, just silently update the database
+        torrent.meta.description = meta.description.clone();
+        torrent.meta.ids = meta.ids.clone();
+        // If uploaded_at, num_files, description or ids was the only change, just silently update the database
         if torrent.meta == meta {
             rw.upsert(torrent.clone())?;
             rw.commit()?;
@@ -723,7 +1037,7 @@ pub async fn update_torrent_meta(
 
     let id = torrent.id.clone();
     let mam_id = meta.mam_id;
-    let diff = torrent.meta.diff(&meta);
+    let diff = original_meta.diff(&meta);
     debug!(
         "Updating meta for torrent {}, diff:\n{}",
         mam_id,
@@ -732,13 +1046,13 @@ pub async fn update_torrent_meta(
             .join("\n")
     );
     torrent.meta = meta.clone();
-    torrent.title_search = normalize_title(&meta.title);
+    torrent.title_search = config.title_rules().normalize_title(&meta.title);
     rw.upsert(torrent.clone())?;
     rw.commit()?;
     drop(guard);
 
     if let Some(library_path) = &torrent.library_path
-        && let serde_json::Value::Object(new) = abs::create_metadata(mam_torrent, &meta)
+        && let serde_json::Value::Object(new) = abs::create_metadata(Some(mam_torrent), &meta)
     {
         let metadata_path = library_path.join("metadata.json");
         if metadata_path.exists() {
@@ -755,7 +1069,7 @@ pub async fn update_torrent_meta(
             debug!("updated ABS metadata file {}", torrent.meta.mam_id);
         }
         if let (Some(abs_id), Some(abs_config)) = (&torrent.abs_id, &config.audiobookshelf) {
-            let abs = Abs::new(abs_config)?;
+            let abs = Abs::new(abs_config, http::client());
             match abs.update_book(abs_id, mam_torrent, &meta).await {
                 Ok(_) => debug!("updated ABS via API {}", torrent.meta.mam_id),
                 Err(err) => warn!("Failed updating book {} in abs: {err}", torrent.meta.mam_id),
@@ -776,7 +1090,7 @@ pub async fn update_torrent_meta(
 async fn update_selected_torrent_meta(
     db: &Database<'_>,
     (guard, rw): (MutexGuard<'_, ()>, RwTransaction<'_>),
-    mam: &MaM<'_>,
+    mam: &impl MaMSession,
     torrent: SelectedTorrent,
     meta: TorrentMeta,
 ) -> Result<()> {
@@ -789,7 +1103,7 @@ async fn update_selected_torrent_meta(
             .map(|field| format!("  {}: {} -> {}", field.field, field.from, field.to))
             .join("\n")
     );
-    let hash = get_mam_torrent_hash(mam, &torrent.dl_link).await.ok();
+    let hash = mam.torrent_hash(&torrent.dl_link).await.ok();
     let mut torrent = torrent;
     torrent.meta = meta;
     rw.upsert(torrent)?;
@@ -810,6 +1124,24 @@ pub async fn get_mam_torrent_hash(mam: &MaM<'_>, dl_link: &str) -> Result<String
     Ok(hash)
 }
 
+/// Whether `meta` may supersede a selected or library torrent of the same
+/// book, by `upgrade_policy` or else only when it is in a more preferred format
+fn is_upgrade(
+    config: &Config,
+    old: &TorrentMeta,
+    meta: &TorrentMeta,
+    preference: Option<usize>,
+) -> bool {
+    if let Some(policy) = &config.upgrade_policy {
+        return policy.allows(config.upgrade_rank(old), config.upgrade_rank(meta));
+    }
+    let old_preference = config
+        .preferred_types(&meta.media_type)
+        .iter()
+        .position(|t| old.filetypes.contains(t));
+    old_preference > preference
+}
+
 fn add_duplicate_torrent(
     rw: &RwTransaction<'_>,
     duplicate_of: Option<String>,
@@ -827,3 +1159,345 @@ fn add_duplicate_torrent(
     })?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use mlm_db::{AudiobookCategory, EbookCategory, Language, MediaType};
+
+    use super::*;
+    use crate::test_helpers::{library_torrent, template_meta};
+
+    /// Answers every search with `torrents`
+    #[derive(Default)]
+    struct MockMaM {
+        torrents: Vec<MaMTorrent>,
+    }
+
+    impl MaMSession for MockMaM {
+        fn account(&self) -> Option<String> {
+            None
+        }
+
+        async fn torrent_hash(&self, _dl_link: &str) -> Result<String> {
+            Err(Error::msg("no torrent files in tests"))
+        }
+
+        async fn search(&self, _query: &SearchQuery) -> Result<SearchResult> {
+            Ok(SearchResult {
+                perpage: 100,
+                start: 0,
+                data: self.torrents.clone(),
+                total: self.torrents.len(),
+                found: self.torrents.len(),
+            })
+        }
+    }
+
+    fn mam_torrent(id: u64, media_type: MediaType) -> MaMTorrent {
+        let (mediatype, category, filetype) = match media_type {
+            MediaType::Audiobook => (1, AudiobookCategory::ActionAdventure.to_id(), "m4b"),
+            _ => (2, EbookCategory::ActionAdventure.to_id(), "epub"),
+        };
+        MaMTorrent {
+            id,
+            added: "2025-07-06 05:40:54".to_owned(),
+            mediatype,
+            category: category as u64,
+            dl: Some(format!("dl{id}")),
+            filetype: filetype.to_owned(),
+            language: 1,
+            size: "100 MiB".to_owned(),
+            title: format!("Book {id}"),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unsat_buffer_per_media_type() {
+        let config: Config = toml::from_str(
+            r#"
+            mam_id = ""
+            unsat_buffer = { audio = 10, ebook = 2 }
+            "#,
+        )
+        .unwrap();
+        let db = native_db::Builder::new()
+            .create_in_memory(&mlm_db::MODELS)
+            .unwrap();
+        let torrents = (1..=20).map(|id| {
+            let media_type = if id % 2 == 1 {
+                MediaType::Audiobook
+            } else {
+                MediaType::Ebook
+            };
+            mam_torrent(id, media_type)
+        });
+        // 15 free unsats, grabbers compute max_torrents with the smallest buffer
+        let max_torrents = 15 - config.unsat_buffer.min();
+        let selected = select_torrents(
+            &config,
+            &db,
+            &MockMaM::default(),
+            torrents,
+            &TorrentFilter::default(),
+            Cost::Ratio,
+            None,
+            None,
+            None,
+            false,
+            max_torrents,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(selected, 13);
+
+        let selected = db
+            .r_transaction()
+            .unwrap()
+            .scan()
+            .primary::<SelectedTorrent>()
+            .unwrap()
+            .all()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let audio = selected
+            .iter()
+            .filter(|t| t.meta.media_type == MediaType::Audiobook)
+            .count();
+        assert_eq!(selected.len(), 13);
+        assert_eq!(audio, 3);
+    }
+
+    fn series_torrent(id: u64, series: &str) -> MaMTorrent {
+        MaMTorrent {
+            series_info: BTreeMap::from([(1, vec![series.into(), id.to_string().into()])]),
+            ..mam_torrent(id, MediaType::Audiobook)
+        }
+    }
+
+    fn selected_ids(db: &Database<'_>) -> Vec<u64> {
+        db.r_transaction()
+            .unwrap()
+            .scan()
+            .primary::<SelectedTorrent>()
+            .unwrap()
+            .all()
+            .unwrap()
+            .map(|t| t.unwrap().mam_id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_select_series_torrents() {
+        let config: Config = toml::from_str(r#"mam_id = """#).unwrap();
+        let db = native_db::Builder::new()
+            .create_in_memory(&mlm_db::MODELS)
+            .unwrap();
+        let mam = MockMaM {
+            torrents: (1..=5)
+                .map(|id| series_torrent(id, "The Series"))
+                .chain([series_torrent(6, "Other Series")])
+                .collect(),
+        };
+        // The first book of the series is already in the library
+        let meta = mam.torrents[0].as_meta(config.title_rules()).unwrap();
+        {
+            let (_guard, rw) = db.rw_async().await.unwrap();
+            rw.insert(library_torrent("hash1", meta)).unwrap();
+            rw.commit().unwrap();
+        }
+        let search: TorrentSearch = toml::from_str(
+            r#"
+            type = "new"
+            cost = "ratio"
+            grab_full_series = true
+            "#,
+        )
+        .unwrap();
+        let series = BTreeSet::from(["The Series".to_string()]);
+
+        let select = |max_torrents| {
+            select_series_torrents(
+                &config,
+                &db,
+                &search,
+                SearchFields::default(),
+                &mam,
+                series.clone(),
+                max_torrents,
+                None,
+            )
+        };
+        assert_eq!(select(2).await.unwrap(), 2);
+        assert_eq!(selected_ids(&db), vec![2, 3]);
+
+        // The rest of the series, skipping the book in the library and the
+        // ones already selected
+        assert_eq!(select(10).await.unwrap(), 2);
+        assert_eq!(selected_ids(&db), vec![2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_max_series_grabs() {
+        let search: TorrentSearch = toml::from_str(r#"type = "new""#).unwrap();
+        assert_eq!(max_series_grabs(&search, 100, 5), 10);
+        assert_eq!(max_series_grabs(&search, 8, 5), 3);
+        assert_eq!(max_series_grabs(&search, 5, 5), 0);
+
+        let search: TorrentSearch = toml::from_str(
+            r#"
+            type = "new"
+            max_series_grabs = 2
+            "#,
+        )
+        .unwrap();
+        assert_eq!(max_series_grabs(&search, 100, 5), 2);
+        assert_eq!(max_series_grabs(&search, 6, 5), 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_torrent_meta_records_diff() {
+        let config: Config = toml::from_str(r#"mam_id = """#).unwrap();
+        let db = native_db::Builder::new()
+            .create_in_memory(&mlm_db::MODELS)
+            .unwrap();
+        let meta = TorrentMeta {
+            mam_id: 1,
+            main_cat: None,
+            language: Some(Language::English),
+            filetypes: vec!["m4b".to_string()],
+            num_files: 1,
+            size: Size::from_bytes(1024),
+            title: "Leviathan Wakes (The Expanse)".to_string(),
+            authors: vec!["James S. A. Corey".to_string()],
+            description: Some("Short".to_string()),
+            ..template_meta()
+        };
+        let torrent = library_torrent("hash", meta.clone());
+        {
+            let (_guard, rw) = db.rw_async().await.unwrap();
+            rw.insert(mlm_db::Torrent {
+                note: Some("gift for Sam".to_string()),
+                ..torrent.clone()
+            })
+            .unwrap();
+            rw.commit().unwrap();
+        }
+
+        let refreshed = TorrentMeta {
+            title: "Leviathan Wakes".to_string(),
+            description: Some("Long ".repeat(1000)),
+            ..meta
+        };
+        update_torrent_meta(
+            &config,
+            &db,
+            db.rw_async().await.unwrap(),
+            &MaMTorrent::default(),
+            torrent,
+            refreshed,
+            false,
+            false,
+        )
+        .await
+        .unwrap();
+
+        let events = db
+            .r_transaction()
+            .unwrap()
+            .scan()
+            .primary::<Event>()
+            .unwrap()
+            .all()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let [event] = events.as_slice() else {
+            panic!("expected one event, got {events:?}");
+        };
+        let EventType::Updated { fields } = &event.event else {
+            panic!("expected an update event, got {:?}", event.event);
+        };
+        let title = fields
+            .iter()
+            .find(|f| f.field.to_string() == "title")
+            .unwrap();
+        assert_eq!(title.from, "Leviathan Wakes (The Expanse)");
+        assert_eq!(title.to, "Leviathan Wakes");
+        // Descriptions are only kept in part
+        let description = fields
+            .iter()
+            .find(|f| f.field.to_string() == "description")
+            .unwrap();
+        assert_eq!(description.from, "Short");
+        assert!(description.to.starts_with("Long Long"));
+        assert!(description.to.ends_with('…'));
+        assert!(description.to.chars().count() <= 301);
+
+        // The note was set after the torrent was read and is kept
+        let torrent = db
+            .r_transaction()
+            .unwrap()
+            .get()
+            .primary::<mlm_db::Torrent>("hash")
+            .unwrap()
+            .unwrap();
+        assert_eq!(torrent.meta.title, "Leviathan Wakes");
+        assert_eq!(torrent.note.as_deref(), Some("gift for Sam"));
+    }
+
+    #[test]
+    fn test_size_budget() {
+        let gib = |n: u64| Size::from_bytes(n * 1024 * 1024 * 1024);
+        let mut budget = SizeBudget::new(gib(10), gib(4).bytes());
+        assert!(budget.take(gib(3)));
+        assert!(!budget.is_exhausted());
+        assert!(budget.take(gib(2)));
+        assert!(!budget.take(gib(2)), "does not fit in the remaining 1 GiB");
+        assert!(budget.is_exhausted());
+        assert!(!budget.take(Size::from_bytes(1)));
+
+        let mut budget = SizeBudget::new(gib(10), 0);
+        assert!(budget.take(gib(10)));
+        assert!(budget.is_exhausted());
+
+        let budget = SizeBudget::new(gib(10), gib(12).bytes());
+        assert!(budget.is_exhausted());
+    }
+
+    #[test]
+    fn test_search_query_flags() {
+        let search: TorrentSearch = toml::from_str(
+            r#"
+            type = "new"
+            flags = { explicit = false, violence = false }
+            "#,
+        )
+        .unwrap();
+        let query = search_query(&search, SearchFields::default(), 100).unwrap();
+        assert_eq!(query.tor.start_number, 100);
+        assert_eq!(query.tor.browse_flags_hide_vs_show, Some(0));
+        assert_eq!(query.tor.browse_flags, vec![1 << 2, 1 << 4]);
+
+        let search: TorrentSearch = toml::from_str(
+            r#"
+            type = "new"
+            flags = { lgbt = true }
+            "#,
+        )
+        .unwrap();
+        let query = search_query(&search, SearchFields::default(), 0).unwrap();
+        assert_eq!(query.tor.browse_flags_hide_vs_show, Some(1));
+        assert_eq!(query.tor.browse_flags, vec![1 << 6]);
+
+        let search: TorrentSearch = toml::from_str(r#"type = "new""#).unwrap();
+        let query = search_query(&search, SearchFields::default(), 0).unwrap();
+        assert_eq!(query.tor.browse_flags_hide_vs_show, None);
+        assert!(query.tor.browse_flags.is_empty());
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, trace};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    pub title: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChapterInfo {
+    title: String,
+    start_offset_ms: u64,
+    length_ms: u64,
+    #[serde(default)]
+    chapters: Vec<ChapterInfo>,
+}
+
+/// Reads chapters from Libation or Audible API json, which has them either at
+/// the top level or under `content_metadata.chapter_info`
+pub fn parse_chapters(json: &str) -> Option<Vec<Chapter>> {
+    let value: Value = serde_json::from_str(json).ok()?;
+    let chapters = [
+        "/content_metadata/chapter_info/chapters",
+        "/chapter_info/chapters",
+        "/chapters",
+    ]
+    .iter()
+    .find_map(|pointer| value.pointer(pointer))?;
+    let chapters: Vec<ChapterInfo> = serde_json::from_value(chapters.clone()).ok()?;
+
+    let mut flat = vec![];
+    flatten(&chapters, &mut flat);
+    flat.sort_by_key(|c| c.start_ms);
+    if flat.is_empty() { None } else { Some(flat) }
+}
+
+// Audible nests sub chapters, the parent only covers the part before its first child
+fn flatten(chapters: &[ChapterInfo], flat: &mut Vec<Chapter>) {
+    for chapter in chapters {
+        if chapter.length_ms > 0 {
+            flat.push(Chapter {
+                title: chapter.title.clone(),
+                start_ms: chapter.start_offset_ms,
+                end_ms: chapter.start_offset_ms + chapter.length_ms,
+            });
+        }
+        flatten(&chapter.chapters, flat);
+    }
+}
+
+/// Formats chapters as an ffmpeg metadata file
+pub fn ffmetadata(chapters: &[Chapter]) -> String {
+    let mut out = String::from(";FFMETADATA1\n");
+    for chapter in chapters {
+        let _ = write!(
+            out,
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            chapter.start_ms,
+            chapter.end_ms,
+            escape(&chapter.title)
+        );
+    }
+    out
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Writes a chapters file from the first of `json_files` with chapter info.
+/// Returns false if none of them had any
+pub fn write_chapters(json_files: &[PathBuf], target: &Path) -> Result<bool> {
+    for json_file in json_files {
+        let Ok(json) = fs::read_to_string(json_file) else {
+            trace!("could not read {json_file:?}");
+            continue;
+        };
+        if let Some(chapters) = parse_chapters(&json) {
+            debug!(
+                "writing {} chapters from {json_file:?} to {target:?}",
+                chapters.len()
+            );
+            fs::write(target, ffmetadata(&chapters))?;
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chapters() {
+        let chapters = parse_chapters(
+            r#"{
+                "content_metadata": {
+                    "chapter_info": {
+                        "chapters": [
+                            { "title": "Opening Credits", "start_offset_ms": 0, "length_ms": 20000 },
+                            {
+                                "title": "Part One",
+                                "start_offset_ms": 20000,
+                                "length_ms": 5000,
+                                "chapters": [
+                                    { "title": "Chapter 1", "start_offset_ms": 25000, "length_ms": 60000 },
+                                    { "title": "Chapter 2", "start_offset_ms": 85000, "length_ms": 70000 }
+                                ]
+                            }
+                        ]
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            chapters
+                .iter()
+                .map(|c| (c.title.as_str(), c.start_ms, c.end_ms))
+                .collect::<Vec<_>>(),
+            vec![
+                ("Opening Credits", 0, 20000),
+                ("Part One", 20000, 25000),
+                ("Chapter 1", 25000, 85000),
+                ("Chapter 2", 85000, 155000),
+            ]
+        );
+
+        assert!(parse_chapters(r#"{ "title": "No chapters" }"#).is_none());
+        assert!(parse_chapters(r#"{ "chapters": [] }"#).is_none());
+        assert!(parse_chapters("not json").is_none());
+    }
+
+    #[test]
+    fn test_ffmetadata() {
+        let chapters = vec![Chapter {
+            title: "Chapter 1; Start = #1".to_string(),
+            start_ms: 0,
+            end_ms: 1500,
+        }];
+        assert_eq!(
+            ffmetadata(&chapters),
+            ";FFMETADATA1\n\n[CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=1500\ntitle=Chapter 1\\; Start \\= \\#1\n"
+        );
+    }
+}
@@ -1,17 +1,19 @@
-use std::{fs, io::ErrorKind, mem, ops::Deref, sync::Arc};
+use std::{cmp::Ordering, collections::BTreeMap, fs, io::ErrorKind, mem, ops::Deref, sync::Arc};
 
 use anyhow::Result;
 use mlm_db::{
     self, DatabaseExt as _, ErroredTorrentId, Event, EventType, Timestamp, Torrent, TorrentKey,
 };
+use mlm_mam::http;
 use native_db::Database;
 use tracing::{debug, info, instrument, trace, warn};
 
 use crate::{
     audiobookshelf::Abs,
-    config::Config,
+    config::{Config, DuplicatePreference},
+    hooks::{HookTorrent, run_hook},
     linker::file_size,
-    logging::{TorrentMetaError, update_errored_torrent, write_event},
+    logging::{TorrentMetaError, should_retry, update_errored_torrent, write_event},
     qbittorrent::ensure_category_exists,
 };
 
@@ -47,45 +49,112 @@ async fn process_batch(config: &Config, db: &Database<'_>, batch: Vec<Torrent>)
     if batch.len() == 1 {
         return Ok(());
     };
+    let mut batch = rank_torrents(config, batch);
+    let keep = batch.remove(keep_index(config, &batch));
+    batch.retain(|t| should_retry(db, ErroredTorrentId::Cleaner(t.id.clone())));
+    replace_torrents(config, db, &keep, batch).await;
+
+    Ok(())
+}
+
+/// Sorts torrents for the same book by `duplicate_policy`, best first. By
+/// default that is the preferred filetypes for their media type, with ties
+/// broken by the size of the linked files.
+pub fn rank_torrents(config: &Config, batch: Vec<Torrent>) -> Vec<Torrent> {
+    if batch.len() < 2 {
+        return batch;
+    }
+    let needs_size = config.duplicate_policy.iter().any(|p| {
+        matches!(
+            p,
+            DuplicatePreference::Larger | DuplicatePreference::Smaller
+        )
+    });
     let mut batch = batch
         .into_iter()
         .map(|torrent| {
             let preferred_types = config.preferred_types(&torrent.meta.media_type);
-            let preference = preferred_types
-                .iter()
-                .position(|t| torrent.meta.filetypes.contains(t))
-                .unwrap_or(usize::MAX);
-            (torrent, preference)
+            let key = RankKey {
+                preference: preferred_types
+                    .iter()
+                    .position(|t| torrent.meta.filetypes.contains(t))
+                    .unwrap_or(usize::MAX),
+                size: if needs_size { linked_size(&torrent) } else { 0 },
+                uploaded_at: torrent.meta.uploaded_at,
+            };
+            (torrent, key)
         })
         .collect::<Vec<_>>();
-    batch.sort_by_key(|(_, preference)| *preference);
-    if batch[0].1 == batch[1].1 {
-        trace!(
-            "need to compare torrent \"{}\" and \"{}\" by size",
-            batch[0].0.meta.title, batch[1].0.meta.title
-        );
-        let mut new_batch = batch
-            .into_iter()
-            .map(|(torrent, preference)| {
-                let mut size = 0;
-                if let Some(library_path) = &torrent.library_path {
-                    for file in &torrent.library_files {
-                        let path = library_path.join(file);
-                        size += fs::metadata(path).map_or(0, |s| file_size(&s));
-                    }
-                }
-                (torrent, preference, size)
-            })
-            .collect::<Vec<_>>();
-        new_batch.sort_by(|a, b| a.1.cmp(&b.1).then(b.2.cmp(&a.2)));
-        trace!("new_batch {:?}", new_batch);
-        batch = new_batch
-            .into_iter()
-            .map(|(torrent, preference, _)| (torrent, preference))
-            .collect();
+    batch.sort_by(|a, b| compare_ranks(&config.duplicate_policy, &a.1, &b.1));
+    trace!(
+        "ranked {:?}",
+        batch
+            .iter()
+            .map(|(torrent, key)| (&torrent.meta.title, key))
+            .collect::<Vec<_>>()
+    );
+    batch.into_iter().map(|(torrent, _)| torrent).collect()
+}
+
+/// The torrent to keep of a ranked batch, the best one unless `upgrade_policy`
+/// does not let it supersede the torrent that was added first
+fn keep_index(config: &Config, batch: &[Torrent]) -> usize {
+    let Some(policy) = &config.upgrade_policy else {
+        return 0;
+    };
+    let Some((first, incumbent)) = batch.iter().enumerate().min_by_key(|(_, t)| t.created_at)
+    else {
+        return 0;
+    };
+    if policy.allows(
+        config.upgrade_rank(&incumbent.meta),
+        config.upgrade_rank(&batch[0].meta),
+    ) {
+        0
+    } else {
+        first
     }
-    let (keep, _) = batch.remove(0);
-    for (mut remove, _) in batch {
+}
+
+#[derive(Debug)]
+struct RankKey {
+    preference: usize,
+    size: u64,
+    uploaded_at: Timestamp,
+}
+
+fn compare_ranks(policy: &[DuplicatePreference], a: &RankKey, b: &RankKey) -> Ordering {
+    policy
+        .iter()
+        .map(|preference| match preference {
+            DuplicatePreference::Format => a.preference.cmp(&b.preference),
+            DuplicatePreference::Larger => b.size.cmp(&a.size),
+            DuplicatePreference::Smaller => a.size.cmp(&b.size),
+            DuplicatePreference::Newer => b.uploaded_at.cmp(&a.uploaded_at),
+            DuplicatePreference::Older => a.uploaded_at.cmp(&b.uploaded_at),
+        })
+        .fold(Ordering::Equal, Ordering::then)
+}
+
+fn linked_size(torrent: &Torrent) -> u64 {
+    let mut size = 0;
+    if let Some(library_path) = &torrent.library_path {
+        for file in &torrent.library_files {
+            let path = library_path.join(file);
+            size += fs::metadata(path).map_or(0, |s| file_size(&s));
+        }
+    }
+    size
+}
+
+/// Cleans the `remove` torrents from the library and marks them as replaced by `keep`
+pub async fn replace_torrents(
+    config: &Config,
+    db: &Database<'_>,
+    keep: &Torrent,
+    remove: Vec<Torrent>,
+) {
+    for mut remove in remove {
         info!(
             "Replacing library torrent \"{}\" {} with {}",
             remove.meta.title, remove.meta.mam_id, keep.meta.mam_id
@@ -101,14 +170,89 @@ async fn process_batch(config: &Config, db: &Database<'_>, batch: Vec<Torrent>)
         .map_err(|err| anyhow::Error::new(TorrentMetaError(remove.meta.clone(), err)));
         update_errored_torrent(
             db,
+            &config.retry,
             ErroredTorrentId::Cleaner(remove.id),
             remove.meta.title,
             result,
         )
         .await
     }
+}
 
-    Ok(())
+pub struct LibraryDuplicates {
+    pub title: String,
+    pub author: String,
+    /// Ranked torrents grouped by media type, the first of each media type is
+    /// the one that would be kept
+    pub torrents: Vec<(Torrent, bool)>,
+}
+
+/// Groups all linked torrents that are the same book by title and author,
+/// regardless of format, edition or library.
+pub fn find_library_duplicates(
+    config: &Config,
+    db: &Database<'_>,
+) -> Result<Vec<LibraryDuplicates>> {
+    let mut groups: BTreeMap<(String, String), Vec<Torrent>> = BTreeMap::new();
+    let r = db.r_transaction()?;
+    for torrent in r
+        .scan()
+        .secondary::<Torrent>(TorrentKey::title_search)?
+        .all()?
+    {
+        let torrent = torrent?;
+        if torrent.library_path.is_none() {
+            continue;
+        }
+        let author = torrent
+            .meta
+            .authors
+            .first()
+            .map(|a| a.to_lowercase())
+            .unwrap_or_default();
+        groups
+            .entry((torrent.title_search.clone(), author))
+            .or_default()
+            .push(torrent);
+    }
+
+    let mut duplicates = vec![];
+    for ((_, author), torrents) in groups {
+        if torrents.len() < 2 {
+            continue;
+        }
+        let mut by_media_type: BTreeMap<_, Vec<Torrent>> = BTreeMap::new();
+        for torrent in torrents {
+            by_media_type
+                .entry(torrent.meta.media_type)
+                .or_default()
+                .push(torrent);
+        }
+        let torrents = by_media_type
+            .into_values()
+            .flat_map(|torrents| {
+                rank_torrents(config, torrents)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, torrent)| (torrent, i == 0))
+            })
+            .collect::<Vec<_>>();
+        let title = torrents[0].0.meta.title.clone();
+        let author = torrents[0]
+            .0
+            .meta
+            .authors
+            .first()
+            .cloned()
+            .unwrap_or(author);
+        duplicates.push(LibraryDuplicates {
+            title,
+            author,
+            torrents,
+        });
+    }
+
+    Ok(duplicates)
 }
 
 #[instrument(skip_all)]
@@ -149,6 +293,8 @@ pub async fn clean_torrent(
 
     let id = remove.id.clone();
     let mam_id = remove.meta.mam_id;
+    let title = remove.meta.title.clone();
+    let library = remove.linker.clone();
     let library_path = remove.library_path.take();
     let mut library_files = remove.library_files.clone();
     remove.library_mismatch = None;
@@ -164,15 +310,31 @@ pub async fn clean_torrent(
         write_event(
             db,
             Event::new(
-                Some(id),
+                Some(id.clone()),
                 Some(mam_id),
                 EventType::Cleaned {
-                    library_path,
+                    library_path: library_path.clone(),
                     files: library_files,
                 },
             ),
         )
         .await;
+        if let Some(command) = &config.post_clean_command {
+            run_hook(
+                config,
+                db,
+                "post_clean_command",
+                command,
+                HookTorrent {
+                    id: &id,
+                    mam_id,
+                    path: &library_path,
+                    title: &title,
+                    library: library.as_deref(),
+                },
+            )
+            .await;
+        }
     }
 
     Ok(())
@@ -187,7 +349,7 @@ pub async fn remove_library_files(
     if delete_in_abs
         && let (Some(abs_id), Some(abs_config)) = (&remove.abs_id, &config.audiobookshelf)
     {
-        let abs = Abs::new(abs_config)?;
+        let abs = Abs::new(abs_config, http::client());
         if let Err(err) = abs.delete_book(abs_id).await {
             warn!("Failed deleting book from abs: {err}");
         }
@@ -214,7 +376,10 @@ pub async fn remove_library_files(
         if let Ok(files) = fs::read_dir(library_path) {
             for file in files {
                 if let Ok(file) = file {
-                    if file.file_name() == "cover.jpg" || file.file_name() == "metadata.json" {
+                    if ["cover.jpg", "metadata.json", "chapters.txt"]
+                        .iter()
+                        .any(|name| file.file_name() == *name)
+                    {
                         files_to_remove.push(file);
                     } else {
                         remove_files = false;
@@ -235,3 +400,55 @@ pub async fn remove_library_files(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use time::{Duration, UtcDateTime};
+
+    use super::*;
+
+    #[test]
+    fn test_duplicate_policy() {
+        use DuplicatePreference::*;
+
+        let now = UtcDateTime::now();
+        // Same format, the first is larger and the second is newer
+        let larger = RankKey {
+            preference: 0,
+            size: 2_000,
+            uploaded_at: Timestamp(now - Duration::days(365)),
+        };
+        let newer = RankKey {
+            preference: 0,
+            size: 1_000,
+            uploaded_at: Timestamp(now),
+        };
+        let winner = |policy: &[DuplicatePreference]| {
+            if compare_ranks(policy, &larger, &newer) == Ordering::Greater {
+                "newer"
+            } else {
+                "larger"
+            }
+        };
+
+        assert_eq!(winner(&[Format, Larger]), "larger");
+        assert_eq!(winner(&[Format, Newer]), "newer");
+        assert_eq!(winner(&[Newer, Larger]), "newer");
+        assert_eq!(winner(&[Smaller]), "newer");
+        assert_eq!(winner(&[Older, Smaller]), "larger");
+
+        let epub = RankKey {
+            preference: 0,
+            ..newer
+        };
+        let pdf = RankKey {
+            preference: 2,
+            ..larger
+        };
+        assert_eq!(
+            compare_ranks(&[Format, Larger], &epub, &pdf),
+            Ordering::Less
+        );
+        assert_eq!(compare_ranks(&[], &epub, &pdf), Ordering::Equal);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::BTreeMap, path::PathBuf};
+use std::{collections::BTreeMap, net::IpAddr, num::NonZeroUsize, path::PathBuf, sync::OnceLock};
 
 use mlm_db::{
     Flags, Language, MediaType, OldDbMainCat, Size,
@@ -8,25 +8,57 @@ use mlm_mam::{
     enums::{Categories, SearchIn, SnatchlistType},
     serde::parse_opt_date,
 };
-use serde::{Deserialize, Serialize};
+use mlm_parse::{TitleLocale, TitleRules};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
 use time::Date;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
+    #[serde(default)]
     pub mam_id: String,
+    /// Extra MaM accounts that autograbs, snatchlists and lists can use
+    #[serde(default)]
+    pub mam: Vec<MamAccount>,
     #[serde(default = "default_host")]
     pub web_host: String,
     #[serde(default = "default_port")]
     pub web_port: u16,
+    #[serde(default)]
+    #[serde(deserialize_with = "parse_opt")]
+    #[schemars(with = "Option<String>")]
+    pub web_bind: Option<WebBind>,
     #[serde(default = "default_min_ratio")]
     pub min_ratio: f64,
-    #[serde(default = "default_unsat_buffer")]
-    pub unsat_buffer: u64,
+    pub ratio_warning: Option<f64>,
+    #[serde(default)]
+    pub unsat_buffer: UnsatBuffer,
     #[serde(default)]
     pub wedge_buffer: u64,
     #[serde(default)]
     pub add_torrents_stopped: bool,
+    /// How grabbed torrents are handed to the torrent client
+    #[serde(default)]
+    pub grab_method: GrabMethod,
+    /// Where torrent files are saved with `grab_method = "watchdir"`
+    pub watch_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub verify_before_start: bool,
+    /// Least seeders a selected torrent must still have when it is grabbed,
+    /// as they are looked up again right before grabbing
+    pub recheck_seeders_before_grab: Option<u64>,
+    /// Days a torrent may stay selected without being grabbed before it is
+    /// unselected by the downloader
+    pub selected_max_age_days: Option<u64>,
+    /// qBittorrent categories to add torrents with by their MaM category, for
+    /// torrents selected without one
+    #[serde(default)]
+    pub category_map: CategoryMap,
+    #[serde(default = "default_grab_delay_ms")]
+    pub grab_delay_ms: u64,
+    #[serde(default = "default_max_grabs_per_minute")]
+    pub max_grabs_per_minute: u64,
     #[serde(default)]
     pub exclude_narrator_in_library_dir: bool,
     #[serde(default = "default_search_interval")]
@@ -36,7 +68,18 @@ pub struct Config {
     #[serde(default = "default_import_interval", alias = "goodreads_interval")]
     pub import_interval: u64,
     #[serde(default)]
+    pub audit_interval: u64,
+    #[serde(default = "default_max_search_pages")]
+    pub max_search_pages: u64,
+    #[serde(default = "default_max_search_results")]
+    pub max_search_results: u64,
+    #[serde(default)]
     pub ignore_torrents: Vec<u64>,
+    pub cover_dir: Option<PathBuf>,
+    pub timezone: Option<String>,
+    /// Start with background tasks paused
+    #[serde(default)]
+    pub maintenance: bool,
 
     #[serde(default = "default_audio_types")]
     pub audio_types: Vec<String>,
@@ -46,16 +89,70 @@ pub struct Config {
     pub music_types: Vec<String>,
     #[serde(default = "default_radio_types")]
     pub radio_types: Vec<String>,
+    #[serde(default = "default_duplicate_policy")]
+    pub duplicate_policy: Vec<DuplicatePreference>,
+    /// Limits when a torrent may supersede one of the same book, instead of
+    /// leaving it to `duplicate_policy`
+    pub upgrade_policy: Option<UpgradePolicy>,
+    /// Command run after a torrent is linked, `{path}`, `{title}` and
+    /// `{library}` in the arguments are replaced
+    pub post_link_command: Option<Vec<String>>,
+    /// Command run after a torrent is cleaned, with the same placeholders as
+    /// `post_link_command`
+    pub post_clean_command: Option<Vec<String>>,
+    /// Seconds a hook command may run before it is stopped. The linker,
+    /// cleaner and list imports wait for their hooks
+    #[serde(default = "default_hook_timeout")]
+    pub hook_timeout: u64,
 
     #[serde(default)]
     pub search: SearchConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+    /// How long to wait before a torrent that failed to be grabbed, linked or
+    /// cleaned is tried again
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Limits for looking up book metadata from other sites
+    #[serde(default)]
+    pub metadata: MetadataConfig,
+    /// Exports tracing spans over OTLP, only in builds with the `otlp` feature
+    pub otlp: Option<OtlpConfig>,
+    /// Maps other spellings and pen names of an author to one canonical name
+    #[serde(default)]
+    pub author_aliases: BTreeMap<String, String>,
+    /// The language of the web UI
+    #[serde(default)]
+    pub language: UiLanguage,
+    /// The language of the articles and edition words removed from titles
+    #[serde(default, deserialize_with = "parse")]
+    #[schemars(with = "String")]
+    pub title_locale: TitleLocale,
+    /// Built from `title_locale` and the title patterns on first use, see
+    /// `Config::title_rules`
+    #[serde(skip)]
+    pub(crate) title_rules: OnceLock<TitleRules>,
+    /// Regexes removed from titles, after the built-in cleanup
+    #[serde(default)]
+    pub title_strip_patterns: Vec<String>,
+    /// Regexes that move a series out of the title, for torrents MaM lists
+    /// without a series
+    #[serde(default)]
+    pub series_extract_patterns: Vec<SeriesExtractPattern>,
+    /// Lets one anthology or box set in the library satisfy every list item
+    /// it contains
+    #[serde(default)]
+    pub match_collections: bool,
     pub audiobookshelf: Option<AudiobookShelfConfig>,
+    pub auth: Option<AuthConfig>,
 
     #[serde(default)]
     #[serde(rename = "autograb")]
     pub autograbs: Vec<TorrentSearch>,
     #[serde(default)]
-    pub snatchlist: Vec<SnatchlistSearch>,
+    pub snatchlist: Vec<Snatchlist>,
 
     #[serde(default)]
     #[serde(rename = "goodreads_list")]
@@ -63,6 +160,12 @@ pub struct Config {
     #[serde(default)]
     #[serde(rename = "notion_list")]
     pub notion_lists: Vec<NotionList>,
+    #[serde(default)]
+    #[serde(rename = "readarr_list")]
+    pub readarr_lists: Vec<ReadarrList>,
+    #[serde(default)]
+    #[serde(rename = "file_list")]
+    pub file_lists: Vec<FileList>,
 
     #[serde(default)]
     #[serde(rename = "tag")]
@@ -71,28 +174,144 @@ pub struct Config {
     #[serde(default)]
     pub qbittorrent: Vec<QbitConfig>,
 
+    #[serde(default)]
+    pub rtorrent: Vec<RtorrentConfig>,
+
     #[serde(default)]
     #[serde(rename = "library")]
     pub libraries: Vec<Library>,
 }
 
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MamAccount {
+    pub name: String,
+    pub mam_id: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct SearchConfig {
     #[serde(deserialize_with = "parse_opt")]
+    #[schemars(with = "Option<String>")]
     pub wedge_over: Option<Size>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    pub user_agent: Option<String>,
+    pub proxy: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Log level per module, like `"mlm::linker" = "trace"`. Levels set in the
+    /// `MLM_LOG` environment variable take precedence
+    #[serde(default)]
+    pub targets: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Minutes to wait after the first failure, doubled for every failure after
+    pub delay: u64,
+    /// The longest wait between tries, in minutes
+    pub max_delay: u64,
+    /// Failures in a row before giving up until retried by hand, 0 never gives up
+    pub max_attempts: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetadataConfig {
+    /// Most provider requests in flight at once, across a whole bulk operation.
+    /// Must be at least 1
+    pub max_concurrency: NonZeroUsize,
+    /// Seconds a whole bulk operation may take before the lookups still
+    /// running are given up on, 0 never gives up. Each request also times out
+    /// on its own after 30 seconds
+    pub timeout: u64,
+    /// Where provider responses are cached, nothing is cached when it is not
+    /// set
+    pub cache_dir: Option<PathBuf>,
+    pub open_library: MetadataProviderConfig,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetadataProviderConfig {
+    /// Seconds a response is served from `cache_dir` before the provider is
+    /// asked again, 0 turns caching off
+    pub cache_ttl_secs: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SeriesExtractPattern {
+    pub pattern: String,
+    /// The capture group with the series name
+    #[serde(default = "default_series_group")]
+    pub series_group: String,
+    /// The capture group with the number in the series
+    #[serde(default = "default_number_group")]
+    pub number_group: String,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct OtlpConfig {
+    /// The OTLP/HTTP collector, like `http://localhost:4318`
+    pub endpoint: String,
+    #[serde(default = "default_otlp_service_name")]
+    pub service_name: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    /// One JSON object per line, for log collectors like Loki or Elasticsearch
+    Json,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub api_token: Option<String>,
+    #[serde(default)]
+    #[serde(deserialize_with = "parse_vec")]
+    #[schemars(with = "Vec<String>")]
+    pub allow_cidrs: Vec<Cidr>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct AudiobookShelfConfig {
     pub url: String,
     pub token: String,
     #[serde(default = "default_abs_interval")]
     pub interval: u64,
+    #[serde(default)]
+    pub write_metadata: bool,
+    #[serde(default)]
+    pub overwrite: bool,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct TorrentSearch {
     #[serde(rename = "type")]
@@ -101,6 +320,7 @@ pub struct TorrentSearch {
     pub cost: Cost,
     pub query: Option<String>,
     #[serde(default)]
+    #[schemars(with = "Vec<String>")]
     pub search_in: Vec<SearchIn>,
     pub sort_by: Option<SortBy>,
     pub max_pages: Option<u8>,
@@ -110,15 +330,24 @@ pub struct TorrentSearch {
     pub search_interval: Option<u64>,
     pub unsat_buffer: Option<u64>,
     pub max_active_downloads: Option<u64>,
+    #[serde(default)]
+    #[serde(deserialize_with = "parse_opt")]
+    #[schemars(with = "Option<String>")]
+    pub max_total_size: Option<Size>,
+    pub max_total_size_days: Option<u64>,
     pub wedge_buffer: Option<u64>,
     #[serde(default)]
     pub dry_run: bool,
     #[serde(default)]
     pub mark_removed: bool,
+    #[serde(default)]
+    pub grab_full_series: bool,
+    pub max_series_grabs: Option<u64>,
     pub category: Option<String>,
+    pub account: Option<String>,
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum Type {
     Bookmarks,
@@ -128,7 +357,7 @@ pub enum Type {
     Uploader(u64),
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum SortBy {
     LowSeeders,
@@ -137,10 +366,19 @@ pub enum SortBy {
     Random,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+/// A search of a MaM snatchlist with `type`, or a file of MaM ids with `file`
+#[derive(Clone, Debug, JsonSchema)]
+#[serde(untagged)]
+pub enum Snatchlist {
+    Search(SnatchlistSearch),
+    File(SnatchlistFile),
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct SnatchlistSearch {
     #[serde(rename = "type")]
+    #[schemars(with = "String")]
     pub kind: SnatchlistType,
     #[serde(default)]
     pub cost: Cost,
@@ -151,15 +389,35 @@ pub struct SnatchlistSearch {
     pub search_interval: Option<u64>,
     #[serde(default)]
     pub dry_run: bool,
+    pub account: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SnatchlistFile {
+    pub file: PathBuf,
+    #[serde(default)]
+    pub cost: Cost,
+    #[serde(flatten)]
+    pub filter: TorrentFilter,
+
+    pub search_interval: Option<u64>,
+    pub unsat_buffer: Option<u64>,
+    pub wedge_buffer: Option<u64>,
+    #[serde(default)]
+    pub dry_run: bool,
+    pub category: Option<String>,
+    pub account: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct GoodreadsList {
     pub url: String,
     pub name: Option<String>,
     #[serde(default)]
     #[serde(deserialize_with = "parse_opt")]
+    #[schemars(with = "Option<String>")]
     pub prefer_format: Option<OldDbMainCat>,
     pub grab: Vec<Grab>,
 
@@ -168,15 +426,106 @@ pub struct GoodreadsList {
     pub wedge_buffer: Option<u64>,
     #[serde(default)]
     pub dry_run: bool,
+    pub min_score: Option<f64>,
+    #[serde(default)]
+    pub require_author_match: bool,
+    /// Results scoring below this are not grabbed, the closest ones are
+    /// logged for review instead
+    pub match_min_score: Option<u64>,
+    /// How far below the best result a result may score and still be grabbed,
+    /// 100 by default
+    pub match_window: Option<u64>,
+    /// Skip results that are not in one of the book's series, books without a
+    /// series are not affected
+    #[serde(default)]
+    pub require_series_match: bool,
+    pub account: Option<String>,
+    #[serde(default)]
+    pub on_satisfied: OnSatisfied,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct NotionList {
     pub data_source: String,
     pub token: String,
     pub name: String,
     pub mam_fields: Vec<String>,
+    /// Title property, items without a MaM link are searched for on MaM when set
+    pub title_property: Option<String>,
+    pub author_property: Option<String>,
+    pub series_property: Option<String>,
+    /// Status or select property, only items with one of `status_values` are
+    /// imported
+    pub status_property: Option<String>,
+    #[serde(default)]
+    pub status_values: Vec<String>,
+    pub grab: Vec<Grab>,
+
+    pub search_interval: Option<u64>,
+    pub unsat_buffer: Option<u64>,
+    pub wedge_buffer: Option<u64>,
+    #[serde(default)]
+    pub dry_run: bool,
+    pub account: Option<String>,
+    #[serde(default)]
+    pub on_satisfied: OnSatisfied,
+}
+
+/// What to do when a book on a list is found in the library
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct OnSatisfied {
+    /// Command run for the book, with the same placeholders as
+    /// `post_link_command`
+    pub notify: Option<Vec<String>>,
+    /// Value `status_property` is set to, only Notion lists can be updated
+    pub update_source: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ReadarrList {
+    pub url: String,
+    pub api_key: String,
+    pub name: Option<String>,
+    #[serde(default)]
+    #[serde(deserialize_with = "parse_opt")]
+    #[schemars(with = "Option<String>")]
+    pub prefer_format: Option<OldDbMainCat>,
+    pub grab: Vec<Grab>,
+
+    pub search_interval: Option<u64>,
+    pub unsat_buffer: Option<u64>,
+    pub wedge_buffer: Option<u64>,
+    #[serde(default)]
+    pub dry_run: bool,
+    pub min_score: Option<f64>,
+    #[serde(default)]
+    pub require_author_match: bool,
+    /// Results scoring below this are not grabbed, the closest ones are
+    /// logged for review instead
+    pub match_min_score: Option<u64>,
+    /// How far below the best result a result may score and still be grabbed,
+    /// 100 by default
+    pub match_window: Option<u64>,
+    /// Skip results that are not in one of the book's series, books without a
+    /// series are not affected
+    #[serde(default)]
+    pub require_series_match: bool,
+    pub account: Option<String>,
+}
+
+/// A text or Markdown file with a "Title - Author" per line
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FileList {
+    pub file: PathBuf,
+    pub name: Option<String>,
+    #[serde(default)]
+    #[serde(deserialize_with = "parse_opt")]
+    #[schemars(with = "Option<String>")]
+    pub prefer_format: Option<OldDbMainCat>,
     pub grab: Vec<Grab>,
 
     pub search_interval: Option<u64>,
@@ -184,18 +533,42 @@ pub struct NotionList {
     pub wedge_buffer: Option<u64>,
     #[serde(default)]
     pub dry_run: bool,
+    pub min_score: Option<f64>,
+    #[serde(default)]
+    pub require_author_match: bool,
+    /// Results scoring below this are not grabbed, the closest ones are
+    /// logged for review instead
+    pub match_min_score: Option<u64>,
+    /// How far below the best result a result may score and still be grabbed,
+    /// 100 by default
+    pub match_window: Option<u64>,
+    /// Skip results that are not in one of the book's series, books without a
+    /// series are not affected
+    #[serde(default)]
+    pub require_series_match: bool,
+    pub account: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct Grab {
     #[serde(default)]
     pub cost: Cost,
+    /// Overrides `match_min_score` of the list
+    pub match_min_score: Option<u64>,
+    /// Overrides `match_window` of the list
+    pub match_window: Option<u64>,
+    /// Overrides `require_series_match` of the list
+    pub require_series_match: Option<bool>,
+    /// Results read by one of these narrators score higher and are grabbed
+    /// before other matches, others are still grabbed when there is no such result
+    #[serde(default)]
+    pub preferred_narrators: Vec<String>,
     #[serde(flatten)]
     pub filter: TorrentFilter,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct TagFilter {
     #[serde(flatten)]
@@ -206,7 +579,7 @@ pub struct TagFilter {
     pub tags: Vec<String>,
 }
 
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct TorrentFilter {
     #[serde(default)]
@@ -214,28 +587,44 @@ pub struct TorrentFilter {
 
     #[serde(default)]
     #[serde(deserialize_with = "parse_vec")]
+    #[schemars(with = "Vec<String>")]
     pub media_type: Vec<MediaType>,
     #[serde(default)]
+    #[schemars(with = "BTreeMap<String, Vec<String>>")]
     pub categories: Categories,
     #[serde(default)]
     #[serde(deserialize_with = "parse_vec")]
+    #[schemars(with = "Vec<String>")]
     pub languages: Vec<Language>,
     #[serde(default)]
+    #[serde(deserialize_with = "parse_vec")]
+    #[schemars(with = "Vec<String>")]
+    pub language_priority: Vec<Language>,
+    #[serde(default)]
+    #[schemars(with = "BTreeMap<String, bool>")]
     pub flags: Flags,
     #[serde(default)]
-    #[serde(deserialize_with = "parse")]
+    #[serde(deserialize_with = "parse_min_size")]
+    #[schemars(with = "String")]
     pub min_size: Size,
     #[serde(default)]
-    #[serde(deserialize_with = "parse")]
+    #[serde(deserialize_with = "parse_max_size")]
+    #[schemars(with = "String")]
     pub max_size: Size,
     #[serde(default)]
     pub exclude_uploader: Vec<String>,
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+    #[serde(default)]
+    pub require_tags: Vec<String>,
 
     #[serde(default)]
     #[serde(deserialize_with = "parse_opt_date")]
+    #[schemars(with = "Option<String>")]
     pub uploaded_after: Option<Date>,
     #[serde(default)]
     #[serde(deserialize_with = "parse_opt_date")]
+    #[schemars(with = "Option<String>")]
     pub uploaded_before: Option<Date>,
     pub min_seeders: Option<u64>,
     pub max_seeders: Option<u64>,
@@ -243,13 +632,20 @@ pub struct TorrentFilter {
     pub max_leechers: Option<u64>,
     pub min_snatched: Option<u64>,
     pub max_snatched: Option<u64>,
+    pub min_files: Option<u64>,
+    pub max_files: Option<u64>,
+    /// `true` to only select dramatized or full cast recordings, `false` to
+    /// never select them
+    pub is_dramatized: Option<bool>,
 }
 
-#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum Cost {
     #[default]
     Free,
+    Vip,
+    PersonalFreeleech,
     Wedge,
     TryWedge,
     #[serde(alias = "all")]
@@ -258,7 +654,7 @@ pub enum Cost {
     MetadataOnlyAdd,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct QbitConfig {
     pub url: String,
@@ -270,9 +666,37 @@ pub struct QbitConfig {
     pub on_invalid_torrent: Option<QbitUpdate>,
     #[serde(default)]
     pub path_mapping: BTreeMap<PathBuf, PathBuf>,
+    /// Seconds before logging in again when qBittorrent can't be reached,
+    /// doubled after each failure up to `link_interval`
+    #[serde(default = "default_reconnect_delay")]
+    pub reconnect_delay: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RtorrentConfig {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum UnsatBuffer {
+    All(u64),
+    ByMediaType(UnsatBufferByMediaType),
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct UnsatBufferByMediaType {
+    #[serde(default = "default_unsat_buffer")]
+    pub audio: u64,
+    #[serde(default = "default_unsat_buffer")]
+    pub ebook: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct QbitUpdate {
     pub category: Option<String>,
@@ -280,32 +704,57 @@ pub struct QbitUpdate {
     pub tags: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryMap {
+    /// By media type, e.g. `radio = "radio"`
+    #[serde(default)]
+    pub media_type: BTreeMap<String, String>,
+    /// By category name, e.g. `"Science Fiction" = "scifi"`. Takes precedence
+    /// over the media type
+    #[serde(default)]
+    pub category: BTreeMap<String, String>,
+    /// For torrents that match neither
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(untagged)]
 pub enum Library {
     ByDir(LibraryByDir),
     ByCategory(LibraryByCategory),
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct LibraryByDir {
-    pub download_dir: PathBuf,
+    pub download_dir: DownloadDirs,
     pub library_dir: PathBuf,
     #[serde(flatten)]
     pub tag_filters: LibraryTagFilters,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum DownloadDirs {
+    One(PathBuf),
+    Many(Vec<PathBuf>),
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct LibraryByCategory {
     pub category: String,
     pub library_dir: PathBuf,
+    /// Where qBittorrent saves new torrents for this library, as qBittorrent
+    /// sees the path
+    pub save_path: Option<PathBuf>,
+    pub content_layout: Option<ContentLayout>,
     #[serde(flatten)]
     pub tag_filters: LibraryTagFilters,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct LibraryTagFilters {
     #[serde(default)]
@@ -313,15 +762,94 @@ pub struct LibraryTagFilters {
 
     #[serde(default)]
     pub method: LibraryLinkMethod,
+    /// What `method = "hardlink"` does instead when the download and library
+    /// are on different filesystems
+    pub hardlink_fallback: Option<HardlinkFallback>,
+    #[serde(default)]
+    pub grouping: LibraryGrouping,
+    /// Overrides the global `exclude_narrator_in_library_dir` for this library
+    pub exclude_narrator_in_library_dir: Option<bool>,
     #[serde(default)]
     pub allow_tags: Vec<String>,
     #[serde(default)]
     pub deny_tags: Vec<String>,
+    /// Only torrents matching this are linked into the library
+    #[serde(default)]
+    pub filter: LibraryFilter,
+    /// Metadata a torrent has to have before it is linked, torrents missing
+    /// any of it wait until their metadata is filled in
+    #[serde(default)]
+    pub require_fields: Vec<RequiredField>,
     pub audio_types: Option<Vec<String>>,
     pub ebook_types: Option<Vec<String>>,
+    /// Files and folders of a torrent that are never linked, `*` matches any
+    /// characters and `?` a single one. Patterns without a `/` are matched
+    /// against every folder and the file name
+    #[serde(default = "default_ignore_file_patterns")]
+    pub ignore_file_patterns: Vec<String>,
+    #[serde(default)]
+    #[serde(deserialize_with = "parse_opt")]
+    #[schemars(with = "Option<String>")]
+    pub path_template: Option<PathTemplate>,
+    #[serde(default)]
+    pub filename_replacement: BTreeMap<String, String>,
+    pub max_filename_len: Option<usize>,
+    #[serde(default)]
+    pub generate_chapters: bool,
+    /// Ebook format other ebook formats are converted to when linked, like
+    /// "epub"
+    pub convert_to: Option<String>,
+    /// Converter run for `convert_to`, `{input}` and `{output}` are replaced
+    /// with the paths of the linked ebook and the converted file
+    pub convert_command: Option<Vec<String>>,
+    /// Larger ebooks are linked without converting them
+    #[serde(default)]
+    #[serde(deserialize_with = "parse_opt")]
+    #[schemars(with = "Option<String>")]
+    pub convert_max_size: Option<Size>,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
+/// Which books a library takes, by their metadata. Each list that is set has to
+/// have a match, names are compared without regard to case
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LibraryFilter {
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(default)]
+    pub series: Vec<String>,
+    /// MaM categories, like "LitRPG"
+    #[serde(default)]
+    pub categories: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RequiredField {
+    Author,
+    Narrator,
+    Series,
+    Language,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathTemplate {
+    pub source: String,
+    pub segments: Vec<Vec<PathTemplateToken>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathTemplateToken {
+    Text(String),
+    Author,
+    Series,
+    SeriesIndex,
+    Title,
+    Edition,
+    Narrator,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, Default, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum LibraryLinkMethod {
     #[default]
@@ -333,6 +861,87 @@ pub enum LibraryLinkMethod {
     NoLink,
 }
 
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GrabMethod {
+    /// Added to the first qBittorrent or rTorrent client through its API
+    #[default]
+    Client,
+    /// Saved as a torrent file in `watch_dir`, for a client that watches it
+    Watchdir,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum UiLanguage {
+    #[default]
+    En,
+    Sv,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HardlinkFallback {
+    Copy,
+    Symlink,
+}
+
+/// How qBittorrent places the files of a new torrent in its save path
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentLayout {
+    Original,
+    Subfolder,
+    NoSubfolder,
+}
+
+/// Top level folder books are placed in when no `path_template` is set
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LibraryGrouping {
+    #[default]
+    Author,
+    Narrator,
+    Series,
+    None,
+}
+
+/// What decides which of several linked torrents of the same book is kept
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePreference {
+    /// Earliest match in `audio_types` or `ebook_types`
+    Format,
+    Larger,
+    Smaller,
+    /// Uploaded to MaM most recently
+    Newer,
+    Older,
+}
+
+/// When a torrent may supersede a selected or linked torrent of the same book.
+/// A torrent in a less preferred format never does
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UpgradePolicy {
+    /// Lets a torrent in a more preferred format of `audio_types` or
+    /// `ebook_types` supersede
+    #[serde(default = "default_allow_format_change")]
+    pub allow_format_change: bool,
+    /// Only lets a torrent in the same format supersede when it is larger
+    #[serde(default)]
+    pub require_larger: bool,
+    /// How many percent larger it has to be with `require_larger`
+    #[serde(default)]
+    pub min_size_increase_pct: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WebBind {
+    Tcp { host: String, port: u16 },
+    Unix(PathBuf),
+}
+
 fn default_host() -> String {
     "0.0.0.0".to_owned()
 }
@@ -349,6 +958,14 @@ fn default_unsat_buffer() -> u64 {
     10
 }
 
+fn default_grab_delay_ms() -> u64 {
+    1000
+}
+
+fn default_max_grabs_per_minute() -> u64 {
+    20
+}
+
 fn default_search_interval() -> u64 {
     30
 }
@@ -361,10 +978,26 @@ fn default_import_interval() -> u64 {
     135
 }
 
+fn default_max_search_pages() -> u64 {
+    5
+}
+
+fn default_max_search_results() -> u64 {
+    500
+}
+
 fn default_abs_interval() -> u64 {
     10
 }
 
+fn default_hook_timeout() -> u64 {
+    60
+}
+
+fn default_reconnect_delay() -> u64 {
+    30
+}
+
 fn default_audio_types() -> Vec<String> {
     ["m4b", "m4a", "mp4", "mp3", "ogg"]
         .iter()
@@ -379,6 +1012,13 @@ fn default_ebook_types() -> Vec<String> {
         .collect()
 }
 
+fn default_ignore_file_patterns() -> Vec<String> {
+    ["*sample.*", "__MACOSX", "._*"]
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
+
 fn default_music_types() -> Vec<String> {
     ["pdf", "mp3"].iter().map(ToString::to_string).collect()
 }
@@ -386,3 +1026,51 @@ fn default_music_types() -> Vec<String> {
 fn default_radio_types() -> Vec<String> {
     ["mp3"].iter().map(ToString::to_string).collect()
 }
+
+fn default_series_group() -> String {
+    "series".to_owned()
+}
+
+fn default_number_group() -> String {
+    "number".to_owned()
+}
+
+fn default_otlp_service_name() -> String {
+    "mlm".to_owned()
+}
+
+fn default_allow_format_change() -> bool {
+    true
+}
+
+fn default_duplicate_policy() -> Vec<DuplicatePreference> {
+    vec![DuplicatePreference::Format, DuplicatePreference::Larger]
+}
+
+// Picked by key instead of untagged, which reports any mistake in a block as it
+// matching neither kind
+impl<'de> Deserialize<'de> for Snatchlist {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let table = serde_json::Map::<String, serde_json::Value>::deserialize(deserializer)?;
+        let result = if table.contains_key("file") {
+            SnatchlistFile::deserialize(serde_json::Value::Object(table)).map(Snatchlist::File)
+        } else if table.contains_key("type") {
+            SnatchlistSearch::deserialize(serde_json::Value::Object(table)).map(Snatchlist::Search)
+        } else {
+            return Err(serde::de::Error::custom(
+                "snatchlist needs either `type` or `file`",
+            ));
+        };
+        result.map_err(serde::de::Error::custom)
+    }
+}
+
+// Errors in flattened filters are reported for the whole table, so the size
+// fields name themselves
+fn parse_min_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Size, D::Error> {
+    parse(deserializer).map_err(|err| serde::de::Error::custom(format!("min_size: {err}")))
+}
+
+fn parse_max_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Size, D::Error> {
+    parse(deserializer).map_err(|err| serde::de::Error::custom(format!("max_size: {err}")))
+}
@@ -0,0 +1,311 @@
+use std::fmt::Write as _;
+
+use schemars::{Schema, schema_for};
+use serde_json::{Map, Value};
+
+use crate::config::Config;
+
+pub fn config_schema() -> Schema {
+    schema_for!(Config)
+}
+
+/// A commented example config with every option, generated from the schema so
+/// it can not get out of sync with `Config`. Options with a default are set to
+/// it, so the example parses to the same config as an empty file
+pub fn config_example() -> String {
+    let schema = config_schema();
+    let root = schema.as_value();
+    let example = Example {
+        defs: root.get("$defs").and_then(Value::as_object),
+    };
+
+    let mut out = String::from(
+        "# Every option MLM supports, options that are commented out have no default\n\n",
+    );
+    let mut tables = String::new();
+    let required = required(root);
+    // Required options first, and all plain options before the first table
+    let (first, rest): (Vec<_>, Vec<_>) =
+        properties(root).partition(|(key, _)| required.contains(&key.as_str()));
+    for (key, property) in first.into_iter().chain(rest) {
+        let schema = example.resolve(property);
+        if example.is_table(schema) {
+            example.table(&mut tables, key, property);
+        } else {
+            let required = required.contains(&key.as_str());
+            example.option(&mut out, key, property, required, true);
+        }
+    }
+    out.push_str(&tables);
+    out
+}
+
+struct Example<'a> {
+    defs: Option<&'a Map<String, Value>>,
+}
+
+impl<'a> Example<'a> {
+    /// Follows `$ref`s and unwraps `Option`s
+    fn resolve(&self, mut schema: &'a Value) -> &'a Value {
+        loop {
+            if let Some(name) = schema
+                .get("$ref")
+                .and_then(Value::as_str)
+                .and_then(|r| r.strip_prefix("#/$defs/"))
+                && let Some(def) = self.defs.and_then(|defs| defs.get(name))
+            {
+                schema = def;
+                continue;
+            }
+            if let Some(variants) = variants(schema) {
+                let not_null = variants
+                    .iter()
+                    .filter(|v| v.get("type").and_then(Value::as_str) != Some("null"))
+                    .collect::<Vec<_>>();
+                if not_null.len() == 1 && not_null.len() < variants.len() {
+                    schema = not_null[0];
+                    continue;
+                }
+            }
+            return schema;
+        }
+    }
+
+    /// Whether the schema is written as a `[table]` rather than as a value
+    fn is_table(&self, schema: &Value) -> bool {
+        match schema_type(schema) {
+            Some("object") => true,
+            Some("array") => schema
+                .get("items")
+                .is_some_and(|items| self.is_table(self.resolve(items))),
+            _ => variants(schema).is_some_and(|variants| {
+                variants
+                    .iter()
+                    .all(|v| schema_type(self.resolve(v)) == Some("object"))
+            }),
+        }
+    }
+
+    fn option(&self, out: &mut String, key: &str, property: &Value, required: bool, top: bool) {
+        let schema = self.resolve(property);
+        for line in description(property, schema).lines() {
+            let _ = writeln!(out, "# {line}");
+        }
+        // Types that are parsed from a string, like sizes, have a default that
+        // is not written the same way as in the config
+        let default = property
+            .get("default")
+            .filter(|d| matches_type(d, schema))
+            .and_then(|d| toml::Value::try_from(d).ok());
+        match default {
+            Some(default) if top => {
+                let _ = writeln!(out, "{key} = {default}");
+            }
+            Some(default) => {
+                let _ = writeln!(out, "# {key} = {default}");
+            }
+            None if required && top => {
+                let _ = writeln!(out, "{key} = {}", placeholder(schema));
+            }
+            None if required => {
+                let _ = writeln!(out, "# {key} = {} # required", self.hint(schema));
+            }
+            None => {
+                let _ = writeln!(out, "# {key} = {}", self.hint(schema));
+            }
+        }
+    }
+
+    fn table(&self, out: &mut String, path: &str, property: &Value) {
+        let schema = self.resolve(property);
+        let (schema, array) = match schema_type(schema) {
+            Some("array") => (self.resolve(&schema["items"]), true),
+            _ => (schema, false),
+        };
+        out.push('\n');
+        for line in description(property, schema).lines() {
+            let _ = writeln!(out, "# {line}");
+        }
+        if array {
+            let _ = writeln!(out, "# [[{path}]]");
+        } else {
+            let _ = writeln!(out, "# [{path}]");
+        }
+
+        // A table that is one of several shapes lists the options of all of them
+        let shapes = variants(schema)
+            .map(|variants| variants.iter().map(|v| self.resolve(v)).collect())
+            .unwrap_or_else(|| vec![schema]);
+        let mut subtables = vec![];
+        let mut seen = vec![];
+        for shape in &shapes {
+            for (key, property) in properties(shape) {
+                if seen.contains(&key) {
+                    continue;
+                }
+                seen.push(key);
+                if self.is_table(self.resolve(property)) {
+                    subtables.push((key, property));
+                } else {
+                    // Only required when it is required in every shape
+                    let required = shapes.iter().all(|s| required_in(s, key));
+                    self.option(out, key, property, required, false);
+                }
+            }
+            if let Some(values) = shape.get("additionalProperties").filter(|v| v.is_object()) {
+                let _ = writeln!(out, "# <key> = {}", self.hint(self.resolve(values)));
+            }
+        }
+        for (key, property) in subtables {
+            self.table(out, &format!("{path}.{key}"), property);
+        }
+    }
+
+    /// A short description of the values the schema accepts
+    fn hint(&self, schema: &Value) -> String {
+        if let Some(values) = enum_values(schema) {
+            return values.join(" | ");
+        }
+        if let Some(variants) = variants(schema) {
+            return variants
+                .iter()
+                .map(|v| self.hint(self.resolve(v)))
+                .collect::<Vec<_>>()
+                .join(" or ");
+        }
+        match schema_type(schema) {
+            Some("array") => match schema.get("items") {
+                Some(items) => format!("[{}, ...]", self.hint(self.resolve(items))),
+                None => "[...]".to_string(),
+            },
+            Some("object") => match schema.get("additionalProperties") {
+                Some(values) if values.is_object() => {
+                    format!("{{ <key> = {} }}", self.hint(self.resolve(values)))
+                }
+                _ => format!(
+                    "{{ {} }}",
+                    properties(schema)
+                        .map(|(key, property)| format!(
+                            "{key} = {}",
+                            self.hint(self.resolve(property))
+                        ))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            },
+            Some(kind) => format!("<{kind}>"),
+            None => "<value>".to_string(),
+        }
+    }
+}
+
+fn properties(schema: &Value) -> impl Iterator<Item = (&String, &Value)> {
+    schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+}
+
+fn required(schema: &Value) -> Vec<&str> {
+    schema
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect()
+}
+
+fn required_in(schema: &Value, key: &str) -> bool {
+    required(schema).contains(&key)
+}
+
+fn variants(schema: &Value) -> Option<&Vec<Value>> {
+    schema
+        .get("anyOf")
+        .or_else(|| schema.get("oneOf"))
+        .and_then(Value::as_array)
+}
+
+/// The type of the schema, ignoring `null` for options
+fn schema_type(schema: &Value) -> Option<&str> {
+    match schema.get("type")? {
+        Value::String(kind) => Some(kind),
+        Value::Array(kinds) => kinds
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|kind| *kind != "null"),
+        _ => None,
+    }
+}
+
+/// Values of an enum of strings, quoted as they are written in toml
+fn enum_values(schema: &Value) -> Option<Vec<String>> {
+    let quote = |v: &Value| v.as_str().map(|v| format!("{v:?}"));
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        return values.iter().map(quote).collect();
+    }
+    if let Some(value) = schema.get("const") {
+        return quote(value).map(|v| vec![v]);
+    }
+    let variants = variants(schema)?;
+    let values = variants
+        .iter()
+        .map(enum_values)
+        .collect::<Option<Vec<_>>>()?;
+    Some(values.concat())
+}
+
+fn description<'a>(property: &'a Value, schema: &'a Value) -> &'a str {
+    property
+        .get("description")
+        .or_else(|| schema.get("description"))
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+fn matches_type(value: &Value, schema: &Value) -> bool {
+    let kind = schema_type(schema);
+    match value {
+        Value::Null => false,
+        Value::Bool(_) => kind == Some("boolean"),
+        Value::Number(_) => matches!(kind, Some("integer") | Some("number")),
+        Value::String(_) => kind == Some("string") || enum_values(schema).is_some(),
+        Value::Array(_) => kind == Some("array"),
+        Value::Object(_) => kind == Some("object"),
+    }
+}
+
+/// An empty value for required options
+fn placeholder(schema: &Value) -> &'static str {
+    match schema_type(schema) {
+        Some("integer") | Some("number") => "0",
+        Some("boolean") => "false",
+        Some("array") => "[]",
+        _ => "\"\"",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_example_parses() {
+        let example = config_example();
+        let config: Config = toml::from_str(&example).unwrap();
+        let empty: Config = toml::from_str(r#"mam_id = """#).unwrap();
+        assert_eq!(config.web_port, empty.web_port);
+        assert_eq!(config.min_ratio, empty.min_ratio);
+        assert_eq!(config.audio_types, empty.audio_types);
+        assert_eq!(config.duplicate_policy, empty.duplicate_policy);
+        assert_eq!(config.unsat_buffer, empty.unsat_buffer);
+        assert!(config.qbittorrent.is_empty());
+
+        assert!(example.contains("\nsearch_interval = 30\n"));
+        assert!(example.contains("\n# [[qbittorrent]]\n"));
+        assert!(example.contains("\n# [[goodreads_list.grab]]\n"));
+        assert!(example.contains("\n# [[library]]\n"));
+    }
+}
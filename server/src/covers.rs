@@ -0,0 +1,267 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use mlm_db::{Torrent, TorrentMeta};
+use mlm_mam::{
+    api::MaM,
+    http,
+    search::{MaMTorrent, SearchFields, SearchQuery, Tor},
+};
+use reqwest::{Client, Url};
+use serde_json::Value;
+use tokio::fs;
+use tracing::{debug, instrument, trace};
+
+use crate::config::Config;
+
+/// How long to wait before looking again for a cover that wasn't found
+const MISSING_RETRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+pub struct Cover {
+    pub content_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+impl Cover {
+    /// Checks that the data is an image, providers sometimes answer with a
+    /// html page or an empty placeholder instead of a 404
+    pub fn new(data: Vec<u8>) -> Option<Self> {
+        Some(Self {
+            content_type: image_type(&data)?,
+            data,
+        })
+    }
+}
+
+fn image_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() > 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// Finds the cover for a torrent, first the `cover.jpg` in its library folder,
+/// then the `cover_dir` cache, and last from OpenLibrary, Google Books or the
+/// MaM thumbnail. Fetched covers are only kept when `cover_dir` is set
+#[instrument(skip_all)]
+pub async fn get_cover(
+    config: &Config,
+    mam: Option<&MaM<'_>>,
+    torrent: &Torrent,
+) -> Result<Option<Cover>> {
+    if let Some(library_path) = &torrent.library_path
+        && let Ok(data) = fs::read(library_path.join("cover.jpg")).await
+        && let Some(cover) = Cover::new(data)
+    {
+        return Ok(Some(cover));
+    }
+    let Some(cover_dir) = &config.cover_dir else {
+        return Ok(None);
+    };
+    // Ids are torrent hashes or MaM ids, anything else should not become a path
+    if !torrent.id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Ok(None);
+    }
+    let cached = cover_dir.join(&torrent.id);
+    if let Ok(data) = fs::read(&cached).await
+        && let Some(cover) = Cover::new(data)
+    {
+        return Ok(Some(cover));
+    }
+    let missing = cover_dir.join(format!("{}.missing", torrent.id));
+    if let Ok(modified) = fs::metadata(&missing).await.and_then(|m| m.modified())
+        && modified.elapsed().is_ok_and(|age| age < MISSING_RETRY)
+    {
+        trace!("No cover found recently for {}", torrent.id);
+        return Ok(None);
+    }
+
+    let mam_torrent = match mam {
+        Some(mam) => lookup_mam_torrent(mam, torrent.mam_id)
+            .await
+            .unwrap_or_else(|err| {
+                debug!(
+                    "Failed looking up torrent {} on MaM: {err:?}",
+                    torrent.mam_id
+                );
+                None
+            }),
+        None => None,
+    };
+    let cover = fetch_cover(&torrent.meta, mam_torrent.as_ref()).await;
+
+    fs::create_dir_all(cover_dir).await?;
+    match cover {
+        Some(cover) => {
+            fs::write(&cached, &cover.data).await?;
+            fs::remove_file(&missing).await.ok();
+            Ok(Some(cover))
+        }
+        None => {
+            fs::write(&missing, b"").await?;
+            Ok(None)
+        }
+    }
+}
+
+async fn lookup_mam_torrent(mam: &MaM<'_>, mam_id: u64) -> Result<Option<MaMTorrent>> {
+    let mut resp = mam
+        .search(&SearchQuery {
+            fields: SearchFields {
+                isbn: true,
+                thumbnail: true,
+                ..Default::default()
+            },
+            tor: Tor {
+                id: mam_id,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await?;
+    Ok(resp.data.pop())
+}
+
+async fn fetch_cover(meta: &TorrentMeta, mam_torrent: Option<&MaMTorrent>) -> Option<Cover> {
+    let client = http::client();
+    let isbn = mam_torrent
+        .and_then(|t| t.isbn.as_deref())
+        .map(clean_isbn)
+        .filter(|isbn| isbn.len() == 10 || isbn.len() == 13);
+
+    if let Some(isbn) = &isbn {
+        let url = format!("https://covers.openlibrary.org/b/isbn/{isbn}-L.jpg?default=false");
+        if let Some(cover) = download(&client, &url).await {
+            return Some(cover);
+        }
+    }
+    if let Some(url) = google_books_cover(&client, isbn.as_deref(), meta).await
+        && let Some(cover) = download(&client, &url).await
+    {
+        return Some(cover);
+    }
+    if let Some(url) = mam_torrent.and_then(|t| t.thumbnail.as_deref())
+        && let Some(cover) = download(&client, url).await
+    {
+        return Some(cover);
+    }
+    None
+}
+
+async fn google_books_cover(
+    client: &Client,
+    isbn: Option<&str>,
+    meta: &TorrentMeta,
+) -> Option<String> {
+    let query = match isbn {
+        Some(isbn) => format!("isbn:{isbn}"),
+        None => {
+            let mut query = format!("intitle:{}", meta.title);
+            if let Some(author) = meta.authors.first() {
+                query.push_str(&format!(" inauthor:{author}"));
+            }
+            query
+        }
+    };
+    let mut url = Url::parse("https://www.googleapis.com/books/v1/volumes").unwrap();
+    url.query_pairs_mut()
+        .append_pair("q", &query)
+        .append_pair("maxResults", "1");
+    let response: Value = http::send_with_retry(client.get(url))
+        .await
+        .and_then(|r| r.error_for_status())
+        .ok()?
+        .json()
+        .await
+        .ok()?;
+    google_books_thumbnail(&response)
+}
+
+fn google_books_thumbnail(response: &Value) -> Option<String> {
+    let links = response.pointer("/items/0/volumeInfo/imageLinks")?;
+    let url = ["thumbnail", "smallThumbnail"]
+        .iter()
+        .find_map(|size| links.get(size)?.as_str())?;
+    // Google hands out http links, and crops a curled page corner on by default
+    Some(
+        url.replacen("http://", "https://", 1)
+            .replace("&edge=curl", ""),
+    )
+}
+
+async fn download(client: &Client, url: &str) -> Option<Cover> {
+    trace!("Fetching cover {url}");
+    let response = http::send_with_retry(client.get(url))
+        .await
+        .and_then(|r| r.error_for_status());
+    let data = match response {
+        Ok(response) => response.bytes().await.ok()?,
+        Err(err) => {
+            debug!("Failed fetching cover {url}: {err}");
+            return None;
+        }
+    };
+    Cover::new(data.to_vec())
+}
+
+fn clean_isbn(isbn: &str) -> String {
+    isbn.chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+        .collect::<String>()
+        .to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_image_type() {
+        assert_eq!(
+            image_type(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10]),
+            Some("image/jpeg")
+        );
+        assert_eq!(image_type(b"\x89PNG\r\n\x1a\n\0\0"), Some("image/png"));
+        assert_eq!(image_type(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(image_type(b"<!DOCTYPE html>"), None);
+        assert_eq!(image_type(b""), None);
+        assert!(Cover::new(b"GIF89a....".to_vec()).is_some());
+        assert!(Cover::new(b"not found".to_vec()).is_none());
+    }
+
+    #[test]
+    fn test_google_books_thumbnail() {
+        let response = json!({
+            "totalItems": 1,
+            "items": [{
+                "volumeInfo": {
+                    "title": "Leviathan Wakes",
+                    "imageLinks": {
+                        "smallThumbnail": "http://books.google.com/books/content?id=a&zoom=5&edge=curl",
+                        "thumbnail": "http://books.google.com/books/content?id=a&zoom=1&edge=curl"
+                    }
+                }
+            }]
+        });
+        assert_eq!(
+            google_books_thumbnail(&response).as_deref(),
+            Some("https://books.google.com/books/content?id=a&zoom=1")
+        );
+        assert_eq!(google_books_thumbnail(&json!({ "totalItems": 0 })), None);
+    }
+
+    #[test]
+    fn test_clean_isbn() {
+        assert_eq!(clean_isbn("978-0-316-12908-4"), "9780316129084");
+        assert_eq!(clean_isbn(" 0-8044-2957-x "), "080442957X");
+    }
+}
@@ -0,0 +1,164 @@
+use std::{path::Path, process::Stdio, time::Duration};
+
+use anyhow::{Result, anyhow};
+use mlm_db::{Event, EventType};
+use native_db::Database;
+use tokio::{process::Command, time::timeout};
+use tracing::{info, warn};
+
+use crate::{config::Config, logging::write_event};
+
+/// What a hook was run for, filled into the `{path}`, `{title}` and
+/// `{library}` placeholders of its arguments
+pub struct HookTorrent<'a> {
+    pub id: &'a str,
+    pub mam_id: u64,
+    pub path: &'a Path,
+    pub title: &'a str,
+    pub library: Option<&'a str>,
+}
+
+/// Runs a `post_link_command` or `post_clean_command`, logging its output and
+/// recording an event when it fails or runs longer than `hook_timeout`
+pub async fn run_hook(
+    config: &Config,
+    db: &Database<'_>,
+    hook: &str,
+    command: &[String],
+    torrent: HookTorrent<'_>,
+) {
+    let args = hook_args(command, &torrent);
+    let limit = Duration::from_secs(config.hook_timeout);
+    let (status, output) = match run_command(&args, limit).await {
+        Ok((status, output)) => {
+            if !output.is_empty() {
+                info!("{hook} for {}: {output}", torrent.title);
+            }
+            if status == Some(0) {
+                return;
+            }
+            warn!("{hook} for {} exited with status {status:?}", torrent.title);
+            (status, output)
+        }
+        Err(err) => {
+            warn!("{hook} for {} failed: {err:#}", torrent.title);
+            (None, format!("{err:#}"))
+        }
+    };
+    write_event(
+        db,
+        Event::new(
+            Some(torrent.id.to_owned()),
+            Some(torrent.mam_id),
+            EventType::HookFailed {
+                hook: hook.to_owned(),
+                status,
+                output,
+            },
+        ),
+    )
+    .await;
+}
+
+/// The command with placeholders replaced, every argument is passed as is
+/// without going through a shell
+fn hook_args(command: &[String], torrent: &HookTorrent<'_>) -> Vec<String> {
+    let path = torrent.path.to_string_lossy();
+    command
+        .iter()
+        .map(|arg| {
+            arg.replace("{path}", &path)
+                .replace("{title}", torrent.title)
+                .replace("{library}", torrent.library.unwrap_or_default())
+        })
+        .collect()
+}
+
+/// Runs the command and returns its exit code and combined stdout and stderr.
+/// It is killed when it runs longer than `limit`
+pub async fn run_command(args: &[String], limit: Duration) -> Result<(Option<i32>, String)> {
+    let (program, args) = args
+        .split_first()
+        .ok_or_else(|| anyhow!("command is empty"))?;
+    let child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let output = timeout(limit, child.wait_with_output())
+        .await
+        .map_err(|_| anyhow!("timed out after {} seconds", limit.as_secs()))??;
+    let mut text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(stderr.trim());
+    }
+    Ok((output.status.code(), text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn torrent(path: &Path) -> HookTorrent<'_> {
+        HookTorrent {
+            id: "abc",
+            mam_id: 1,
+            path,
+            title: "Title; rm -rf /",
+            library: None,
+        }
+    }
+
+    #[test]
+    fn test_hook_args() {
+        let command = vec![
+            "scan".to_string(),
+            "--path={path}".to_string(),
+            "{title}".to_string(),
+            "{library}".to_string(),
+        ];
+        let args = hook_args(&command, &torrent(Path::new("/books/Author/Title")));
+        assert_eq!(
+            args,
+            vec!["scan", "--path=/books/Author/Title", "Title; rm -rf /", ""]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_command() {
+        let marker = std::env::temp_dir().join(format!("mlm-hook-test-{}", std::process::id()));
+        let command = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            r#"printf '%s' "$1" > "$2"; echo done; echo oops >&2; exit 3"#.to_string(),
+            "sh".to_string(),
+            "{title}".to_string(),
+            "{path}".to_string(),
+        ];
+        let args = hook_args(&command, &torrent(&marker));
+        let (status, output) = run_command(&args, Duration::from_secs(10)).await.unwrap();
+        assert_eq!(status, Some(3));
+        assert_eq!(output, "done\noops");
+        assert_eq!(std::fs::read_to_string(&marker).unwrap(), "Title; rm -rf /");
+        std::fs::remove_file(&marker).unwrap();
+
+        assert!(run_command(&[], Duration::from_secs(10)).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_command_timeout() {
+        let args = vec!["sleep".to_string(), "5".to_string()];
+        let err = run_command(&args, Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "timed out after 0 seconds");
+    }
+}
@@ -3,29 +3,27 @@ use std::os::unix::fs::MetadataExt as _;
 #[cfg(target_family = "windows")]
 use std::os::windows::fs::MetadataExt as _;
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fs::{self, File, Metadata},
     io::{BufWriter, ErrorKind, Write},
     ops::Deref,
     path::{Component, Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use anyhow::{Context, Result, anyhow, bail};
 use file_id::get_file_id;
 use log::error;
 use mlm_db::{
-    ClientStatus, DatabaseExt as _, ErroredTorrentId, Event, EventType, LibraryMismatch,
-    SelectedTorrent, SelectedTorrentKey, Size, Timestamp, Torrent, TorrentKey, TorrentMeta,
+    ClientStatus, DatabaseExt as _, ErroredTorrentId, Event, EventType, LibraryMismatch, MediaType,
+    MetadataSource, SelectedTorrent, SelectedTorrentKey, Size, Timestamp, Torrent, TorrentKey,
+    TorrentMeta,
 };
 use mlm_mam::{api::MaM, meta::MetaError, search::MaMTorrent};
-use mlm_parse::normalize_title;
 use native_db::Database;
 use once_cell::sync::Lazy;
-use qbit::{
-    models::{Torrent as QbitTorrent, TorrentContent},
-    parameters::TorrentListParams,
-};
+use qbit::{models::Torrent as QbitTorrent, parameters::TorrentListParams};
 use regex::Regex;
 use tokio::fs::create_dir_all;
 use tracing::{Level, debug, instrument, span, trace, warn};
@@ -33,21 +31,36 @@ use tracing::{Level, debug, instrument, span, trace, warn};
 use crate::{
     audiobookshelf::{self as abs},
     autograbber::update_torrent_meta,
+    chapters::write_chapters,
     cleaner::remove_library_files,
-    config::{Config, Library, LibraryLinkMethod, QbitConfig},
-    logging::{TorrentMetaError, update_errored_torrent, write_event},
+    config::{
+        Config, HardlinkFallback, Library, LibraryByCategory, LibraryGrouping, LibraryLinkMethod,
+        PathTemplate, PathTemplateToken, QbitConfig, RequiredField,
+    },
+    hooks::{HookTorrent, run_command, run_hook},
+    logging::{
+        TorrentMetaError, should_retry, update_errored_torrent, update_pending_torrent, write_event,
+    },
     qbittorrent::ensure_category_exists,
+    stats::{ProgressReporter, ProgressTask, Stats},
 };
 
 pub static DISK_PATTERN: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"(?:CD|Disc|Disk)\s*(\d+)").unwrap());
 
+/// How long a `convert_command` may run for one book before it is killed
+const CONVERT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Libraries that have been warned about falling back from hardlinking
+static CROSS_DEVICE_WARNED: Lazy<Mutex<BTreeSet<PathBuf>>> = Lazy::new(Default::default);
+
 #[instrument(skip_all)]
 pub async fn link_torrents_to_library(
     config: Arc<Config>,
     db: Arc<Database<'_>>,
     qbit: (&QbitConfig, &qbit::Api),
     mam: Arc<MaM<'_>>,
+    stats: &Stats,
 ) -> Result<()> {
     let torrents = qbit
         .1
@@ -55,28 +68,32 @@ pub async fn link_torrents_to_library(
         .await
         .context("qbit main data")?;
 
+    let mut progress = ProgressReporter::start(stats, ProgressTask::Linker, torrents.len()).await;
     for torrent in torrents {
+        progress.advance().await;
         if torrent.progress < 1.0 {
             continue;
         }
-        let library = find_library(&config, &torrent);
         let r = db.r_transaction()?;
         let mut existing_torrent: Option<Torrent> = r.get().primary(torrent.hash.clone())?;
-        {
-            let selected_torrent: Option<SelectedTorrent> = r.get().secondary::<SelectedTorrent>(
-                SelectedTorrentKey::hash,
-                Some(torrent.hash.clone()),
-            )?;
-            if let Some(selected_torrent) = selected_torrent {
-                debug!(
-                    "Finished Downloading torrent {} {}",
-                    selected_torrent.mam_id, selected_torrent.meta.title
-                );
-                let (_guard, rw) = db.rw_async().await?;
-                rw.remove(selected_torrent)?;
-                rw.commit()?;
-            }
+        let selected_torrent: Option<SelectedTorrent> = r
+            .get()
+            .secondary::<SelectedTorrent>(SelectedTorrentKey::hash, Some(torrent.hash.clone()))?;
+        let selected_meta = selected_torrent.as_ref().map(|t| t.meta.clone());
+        if let Some(selected_torrent) = selected_torrent {
+            debug!(
+                "Finished Downloading torrent {} {}",
+                selected_torrent.mam_id, selected_torrent.meta.title
+            );
+            let (_guard, rw) = db.rw_async().await?;
+            rw.remove(selected_torrent)?;
+            rw.commit()?;
         }
+        let meta = existing_torrent
+            .as_ref()
+            .map(|t| &t.meta)
+            .or(selected_meta.as_ref());
+        let library = find_library(&config, &torrent, meta);
         if let Some(t) = &mut existing_torrent {
             let library_name = library.and_then(|l| l.tag_filters().name.as_ref());
             if t.linker.as_ref() != library_name {
@@ -119,63 +136,17 @@ pub async fn link_torrents_to_library(
                 }
             }
             if let Some(library_path) = &t.library_path {
-                let Some(library) = find_library(&config, &torrent) else {
-                    if t.library_mismatch != Some(LibraryMismatch::NoLibrary) {
-                        debug!("no library: {library_path:?}",);
-                        t.library_mismatch = Some(LibraryMismatch::NoLibrary);
-                        let (_guard, rw) = db.rw_async().await?;
-                        rw.upsert(t.clone())?;
-                        rw.commit()?;
-                    }
+                // Missing files are more important to see, the library audit clears them
+                if matches!(t.library_mismatch, Some(LibraryMismatch::MissingFiles(_))) {
                     continue;
-                };
-                if !library_path.starts_with(library.library_dir()) {
-                    let wanted = Some(LibraryMismatch::NewLibraryDir(
-                        library.library_dir().clone(),
-                    ));
-                    if t.library_mismatch != wanted {
-                        debug!(
-                            "library differs: {library_path:?} != {:?}",
-                            library.library_dir()
-                        );
-                        t.library_mismatch = wanted;
-                        let (_guard, rw) = db.rw_async().await?;
-                        rw.upsert(t.clone())?;
-                        rw.commit()?;
-                    }
-                } else {
-                    let dir = library_dir(config.exclude_narrator_in_library_dir, library, &t.meta);
-                    let mut is_wrong = Some(library_path) != dir.as_ref();
-                    let wanted = match dir {
-                        Some(dir) => Some(LibraryMismatch::NewPath(dir)),
-                        None => Some(LibraryMismatch::NoLibrary),
-                    };
-
-                    if t.library_mismatch != wanted {
-                        if is_wrong {
-                            // Try another attempt at matching with exclude_narrator flipped
-                            let dir_2 = library_dir(
-                                !config.exclude_narrator_in_library_dir,
-                                library,
-                                &t.meta,
-                            );
-                            if Some(library_path) == dir_2.as_ref() {
-                                is_wrong = false
-                            }
-                        }
-                        if is_wrong {
-                            debug!("path differs: {library_path:?} != {:?}", wanted);
-                            t.library_mismatch = wanted;
-                            let (_guard, rw) = db.rw_async().await?;
-                            rw.upsert(t.clone())?;
-                            rw.commit()?;
-                        } else if t.library_mismatch.is_some() {
-                            t.library_mismatch = None;
-                            let (_guard, rw) = db.rw_async().await?;
-                            rw.upsert(t.clone())?;
-                            rw.commit()?;
-                        }
-                    }
+                }
+                let wanted = library_mismatch(&config, library, library_path, &t.meta);
+                if t.library_mismatch != wanted {
+                    debug!("library mismatch for {library_path:?}: {wanted:?}");
+                    t.library_mismatch = wanted;
+                    let (_guard, rw) = db.rw_async().await?;
+                    rw.upsert(t.clone())?;
+                    rw.commit()?;
                 }
                 continue;
             }
@@ -196,6 +167,11 @@ pub async fn link_torrents_to_library(
             continue;
         }
 
+        let error_id = ErroredTorrentId::Linker(torrent.hash.clone());
+        if !should_retry(&db, error_id.clone()) {
+            trace!("Waiting to retry linking torrent \"{}\"", torrent.name);
+            continue;
+        }
         let result = match_torrent(
             config.clone(),
             db.clone(),
@@ -208,18 +184,116 @@ pub async fn link_torrents_to_library(
         )
         .await
         .context("match_torrent");
-        update_errored_torrent(
-            &db,
-            ErroredTorrentId::Linker(torrent.hash.clone()),
-            torrent.name,
-            result,
-        )
-        .await;
+        match result {
+            Ok(MatchOutcome::PendingMetadata { meta, missing }) => {
+                wait_for_metadata(
+                    &config,
+                    &db,
+                    error_id,
+                    &torrent.hash,
+                    torrent.name,
+                    meta,
+                    &missing,
+                )
+                .await?;
+            }
+            result => {
+                let result = result.map(|_| ());
+                update_errored_torrent(&db, &config.retry, error_id, torrent.name, result).await;
+            }
+        }
     }
 
     Ok(())
 }
 
+/// Compares where a torrent is linked with where its library would put it now
+pub fn library_mismatch(
+    config: &Config,
+    library: Option<&Library>,
+    library_path: &Path,
+    meta: &TorrentMeta,
+) -> Option<LibraryMismatch> {
+    let Some(library) = library else {
+        return Some(LibraryMismatch::NoLibrary);
+    };
+    if !library_path.starts_with(library.library_dir()) {
+        return Some(LibraryMismatch::NewLibraryDir(
+            library.library_dir().clone(),
+        ));
+    }
+    let exclude_narrator = library.exclude_narrator_in_library_dir(config);
+    let Some(dir) = library_dir(exclude_narrator, library, meta) else {
+        return Some(LibraryMismatch::NoLibrary);
+    };
+    if library_path == dir {
+        return None;
+    }
+    // Also accept the path with exclude_narrator flipped, and for the media
+    // types that never get a narrator now, the path that still has one
+    let old_dir = |exclude_narrator| render_library_dir(exclude_narrator, library, meta);
+    if [!exclude_narrator, false]
+        .into_iter()
+        .any(|exclude_narrator| old_dir(exclude_narrator).as_deref() == Some(library_path))
+    {
+        return None;
+    }
+    Some(LibraryMismatch::NewPath(dir))
+}
+
+/// Recomputes `library_mismatch` for every linked torrent, e.g. after changing
+/// the `path_template` of a library, so they can be reviewed and relinked from
+/// the torrents page. Torrents are matched to the library that linked them, or
+/// the one their files are in when that library has no name.
+/// Returns how many torrents are mismatched
+#[instrument(skip_all)]
+pub async fn update_library_mismatches(config: &Config, db: &Database<'_>) -> Result<u64> {
+    let torrents = db
+        .r_transaction()?
+        .scan()
+        .primary::<Torrent>()?
+        .all()?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut mismatched = 0;
+    for mut torrent in torrents {
+        let Some(library_path) = &torrent.library_path else {
+            continue;
+        };
+        // Missing files are more important to see, the library audit clears them
+        if matches!(
+            torrent.library_mismatch,
+            Some(LibraryMismatch::MissingFiles(_))
+        ) {
+            mismatched += 1;
+            continue;
+        }
+        let library = config
+            .libraries
+            .iter()
+            .find(|l| torrent.linker.is_some() && l.tag_filters().name == torrent.linker)
+            .or_else(|| {
+                config
+                    .libraries
+                    .iter()
+                    .find(|l| library_path.starts_with(l.library_dir()))
+            });
+        let wanted = library_mismatch(config, library, library_path, &torrent.meta);
+        if wanted.is_some() {
+            mismatched += 1;
+        }
+        if torrent.library_mismatch != wanted {
+            debug!("library mismatch for {library_path:?}: {wanted:?}");
+            torrent.library_mismatch = wanted;
+            let (_guard, rw) = db.rw_async().await?;
+            rw.upsert(torrent)?;
+            rw.commit()?;
+        }
+    }
+
+    Ok(mismatched)
+}
+
 #[instrument(skip_all)]
 #[allow(clippy::too_many_arguments)]
 async fn match_torrent(
@@ -231,24 +305,84 @@ async fn match_torrent(
     torrent: &QbitTorrent,
     library: &Library,
     existing_torrent: Option<Torrent>,
-) -> Result<()> {
+) -> Result<MatchOutcome> {
     let mut existing_torrent = existing_torrent;
     let files = qbit.1.files(hash, None).await?;
-    let selected_audio_format = select_format(
-        &library.tag_filters().audio_types,
-        &config.audio_types,
-        &files,
-    );
-    let selected_ebook_format = select_format(
-        &library.tag_filters().ebook_types,
-        &config.ebook_types,
-        &files,
-    );
+    let file_names = files.iter().map(|f| f.name.as_str()).collect::<Vec<_>>();
+    let (mut selected_audio_format, mut selected_ebook_format) =
+        select_formats(&config, library, &file_names);
 
     if selected_audio_format.is_none() && selected_ebook_format.is_none() {
         bail!("Could not find any wanted formats in torrent");
     }
-    let Some(mam_torrent) = mam.get_torrent_info(hash).await.context("get_mam_info")? else {
+    // Imported without a MaM torrent, it is linked with the metadata it was
+    // imported with
+    let file_meta = existing_torrent
+        .as_ref()
+        .filter(|t| t.meta.source == MetadataSource::File)
+        .map(|t| t.meta.clone());
+    let (mam_torrent, meta) = match file_meta {
+        Some(meta) => (None, meta),
+        None => {
+            let (mam_torrent, meta) =
+                mam_meta(&config, &db, qbit, &mam, torrent, &mut existing_torrent).await?;
+            (Some(mam_torrent), meta)
+        }
+    };
+    // The library was picked without the metadata, its filter may route the
+    // torrent to a later library or to none
+    let library = match find_library(&config, torrent, Some(&meta)) {
+        Some(l) if std::ptr::eq(l, library) => library,
+        Some(l) => {
+            (selected_audio_format, selected_ebook_format) =
+                select_formats(&config, l, &file_names);
+            if selected_audio_format.is_none() && selected_ebook_format.is_none() {
+                bail!("Could not find any wanted formats in torrent");
+            }
+            l
+        }
+        None => bail!("No library filter matches \"{}\"", meta.title),
+    };
+    let missing = library.tag_filters().missing_fields(&meta);
+    if !missing.is_empty() {
+        return Ok(MatchOutcome::PendingMetadata { meta, missing });
+    }
+
+    link_torrent(
+        &config,
+        &db,
+        hash,
+        map_path(&qbit.0.path_mapping, &torrent.save_path),
+        &torrent.category,
+        files.into_iter().map(|file| file.name).collect(),
+        selected_audio_format,
+        selected_ebook_format,
+        library,
+        mam_torrent.as_ref(),
+        existing_torrent.as_ref(),
+        &meta,
+    )
+    .await
+    .context("link_torrent")
+    .map_err(|err| anyhow::Error::new(TorrentMetaError(meta, err)))?;
+    Ok(MatchOutcome::Linked)
+}
+
+/// Looks up the torrent on MaM and its metadata, a torrent MaM has under
+/// another hash becomes the existing torrent
+async fn mam_meta(
+    config: &Config,
+    db: &Database<'_>,
+    qbit: (&QbitConfig, &qbit::Api),
+    mam: &MaM<'_>,
+    torrent: &QbitTorrent,
+    existing_torrent: &mut Option<Torrent>,
+) -> Result<(MaMTorrent, TorrentMeta)> {
+    let Some(mam_torrent) = mam
+        .get_torrent_info(&torrent.hash)
+        .await
+        .context("get_mam_info")?
+    else {
         bail!("Could not find torrent on mam");
     };
     if existing_torrent.is_none()
@@ -257,14 +391,14 @@ async fn match_torrent(
             .get()
             .secondary::<Torrent>(TorrentKey::mam_id, mam_torrent.id)?
     {
-        if old_torrent.id != hash {
+        if old_torrent.id != torrent.hash {
             let (_guard, rw) = db.rw_async().await?;
             rw.remove(old_torrent.clone())?;
             rw.commit()?;
         }
-        existing_torrent = Some(old_torrent);
+        *existing_torrent = Some(old_torrent);
     }
-    let meta = match mam_torrent.as_meta() {
+    let meta = match mam_torrent.as_meta(config.title_rules()) {
         Ok(meta) => meta,
         Err(err) => {
             if let MetaError::UnknownMediaType(_) = err {
@@ -296,24 +430,62 @@ async fn match_torrent(
             return Err(err).context("as_meta");
         }
     };
+    Ok((mam_torrent, meta))
+}
 
-    link_torrent(
-        &config,
-        qbit.0,
-        &db,
-        hash,
-        torrent,
-        files,
-        selected_audio_format,
-        selected_ebook_format,
-        library,
-        mam_torrent,
-        existing_torrent.as_ref(),
-        &meta,
-    )
-    .await
-    .context("link_torrent")
-    .map_err(|err| anyhow::Error::new(TorrentMetaError(meta, err)))
+/// What became of a torrent the linker tried to link
+enum MatchOutcome {
+    Linked,
+    /// Its library requires metadata that MaM does not have for it yet
+    PendingMetadata {
+        meta: TorrentMeta,
+        missing: Vec<RequiredField>,
+    },
+}
+
+/// Holds back linking until the metadata a library requires is there. The
+/// torrent shows up on the errors page, with an event when it starts waiting,
+/// and is flagged for a metadata update so the next try uses fresh metadata
+/// from MaM
+async fn wait_for_metadata(
+    config: &Config,
+    db: &Database<'_>,
+    error_id: ErroredTorrentId,
+    hash: &str,
+    title: String,
+    meta: TorrentMeta,
+    missing: &[RequiredField],
+) -> Result<()> {
+    let missing = missing.iter().map(ToString::to_string).collect::<Vec<_>>();
+    let reason = format!(
+        "Waiting for metadata before linking, missing {}",
+        missing.join(", ")
+    );
+    debug!("{reason}: \"{title}\"");
+    let mam_id = meta.mam_id;
+    if update_pending_torrent(db, &config.retry, error_id, title, reason, Some(meta)).await {
+        write_event(
+            db,
+            Event::new(
+                Some(hash.to_owned()),
+                Some(mam_id),
+                EventType::MetadataPending { missing },
+            ),
+        )
+        .await;
+    }
+    if let Some(mut torrent) = db
+        .r_transaction()?
+        .get()
+        .primary::<Torrent>(hash.to_owned())?
+        && !torrent.request_matadata_update
+    {
+        let (_guard, rw) = db.rw_async().await?;
+        torrent.request_matadata_update = true;
+        rw.upsert(torrent)?;
+        rw.commit()?;
+    }
+    Ok(())
 }
 
 #[instrument(skip_all)]
@@ -334,7 +506,9 @@ pub async fn refresh_metadata(
     else {
         bail!("Could not find torrent \"{}\" on mam", torrent.meta.title);
     };
-    let meta = mam_torrent.as_meta().context("as_meta")?;
+    let meta = mam_torrent
+        .as_meta(config.title_rules())
+        .context("as_meta")?;
 
     if torrent.meta != meta {
         update_torrent_meta(
@@ -397,43 +571,36 @@ pub async fn refresh_metadata_relink(
     let Some((qbit_conf, qbit, qbit_torrent)) = torrent else {
         bail!("Could not find torrent in qbit");
     };
-    let Some(library) = find_library(config, &qbit_torrent) else {
+    let (torrent, mam_torrent) = refresh_metadata(config, db, mam, hash.clone()).await?;
+    let Some(library) = find_library(config, &qbit_torrent, Some(&torrent.meta)) else {
         bail!("Could not find matching library for torrent");
     };
     let files = qbit.files(&hash, None).await?;
-    let selected_audio_format = select_format(
-        &library.tag_filters().audio_types,
-        &config.audio_types,
-        &files,
-    );
-    let selected_ebook_format = select_format(
-        &library.tag_filters().ebook_types,
-        &config.ebook_types,
-        &files,
-    );
+    let file_names = files.iter().map(|f| f.name.as_str()).collect::<Vec<_>>();
+    let (selected_audio_format, selected_ebook_format) =
+        select_formats(config, library, &file_names);
 
     if selected_audio_format.is_none() && selected_ebook_format.is_none() {
         bail!("Could not find any wanted formats in torrent");
     }
-    let (torrent, mam_torrent) = refresh_metadata(config, db, mam, hash.clone()).await?;
     let library_path_changed = torrent.library_path
         != library_dir(
-            config.exclude_narrator_in_library_dir,
+            library.exclude_narrator_in_library_dir(config),
             library,
             &torrent.meta,
         );
     remove_library_files(config, &torrent, library_path_changed).await?;
     link_torrent(
         config,
-        qbit_conf,
         db,
         &hash,
-        &qbit_torrent,
-        files,
+        map_path(&qbit_conf.path_mapping, &qbit_torrent.save_path),
+        &qbit_torrent.category,
+        files.into_iter().map(|file| file.name).collect(),
         selected_audio_format,
         selected_ebook_format,
         library,
-        mam_torrent,
+        Some(&mam_torrent),
         Some(&torrent),
         &torrent.meta,
     )
@@ -446,45 +613,51 @@ pub async fn refresh_metadata_relink(
 #[allow(clippy::too_many_arguments)]
 async fn link_torrent(
     config: &Config,
-    qbit_config: &QbitConfig,
     db: &Database<'_>,
     hash: &str,
-    torrent: &QbitTorrent,
-    files: Vec<TorrentContent>,
+    download_dir: PathBuf,
+    category: &str,
+    files: Vec<String>,
     selected_audio_format: Option<String>,
     selected_ebook_format: Option<String>,
     library: &Library,
-    mam_torrent: MaMTorrent,
+    mam_torrent: Option<&MaMTorrent>,
     existing_torrent: Option<&Torrent>,
     meta: &TorrentMeta,
 ) -> Result<()> {
     let mut library_files = vec![];
+    let mut converted = vec![];
 
     let library_path = if library.tag_filters().method != LibraryLinkMethod::NoLink {
-        let Some(mut dir) = library_dir(config.exclude_narrator_in_library_dir, library, meta)
-        else {
+        let exclude_narrator = library.exclude_narrator_in_library_dir(config);
+        let Some(mut dir) = library_dir(exclude_narrator, library, meta) else {
             bail!("Torrent has no author");
         };
-        if config.exclude_narrator_in_library_dir && !meta.narrators.is_empty() && dir.exists() {
+        if exclude_narrator && !meta.narrators.is_empty() && dir.exists() {
             dir = library_dir(false, library, meta).unwrap();
         }
-        let metadata = abs::create_metadata(&mam_torrent, meta);
+        let metadata = abs::create_metadata(mam_torrent, meta);
+        let names = NameRules::new(library);
+        let json_files = files
+            .iter()
+            .filter(|file| file.to_lowercase().ends_with(".json"))
+            .map(|file| download_dir.join(qbit_file_path(file)))
+            .collect::<Vec<_>>();
 
         create_dir_all(&dir).await?;
         for file in files {
-            let span = span!(Level::TRACE, "file: {:?}", file.name);
+            let span = span!(Level::TRACE, "file: {:?}", file);
             let _s = span.enter();
-            if !(selected_audio_format
-                .as_ref()
-                .is_some_and(|ext| file.name.ends_with(ext))
-                || selected_ebook_format
-                    .as_ref()
-                    .is_some_and(|ext| file.name.ends_with(ext)))
-            {
-                debug!("Skiping \"{}\"", file.name);
+            if !is_linked_file(
+                library,
+                &selected_audio_format,
+                &selected_ebook_format,
+                &file,
+            ) {
+                debug!("Skiping \"{}\"", file);
                 continue;
             }
-            let torrent_path = qbit_file_path(&file.name);
+            let torrent_path = qbit_file_path(&file);
             let mut path_components = torrent_path.components();
             let file_name = path_components.next_back().unwrap();
             let dir_name = path_components.next_back().and_then(|dir_name| {
@@ -496,21 +669,27 @@ async fn link_torrent(
                 }
                 None
             });
-            let file_path = if let Some(dir_name) = dir_name {
-                let sub_dir = PathBuf::from(dir_name);
+            let sub_dir = dir_name.map(PathBuf::from).unwrap_or_default();
+            let file_name = file_name.as_os_str().to_string_lossy();
+            let file_name = names.unique_file_name(&file_name, |name| {
+                library_files.contains(&sub_dir.join(name))
+            });
+            if !sub_dir.as_os_str().is_empty() {
                 create_dir_all(dir.join(&sub_dir)).await?;
-                sub_dir.join(file_name)
-            } else {
-                PathBuf::from(&file_name)
-            };
+            }
+            let file_path = sub_dir.join(file_name);
             let library_path = dir.join(&file_path);
             library_files.push(file_path.clone());
-            let download_path = map_path(&qbit_config.path_mapping, &torrent.save_path)
-                .join(&torrent_path);
+            let download_path = download_dir.join(&torrent_path);
             match library.method() {
-                LibraryLinkMethod::Hardlink => {
-                    hard_link(&download_path, &library_path, &file_path)?
-                }
+                LibraryLinkMethod::Hardlink => hard_link_with_fallback(
+                    library,
+                    || hard_link(&download_path, &library_path, &file_path),
+                    |fallback| match fallback {
+                        HardlinkFallback::Copy => copy(&download_path, &library_path),
+                        HardlinkFallback::Symlink => symlink(&download_path, &library_path),
+                    },
+                )?,
                 LibraryLinkMethod::HardlinkOrCopy => {
                     hard_link(&download_path, &library_path, &file_path)
                         .or_else(|_| copy(&download_path, &library_path))?
@@ -524,8 +703,32 @@ async fn link_torrent(
                 LibraryLinkMethod::NoLink => {}
             };
         }
+        if let (Some(convert_to), Some(ebook_format)) =
+            (&library.tag_filters().convert_to, &selected_ebook_format)
+        {
+            converted =
+                convert_ebooks(library, &dir, ebook_format, convert_to, &library_files).await;
+            library_files.extend(converted.iter().map(|(_, to)| to.clone()));
+        }
         library_files.sort();
 
+        // Chapters only make sense for a book that is a single audio file
+        let audio_files = library_files
+            .iter()
+            .filter(|file| {
+                selected_audio_format
+                    .as_ref()
+                    .is_some_and(|ext| file.to_string_lossy().ends_with(ext))
+            })
+            .count();
+        if library.tag_filters().generate_chapters && audio_files == 1 {
+            match write_chapters(&json_files, &dir.join("chapters.txt")) {
+                Ok(true) => {}
+                Ok(false) => debug!("No chapter info found for {}", meta.title),
+                Err(err) => warn!("Failed writing chapters for {}: {err:?}", meta.title),
+            }
+        }
+
         let file = File::create(dir.join("metadata.json"))?;
         let mut writer = BufWriter::new(file);
         serde_json::to_writer(&mut writer, &metadata)?;
@@ -546,14 +749,10 @@ async fn link_torrent(
             library_path: library_path.clone(),
             library_files,
             linker: library.tag_filters().name.clone(),
-            category: if torrent.category.is_empty() {
-                None
-            } else {
-                Some(torrent.category.clone())
-            },
+            category: (!category.is_empty()).then(|| category.to_owned()),
             selected_audio_format,
             selected_ebook_format,
-            title_search: normalize_title(&meta.title),
+            title_search: config.title_rules().normalize_title(&meta.title),
             meta: meta.clone(),
             created_at: existing_torrent
                 .map(|t| t.created_at)
@@ -562,6 +761,7 @@ async fn link_torrent(
             request_matadata_update: false,
             library_mismatch: None,
             client_status: existing_torrent.and_then(|t| t.client_status.clone()),
+            note: existing_torrent.and_then(|t| t.note.clone()),
         })?;
         rw.commit()?;
     }
@@ -574,16 +774,124 @@ async fn link_torrent(
                 Some(meta.mam_id),
                 EventType::Linked {
                     linker: library.tag_filters().name.clone(),
-                    library_path,
+                    library_path: library_path.clone(),
                 },
             ),
         )
         .await;
+        for (from, to) in converted {
+            write_event(
+                db,
+                Event::new(
+                    Some(hash.to_owned()),
+                    Some(meta.mam_id),
+                    EventType::Converted { from, to },
+                ),
+            )
+            .await;
+        }
+        if let Some(command) = &config.post_link_command {
+            run_hook(
+                config,
+                db,
+                "post_link_command",
+                command,
+                HookTorrent {
+                    id: hash,
+                    mam_id: meta.mam_id,
+                    path: &library_path,
+                    title: &meta.title,
+                    library: library.tag_filters().name.as_deref(),
+                },
+            )
+            .await;
+        }
     }
 
     Ok(())
 }
 
+/// Converts the linked ebooks to `convert_to` next to them, and returns the
+/// files that were converted and what they were converted to. Failures are
+/// logged and leave the book as it was linked
+async fn convert_ebooks(
+    library: &Library,
+    dir: &Path,
+    ebook_format: &str,
+    convert_to: &str,
+    library_files: &[PathBuf],
+) -> Vec<(PathBuf, PathBuf)> {
+    let convert_to = convert_to.trim_start_matches('.').to_lowercase();
+    if ebook_format.trim_start_matches('.') == convert_to {
+        return vec![];
+    }
+    let command = library
+        .tag_filters()
+        .convert_command
+        .clone()
+        .unwrap_or_else(default_convert_command);
+    let mut converted = vec![];
+    for file in library_files {
+        if !file.to_string_lossy().ends_with(ebook_format) {
+            continue;
+        }
+        let output = file.with_extension(&convert_to);
+        if library_files.contains(&output) {
+            continue;
+        }
+        let input_path = dir.join(file);
+        let output_path = dir.join(&output);
+        if output_path.exists() {
+            // Converted when the torrent was linked before
+            converted.push((file.clone(), output));
+            continue;
+        }
+        if let Some(max_size) = library.tag_filters().convert_max_size
+            && fs::metadata(&input_path).is_ok_and(|m| file_size(&m) > max_size.bytes())
+        {
+            debug!("Not converting {file:?}, it is larger than {max_size}");
+            continue;
+        }
+        let args = command
+            .iter()
+            .map(|arg| {
+                arg.replace("{input}", &input_path.to_string_lossy())
+                    .replace("{output}", &output_path.to_string_lossy())
+            })
+            .collect::<Vec<_>>();
+        debug!("converting: {:?} -> {:?}", input_path, output_path);
+        match run_command(&args, CONVERT_TIMEOUT).await {
+            Ok((Some(0), _)) if output_path.exists() => converted.push((file.clone(), output)),
+            Ok((status, log)) => {
+                warn!("Converting {file:?} failed with status {status:?}: {log}");
+                fs::remove_file(&output_path).ok();
+            }
+            Err(err) => {
+                if err
+                    .downcast_ref::<std::io::Error>()
+                    .is_some_and(|err| err.kind() == ErrorKind::NotFound)
+                {
+                    warn!(
+                        "Converter {:?} is not installed, linking {file:?} without converting it",
+                        command.first().map(String::as_str).unwrap_or_default()
+                    );
+                    break;
+                }
+                warn!("Converting {file:?} failed: {err:#}");
+                fs::remove_file(&output_path).ok();
+            }
+        }
+    }
+    converted
+}
+
+fn default_convert_command() -> Vec<String> {
+    ["ebook-convert", "{input}", "{output}"]
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
+
 pub fn map_path(path_mapping: &BTreeMap<PathBuf, PathBuf>, save_path: &str) -> PathBuf {
     let mut path = PathBuf::from(save_path);
     for (from, to) in path_mapping.iter().rev() {
@@ -599,88 +907,367 @@ pub fn map_path(path_mapping: &BTreeMap<PathBuf, PathBuf>, save_path: &str) -> P
     path
 }
 
-pub fn find_library<'a>(config: &'a Config, torrent: &QbitTorrent) -> Option<&'a Library> {
+/// The first library, in config order, whose download dir or category, tags
+/// and filter match the torrent. The filter is only checked when the metadata
+/// of the torrent is known
+pub fn find_library<'a>(
+    config: &'a Config,
+    torrent: &QbitTorrent,
+    meta: Option<&TorrentMeta>,
+) -> Option<&'a Library> {
+    find_library_for(
+        config,
+        Path::new(&torrent.save_path),
+        &torrent.category,
+        &torrent.tags,
+        meta,
+    )
+}
+
+/// Like [`find_library`], by the save path, category and comma separated tags
+/// of a torrent
+fn find_library_for<'a>(
+    config: &'a Config,
+    save_path: &Path,
+    category: &str,
+    tags: &str,
+    meta: Option<&TorrentMeta>,
+) -> Option<&'a Library> {
     config
         .libraries
         .iter()
-        .filter(|l| match l {
-            Library::ByDir(l) => PathBuf::from(&torrent.save_path).starts_with(&l.download_dir),
-            Library::ByCategory(l) => torrent.category == l.category,
-        })
-        .find(|l| {
-            let filters = l.tag_filters();
-            if filters
-                .deny_tags
-                .iter()
-                .any(|tag| torrent.tags.split(", ").any(|t| t == tag.as_str()))
-            {
-                return false;
-            }
-            if filters.allow_tags.is_empty() {
-                return true;
-            }
-            filters
-                .allow_tags
-                .iter()
-                .any(|tag| torrent.tags.split(", ").any(|t| t == tag.as_str()))
-        })
+        .find(|l| library_accepts(l, save_path, category, tags, meta))
+}
+
+/// The library by category a newly added torrent will be linked into, which
+/// decides where qBittorrent saves it
+pub fn find_category_library<'a>(
+    config: &'a Config,
+    category: Option<&str>,
+    tags: &[String],
+    meta: &TorrentMeta,
+) -> Option<&'a LibraryByCategory> {
+    let category = category?;
+    let tags = tags.join(", ");
+    config.libraries.iter().find_map(|library| match library {
+        Library::ByCategory(l)
+            if library_accepts(library, Path::new(""), category, &tags, Some(meta)) =>
+        {
+            Some(l)
+        }
+        _ => None,
+    })
+}
+
+fn library_accepts(
+    library: &Library,
+    save_path: &Path,
+    category: &str,
+    tags: &str,
+    meta: Option<&TorrentMeta>,
+) -> bool {
+    let matches = match library {
+        Library::ByDir(l) => l.download_dir.find(save_path).is_some(),
+        Library::ByCategory(l) => category == l.category,
+    };
+    if !matches {
+        return false;
+    }
+    let filters = library.tag_filters();
+    if filters
+        .deny_tags
+        .iter()
+        .any(|tag| tags.split(", ").any(|t| t == tag.as_str()))
+    {
+        return false;
+    }
+    if !filters.allow_tags.is_empty()
+        && !filters
+            .allow_tags
+            .iter()
+            .any(|tag| tags.split(", ").any(|t| t == tag.as_str()))
+    {
+        return false;
+    }
+    meta.is_none_or(|meta| filters.filter.matches(meta))
 }
 
 pub fn library_dir(
     exclude_narrator_in_library_dir: bool,
     library: &Library,
     meta: &TorrentMeta,
+) -> Option<PathBuf> {
+    // Only audio has narrators, other books never get one in their folder
+    let exclude_narrator_in_library_dir = exclude_narrator_in_library_dir
+        || matches!(
+            meta.media_type,
+            MediaType::Ebook | MediaType::Manga | MediaType::ComicBook | MediaType::PeriodicalEbook
+        );
+    render_library_dir(exclude_narrator_in_library_dir, library, meta)
+}
+
+fn render_library_dir(
+    exclude_narrator_in_library_dir: bool,
+    library: &Library,
+    meta: &TorrentMeta,
 ) -> Option<PathBuf> {
     let author = meta.authors.first()?;
-    let mut dir = match meta
+    let names = NameRules::new(library);
+    if let Some(template) = &library.tag_filters().path_template {
+        let dir = render_path_template(
+            template,
+            &names,
+            exclude_narrator_in_library_dir,
+            author,
+            meta,
+        )?;
+        return Some(library.library_dir().join(dir));
+    }
+    let series = meta
         .series
         .iter()
         .find(|s| !s.entries.0.is_empty())
-        .or(meta.series.first())
-    {
-        Some(series) => PathBuf::from(sanitize_filename::sanitize(author).to_string())
-            .join(sanitize_filename::sanitize(&series.name).to_string())
-            .join(
-                sanitize_filename::sanitize(if series.entries.0.is_empty() {
-                    meta.title.clone()
-                } else {
-                    format!("{} #{} - {}", series.name, series.entries, meta.title)
-                })
-                .to_string(),
-            ),
-        None => PathBuf::from(sanitize_filename::sanitize(author).to_string())
-            .join(sanitize_filename::sanitize(&meta.title).to_string()),
+        .or(meta.series.first());
+    let grouping = library.tag_filters().grouping;
+    let mut dir = PathBuf::new();
+    match grouping {
+        LibraryGrouping::Author | LibraryGrouping::Narrator => {
+            // Ebooks and some audiobooks have no narrator, keep those under the author
+            let group = match grouping {
+                LibraryGrouping::Narrator => meta.narrators.first().unwrap_or(author),
+                _ => author,
+            };
+            dir.push(names.clean(group));
+            if let Some(series) = series {
+                dir.push(names.clean(&series.name));
+            }
+        }
+        LibraryGrouping::Series => {
+            dir.push(names.clean(series.map_or(author, |s| &s.name)));
+        }
+        LibraryGrouping::None => {}
+    }
+    let prefix = match series {
+        Some(series) if !series.entries.0.is_empty() => {
+            format!("{} #{} - ", series.name, series.entries)
+        }
+        _ => String::new(),
     };
+    let mut suffix = String::new();
     if let Some((edition, _)) = &meta.edition {
-        dir.set_file_name(
-            sanitize_filename::sanitize(format!(
-                "{}, {}",
-                dir.file_name().unwrap().to_string_lossy(),
-                edition
-            ))
-            .to_string(),
-        );
+        suffix.push_str(&format!(", {edition}"));
     }
     if let Some(narrator) = meta.narrators.first()
         && !exclude_narrator_in_library_dir
+        && grouping != LibraryGrouping::Narrator
     {
-        dir.set_file_name(
-            sanitize_filename::sanitize(format!(
-                "{} {{{}}}",
-                dir.file_name().unwrap().to_string_lossy(),
-                narrator
-            ))
-            .to_string(),
-        );
+        suffix.push_str(&format!(" {{{narrator}}}"));
     }
+    dir.push(names.fit(&prefix, &meta.title, &suffix));
     let dir = library.library_dir().join(dir);
     Some(dir)
 }
 
+fn render_path_template(
+    template: &PathTemplate,
+    names: &NameRules,
+    exclude_narrator: bool,
+    author: &str,
+    meta: &TorrentMeta,
+) -> Option<PathBuf> {
+    let series = meta
+        .series
+        .iter()
+        .find(|s| !s.entries.0.is_empty())
+        .or(meta.series.first());
+    let value = |token: &PathTemplateToken| -> Option<String> {
+        let value = match token {
+            PathTemplateToken::Text(_) => return None,
+            PathTemplateToken::Author => author.to_string(),
+            PathTemplateToken::Series => series.map(|s| s.name.clone())?,
+            PathTemplateToken::SeriesIndex => series.map(|s| s.entries.to_string())?,
+            PathTemplateToken::Title => meta.title.clone(),
+            PathTemplateToken::Edition => meta.edition.as_ref().map(|(e, _)| e.clone())?,
+            PathTemplateToken::Narrator if exclude_narrator => return None,
+            PathTemplateToken::Narrator => meta.narrators.first()?.clone(),
+        };
+        Some(value).filter(|v| !v.trim().is_empty())
+    };
+
+    let mut dir = PathBuf::new();
+    for segment in &template.segments {
+        let values = segment.iter().map(value).collect::<Vec<_>>();
+        let mut rendered = String::new();
+        for (i, token) in segment.iter().enumerate() {
+            match token {
+                // Separators next to a missing value are dropped so that e.g. " - "
+                // or " ()" doesn't linger around a token that collapsed
+                PathTemplateToken::Text(text) => {
+                    const CLOSING: [char; 3] = [')', ']', '}'];
+                    let mut text = text.as_str();
+                    if i > 0 && values[i - 1].is_none() {
+                        text = text.trim_start_matches(CLOSING);
+                        if values[..i].iter().all(Option::is_none) {
+                            text = "";
+                        }
+                    }
+                    if i + 1 < segment.len() && values[i + 1].is_none() {
+                        let closing = text.len() - text.trim_start_matches(CLOSING).len();
+                        text = &text[..closing];
+                    }
+                    rendered.push_str(text);
+                }
+                _ => {
+                    if let Some(value) = &values[i] {
+                        rendered.push_str(value);
+                    }
+                }
+            }
+        }
+        let rendered = names.clean(rendered.trim());
+        let rendered = rendered.trim();
+        if !rendered.is_empty() {
+            dir.push(rendered);
+        }
+    }
+
+    if dir.as_os_str().is_empty() {
+        None
+    } else {
+        Some(dir)
+    }
+}
+
+/// How names of library files and folders are cleaned up, see `filename_replacement`
+/// and `max_filename_len` on the library config
+#[derive(Default)]
+struct NameRules {
+    replacements: Vec<(String, String)>,
+    max_len: Option<usize>,
+}
+
+impl NameRules {
+    fn new(library: &Library) -> Self {
+        let filters = library.tag_filters();
+        NameRules {
+            replacements: filters
+                .filename_replacement
+                .iter()
+                .map(|(from, to)| (from.clone(), to.clone()))
+                .collect(),
+            max_len: filters.max_filename_len,
+        }
+    }
+
+    fn replace(&self, name: &str) -> String {
+        let mut name = name.to_string();
+        for (from, to) in &self.replacements {
+            name = name.replace(from.as_str(), to);
+        }
+        name
+    }
+
+    /// A safe folder name, cut at the end if it is too long
+    fn clean(&self, name: &str) -> String {
+        let name = sanitize_filename::sanitize(self.replace(name));
+        match self.max_len {
+            Some(max_len) => truncate_name(&name, max_len).trim_end().to_string(),
+            None => name,
+        }
+    }
+
+    /// A safe "{prefix}{title}{suffix}" folder name, shortening the title to
+    /// keep series and narrator when the name is too long
+    fn fit(&self, prefix: &str, title: &str, suffix: &str) -> String {
+        let name = sanitize_filename::sanitize(self.replace(&format!("{prefix}{title}{suffix}")));
+        let Some(max_len) = self.max_len else {
+            return name;
+        };
+        if name.len() <= max_len {
+            return name;
+        }
+        let title = self.replace(title);
+        let title = truncate_name(&title, title.len().saturating_sub(name.len() - max_len));
+        let name = sanitize_filename::sanitize(
+            self.replace(&format!("{prefix}{}{suffix}", title.trim_end())),
+        );
+        truncate_name(&name, max_len).trim_end().to_string()
+    }
+
+    /// A file name with replacements applied and `extra` added before the
+    /// extension, shortening the name but keeping the extension when it is too long
+    fn file_name(&self, name: &str, extra: &str) -> String {
+        let name = self.replace(name);
+        let (stem, ext) = split_extension(&name);
+        let stem = match self.max_len {
+            Some(max_len) => {
+                let short = truncate_name(stem, max_len.saturating_sub(extra.len() + ext.len()));
+                if short.len() < stem.len() {
+                    short.trim_end()
+                } else {
+                    short
+                }
+            }
+            None => stem,
+        };
+        format!("{stem}{extra}{ext}")
+    }
+
+    /// Like `file_name` but adds a counter if the name is already taken, as
+    /// shortening can make different files end up with the same name
+    fn unique_file_name(&self, name: &str, is_taken: impl Fn(&str) -> bool) -> String {
+        std::iter::once(String::new())
+            .chain((2..).map(|i| format!(" ({i})")))
+            .map(|extra| self.file_name(name, &extra))
+            .find(|file_name| !is_taken(file_name))
+            .unwrap()
+    }
+}
+
+/// Splits "name.ext" into ("name", ".ext"), extensions longer than a few
+/// characters are assumed to be part of the name
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(i) if i > 0 && name.len() - i <= 6 => name.split_at(i),
+        _ => (name, ""),
+    }
+}
+
+/// Cuts a name down to at most `max_len` bytes without splitting a character
+fn truncate_name(name: &str, max_len: usize) -> &str {
+    if name.len() <= max_len {
+        return name;
+    }
+    let mut end = max_len;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+/// The audio and ebook format to link from the files of a torrent, files the
+/// library ignores are left out so they can't decide the format
+fn select_formats(
+    config: &Config,
+    library: &Library,
+    files: &[&str],
+) -> (Option<String>, Option<String>) {
+    let filters = library.tag_filters();
+    let files = files
+        .iter()
+        .copied()
+        .filter(|name| !filters.is_ignored_file(name))
+        .collect::<Vec<_>>();
+    (
+        select_format(&filters.audio_types, &config.audio_types, &files),
+        select_format(&filters.ebook_types, &config.ebook_types, &files),
+    )
+}
+
 fn select_format(
     overridden_wanted_formats: &Option<Vec<String>>,
     wanted_formats: &[String],
-    files: &[TorrentContent],
+    files: &[&str],
 ) -> Option<String> {
     overridden_wanted_formats
         .as_deref()
@@ -694,7 +1281,21 @@ fn select_format(
                 format!(".{ext}")
             }
         })
-        .find(|ext| files.iter().any(|f| f.name.to_lowercase().ends_with(ext)))
+        .find(|ext| files.iter().any(|f| f.to_lowercase().ends_with(ext)))
+}
+
+/// Whether a file of a torrent is linked into the library, it has to be in
+/// one of the selected formats and not be ignored by the library
+fn is_linked_file(
+    library: &Library,
+    selected_audio_format: &Option<String>,
+    selected_ebook_format: &Option<String>,
+    name: &str,
+) -> bool {
+    let is_selected =
+        |format: &Option<String>| format.as_ref().is_some_and(|ext| name.ends_with(ext));
+    (is_selected(selected_audio_format) || is_selected(selected_ebook_format))
+        && !library.tag_filters().is_ignored_file(name)
 }
 
 #[instrument(skip_all)]
@@ -732,6 +1333,45 @@ fn hard_link(download_path: &Path, library_path: &Path, file_path: &Path) -> Res
     Ok(())
 }
 
+/// Hardlinks a file, or uses the library's `hardlink_fallback` when the
+/// download and library are on different filesystems
+fn hard_link_with_fallback(
+    library: &Library,
+    hard_link: impl FnOnce() -> Result<()>,
+    fallback: impl FnOnce(HardlinkFallback) -> Result<()>,
+) -> Result<()> {
+    let Err(err) = hard_link() else {
+        return Ok(());
+    };
+    if !is_cross_device(&err) {
+        return Err(err);
+    }
+    let Some(method) = library.tag_filters().hardlink_fallback else {
+        return Err(err.context(format!(
+            "{} is on another filesystem than the download, set hardlink_fallback = \"copy\" on the library to copy files instead",
+            library.library_dir().display()
+        )));
+    };
+    if CROSS_DEVICE_WARNED
+        .lock()
+        .unwrap()
+        .insert(library.library_dir().clone())
+    {
+        warn!(
+            "Can not hardlink into {} from another filesystem, falling back to {method:?}",
+            library.library_dir().display()
+        );
+    }
+    fallback(method)
+}
+
+/// Whether a link failed because the two paths are on different filesystems,
+/// EXDEV on unix and ERROR_NOT_SAME_DEVICE on Windows
+fn is_cross_device(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .is_some_and(|err| err.kind() == ErrorKind::CrossesDevices)
+}
+
 #[instrument(skip_all)]
 fn copy(download_path: &Path, library_path: &Path) -> Result<()> {
     debug!("copying: {:?} -> {:?}", download_path, library_path);
@@ -856,7 +1496,621 @@ pub fn file_size(m: &Metadata) -> u64 {
 
 #[cfg(test)]
 mod tests {
+    use mlm_db::{Category, ErroredTorrent, MediaType, Series, SeriesEntries, SeriesEntry};
+
     use super::*;
+    use crate::test_helpers::{library_torrent, template_meta};
+
+    fn render(template: &str, meta: &TorrentMeta) -> Option<PathBuf> {
+        let template: PathTemplate = template.parse().unwrap();
+        render_path_template(
+            &template,
+            &NameRules::default(),
+            false,
+            &meta.authors[0],
+            meta,
+        )
+    }
+
+    #[test]
+    fn test_path_template_full() {
+        let mut meta = template_meta();
+        meta.series = vec![Series {
+            name: "The Series".to_string(),
+            entries: SeriesEntries::new(vec![SeriesEntry::Num(2.0)]),
+        }];
+        meta.edition = Some(("2nd edition".to_string(), 2));
+        meta.narrators = vec!["Narrator Name".to_string()];
+        assert_eq!(
+            render(
+                "{author}/{series}/{series_index} - {title} ({edition}) {{{narrator}}}",
+                &meta
+            ),
+            Some(PathBuf::from(
+                "Author Name/The Series/2 - The Title (2nd edition) {Narrator Name}"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_path_template_missing_series() {
+        let meta = template_meta();
+        assert_eq!(
+            render("{author}/{series}/{series_index} - {title}", &meta),
+            Some(PathBuf::from("Author Name/The Title"))
+        );
+        assert_eq!(
+            render("{author}/{series} #{series_index} - {title}", &meta),
+            Some(PathBuf::from("Author Name/The Title"))
+        );
+    }
+
+    #[test]
+    fn test_path_template_missing_optional_tokens() {
+        let mut meta = template_meta();
+        meta.series = vec![Series {
+            name: "The Series".to_string(),
+            entries: SeriesEntries::new(vec![]),
+        }];
+        assert_eq!(
+            render(
+                "{author}/{series} #{series_index} - {title} ({edition}) [{narrator}]",
+                &meta
+            ),
+            Some(PathBuf::from("Author Name/The Series - The Title"))
+        );
+        assert_eq!(
+            render("{author}/{title} ({edition}) {narrator}", &meta),
+            Some(PathBuf::from("Author Name/The Title"))
+        );
+    }
+
+    #[test]
+    fn test_path_template_illegal_characters() {
+        let mut meta = template_meta();
+        meta.title = "What/Why: A <Story>?".to_string();
+        meta.authors = vec!["AC/DC".to_string()];
+        assert_eq!(
+            render("{author}/{title}", &meta),
+            Some(PathBuf::from("ACDC/WhatWhy A Story"))
+        );
+    }
+
+    #[test]
+    fn test_path_template_exclude_narrator() {
+        let mut meta = template_meta();
+        meta.narrators = vec!["Narrator Name".to_string()];
+        let template: PathTemplate = "{author}/{title} {{{narrator}}}".parse().unwrap();
+        assert_eq!(
+            render_path_template(
+                &template,
+                &NameRules::default(),
+                true,
+                &meta.authors[0],
+                &meta,
+            ),
+            Some(PathBuf::from("Author Name/The Title"))
+        );
+    }
+
+    #[test]
+    fn test_path_template_invalid() {
+        assert!("{author}/{titel}".parse::<PathTemplate>().is_err());
+        assert!("{author}/{title".parse::<PathTemplate>().is_err());
+        assert!("{author}/{series}".parse::<PathTemplate>().is_err());
+    }
+
+    fn grouped_library(grouping: &str) -> Library {
+        toml::from_str(&format!(
+            r#"
+            download_dir = "/downloads"
+            library_dir = "/library"
+            grouping = "{grouping}"
+            "#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_grouping_author() {
+        let library = grouped_library("author");
+        let mut meta = template_meta();
+        meta.narrators = vec!["First Narrator".to_string(), "Second Narrator".to_string()];
+        assert_eq!(
+            library_dir(false, &library, &meta),
+            Some(PathBuf::from(
+                "/library/Author Name/The Title {First Narrator}"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_grouping_narrator() {
+        let library = grouped_library("narrator");
+        let mut meta = template_meta();
+        meta.narrators = vec!["First Narrator".to_string(), "Second Narrator".to_string()];
+        meta.series = vec![Series {
+            name: "Series".to_string(),
+            entries: SeriesEntries::new(vec![SeriesEntry::Num(2.0)]),
+        }];
+        // Grouped under the first narrator, without repeating it in the book folder
+        assert_eq!(
+            library_dir(false, &library, &meta),
+            Some(PathBuf::from(
+                "/library/First Narrator/Series/Series #2 - The Title"
+            ))
+        );
+
+        meta.narrators = vec![];
+        assert_eq!(
+            library_dir(false, &library, &meta),
+            Some(PathBuf::from(
+                "/library/Author Name/Series/Series #2 - The Title"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_grouping_series_and_none() {
+        let mut meta = template_meta();
+        meta.series = vec![Series {
+            name: "Series".to_string(),
+            entries: SeriesEntries::new(vec![SeriesEntry::Num(2.0)]),
+        }];
+        assert_eq!(
+            library_dir(true, &grouped_library("series"), &meta),
+            Some(PathBuf::from("/library/Series/Series #2 - The Title"))
+        );
+        assert_eq!(
+            library_dir(true, &grouped_library("none"), &meta),
+            Some(PathBuf::from("/library/Series #2 - The Title"))
+        );
+
+        meta.series = vec![];
+        assert_eq!(
+            library_dir(true, &grouped_library("series"), &meta),
+            Some(PathBuf::from("/library/Author Name/The Title"))
+        );
+    }
+
+    fn named_library(options: &str) -> Library {
+        toml::from_str(&format!(
+            r#"
+            download_dir = "/downloads"
+            library_dir = "/library"
+            {options}
+            "#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_exclude_narrator_per_library() {
+        let config: Config = toml::from_str(
+            r#"
+            mam_id = "abc"
+            exclude_narrator_in_library_dir = true
+            "#,
+        )
+        .unwrap();
+        let mut meta = template_meta();
+        meta.narrators = vec!["Narrator Name".to_string()];
+
+        let library = named_library("");
+        let exclude = library.exclude_narrator_in_library_dir(&config);
+        assert!(exclude);
+        assert_eq!(
+            library_dir(exclude, &library, &meta),
+            Some(PathBuf::from("/library/Author Name/The Title"))
+        );
+
+        // The library's setting wins over the global one
+        let library = named_library("exclude_narrator_in_library_dir = false");
+        let exclude = library.exclude_narrator_in_library_dir(&config);
+        assert!(!exclude);
+        assert_eq!(
+            library_dir(exclude, &library, &meta),
+            Some(PathBuf::from(
+                "/library/Author Name/The Title {Narrator Name}"
+            ))
+        );
+
+        // Ebooks never get a narrator in their folder
+        meta.media_type = MediaType::Ebook;
+        for exclude in [false, true] {
+            assert_eq!(
+                library_dir(exclude, &library, &meta),
+                Some(PathBuf::from("/library/Author Name/The Title"))
+            );
+        }
+        let library = named_library(r#"path_template = "{author}/{title} ({narrator})""#);
+        assert_eq!(
+            library_dir(false, &library, &meta),
+            Some(PathBuf::from("/library/Author Name/The Title"))
+        );
+    }
+
+    #[test]
+    fn test_ignore_file_patterns() {
+        let config: Config = toml::from_str(r#"mam_id = "abc""#).unwrap();
+        let library = named_library("");
+        let files = [
+            "Book/Bonus/Book - Sample.m4b",
+            "Book/__MACOSX/Book/._Book 01.mp3",
+            "Book/Book 01.mp3",
+            "Book/Book 02.mp3",
+            "Book/Sample Chapters.pdf",
+        ];
+
+        // The sample is in the most wanted format but doesn't decide it
+        let (audio, ebook) = select_formats(&config, &library, &files);
+        assert_eq!(audio.as_deref(), Some(".mp3"));
+        assert_eq!(ebook.as_deref(), Some(".pdf"));
+        let linked = files
+            .iter()
+            .filter(|name| is_linked_file(&library, &audio, &ebook, name))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            linked,
+            [
+                &"Book/Book 01.mp3",
+                &"Book/Book 02.mp3",
+                &"Book/Sample Chapters.pdf"
+            ]
+        );
+
+        let library = named_library(r#"ignore_file_patterns = ["bonus", "*.pdf"]"#);
+        let (audio, ebook) = select_formats(&config, &library, &files);
+        assert_eq!(audio.as_deref(), Some(".mp3"));
+        assert_eq!(ebook, None);
+        assert!(is_linked_file(
+            &library,
+            &audio,
+            &ebook,
+            "Book/__MACOSX/Book/._Book 01.mp3"
+        ));
+        assert!(!is_linked_file(
+            &library,
+            &Some(".m4b".to_string()),
+            &ebook,
+            "Book/Bonus/Book - Sample.m4b"
+        ));
+    }
+
+    #[test]
+    fn test_library_mismatch() {
+        let config: Config = toml::from_str(r#"mam_id = "abc""#).unwrap();
+        let meta = template_meta();
+        let library = named_library("");
+        let library_path = PathBuf::from("/library/Author Name/The Title");
+        assert_eq!(
+            library_mismatch(&config, Some(&library), &library_path, &meta),
+            None
+        );
+
+        let library = named_library(r#"path_template = "{title}""#);
+        assert_eq!(
+            library_mismatch(&config, Some(&library), &library_path, &meta),
+            Some(LibraryMismatch::NewPath(PathBuf::from(
+                "/library/The Title"
+            )))
+        );
+        assert_eq!(
+            library_mismatch(
+                &config,
+                Some(&library),
+                Path::new("/other/Author Name/The Title"),
+                &meta
+            ),
+            Some(LibraryMismatch::NewLibraryDir(PathBuf::from("/library")))
+        );
+        assert_eq!(
+            library_mismatch(&config, None, &library_path, &meta),
+            Some(LibraryMismatch::NoLibrary)
+        );
+    }
+
+    #[test]
+    fn test_library_mismatch_ebook_with_narrator() {
+        let config: Config = toml::from_str(r#"mam_id = "abc""#).unwrap();
+        let mut meta = template_meta();
+        meta.media_type = MediaType::Ebook;
+        meta.narrators = vec!["Narrator Name".to_string()];
+        // Linked before ebooks stopped getting a narrator in their folder
+        let old_path = PathBuf::from("/library/Author Name/The Title {Narrator Name}");
+        for exclude in ["true", "false"] {
+            let library = named_library(&format!("exclude_narrator_in_library_dir = {exclude}"));
+            assert_eq!(
+                library_mismatch(&config, Some(&library), &old_path, &meta),
+                None
+            );
+            assert_eq!(
+                library_mismatch(
+                    &config,
+                    Some(&library),
+                    Path::new("/library/Author Name/The Title"),
+                    &meta
+                ),
+                None
+            );
+        }
+
+        let library = named_library("");
+        assert_eq!(
+            library_mismatch(
+                &config,
+                Some(&library),
+                Path::new("/library/Author Name/The Title {Other Narrator}"),
+                &meta
+            ),
+            Some(LibraryMismatch::NewPath(PathBuf::from(
+                "/library/Author Name/The Title"
+            )))
+        );
+    }
+
+    fn route<'a>(
+        config: &'a Config,
+        tags: &str,
+        meta: Option<&TorrentMeta>,
+    ) -> Option<&'a Library> {
+        find_library_for(config, Path::new("/downloads"), "", tags, meta)
+    }
+
+    fn routed<'a>(config: &'a Config, tags: &str, meta: Option<&TorrentMeta>) -> Option<&'a str> {
+        route(config, tags, meta).and_then(|l| l.tag_filters().name.as_deref())
+    }
+
+    #[test]
+    fn test_library_filter_series() {
+        let config: Config = toml::from_str(
+            r#"
+            mam_id = "abc"
+
+            [[library]]
+            name = "discworld"
+            download_dir = "/downloads"
+            library_dir = "/library/discworld"
+            filter = { series = ["discworld"] }
+
+            [[library]]
+            name = "litrpg"
+            download_dir = "/downloads"
+            library_dir = "/library/litrpg"
+            filter = { categories = ["LitRPG"] }
+            "#,
+        )
+        .unwrap();
+        let mut meta = template_meta();
+        assert_eq!(routed(&config, "", Some(&meta)), None);
+        // Without metadata the filters can not be checked
+        assert_eq!(routed(&config, "", None), Some("discworld"));
+
+        meta.categories = vec![Category::LitRPG];
+        assert_eq!(routed(&config, "", Some(&meta)), Some("litrpg"));
+
+        // The first library that matches wins
+        meta.series = vec![Series {
+            name: "Discworld".to_string(),
+            entries: SeriesEntries::new(vec![SeriesEntry::Num(1.0)]),
+        }];
+        assert_eq!(routed(&config, "", Some(&meta)), Some("discworld"));
+
+        let library = route(&config, "", Some(&meta));
+        assert_eq!(
+            library_mismatch(
+                &config,
+                library,
+                Path::new("/library/other/Author Name/The Title"),
+                &meta
+            ),
+            Some(LibraryMismatch::NewLibraryDir(PathBuf::from(
+                "/library/discworld"
+            )))
+        );
+        meta.series = vec![];
+        meta.categories = vec![];
+        let library = route(&config, "", Some(&meta));
+        assert_eq!(
+            library_mismatch(
+                &config,
+                library,
+                Path::new("/library/discworld/Author Name/The Title"),
+                &meta
+            ),
+            Some(LibraryMismatch::NoLibrary)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_require_fields() {
+        let library = named_library(r#"require_fields = ["author", "series"]"#);
+        let mut meta = template_meta();
+        meta.authors = vec![];
+        let missing = library.tag_filters().missing_fields(&meta);
+        assert_eq!(missing, [RequiredField::Author, RequiredField::Series]);
+        assert!(
+            named_library("")
+                .tag_filters()
+                .missing_fields(&meta)
+                .is_empty()
+        );
+
+        let config: Config = toml::from_str(r#"mam_id = "abc""#).unwrap();
+        let db = native_db::Builder::new()
+            .create_in_memory(&mlm_db::MODELS)
+            .unwrap();
+        {
+            let (_guard, rw) = db.rw_async().await.unwrap();
+            rw.upsert(library_torrent("abc", meta.clone())).unwrap();
+            rw.commit().unwrap();
+        }
+        let error_id = ErroredTorrentId::Linker("abc".to_string());
+        for _ in 0..2 {
+            wait_for_metadata(
+                &config,
+                &db,
+                error_id.clone(),
+                "abc",
+                meta.title.clone(),
+                meta.clone(),
+                &missing,
+            )
+            .await
+            .unwrap();
+        }
+
+        let r = db.r_transaction().unwrap();
+        let error = r
+            .get()
+            .primary::<ErroredTorrent>(error_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            error.error,
+            "Waiting for metadata before linking, missing author, series"
+        );
+        let torrent = r
+            .get()
+            .primary::<Torrent>("abc".to_string())
+            .unwrap()
+            .unwrap();
+        assert!(torrent.request_matadata_update);
+        // Waiting again for the same fields is not a new event
+        let events = r
+            .scan()
+            .primary::<Event>()
+            .unwrap()
+            .all()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let [event] = events.as_slice() else {
+            panic!("expected one event, got {events:?}");
+        };
+        assert_eq!(event.mam_id, Some(meta.mam_id));
+        assert!(matches!(
+            &event.event,
+            EventType::MetadataPending { missing } if missing == &["author", "series"]
+        ));
+    }
+
+    #[test]
+    fn test_hardlink_fallback() {
+        let dir = std::env::temp_dir().join(format!("mlm-hardlink-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let download_path = dir.join("download.m4b");
+        let library_path = dir.join("library.m4b");
+        fs::write(&download_path, "audio").unwrap();
+        let cross_device = || Err(std::io::Error::from(ErrorKind::CrossesDevices).into());
+
+        let library = named_library(r#"hardlink_fallback = "copy""#);
+        hard_link_with_fallback(&library, cross_device, |fallback| {
+            assert_eq!(fallback, HardlinkFallback::Copy);
+            copy(&download_path, &library_path)
+        })
+        .unwrap();
+        assert_eq!(fs::read_to_string(&library_path).unwrap(), "audio");
+
+        // Without a fallback it still fails, with a hint about the option
+        let err = hard_link_with_fallback(&named_library(""), cross_device, |_| unreachable!())
+            .unwrap_err();
+        assert!(is_cross_device(&err));
+        assert!(err.to_string().contains("hardlink_fallback"), "{err}");
+
+        // Other errors don't fall back
+        let denied = || Err(std::io::Error::from(ErrorKind::PermissionDenied).into());
+        let err = hard_link_with_fallback(&library, denied, |_| unreachable!()).unwrap_err();
+        assert!(!is_cross_device(&err));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_library_filter_tags() {
+        let config: Config = toml::from_str(
+            r#"
+            mam_id = "abc"
+
+            [[library]]
+            name = "kids"
+            download_dir = "/downloads"
+            library_dir = "/library/kids"
+            allow_tags = ["kids"]
+            filter = { authors = ["author name"] }
+
+            [[library]]
+            name = "main"
+            download_dir = "/downloads"
+            library_dir = "/library/main"
+            deny_tags = ["skip"]
+            "#,
+        )
+        .unwrap();
+        let meta = template_meta();
+        assert_eq!(routed(&config, "kids", Some(&meta)), Some("kids"));
+        assert_eq!(routed(&config, "other, kids", Some(&meta)), Some("kids"));
+        assert_eq!(routed(&config, "", Some(&meta)), Some("main"));
+        assert_eq!(routed(&config, "skip", Some(&meta)), None);
+
+        let mut other = template_meta();
+        other.authors = vec!["Someone Else".to_string()];
+        assert_eq!(routed(&config, "kids", Some(&other)), Some("main"));
+        other.original_authors = Some(vec!["Author Name".to_string()]);
+        assert_eq!(routed(&config, "kids", Some(&other)), Some("kids"));
+    }
+
+    #[test]
+    fn test_filename_replacement() {
+        let library = named_library(r#"filename_replacement = { ":" = " -", "?" = "" }"#);
+        let mut meta = template_meta();
+        meta.title = "Dune: Messiah?".to_string();
+        meta.authors = vec!["Author: Name".to_string()];
+        assert_eq!(
+            library_dir(true, &library, &meta),
+            Some(PathBuf::from("/library/Author - Name/Dune - Messiah"))
+        );
+        let names = NameRules::new(&library);
+        assert_eq!(
+            names.file_name("Dune: Messiah - Part 1.mp3", ""),
+            "Dune - Messiah - Part 1.mp3"
+        );
+    }
+
+    #[test]
+    fn test_max_filename_len() {
+        let library = named_library("max_filename_len = 40");
+        let mut meta = template_meta();
+        meta.title = "A Very Long Title That Goes On And On Forever".to_string();
+        meta.narrators = vec!["Narrator".to_string()];
+        meta.series = vec![Series {
+            name: "Series".to_string(),
+            entries: SeriesEntries::new(vec![SeriesEntry::Num(2.0)]),
+        }];
+        // The title is shortened, series and narrator are kept
+        let dir = library_dir(false, &library, &meta).unwrap();
+        let book_dir = dir.file_name().unwrap().to_string_lossy().to_string();
+        assert_eq!(book_dir, "Series #2 - A Very Long Title {Narrator}");
+        assert!(book_dir.len() <= 40);
+
+        let names = NameRules::new(&library);
+        let long_name = format!("{} - Chapter 1.m4b", "x".repeat(60));
+        let short_name = names.file_name(&long_name, "");
+        assert_eq!(short_name.len(), 40);
+        assert!(short_name.ends_with(".m4b"));
+
+        // Files that only differ after the cut stay unique
+        let mut taken = vec![];
+        for chapter in 1..=3 {
+            let name = format!("{} - Chapter {chapter}.m4b", "x".repeat(60));
+            let name = names.unique_file_name(&name, |name| taken.contains(&name.to_string()));
+            assert!(name.len() <= 40);
+            assert!(name.ends_with(".m4b"));
+            taken.push(name);
+        }
+        assert_eq!(taken[1], format!("{} (2).m4b", "x".repeat(32)));
+        assert_eq!(taken[2], format!("{} (3).m4b", "x".repeat(32)));
+    }
 
     #[test]
     fn test_map_path() {
@@ -944,4 +2198,126 @@ mod tests {
         let path = PathBuf::from("/tmp/test/file.m4b");
         assert_eq!(windows_fs_path(&path), path);
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_convert_ebooks() {
+        let dir = std::env::temp_dir().join(format!("mlm-convert-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Book.azw3"), b"ebook").unwrap();
+        fs::write(dir.join("Big.azw3"), vec![0; 2048]).unwrap();
+        fs::write(dir.join("cover.jpg"), b"image").unwrap();
+        let library = named_library(
+            r#"
+            convert_to = "epub"
+            convert_command = ["sh", "-c", "cp \"$1\" \"$2\"", "sh", "{input}", "{output}"]
+            convert_max_size = "1 KiB"
+            "#,
+        );
+        let library_files = vec![
+            PathBuf::from("Big.azw3"),
+            PathBuf::from("Book.azw3"),
+            PathBuf::from("cover.jpg"),
+        ];
+
+        let converted = convert_ebooks(&library, &dir, ".azw3", "epub", &library_files).await;
+        assert_eq!(
+            converted,
+            vec![(PathBuf::from("Book.azw3"), PathBuf::from("Book.epub"))]
+        );
+        assert_eq!(fs::read(dir.join("Book.epub")).unwrap(), b"ebook");
+        assert!(!dir.join("Big.epub").exists());
+
+        // Already in the wanted format
+        let converted = convert_ebooks(&library, &dir, ".epub", "epub", &library_files).await;
+        assert!(converted.is_empty());
+
+        // A missing converter leaves the book unconverted
+        fs::remove_file(dir.join("Book.epub")).unwrap();
+        let library = named_library(
+            r#"
+            convert_to = "epub"
+            convert_command = ["mlm-no-such-converter", "{input}", "{output}"]
+            "#,
+        );
+        let converted = convert_ebooks(&library, &dir, ".azw3", "epub", &library_files).await;
+        assert!(converted.is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_link_imported_torrent() {
+        let dir = std::env::temp_dir().join(format!("mlm-link-imported-{}", std::process::id()));
+        let download_dir = dir.join("downloads");
+        let library_dir = dir.join("library");
+        fs::create_dir_all(download_dir.join("Project Hail Mary")).unwrap();
+        let files = vec![
+            "Project Hail Mary/01.mp3".to_string(),
+            "Project Hail Mary/02.mp3".to_string(),
+        ];
+        for file in &files {
+            fs::write(download_dir.join(file), b"audio").unwrap();
+        }
+        let config: Config = toml::from_str(r#"mam_id = "abc""#).unwrap();
+        let library: Library = toml::from_str(&format!(
+            r#"
+            download_dir = {download_dir:?}
+            library_dir = {library_dir:?}
+            method = "copy"
+            "#
+        ))
+        .unwrap();
+        let db = native_db::Builder::new()
+            .create_in_memory(&mlm_db::MODELS)
+            .unwrap();
+        // As --import-qbit imports a torrent MaM doesn't know
+        let meta = crate::qbittorrent::file_meta(
+            &config,
+            "Andy Weir - Project Hail Mary",
+            &files,
+            abs::IMPORTED_MAM_ID_START,
+        )
+        .unwrap();
+        let imported = library_torrent("local", meta);
+        {
+            let (_guard, rw) = db.rw_async().await.unwrap();
+            rw.upsert(imported.clone()).unwrap();
+            rw.commit().unwrap();
+        }
+
+        let names = files.iter().map(String::as_str).collect::<Vec<_>>();
+        let (audio, ebook) = select_formats(&config, &library, &names);
+        link_torrent(
+            &config,
+            &db,
+            "local",
+            download_dir,
+            "Audiobooks",
+            files,
+            audio,
+            ebook,
+            &library,
+            None,
+            Some(&imported),
+            &imported.meta,
+        )
+        .await
+        .unwrap();
+
+        let torrent = db
+            .r_transaction()
+            .unwrap()
+            .get()
+            .primary::<Torrent>("local".to_string())
+            .unwrap()
+            .unwrap();
+        let library_path = torrent.library_path.unwrap();
+        assert!(library_path.starts_with(&library_dir), "{library_path:?}");
+        assert_eq!(torrent.library_files.len(), 2);
+        assert!(library_path.join(&torrent.library_files[0]).exists());
+        assert_eq!(torrent.meta.source, MetadataSource::File);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
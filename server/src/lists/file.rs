@@ -0,0 +1,246 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use mlm_db::{DatabaseExt as _, List, ListItem, OldDbMainCat, Timestamp, Torrent};
+use mlm_mam::api::MaM;
+use native_db::Database;
+use tokio::{fs, time::sleep};
+use tracing::{debug, instrument, trace};
+
+use crate::{
+    config::{Config, FileList},
+    lists::{ListGrabber, search_item},
+    stats::{ProgressReporter, ProgressTask, Stats},
+};
+
+static IMPORT_MUTEX: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[instrument(skip_all)]
+pub async fn run_file_import(
+    config: Arc<Config>,
+    db: Arc<Database<'_>>,
+    mam: Arc<MaM<'_>>,
+    list: &FileList,
+    max_torrents: u64,
+    stats: &Stats,
+    index: usize,
+) -> Result<Vec<(ListItem, Torrent)>> {
+    // Make sure we are only running one import at a time
+    let _guard = IMPORT_MUTEX.lock().await;
+    let mut satisfied = vec![];
+
+    let content = fs::read_to_string(&list.file)
+        .await
+        .with_context(|| format!("read {}", list.file.display()))?;
+    let books = parse_wishlist(&content);
+    debug!(
+        "file list {}, {} books in file",
+        list.file.display(),
+        books.len()
+    );
+
+    let list_id = list.list_id();
+
+    if !list.dry_run {
+        let (_guard, rw) = db.rw_async().await?;
+        rw.upsert(List {
+            id: list_id.clone(),
+            title: list.name.clone().unwrap_or_else(|| {
+                list.file
+                    .file_stem()
+                    .map_or_else(|| list_id.clone(), |stem| stem.to_string_lossy().into())
+            }),
+            updated_at: Some(Timestamp::now()),
+            build_date: Some(Timestamp::now()),
+            etag: None,
+            last_modified: None,
+        })?;
+        rw.commit()?;
+    }
+
+    let grabber = ListGrabber {
+        grab: &list.grab,
+        prefer_format: list.prefer_format,
+        unsat_buffer: list.unsat_buffer,
+        wedge_buffer: list.wedge_buffer,
+        dry_run: list.dry_run,
+        min_score: list.min_score,
+        require_author_match: list.require_author_match,
+        match_min_score: list.match_min_score,
+        match_window: list.match_window,
+        require_series_match: list.require_series_match,
+    };
+
+    let mut progress =
+        ProgressReporter::start(stats, ProgressTask::Import(index), books.len()).await;
+    for book in books {
+        progress.advance().await;
+        let item = book.as_list_item(&list_id, list);
+        let db_item = match db
+            .r_transaction()?
+            .get()
+            .primary::<ListItem>(item.guid.clone())?
+        {
+            Some(mut db_item) => {
+                if db_item.prefer_format != list.prefer_format
+                    || db_item.allow_audio != list.allow_audio()
+                    || db_item.allow_ebook != list.allow_ebook()
+                {
+                    db_item.prefer_format = list.prefer_format;
+                    db_item.allow_audio = list.allow_audio();
+                    db_item.allow_ebook = list.allow_ebook();
+                    if !list.dry_run {
+                        let (_guard, rw) = db.rw_async().await?;
+                        rw.upsert(db_item.clone())?;
+                        rw.commit()?;
+                    }
+                }
+                if (db_item.audio_torrent.is_some() && db_item.ebook_torrent.is_some())
+                    || (list.prefer_format == Some(OldDbMainCat::Audio)
+                        && db_item.audio_torrent.is_some())
+                    || (list.prefer_format == Some(OldDbMainCat::Ebook)
+                        && db_item.ebook_torrent.is_some())
+                {
+                    continue;
+                }
+                db_item
+            }
+            None => {
+                if !list.dry_run {
+                    let (_guard, rw) = db.rw_async().await?;
+                    rw.insert(item.clone())?;
+                    rw.commit()?;
+                }
+                item
+            }
+        };
+        trace!("Searching for book {} from file list", db_item.title);
+        search_item(
+            &config,
+            &db,
+            &mam,
+            &grabber,
+            None,
+            db_item,
+            max_torrents,
+            &mut satisfied,
+        )
+        .await
+        .context("search file list book")?;
+        sleep(Duration::from_millis(400)).await;
+    }
+
+    Ok(satisfied)
+}
+
+#[derive(Debug, PartialEq)]
+struct WishlistBook {
+    title: String,
+    authors: Vec<String>,
+}
+
+/// Reads a "Title - Author" or "Title by Author" per line, optionally as a
+/// Markdown list or checklist. Blank lines, `#` comments and headings, checked
+/// items and lines without an author are skipped
+fn parse_wishlist(content: &str) -> Vec<WishlistBook> {
+    content.lines().filter_map(parse_wishlist_line).collect()
+}
+
+fn parse_wishlist_line(line: &str) -> Option<WishlistBook> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let line = ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|bullet| line.strip_prefix(bullet))
+        .unwrap_or(line)
+        .trim_start();
+    if line.starts_with("[x]") || line.starts_with("[X]") {
+        return None;
+    }
+    let line = line.strip_prefix("[ ]").unwrap_or(line).trim();
+
+    let (title, authors) = line
+        .rsplit_once(" - ")
+        .or_else(|| line.rsplit_once(" by "))?;
+    let title = title.trim();
+    let authors = authors
+        .split([',', '&'])
+        .map(str::trim)
+        .filter(|author| !author.is_empty())
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    if title.is_empty() || authors.is_empty() {
+        return None;
+    }
+    Some(WishlistBook {
+        title: title.to_owned(),
+        authors,
+    })
+}
+
+impl WishlistBook {
+    fn as_list_item(&self, list_id: &str, list: &FileList) -> ListItem {
+        ListItem {
+            // The line is the only identity a book has in the file
+            guid: (
+                list_id.to_owned(),
+                format!("{} - {}", self.title, self.authors.join(", ")).to_lowercase(),
+            ),
+            list_id: list_id.to_owned(),
+            title: self.title.clone(),
+            authors: self.authors.clone(),
+            series: vec![],
+            cover_url: String::new(),
+            book_url: None,
+            isbn: None,
+            prefer_format: list.prefer_format,
+            allow_audio: list.allow_audio(),
+            audio_torrent: None,
+            allow_ebook: list.allow_ebook(),
+            ebook_torrent: None,
+            created_at: Timestamp::now(),
+            marked_done_at: None,
+            candidates: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(title: &str, authors: &[&str]) -> WishlistBook {
+        WishlistBook {
+            title: title.to_string(),
+            authors: authors.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_wishlist() {
+        let books = parse_wishlist(
+            "# Wishlist
+
+Leviathan Wakes - James S. A. Corey
+Project Hail Mary by Andy Weir
+- Good Omens - Terry Pratchett & Neil Gaiman
+* [ ] The Fifth Season - N. K. Jemisin
+- [x] Dune - Frank Herbert
+1984
+Spider-Man - Into the Spider-Verse - Stan Lee
+",
+        );
+        assert_eq!(
+            books,
+            vec![
+                book("Leviathan Wakes", &["James S. A. Corey"]),
+                book("Project Hail Mary", &["Andy Weir"]),
+                book("Good Omens", &["Terry Pratchett", "Neil Gaiman"]),
+                book("The Fifth Season", &["N. K. Jemisin"]),
+                book("Spider-Man - Into the Spider-Verse", &["Stan Lee"]),
+            ]
+        );
+    }
+}
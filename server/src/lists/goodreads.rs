@@ -1,30 +1,33 @@
 use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::{Context, Result};
-use mlm_db::{
-    DatabaseExt as _, List, ListItem, ListItemTorrent, OldDbMainCat, OldMainCat, Timestamp,
-    TorrentMeta, TorrentStatus,
-};
-use mlm_mam::{api::MaM, search::MaMTorrent};
+use anyhow::{Context, Result, bail};
+use mlm_db::{DatabaseExt as _, List, ListItem, ListItemKey, OldDbMainCat, Timestamp, Torrent};
+use mlm_mam::{api::MaM, http};
 use mlm_parse::clean_value;
 use native_db::Database;
 use once_cell::sync::Lazy;
-use quick_xml::de::from_reader;
+use quick_xml::{
+    Reader,
+    de::from_str,
+    events::{BytesStart, Event},
+};
 use regex::Regex;
+use reqwest::{StatusCode, header};
 use scraper::{Html, Selector};
-use serde::Deserialize;
+use serde::{Deserialize, de::DeserializeOwned};
 use tokio::time::sleep;
-use tracing::{debug, instrument, trace, warn};
+use tracing::{info, instrument, trace};
 
 use crate::{
-    autograbber::select_torrents,
-    config::{Config, Cost, GoodreadsList, Grab},
-    lists::{search_grab, search_library},
+    config::{Config, GoodreadsList},
+    lists::{ListGrabber, search_item},
+    stats::{ProgressReporter, ProgressTask, Stats},
 };
 
 pub static SERIES_PATTERN: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"(.*?) \(([^)]*?),? #?(\d+(?:\.\d+)?)\)$").unwrap());
+static BOOK_ID_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"/book/show/(\d+)").unwrap());
 
 static IMPORT_MUTEX: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
 
@@ -35,45 +38,118 @@ pub async fn run_goodreads_import(
     mam: Arc<MaM<'_>>,
     list: &GoodreadsList,
     max_torrents: u64,
-) -> Result<()> {
+    stats: &Stats,
+    index: usize,
+) -> Result<Vec<(ListItem, Torrent)>> {
     // Make sure we are only running one import at a time
     let _guard = IMPORT_MUTEX.lock().await;
 
-    let content = reqwest::Client::new()
+    let list_id = list.list_id()?;
+    let db_list = db.r_transaction()?.get().primary::<List>(list_id.clone())?;
+
+    let mut request = http::client()
         .get(&list.url)
-        .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/143.0.0.0 Safari/537.36")
-        .send()
-        .await?
-        .bytes()
-        .await?;
+        .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/143.0.0.0 Safari/537.36");
+    if let Some(db_list) = &db_list {
+        if let Some(etag) = &db_list.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &db_list.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let response = http::send_with_retry(request).await?.error_for_status()?;
+    let mut satisfied = vec![];
 
-    let mut rss: Rss = from_reader(&content[..])?;
-    trace!("Scanning Goodreads list {}", rss.channel.title);
+    let grabber = ListGrabber {
+        grab: &list.grab,
+        prefer_format: list.prefer_format,
+        unsat_buffer: list.unsat_buffer,
+        wedge_buffer: list.wedge_buffer,
+        dry_run: list.dry_run,
+        min_score: list.min_score,
+        require_author_match: list.require_author_match,
+        match_min_score: list.match_min_score,
+        match_window: list.match_window,
+        require_series_match: list.require_series_match,
+    };
 
-    let list_id = list.list_id()?;
+    let not_modified = response.status() == StatusCode::NOT_MODIFIED;
+    let etag = header_value(&response, header::ETAG);
+    let last_modified = header_value(&response, header::LAST_MODIFIED);
+    let content = response.text().await?;
+
+    // An unchanged list searches for its stored books again, otherwise the
+    // feed is read one item at a time as it is processed
+    let (stored_items, feed) = if not_modified {
+        info!(
+            "Goodreads list {} is unchanged, searching for stored books",
+            db_list.as_ref().map_or(&list_id, |l| &l.title)
+        );
+        let items = db
+            .r_transaction()?
+            .scan()
+            .secondary::<ListItem>(ListItemKey::list_id)?
+            .range(list_id.clone()..=list_id.clone())?
+            .collect::<Result<Vec<_>, _>>()?;
+        (items, None)
+    } else {
+        (vec![], Some(RssItems::new(&content)?))
+    };
+    let total = feed
+        .as_ref()
+        .map_or(stored_items.len(), |(_, items)| items.item_count());
+    let mut progress = ProgressReporter::start(stats, ProgressTask::Import(index), total).await;
+
+    let Some((title, items)) = feed else {
+        for db_item in stored_items {
+            progress.advance().await;
+            if is_done(list, &db_item) {
+                continue;
+            }
+            let book_id = db_item.book_url.as_deref().and_then(book_id_from_url);
+            search_book(
+                &config,
+                &db,
+                &mam,
+                &grabber,
+                book_id,
+                db_item,
+                max_torrents,
+                &mut satisfied,
+            )
+            .await?;
+        }
+        return Ok(satisfied);
+    };
+    trace!("Scanning Goodreads list {}", title);
 
+    // The validators are only stored once every item is processed, so a
+    // failed run fetches the whole list again
+    let mut db_list = List {
+        id: list_id.clone(),
+        title,
+        updated_at: Some(Timestamp::now()),
+        // TODO: Parse
+        build_date: Some(Timestamp::now()),
+        etag: None,
+        last_modified: None,
+    };
     if !list.dry_run {
         let (_guard, rw) = db.rw_async().await?;
-        rw.upsert(List {
-            id: list_id.clone(),
-            title: rss.channel.title,
-            updated_at: Some(Timestamp::now()),
-            // TODO: Parse
-            build_date: Some(Timestamp::now()),
-        })?;
+        rw.upsert(db_list.clone())?;
         rw.commit()?;
     }
 
-    for item in rss.channel.items.iter_mut() {
+    for item in items {
+        progress.advance().await;
+        let mut item = item?;
         if let Some((_, [title, series_name, series_num])) =
             SERIES_PATTERN.captures(&item.title).map(|c| c.extract())
         {
             item.series = Some((series_name.to_owned(), series_num.parse()?));
             item.title = title.to_owned();
         }
-    }
-
-    for mut item in rss.channel.items.into_iter() {
         if let Ok(title) = clean_value(&item.title) {
             item.title = title;
         }
@@ -110,18 +186,13 @@ pub async fn run_goodreads_import(
                         rw.commit()?;
                     }
                 }
-                if (db_item.audio_torrent.is_some() && db_item.ebook_torrent.is_some())
-                    || (list.prefer_format == Some(OldDbMainCat::Audio)
-                        && db_item.audio_torrent.is_some())
-                    || (list.prefer_format == Some(OldDbMainCat::Ebook)
-                        && db_item.ebook_torrent.is_some())
-                {
+                if is_done(list, &db_item) {
                     continue;
                 }
                 db_item
             }
             None => {
-                let db_item = item.as_list_item(&list_id, &list);
+                let db_item = item.as_list_item(&list_id, list);
                 if !list.dry_run {
                     let (_guard, rw) = db.rw_async().await?;
                     rw.insert(db_item.clone())?;
@@ -130,167 +201,145 @@ pub async fn run_goodreads_import(
                 db_item
             }
         };
-        trace!("Searching for book {} from Goodreads list", item.title);
-        search_item(&config, &db, &mam, &list, &item, db_item, max_torrents)
-            .await
-            .context("search goodreads book")?;
-        sleep(Duration::from_millis(400)).await;
+        search_book(
+            &config,
+            &db,
+            &mam,
+            &grabber,
+            item.book_id,
+            db_item,
+            max_torrents,
+            &mut satisfied,
+        )
+        .await?;
     }
 
-    Ok(())
+    if !list.dry_run {
+        db_list.etag = etag;
+        db_list.last_modified = last_modified;
+        let (_guard, rw) = db.rw_async().await?;
+        rw.upsert(db_list)?;
+        rw.commit()?;
+    }
+
+    Ok(satisfied)
 }
 
-#[instrument(skip_all)]
-async fn search_item(
+fn is_done(list: &GoodreadsList, db_item: &ListItem) -> bool {
+    (db_item.audio_torrent.is_some() && db_item.ebook_torrent.is_some())
+        || (list.prefer_format == Some(OldDbMainCat::Audio) && db_item.audio_torrent.is_some())
+        || (list.prefer_format == Some(OldDbMainCat::Ebook) && db_item.ebook_torrent.is_some())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn search_book(
     config: &Config,
     db: &Database<'_>,
     mam: &MaM<'_>,
-    list: &GoodreadsList,
-    item: &Item,
-    mut db_item: ListItem,
+    grabber: &ListGrabber<'_>,
+    book_id: Option<u64>,
+    db_item: ListItem,
     max_torrents: u64,
-) -> Result<u64> {
-    if !db_item.want_audio() && !db_item.want_ebook() {
-        return Ok(0);
-    }
+    satisfied: &mut Vec<(ListItem, Torrent)>,
+) -> Result<()> {
+    trace!("Searching for book {} from Goodreads list", db_item.title);
+    search_item(
+        config,
+        db,
+        mam,
+        grabber,
+        book_id,
+        db_item,
+        max_torrents,
+        satisfied,
+    )
+    .await
+    .context("search goodreads book")?;
+    sleep(Duration::from_millis(400)).await;
+    Ok(())
+}
 
-    let has_updates = search_library(config, db, &mut db_item).context("search_library")?;
-    if !list.dry_run && has_updates {
-        let (_guard, rw) = db.rw_async().await?;
-        rw.upsert(db_item.clone())?;
-        rw.commit()?;
-    }
-    if !db_item.want_audio() && !db_item.want_ebook() {
-        return Ok(0);
-    }
+fn header_value(response: &reqwest::Response, name: header::HeaderName) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
 
-    let mut torrents = vec![];
-    for grab in &list.grab {
-        let results = search_grab(config, mam, &db_item, grab)
-            .await
-            .context("search_grab")?;
-        torrents.push(results);
-    }
-    let mut audiobook = select_torrent(&torrents, OldMainCat::Audio);
-    let mut ebook = select_torrent(&torrents, OldMainCat::Ebook);
-
-    let mut has_updates = false;
-    if audiobook.is_some() && ebook.is_some() {
-        match list.prefer_format {
-            Some(OldDbMainCat::Audio) => {
-                let updated = not_wanted(&mut db_item.ebook_torrent, &mut ebook);
-                has_updates = updated || has_updates;
-            }
-            Some(OldDbMainCat::Ebook) => {
-                let updated = not_wanted(&mut db_item.audio_torrent, &mut audiobook);
-                has_updates = updated || has_updates;
+fn book_id_from_url(url: &str) -> Option<u64> {
+    let (_, [id]) = BOOK_ID_PATTERN.captures(url)?.extract();
+    id.parse().ok()
+}
+
+/// Reads the items of a Goodreads RSS feed one at a time, so a large shelf
+/// is never deserialized all at once
+struct RssItems<'a> {
+    content: &'a str,
+    reader: Reader<&'a [u8]>,
+}
+
+impl<'a> RssItems<'a> {
+    /// Reads up to the channel title and returns it with the remaining items
+    fn new(content: &'a str) -> Result<(String, Self)> {
+        let mut reader = Reader::from_str(content);
+        let mut depth = 0;
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) if depth == 2 && e.name().as_ref() == b"title" => {
+                    let title: Text = read_element(&mut reader, content, &e)?;
+                    return Ok((title.text, RssItems { content, reader }));
+                }
+                Event::Start(e) if e.name().as_ref() == b"item" => {
+                    bail!("Goodreads RSS feed has no channel title");
+                }
+                Event::Start(_) => depth += 1,
+                Event::End(_) => depth -= 1,
+                Event::Eof => bail!("Goodreads RSS feed has no channel title"),
+                _ => {}
             }
-            None => {}
         }
     }
-    if !list.dry_run && has_updates {
-        let (_guard, rw) = db.rw_async().await?;
-        rw.upsert(db_item.clone())?;
-        rw.commit()?;
-    }
 
-    let mut has_updates = false;
-    if check_cost(&mut db_item.audio_torrent, &mut audiobook) {
-        has_updates = true;
-    }
-    if check_cost(&mut db_item.ebook_torrent, &mut ebook) {
-        has_updates = true;
-    }
-    if !list.dry_run && has_updates {
-        let (_guard, rw) = db.rw_async().await?;
-        rw.upsert(db_item.clone())?;
-        rw.commit()?;
+    /// The number of items in the feed, counted without parsing them
+    fn item_count(&self) -> usize {
+        self.content.matches("<item>").count()
     }
+}
 
-    let mut has_updates = false;
-    if let Some(found) = audiobook
-        && db_item
-            .audio_torrent
-            .as_ref()
-            .is_none_or(|t| !(t.status == TorrentStatus::Selected && t.mam_id == found.0.id))
-    {
-        db_item.audio_torrent = Some(ListItemTorrent {
-            mam_id: found.0.id,
-            status: TorrentStatus::Selected,
-            at: Timestamp::now(),
-        });
-        has_updates = true;
-    }
-    if let Some(found) = ebook
-        && db_item
-            .ebook_torrent
-            .as_ref()
-            .is_none_or(|t| !(t.status == TorrentStatus::Selected && t.mam_id == found.0.id))
-    {
-        db_item.ebook_torrent = Some(ListItemTorrent {
-            mam_id: found.0.id,
-            status: TorrentStatus::Selected,
-            at: Timestamp::now(),
-        });
-        has_updates = true;
-    }
-    if !list.dry_run && has_updates {
-        let (_guard, rw) = db.rw_async().await?;
-        rw.upsert(db_item.clone())?;
-        rw.commit()?;
-    }
+impl Iterator for RssItems<'_> {
+    type Item = Result<Item>;
 
-    let mut selected_torrents = 0;
-    if let Some(audiobook) = audiobook {
-        selected_torrents += select_torrents(
-            config,
-            db,
-            mam,
-            [audiobook.0.clone()].into_iter(),
-            &audiobook.3.filter,
-            audiobook.3.cost,
-            list.unsat_buffer,
-            list.wedge_buffer,
-            None,
-            list.dry_run,
-            max_torrents,
-            item.book_id,
-        )
-        .await
-        .context("select_torrents")?;
-    }
-    if let Some(ebook) = ebook {
-        selected_torrents += select_torrents(
-            config,
-            db,
-            mam,
-            [ebook.0.clone()].into_iter(),
-            &ebook.3.filter,
-            ebook.3.cost,
-            list.unsat_buffer,
-            list.wedge_buffer,
-            None,
-            list.dry_run,
-            max_torrents,
-            item.book_id,
-        )
-        .await
-        .context("select_torrents")?;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.reader.read_event() {
+                Ok(Event::Start(e)) if e.name().as_ref() == b"item" => {
+                    return Some(read_element(&mut self.reader, self.content, &e));
+                }
+                Ok(Event::Eof) => return None,
+                Ok(_) => {}
+                Err(err) => return Some(Err(err.into())),
+            }
+        }
     }
-
-    Ok(selected_torrents)
 }
 
-#[derive(Debug, Deserialize)]
-struct Rss {
-    channel: Channel,
+/// Deserializes the element started by `start` without parsing the rest of the feed
+fn read_element<T: DeserializeOwned>(
+    reader: &mut Reader<&[u8]>,
+    content: &str,
+    start: &BytesStart<'_>,
+) -> Result<T> {
+    let span = reader.read_to_end(start.name())?;
+    let inner = &content[span.start as usize..span.end as usize];
+    let name = String::from_utf8_lossy(start.name().as_ref()).into_owned();
+    Ok(from_str(&format!("<{name}>{inner}</{name}>"))?)
 }
 
 #[derive(Debug, Deserialize)]
-struct Channel {
-    title: String,
-    #[serde(rename = "item")]
-    items: Vec<Item>,
+struct Text {
+    #[serde(rename = "$text", default)]
+    text: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -366,70 +415,57 @@ impl Item {
             ebook_torrent: None,
             created_at: Timestamp::now(),
             marked_done_at: None,
+            candidates: vec![],
         }
     }
 }
 
-fn select_torrent(
-    torrents: &[Vec<(MaMTorrent, TorrentMeta, usize, Grab)>],
-    main_cat: OldMainCat,
-) -> Option<&(MaMTorrent, TorrentMeta, usize, Grab)> {
-    torrents
-        .iter()
-        .flatten()
-        .filter(|t| t.1.media_type.matches(main_cat.into()))
-        .find(|t| t.3.cost != Cost::Free || t.0.is_free())
-        .or_else(|| {
-            torrents
-                .iter()
-                .flatten()
-                .find(|t| t.1.media_type.matches(main_cat.into()))
-        })
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn not_wanted(
-    field: &mut Option<ListItemTorrent>,
-    unwanted: &mut Option<&(MaMTorrent, TorrentMeta, usize, Grab)>,
-) -> bool {
-    let found = unwanted.take().unwrap();
-    if field
-        .as_ref()
-        .is_none_or(|t| t.status != TorrentStatus::NotWanted)
-    {
-        debug!("Skipped {:?} torrent as is not wanted", found.1.main_cat);
-        field.replace(ListItemTorrent {
-            mam_id: found.0.id,
-            status: TorrentStatus::NotWanted,
-            at: Timestamp::now(),
-        });
-        true
-    } else {
-        false
+    #[test]
+    fn test_rss_items() {
+        let content = r#"<?xml version="1.0"?>
+<rss version="2.0">
+  <channel>
+    <xhtml:meta xmlns:xhtml="http://www.w3.org/1999/xhtml" name="robots" content="noindex" />
+    <title>Mouse's bookshelf: to-read</title>
+    <image><title>Goodreads</title></image>
+    <item>
+      <guid><![CDATA[https://www.goodreads.com/review/show/1]]></guid>
+      <title><![CDATA[Leviathan Wakes (The Expanse, #1)]]></title>
+      <book_id>8855321</book_id>
+      <author_name>James S.A. Corey</author_name>
+      <isbn>0316129089</isbn>
+      <description><![CDATA[<a href="https://www.goodreads.com/book/show/8855321">cover</a>]]></description>
+    </item>
+    <item>
+      <guid><![CDATA[https://www.goodreads.com/review/show/2]]></guid>
+      <title>Piranesi</title>
+      <book_id>50202953</book_id>
+      <description></description>
+    </item>
+  </channel>
+</rss>"#;
+        let (title, items) = RssItems::new(content).unwrap();
+        assert_eq!(title, "Mouse's bookshelf: to-read");
+        assert_eq!(items.item_count(), 2);
+        let items = items.collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title, "Leviathan Wakes (The Expanse, #1)");
+        assert_eq!(items[0].book_id, Some(8855321));
+        assert_eq!(items[0].author_name.as_deref(), Some("James S.A. Corey"));
+        assert_eq!(items[1].guid, "https://www.goodreads.com/review/show/2");
+        assert_eq!(items[1].author_name, None);
     }
-}
-fn check_cost(
-    field: &mut Option<ListItemTorrent>,
-    selected: &mut Option<&(MaMTorrent, TorrentMeta, usize, Grab)>,
-) -> bool {
-    let take =
-        selected.is_some_and(|selected| selected.3.cost == Cost::Free && !selected.0.is_free());
-    if take {
-        let found = selected.take().unwrap();
-        warn!("Skipped {:?} torrent as it is not free", found.1.main_cat);
-        if field
-            .as_ref()
-            .is_none_or(|t| !(t.status == TorrentStatus::Wanted && t.mam_id == found.0.id))
-        {
-            field.replace(ListItemTorrent {
-                mam_id: found.0.id,
-                status: TorrentStatus::Wanted,
-                at: Timestamp::now(),
-            });
-            true
-        } else {
-            false
-        }
-    } else {
-        false
+
+    #[test]
+    fn test_book_id_from_url() {
+        assert_eq!(
+            book_id_from_url("https://www.goodreads.com/book/show/8855321-leviathan-wakes"),
+            Some(8855321)
+        );
+        assert_eq!(book_id_from_url("https://example.com/"), None);
     }
 }
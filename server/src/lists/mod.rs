@@ -1,13 +1,16 @@
+mod file;
 mod goodreads;
 mod notion;
+mod readarr;
 
-use std::{borrow::Cow, sync::Arc};
+use std::{borrow::Cow, cmp::Reverse, future::pending, path::Path, sync::Arc};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use itertools::Itertools;
 use matchr::score;
 use mlm_db::{
-    ListItem, ListItemTorrent, OldMainCat, Torrent, TorrentKey, TorrentMeta, TorrentStatus,
+    DatabaseExt as _, ListItem, ListItemCandidate, ListItemTorrent, OldDbMainCat, OldMainCat,
+    SelectedTorrent, Series, Size, Timestamp, Torrent, TorrentKey, TorrentMeta, TorrentStatus,
 };
 use mlm_mam::{
     api::MaM,
@@ -15,22 +18,32 @@ use mlm_mam::{
     search::{MaMTorrent, SearchFields, SearchQuery, SearchResult, Tor},
     serde::DATE_FORMAT,
 };
-use mlm_parse::normalize_title;
 use native_db::Database;
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde_json::Value;
 use tokio::sync::watch::Sender;
-use tracing::{debug, instrument, trace};
+use tracing::{debug, info, instrument, trace, warn};
 
 use crate::{
-    config::{Config, GoodreadsList, Grab, NotionList},
-    lists::{goodreads::run_goodreads_import, notion::run_notion_import},
+    autograbber::select_torrents,
+    config::{Config, Cost, FileList, GoodreadsList, Grab, NotionList, ReadarrList, TorrentFilter},
+    hooks::{HookTorrent, run_hook},
+    lists::{
+        file::run_file_import,
+        goodreads::run_goodreads_import,
+        notion::{run_notion_import, update_notion_status},
+        readarr::run_readarr_import,
+    },
+    snatchlist::wait_for_file_change,
+    stats::Stats,
 };
 
 pub enum List {
     Goodreads(GoodreadsList),
     Notion(NotionList),
+    Readarr(ReadarrList),
+    File(FileList),
 }
 
 impl List {
@@ -38,20 +51,41 @@ impl List {
         match self {
             List::Goodreads(_) => "Goodreads",
             List::Notion(_) => "Notion",
+            List::Readarr(_) => "Readarr",
+            List::File(_) => "File",
         }
     }
 
-    pub fn display_name(&self, index: usize) -> String {
+    pub fn name(&self) -> Option<&str> {
         match self {
-            List::Goodreads(list) => list.name.clone().unwrap_or_else(|| index.to_string()),
-            List::Notion(list) => list.name.clone(),
+            List::Goodreads(list) => list.name.as_deref(),
+            List::Notion(list) => Some(&list.name),
+            List::Readarr(list) => list.name.as_deref(),
+            List::File(list) => list.name.as_deref(),
         }
     }
 
+    pub fn display_name(&self, index: usize) -> String {
+        self.name()
+            .map(ToOwned::to_owned)
+            .unwrap_or_else(|| index.to_string())
+    }
+
     pub fn search_interval(&self) -> Option<u64> {
         match self {
             List::Goodreads(list) => list.search_interval,
             List::Notion(list) => list.search_interval,
+            List::Readarr(list) => list.search_interval,
+            List::File(list) => list.search_interval,
+        }
+    }
+
+    pub fn account(&self) -> Option<&str> {
+        match self {
+            List::Goodreads(list) => list.account.as_deref(),
+            List::Notion(list) => list.account.as_deref(),
+            List::Readarr(list) => list.account.as_deref(),
+            List::File(list) => list.account.as_deref(),
         }
     }
 
@@ -59,10 +93,21 @@ impl List {
         match self {
             List::Goodreads(list) => list.unsat_buffer,
             List::Notion(list) => list.unsat_buffer,
+            List::Readarr(list) => list.unsat_buffer,
+            List::File(list) => list.unsat_buffer,
         }
     }
 }
 
+/// Resolves when the file of a file list has been modified, never resolves
+/// for other lists
+pub async fn wait_for_list_change(list: &List) {
+    let List::File(list) = list else {
+        return pending().await;
+    };
+    wait_for_file_change(&list.file).await;
+}
+
 pub fn get_lists(config: &Config) -> Vec<List> {
     let mut lists = vec![];
     for goodreads in &config.goodreads_lists {
@@ -71,6 +116,12 @@ pub fn get_lists(config: &Config) -> Vec<List> {
     for notion in &config.notion_lists {
         lists.push(List::Notion(notion.clone()));
     }
+    for readarr in &config.readarr_lists {
+        lists.push(List::Readarr(readarr.clone()));
+    }
+    for file in &config.file_lists {
+        lists.push(List::File(file.clone()));
+    }
     lists
 }
 
@@ -82,6 +133,7 @@ pub async fn run_list_import(
     list: Arc<List>,
     index: usize,
     autograb_trigger: Sender<()>,
+    stats: &Stats,
 ) -> Result<()> {
     let user_info = mam.user_info().await?;
     let max_torrents = user_info.unsat.limit.saturating_sub(user_info.unsat.count);
@@ -93,16 +145,61 @@ pub async fn run_list_import(
     );
 
     let max_torrents =
-        max_torrents.saturating_sub(list.unsat_buffer().unwrap_or(config.unsat_buffer));
+        max_torrents.saturating_sub(list.unsat_buffer().unwrap_or(config.unsat_buffer.min()));
 
     if max_torrents > 0 {
-        match list.as_ref() {
+        let satisfied = match list.as_ref() {
             List::Goodreads(list) => {
-                run_goodreads_import(config, db, mam, list, max_torrents).await?;
+                run_goodreads_import(
+                    config.clone(),
+                    db.clone(),
+                    mam,
+                    list,
+                    max_torrents,
+                    stats,
+                    index,
+                )
+                .await?
             }
             List::Notion(list) => {
-                run_notion_import(config, db, mam, list, max_torrents).await?;
+                run_notion_import(
+                    config.clone(),
+                    db.clone(),
+                    mam,
+                    list,
+                    max_torrents,
+                    stats,
+                    index,
+                )
+                .await?
+            }
+            List::Readarr(list) => {
+                run_readarr_import(
+                    config.clone(),
+                    db.clone(),
+                    mam,
+                    list,
+                    max_torrents,
+                    stats,
+                    index,
+                )
+                .await?
+            }
+            List::File(list) => {
+                run_file_import(
+                    config.clone(),
+                    db.clone(),
+                    mam,
+                    list,
+                    max_torrents,
+                    stats,
+                    index,
+                )
+                .await?
             }
+        };
+        if !satisfied.is_empty() {
+            on_satisfied(&config, &db, &list, index, &satisfied).await?;
         }
     }
 
@@ -111,22 +208,100 @@ pub async fn run_list_import(
     Ok(())
 }
 
+/// Notifies about and updates the source of items that were found in the
+/// library during an import, and stores each item once that is done
+async fn on_satisfied(
+    config: &Config,
+    db: &Database<'_>,
+    list: &List,
+    index: usize,
+    satisfied: &[(ListItem, Torrent)],
+) -> Result<()> {
+    let on_satisfied = match list {
+        List::Goodreads(list) => Some(&list.on_satisfied),
+        List::Notion(list) => Some(&list.on_satisfied),
+        List::Readarr(_) | List::File(_) => None,
+    };
+    if let (List::Notion(list), Some(status)) = (
+        list,
+        on_satisfied.and_then(|on_satisfied| on_satisfied.update_source.as_ref()),
+    ) {
+        update_notion_status(list, satisfied.iter().map(|(item, _)| item), status)
+            .await
+            .context("update notion status")?;
+    }
+    for (item, torrent) in satisfied {
+        info!(
+            "{} list {}: {} is in the library",
+            list.list_type(),
+            list.display_name(index),
+            item.title
+        );
+        if let Some(command) = on_satisfied.and_then(|on_satisfied| on_satisfied.notify.as_ref()) {
+            run_hook(
+                config,
+                db,
+                "on_satisfied.notify",
+                command,
+                HookTorrent {
+                    id: &torrent.id,
+                    mam_id: torrent.mam_id,
+                    path: torrent.library_path.as_deref().unwrap_or(Path::new("")),
+                    title: &item.title,
+                    library: torrent.linker.as_deref(),
+                },
+            )
+            .await;
+        }
+        let (_guard, rw) = db.rw_async().await?;
+        rw.upsert(item.clone())?;
+        rw.commit()?;
+    }
+    Ok(())
+}
+
 static BAD_CHARATERS: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"['`/?]|\s+[\(\[][^\)\]]+[\)\]]").unwrap());
 
 static AND: Lazy<Regex> = Lazy::new(|| Regex::new(r"&|\band\b").unwrap());
 
+/// A series with its position, like "The Expanse, #2"
+static SERIES_POSITION: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(.*?),?\s*#(\d+(?:\.\d+)?)$").unwrap());
+
 #[instrument(skip_all)]
 fn search_library(config: &Config, db: &Database<'_>, db_item: &mut ListItem) -> Result<bool> {
     let r = db.r_transaction()?;
-    let title_search = normalize_title(&db_item.title);
+    let title_search = config.title_rules().normalize_title(&db_item.title);
     let mut library = {
         r.scan()
             .secondary::<Torrent>(TorrentKey::title_search)?
             .start_with(title_search.as_str())?
-            .filter(|t| t.as_ref().is_ok_and(|t| db_item.matches(&t.meta)))
+            .filter(|t| {
+                t.as_ref()
+                    .is_ok_and(|t| covers_item(config, db_item, &t.meta))
+            })
             .collect::<Result<Vec<_>, _>>()
     }?;
+    // Omnibus editions are usually titled after the series rather than the book
+    for (series_name, _) in &db_item.series {
+        let series_search = config.title_rules().normalize_title(series_name);
+        for torrent in r
+            .scan()
+            .secondary::<Torrent>(TorrentKey::title_search)?
+            .start_with(series_search.as_str())?
+        {
+            let torrent = torrent?;
+            let in_collection = db_item.in_omnibus(&torrent.meta)
+                || (config.match_collections && db_item.in_collection(&torrent.meta));
+            if in_collection
+                && covers_item(config, db_item, &torrent.meta)
+                && !library.iter().any(|t| t.mam_id == torrent.mam_id)
+            {
+                library.push(torrent);
+            }
+        }
+    }
 
     library.sort_by_key(|torrent| {
         let preferred_types = config.preferred_types(&torrent.meta.media_type);
@@ -163,6 +338,26 @@ fn search_library(config: &Config, db: &Database<'_>, db_item: &mut ListItem) ->
     Ok(updated_any)
 }
 
+/// Checks if a library torrent has the book of a list item, with
+/// `match_collections` one collection torrent covers each book in it, so it
+/// is stored as the existing torrent of all of those items
+fn covers_item(config: &Config, db_item: &ListItem, meta: &TorrentMeta) -> bool {
+    db_item.matches(meta) || (config.match_collections && db_item.in_collection(meta))
+}
+
+/// The MaM id of the library torrent that made a wanted item no longer wanted,
+/// when `updated` from `search_library` says that just happened
+fn satisfied_by(db_item: &ListItem, updated: bool) -> Option<u64> {
+    if !updated || db_item.want_audio() || db_item.want_ebook() {
+        return None;
+    }
+    [&db_item.audio_torrent, &db_item.ebook_torrent]
+        .into_iter()
+        .flatten()
+        .find(|t| t.status == TorrentStatus::Existing)
+        .map(|t| t.mam_id)
+}
+
 fn set_existing(field: &mut Option<ListItemTorrent>, torrent: &Torrent) -> bool {
     if let Some(field) = field {
         if field.status == TorrentStatus::Selected {
@@ -184,10 +379,11 @@ fn set_existing(field: &mut Option<ListItemTorrent>, torrent: &Torrent) -> bool
 async fn search_grab(
     config: &Config,
     mam: &MaM<'_>,
+    list: &ListGrabber<'_>,
     db_item: &ListItem,
     grab: &Grab,
-) -> Result<Vec<(MaMTorrent, TorrentMeta, usize, Grab)>> {
-    let (flags_is_hide, flags) = grab.filter.flags.as_search_bitfield();
+) -> Result<GrabResults> {
+    let (browse_flags_hide_vs_show, browse_flags) = grab.filter.flags.as_search_params();
 
     let title_query = db_item.title.replace("*", "\"*\"");
     let title_query = BAD_CHARATERS.replace_all(&title_query, " ");
@@ -198,7 +394,13 @@ async fn search_grab(
     let query = format!(
         "@title {} @author ({})",
         title_query,
-        db_item.authors.iter().map(|a| format!("\"{a}\"")).join("|")
+        db_item
+            .authors
+            .iter()
+            .flat_map(|a| config.author_names(a))
+            .unique()
+            .map(|a| format!("\"{a}\""))
+            .join("|")
     );
 
     let mut categories = grab.filter.categories.clone();
@@ -209,39 +411,39 @@ async fn search_grab(
         categories.ebook = Some(vec![])
     }
 
-    let mut results: Option<SearchResult> = None;
-    loop {
-        let mut page_results = mam
-            .search(&SearchQuery {
+    let start_date = grab
+        .filter
+        .uploaded_after
+        .map_or_else(|| Ok(String::new()), |d| d.format(&DATE_FORMAT))?;
+    let end_date = grab
+        .filter
+        .uploaded_before
+        .map_or_else(|| Ok(String::new()), |d| d.format(&DATE_FORMAT))?;
+    let (query, categories, browse_flags) = (&query, &categories, &browse_flags);
+    let results = fetch_search_pages(
+        config.max_search_pages,
+        config.max_search_results,
+        move |start_number| {
+            let search_query = SearchQuery {
                 fields: SearchFields {
                     dl_link: true,
                     ..Default::default()
                 },
                 perpage: 100,
                 tor: Tor {
-                    start_number: results.as_ref().map_or(0, |r| r.data.len() as u64),
+                    start_number,
                     text: query.clone(),
                     srch_in: vec![SearchIn::Title, SearchIn::Author],
                     main_cat: categories.get_main_cats(),
                     cat: categories.get_cats(),
                     browse_lang: grab.filter.languages.iter().map(|l| l.to_id()).collect(),
-                    browse_flags_hide_vs_show: if flags.is_empty() {
-                        None
-                    } else {
-                        Some(if flags_is_hide { 0 } else { 1 })
-                    },
-                    browse_flags: flags.clone(),
-                    start_date: grab
-                        .filter
-                        .uploaded_after
-                        .map_or_else(|| Ok(String::new()), |d| d.format(&DATE_FORMAT))?,
-                    end_date: grab
-                        .filter
-                        .uploaded_before
-                        .map_or_else(|| Ok(String::new()), |d| d.format(&DATE_FORMAT))?,
+                    browse_flags_hide_vs_show,
+                    browse_flags: browse_flags.clone(),
+                    start_date: start_date.clone(),
+                    end_date: end_date.clone(),
                     min_size: grab.filter.min_size.bytes(),
                     max_size: grab.filter.max_size.bytes(),
-                    unit: grab.filter.min_size.unit().max(grab.filter.max_size.unit()),
+                    unit: Size::range_unit(grab.filter.min_size, grab.filter.max_size),
                     min_seeders: grab.filter.min_seeders,
                     max_seeders: grab.filter.max_seeders,
                     min_leechers: grab.filter.min_leechers,
@@ -252,9 +454,140 @@ async fn search_grab(
                 },
 
                 ..Default::default()
-            })
-            .await
-            .context("search")?;
+            };
+            async move { mam.search(&search_query).await.context("search") }
+        },
+    )
+    .await?;
+
+    let settings = list.match_settings(grab);
+    let scored = results
+        .data
+        .into_iter()
+        .filter(|t| grab.filter.matches(t))
+        .filter(|t| list.accepts(config, &db_item.title, &db_item.authors, t))
+        .filter(|t| !settings.require_series_match || series_matches(&db_item.series, t))
+        .map(|t| {
+            let score = match_score(config, db_item, &t);
+            (t, score)
+        })
+        .collect::<Vec<_>>();
+    let torrents = match settings.best_matches(scored) {
+        Ok(torrents) => torrents,
+        Err(unmatched) => {
+            return Ok(GrabResults {
+                torrents: vec![],
+                unmatched,
+            });
+        }
+    };
+    let mut torrents = torrents
+        .into_iter()
+        .map(|t| {
+            let meta = t.as_meta(config.title_rules())?;
+            let preferred_types = config.preferred_types(&meta.media_type);
+            let preference = preferred_types
+                .iter()
+                .position(|t| meta.filetypes.contains(t));
+            Ok((t, meta, preference.unwrap_or_default(), grab.clone()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    rank_torrents(&mut torrents);
+
+    Ok(GrabResults {
+        torrents,
+        unmatched: vec![],
+    })
+}
+
+/// The results of searching MaM for a list item with one grab block
+struct GrabResults {
+    torrents: Vec<(MaMTorrent, TorrentMeta, usize, Grab)>,
+    /// The best results with their score, when none of them scored at least
+    /// `match_min_score`
+    unmatched: Vec<(MaMTorrent, usize)>,
+}
+
+/// How well a search result matches a list item: the title and author scores
+/// count double, plus the score of each series of the item
+fn match_score(config: &Config, db_item: &ListItem, t: &MaMTorrent) -> usize {
+    let title_score = score(&db_item.title, &t.title);
+    let author_score = t
+        .author_info
+        .values()
+        .map(|author| {
+            let author = config.canonical_author(author);
+            db_item
+                .authors
+                .iter()
+                .map(|author_name| score(config.canonical_author(author_name), author))
+                .max()
+                .unwrap_or_default()
+        })
+        .max()
+        .unwrap_or_default();
+    let series_score: usize = db_item
+        .series
+        .iter()
+        .map(|(i_name, i_num)| {
+            t.series_info
+                .values()
+                .map(|series| {
+                    let Value::String(t_name) = series.first().unwrap_or(&Value::Null) else {
+                        return 0;
+                    };
+                    let Value::String(t_num) = series.get(1).unwrap_or(&Value::Null) else {
+                        return 0;
+                    };
+                    score(i_name, t_name) + series_num_score(*i_num, t_num)
+                })
+                .max()
+                .unwrap_or_default()
+        })
+        .sum();
+    title_score * 2 + author_score * 2 + series_score
+}
+
+/// 100 when the series position of a result covers the position of the item.
+/// Positions are compared as numbers, so a novella at 2.5 matches "2.5" but
+/// not "2" or "25"
+fn series_num_score(num: f64, entries: &str) -> usize {
+    match Series::try_from((String::new(), entries.to_string())) {
+        Ok(series) if series.entries.contains(num as f32) => 100,
+        Ok(_) => 0,
+        Err(_) => score(&num.to_string(), entries),
+    }
+}
+
+/// Whether the result is in one of the series of the item, items without a
+/// series match everything
+fn series_matches(series: &[(String, f64)], torrent: &MaMTorrent) -> bool {
+    series.is_empty()
+        || torrent.series_info.values().any(|t_series| {
+            let Some(Value::String(t_name)) = t_series.first() else {
+                return false;
+            };
+            series
+                .iter()
+                .any(|(name, _)| relative_score(name, t_name) >= SERIES_MATCH_THRESHOLD)
+        })
+}
+
+/// Fetches pages of search results until all are found, or the configured caps
+/// on pages and results are reached
+async fn fetch_search_pages<F, Fut>(
+    max_pages: u64,
+    max_results: u64,
+    mut fetch_page: F,
+) -> Result<SearchResult>
+where
+    F: FnMut(u64) -> Fut,
+    Fut: Future<Output = Result<SearchResult>>,
+{
+    let mut results: Option<SearchResult> = None;
+    for page in 1.. {
+        let mut page_results =
+            fetch_page(results.as_ref().map_or(0, |r| r.data.len() as u64)).await?;
 
         debug!(
             "result: perpage: {}, start: {}, data: {}, total: {}, found: {}",
@@ -278,80 +611,1076 @@ async fn search_grab(
             results = Some(page_results);
         }
 
-        let results = results.as_ref().unwrap();
+        let results = results.as_mut().unwrap();
         if results.data.len() >= results.found {
             break;
         }
+        if page >= max_pages || results.data.len() as u64 >= max_results {
+            results.data.truncate(max_results as usize);
+            info!(
+                "Search truncated after {page} pages, using {} of {} results",
+                results.data.len(),
+                results.found
+            );
+            break;
+        }
     }
 
-    let mut torrents = results
-        .unwrap()
-        .data
-        .into_iter()
-        .filter(|t| grab.filter.matches(t))
-        .map(|t| {
-            let title_score = score(&db_item.title, &t.title);
-            let author_score = t
-                .author_info
-                .values()
-                .map(|author| {
-                    db_item
-                        .authors
-                        .iter()
-                        .map(|author_name| score(author_name, author))
-                        .max()
-                        .unwrap_or_default()
+    Ok(results.unwrap_or_default())
+}
+
+/// Orders the matched results by preference, a result read by one of the
+/// `preferred_narrators` goes before the other editions in the same language
+fn rank_torrents(torrents: &mut [(MaMTorrent, TorrentMeta, usize, Grab)]) {
+    torrents.sort_by(|a, b| {
+        a.3.filter
+            .language_rank(a.1.language)
+            .cmp(&b.3.filter.language_rank(b.1.language))
+            .then(
+                a.3.prefers_narrator(a.1.narrators.iter())
+                    .cmp(&b.3.prefers_narrator(b.1.narrators.iter()))
+                    .reverse(),
+            )
+            .then(a.2.cmp(&b.2))
+            .then(a.0.numfiles.cmp(&b.0.numfiles))
+            .then(a.1.size.bytes().cmp(&b.1.size.bytes()).reverse())
+    });
+}
+
+/// The settings of a list used when searching MaM for one of its items
+struct ListGrabber<'a> {
+    grab: &'a [Grab],
+    prefer_format: Option<OldDbMainCat>,
+    unsat_buffer: Option<u64>,
+    wedge_buffer: Option<u64>,
+    dry_run: bool,
+    min_score: Option<f64>,
+    require_author_match: bool,
+    match_min_score: Option<u64>,
+    match_window: Option<u64>,
+    require_series_match: bool,
+}
+
+/// How well an author has to match for `require_author_match`
+const AUTHOR_MATCH_THRESHOLD: f64 = 0.5;
+
+/// How well a series name has to match for `require_series_match`
+const SERIES_MATCH_THRESHOLD: f64 = 0.8;
+
+/// `match_window` when neither the grab block nor the list sets it
+const DEFAULT_MATCH_WINDOW: u64 = 100;
+
+/// How many of the best results are kept for review when none is good enough
+const UNMATCHED_CANDIDATES: usize = 5;
+
+impl ListGrabber<'_> {
+    /// The match settings of a grab block, falling back to those of the list
+    fn match_settings(&self, grab: &Grab) -> MatchSettings {
+        MatchSettings {
+            min_score: grab
+                .match_min_score
+                .or(self.match_min_score)
+                .unwrap_or_default() as usize,
+            window: grab
+                .match_window
+                .or(self.match_window)
+                .unwrap_or(DEFAULT_MATCH_WINDOW) as usize,
+            require_series_match: grab
+                .require_series_match
+                .unwrap_or(self.require_series_match),
+        }
+    }
+
+    /// Rejects results with a title that matches worse than `min_score`, or
+    /// with no matching author when `require_author_match` is set, so a book
+    /// with the same title by someone else is not grabbed. Authors are compared
+    /// by their canonical name in `author_aliases`
+    fn accepts(
+        &self,
+        config: &Config,
+        title: &str,
+        authors: &[String],
+        torrent: &MaMTorrent,
+    ) -> bool {
+        if let Some(min_score) = self.min_score
+            && relative_score(title, &torrent.title) < min_score
+        {
+            return false;
+        }
+        if self.require_author_match
+            && !torrent.author_info.values().any(|author| {
+                let author = config.canonical_author(author);
+                authors.iter().any(|name| {
+                    relative_score(config.canonical_author(name), author) >= AUTHOR_MATCH_THRESHOLD
                 })
-                .max()
-                .unwrap_or_default();
-            let series_score: usize = db_item
-                .series
+            })
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// How close the `match_score` of a result has to be to be grabbed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct MatchSettings {
+    min_score: usize,
+    window: usize,
+    require_series_match: bool,
+}
+
+impl MatchSettings {
+    /// The best result and those scoring less than `window` below it, best
+    /// first. When the best one scores below `min_score` nothing is grabbed and
+    /// the best few are returned with their score as the error instead
+    fn best_matches<T>(&self, mut scored: Vec<(T, usize)>) -> Result<Vec<T>, Vec<(T, usize)>> {
+        scored.sort_by_key(|t| Reverse(t.1));
+        let Some(&(_, max_score)) = scored.first() else {
+            return Ok(vec![]);
+        };
+        if max_score < self.min_score {
+            scored.truncate(UNMATCHED_CANDIDATES);
+            return Err(scored);
+        }
+        Ok(scored
+            .into_iter()
+            .take_while(|t| max_score - t.1 < self.window.max(1))
+            .map(|(t, _)| t)
+            .collect())
+    }
+}
+
+/// Match score from 0 to 1, relative to how `query` scores against itself
+fn relative_score(query: &str, candidate: &str) -> f64 {
+    let perfect = score(query, query);
+    if perfect == 0 {
+        return 0.0;
+    }
+    (score(query, candidate) as f64 / perfect as f64).min(1.0)
+}
+
+/// Searches the library and MaM for a list item, items that are found in the
+/// library are added to `satisfied` with their torrent
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip_all)]
+async fn search_item(
+    config: &Config,
+    db: &Database<'_>,
+    mam: &MaM<'_>,
+    list: &ListGrabber<'_>,
+    goodreads_id: Option<u64>,
+    mut db_item: ListItem,
+    max_torrents: u64,
+    satisfied: &mut Vec<(ListItem, Torrent)>,
+) -> Result<u64> {
+    if !db_item.want_audio() && !db_item.want_ebook() {
+        return Ok(0);
+    }
+
+    let has_updates = search_library(config, db, &mut db_item).context("search_library")?;
+    // A satisfied item is stored by `on_satisfied` once its actions are done,
+    // so a failed import finds it satisfied again on the next run
+    if !list.dry_run
+        && let Some(mam_id) = satisfied_by(&db_item, has_updates)
+        && let Some(torrent) = db
+            .r_transaction()?
+            .get()
+            .secondary::<Torrent>(TorrentKey::mam_id, mam_id)?
+    {
+        satisfied.push((db_item, torrent));
+        return Ok(0);
+    }
+    if !list.dry_run && has_updates {
+        let (_guard, rw) = db.rw_async().await?;
+        rw.upsert(db_item.clone())?;
+        rw.commit()?;
+    }
+    if !db_item.want_audio() && !db_item.want_ebook() {
+        return Ok(0);
+    }
+
+    let mut torrents = vec![];
+    let mut unmatched = vec![];
+    for grab in list.grab {
+        let results = search_grab(config, mam, list, &db_item, grab)
+            .await
+            .context("search_grab")?;
+        torrents.push(results.torrents);
+        unmatched.extend(results.unmatched);
+    }
+    if torrents.iter().all(|t| t.is_empty()) && !unmatched.is_empty() {
+        unmatched.sort_by_key(|t| Reverse(t.1));
+        let unmatched = unmatched
+            .into_iter()
+            .unique_by(|t| t.0.id)
+            .take(UNMATCHED_CANDIDATES)
+            .collect::<Vec<_>>();
+        info!(
+            "No confident match for {}, best results: {}",
+            db_item.title,
+            unmatched
                 .iter()
-                .map(|(i_name, i_num)| {
-                    t.series_info
-                        .values()
-                        .map(|series| {
-                            let Value::String(t_name) = series.first().unwrap_or(&Value::Null)
-                            else {
-                                return 0;
-                            };
-                            let Value::String(t_num) = series.get(1).unwrap_or(&Value::Null) else {
-                                return 0;
-                            };
-                            score(i_name, t_name) + score(&i_num.to_string(), t_num)
-                        })
-                        .max()
-                        .unwrap_or_default()
+                .map(|(t, score)| format!(
+                    "{} by {} ({score})",
+                    t.title,
+                    t.author_info.values().join(", ")
+                ))
+                .join("; ")
+        );
+        let candidates = unmatched
+            .iter()
+            .filter_map(|(t, score)| {
+                let meta = t.as_meta(config.title_rules()).ok()?;
+                Some(ListItemCandidate {
+                    mam_id: t.id,
+                    title: meta.title,
+                    authors: meta.authors,
+                    media_type: meta.media_type,
+                    filetypes: meta.filetypes,
+                    score: *score as u64,
                 })
-                .sum();
-            (t, title_score * 2 + author_score * 2 + series_score)
-        })
-        .collect::<Vec<_>>();
-    torrents.sort_by_key(|t| -(t.1 as i64));
+            })
+            .collect::<Vec<_>>();
+        if !list.dry_run && db_item.candidates != candidates {
+            db_item.candidates = candidates;
+            let (_guard, rw) = db.rw_async().await?;
+            rw.upsert(db_item)?;
+            rw.commit()?;
+        }
+        return Ok(0);
+    }
+    let mut audiobook = select_torrent(&torrents, OldMainCat::Audio);
+    let mut ebook = select_torrent(&torrents, OldMainCat::Ebook);
 
-    if torrents.is_empty() {
-        return Ok(Default::default());
+    let mut has_updates = false;
+    if audiobook.is_some() && ebook.is_some() {
+        match list.prefer_format {
+            Some(OldDbMainCat::Audio) => {
+                let updated = not_wanted(&mut db_item.ebook_torrent, &mut ebook);
+                has_updates = updated || has_updates;
+            }
+            Some(OldDbMainCat::Ebook) => {
+                let updated = not_wanted(&mut db_item.audio_torrent, &mut audiobook);
+                has_updates = updated || has_updates;
+            }
+            None => {}
+        }
     }
-    let max_score = torrents[0].1;
-    let mut torrents = torrents
-        .into_iter()
-        .take_while(|t| t.1 > max_score - 100)
-        .map(|(t, _)| {
-            let meta = t.as_meta()?;
-            let preferred_types = config.preferred_types(&meta.media_type);
-            let preference = preferred_types
+    if !list.dry_run && has_updates {
+        let (_guard, rw) = db.rw_async().await?;
+        rw.upsert(db_item.clone())?;
+        rw.commit()?;
+    }
+
+    let mut has_updates = false;
+    if check_cost(&mut db_item.audio_torrent, &mut audiobook) {
+        has_updates = true;
+    }
+    if check_cost(&mut db_item.ebook_torrent, &mut ebook) {
+        has_updates = true;
+    }
+    if !list.dry_run && has_updates {
+        let (_guard, rw) = db.rw_async().await?;
+        rw.upsert(db_item.clone())?;
+        rw.commit()?;
+    }
+
+    let mut has_updates = false;
+    if (audiobook.is_some() || ebook.is_some()) && !db_item.candidates.is_empty() {
+        db_item.candidates.clear();
+        has_updates = true;
+    }
+    if let Some(found) = audiobook
+        && db_item
+            .audio_torrent
+            .as_ref()
+            .is_none_or(|t| !(t.status == TorrentStatus::Selected && t.mam_id == found.0.id))
+    {
+        db_item.audio_torrent = Some(ListItemTorrent {
+            mam_id: found.0.id,
+            status: TorrentStatus::Selected,
+            at: Timestamp::now(),
+        });
+        has_updates = true;
+    }
+    if let Some(found) = ebook
+        && db_item
+            .ebook_torrent
+            .as_ref()
+            .is_none_or(|t| !(t.status == TorrentStatus::Selected && t.mam_id == found.0.id))
+    {
+        db_item.ebook_torrent = Some(ListItemTorrent {
+            mam_id: found.0.id,
+            status: TorrentStatus::Selected,
+            at: Timestamp::now(),
+        });
+        has_updates = true;
+    }
+    if !list.dry_run && has_updates {
+        let (_guard, rw) = db.rw_async().await?;
+        rw.upsert(db_item.clone())?;
+        rw.commit()?;
+    }
+
+    let mut selected_torrents = 0;
+    if let Some(audiobook) = audiobook {
+        selected_torrents += select_torrents(
+            config,
+            db,
+            mam,
+            [audiobook.0.clone()].into_iter(),
+            &audiobook.3.filter,
+            audiobook.3.cost,
+            list.unsat_buffer,
+            list.wedge_buffer,
+            None,
+            list.dry_run,
+            max_torrents,
+            goodreads_id,
+            None,
+        )
+        .await
+        .context("select_torrents")?;
+    }
+    if let Some(ebook) = ebook {
+        selected_torrents += select_torrents(
+            config,
+            db,
+            mam,
+            [ebook.0.clone()].into_iter(),
+            &ebook.3.filter,
+            ebook.3.cost,
+            list.unsat_buffer,
+            list.wedge_buffer,
+            None,
+            list.dry_run,
+            max_torrents,
+            goodreads_id,
+            None,
+        )
+        .await
+        .context("select_torrents")?;
+    }
+
+    Ok(selected_torrents)
+}
+
+/// Grabs a search result that was picked by hand for a list item without a
+/// confident match, and marks the item as satisfied by it
+pub async fn pick_candidate(
+    config: &Config,
+    db: &Database<'_>,
+    mam: &MaM<'_>,
+    guid: (String, String),
+    mam_id: u64,
+) -> Result<()> {
+    let Some(mut db_item) = db.r_transaction()?.get().primary::<ListItem>(guid)? else {
+        bail!("Could not find list item");
+    };
+    if !db_item.candidates.iter().any(|c| c.mam_id == mam_id) {
+        bail!("Torrent {mam_id} is not a candidate for {}", db_item.title);
+    }
+    let Some(torrent) = mam.get_torrent_info_by_id(mam_id).await? else {
+        bail!("Could not find torrent {mam_id} on MaM");
+    };
+    let meta = torrent.as_meta(config.title_rules())?;
+    select_torrents(
+        config,
+        db,
+        mam,
+        [torrent].into_iter(),
+        &TorrentFilter::default(),
+        Cost::Ratio,
+        None,
+        None,
+        None,
+        false,
+        1,
+        None,
+        None,
+    )
+    .await
+    .context("select_torrents")?;
+    {
+        // Already selected or downloaded torrents count as picked as well
+        let r = db.r_transaction()?;
+        let selected = r
+            .get()
+            .primary::<SelectedTorrent>(mam_id)?
+            .is_some_and(|t| t.removed_at.is_none())
+            || r.get()
+                .secondary::<Torrent>(TorrentKey::mam_id, mam_id)?
+                .is_some();
+        if !selected {
+            bail!("Torrent {mam_id} was not selected, check the log for why");
+        }
+    }
+
+    let field = if meta.media_type.matches(OldMainCat::Audio.into()) {
+        &mut db_item.audio_torrent
+    } else {
+        &mut db_item.ebook_torrent
+    };
+    *field = Some(ListItemTorrent {
+        mam_id,
+        status: TorrentStatus::Selected,
+        at: Timestamp::now(),
+    });
+    db_item.candidates.clear();
+    let (_guard, rw) = db.rw_async().await?;
+    rw.upsert(db_item)?;
+    rw.commit()?;
+    Ok(())
+}
+
+fn select_torrent(
+    torrents: &[Vec<(MaMTorrent, TorrentMeta, usize, Grab)>],
+    main_cat: OldMainCat,
+) -> Option<&(MaMTorrent, TorrentMeta, usize, Grab)> {
+    torrents
+        .iter()
+        .flatten()
+        .filter(|t| t.1.media_type.matches(main_cat.into()))
+        .find(|t| t.3.cost.matches(&t.0))
+        .or_else(|| {
+            torrents
                 .iter()
-                .position(|t| meta.filetypes.contains(t));
-            Ok((t, meta, preference.unwrap_or_default(), grab.clone()))
+                .flatten()
+                .find(|t| t.1.media_type.matches(main_cat.into()))
         })
-        .collect::<Result<Vec<_>>>()?;
-    torrents.sort_by(|a, b| {
-        a.2.cmp(&b.2).then(
-            a.0.numfiles
-                .cmp(&b.0.numfiles)
-                .then(a.1.size.bytes().cmp(&b.1.size.bytes()).reverse()),
+}
+
+fn not_wanted(
+    field: &mut Option<ListItemTorrent>,
+    unwanted: &mut Option<&(MaMTorrent, TorrentMeta, usize, Grab)>,
+) -> bool {
+    let found = unwanted.take().unwrap();
+    if field
+        .as_ref()
+        .is_none_or(|t| t.status != TorrentStatus::NotWanted)
+    {
+        debug!("Skipped {:?} torrent as is not wanted", found.1.main_cat);
+        field.replace(ListItemTorrent {
+            mam_id: found.0.id,
+            status: TorrentStatus::NotWanted,
+            at: Timestamp::now(),
+        });
+        true
+    } else {
+        false
+    }
+}
+fn check_cost(
+    field: &mut Option<ListItemTorrent>,
+    selected: &mut Option<&(MaMTorrent, TorrentMeta, usize, Grab)>,
+) -> bool {
+    let take = selected.is_some_and(|selected| !selected.3.cost.matches(&selected.0));
+    if take {
+        let found = selected.take().unwrap();
+        warn!(
+            "Skipped {:?} torrent as it does not match cost {:?}",
+            found.1.main_cat, found.3.cost
+        );
+        if field
+            .as_ref()
+            .is_none_or(|t| !(t.status == TorrentStatus::Wanted && t.mam_id == found.0.id))
+        {
+            field.replace(ListItemTorrent {
+                mam_id: found.0.id,
+                status: TorrentStatus::Wanted,
+                at: Timestamp::now(),
+            });
+            true
+        } else {
+            false
+        }
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mlm_db::{
+        AudiobookCategory, Language, Series, SeriesEntries, SeriesEntry, Size, Timestamp,
+    };
+
+    use super::*;
+    use crate::{
+        config::{Cost, TorrentFilter},
+        test_helpers::{library_torrent, template_meta},
+    };
+
+    fn candidate(
+        mam_id: u64,
+        language: Language,
+        grab: &Grab,
+    ) -> (MaMTorrent, TorrentMeta, usize, Grab) {
+        let torrent = MaMTorrent {
+            id: mam_id,
+            numfiles: 1,
+            ..Default::default()
+        };
+        let meta = TorrentMeta {
+            mam_id,
+            language: Some(language),
+            filetypes: vec!["m4b".to_string()],
+            num_files: 1,
+            size: Size::from_bytes(1000),
+            title: "The Book".to_string(),
+            authors: vec!["An Author".to_string()],
+            ..template_meta()
+        };
+        (torrent, meta, 0, grab.clone())
+    }
+
+    #[test]
+    fn test_require_author_match() {
+        let mut list = ListGrabber {
+            grab: &[],
+            prefer_format: None,
+            unsat_buffer: None,
+            wedge_buffer: None,
+            dry_run: true,
+            min_score: None,
+            require_author_match: false,
+            match_min_score: None,
+            match_window: None,
+            require_series_match: false,
+        };
+        let config: Config = toml::from_str(r#"mam_id = "abc""#).unwrap();
+        let authors = vec!["James S. A. Corey".to_string()];
+        let torrent = |author: &str| MaMTorrent {
+            title: "Leviathan Wakes".to_string(),
+            author_info: [(1, author.to_string())].into(),
+            ..Default::default()
+        };
+        let right = torrent("James S. A. Corey");
+        let wrong = torrent("Zzz Qqq");
+        assert!(relative_score("Leviathan Wakes", &wrong.title) >= 0.99);
+
+        assert!(list.accepts(&config, "Leviathan Wakes", &authors, &wrong));
+        list.require_author_match = true;
+        assert!(!list.accepts(&config, "Leviathan Wakes", &authors, &wrong));
+        assert!(list.accepts(&config, "Leviathan Wakes", &authors, &right));
+
+        list.min_score = Some(0.9);
+        assert!(list.accepts(&config, "Leviathan Wakes", &authors, &right));
+        assert!(!list.accepts(&config, "Caliban's War", &authors, &right));
+    }
+
+    #[test]
+    fn test_author_alias_match() {
+        let config: Config = toml::from_str(
+            r#"
+            mam_id = "abc"
+
+            [author_aliases]
+            "Robert Galbraith" = "J.K. Rowling"
+            "#,
         )
-    });
+        .unwrap();
+        let list = ListGrabber {
+            grab: &[],
+            prefer_format: None,
+            unsat_buffer: None,
+            wedge_buffer: None,
+            dry_run: true,
+            min_score: None,
+            require_author_match: true,
+            match_min_score: None,
+            match_window: None,
+            require_series_match: false,
+        };
+        let torrent = MaMTorrent {
+            title: "The Cuckoo's Calling".to_string(),
+            author_info: [(1, "Robert Galbraith".to_string())].into(),
+            ..Default::default()
+        };
+        let authors = vec!["J.K. Rowling".to_string()];
+        assert!(list.accepts(&config, "The Cuckoo's Calling", &authors, &torrent));
 
-    Ok(torrents)
+        assert_eq!(
+            config.author_names("j.k. rowling"),
+            vec!["J.K. Rowling", "Robert Galbraith"]
+        );
+        assert_eq!(config.canonical_author("robert galbraith"), "J.K. Rowling");
+    }
+
+    fn list_item(title: &str, author: &str, series: &[(&str, f64)]) -> ListItem {
+        ListItem {
+            guid: ("list".to_string(), title.to_string()),
+            list_id: "list".to_string(),
+            title: title.to_string(),
+            authors: vec![author.to_string()],
+            series: series
+                .iter()
+                .map(|(name, num)| (name.to_string(), *num))
+                .collect(),
+            cover_url: String::new(),
+            book_url: None,
+            isbn: None,
+            prefer_format: None,
+            allow_audio: true,
+            audio_torrent: None,
+            allow_ebook: true,
+            ebook_torrent: None,
+            created_at: Timestamp::now(),
+            marked_done_at: None,
+            candidates: vec![],
+        }
+    }
+
+    fn search_result(id: u64, title: &str, author: &str, series: Option<&str>) -> MaMTorrent {
+        MaMTorrent {
+            id,
+            title: title.to_string(),
+            author_info: [(1, author.to_string())].into(),
+            series_info: series
+                .map(|name| [(1, vec![Value::from(name), Value::from("1")])].into())
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_match_min_score_borderline() {
+        let config: Config = toml::from_str(r#"mam_id = "abc""#).unwrap();
+        let item = list_item("Leviathan Wakes", "James S. A. Corey", &[]);
+        let exact = search_result(1, "Leviathan Wakes", "James S. A. Corey", None);
+        let borderline = search_result(2, "Leviathan Falls", "James S. A. Corey", None);
+        let exact_score = match_score(&config, &item, &exact);
+        let borderline_score = match_score(&config, &item, &borderline);
+        assert!(borderline_score < exact_score);
+
+        let settings = |min_score| MatchSettings {
+            min_score,
+            window: DEFAULT_MATCH_WINDOW as usize,
+            require_series_match: false,
+        };
+        let scored = vec![(borderline.clone(), borderline_score)];
+        let accepted = settings(borderline_score).best_matches(scored.clone());
+        assert_eq!(accepted.unwrap()[0].id, 2);
+        let rejected = settings(borderline_score + 1).best_matches(scored);
+        let unmatched = rejected.unwrap_err();
+        assert_eq!(unmatched.len(), 1);
+        assert_eq!(unmatched[0].0.id, 2);
+        assert_eq!(unmatched[0].1, borderline_score);
+
+        // A good enough result is grabbed even with a borderline one around
+        let scored = vec![(borderline, borderline_score), (exact, exact_score)];
+        let accepted = settings(borderline_score + 1).best_matches(scored);
+        assert_eq!(accepted.unwrap()[0].id, 1);
+    }
+
+    #[test]
+    fn test_match_score_novella() {
+        let config: Config = toml::from_str(r#"mam_id = "abc""#).unwrap();
+        let result = |id, title: &str, num: &str| MaMTorrent {
+            series_info: [(1, vec![Value::from("The Expanse"), Value::from(num)])].into(),
+            ..search_result(id, title, "James S. A. Corey", None)
+        };
+        let novella = result(1, "Gods of Risk", "2.5");
+        let book = result(2, "Caliban's War", "2");
+        let item = list_item("Gods of Risk", "James S. A. Corey", &[("The Expanse", 2.5)]);
+        assert!(match_score(&config, &item, &novella) > match_score(&config, &item, &book));
+
+        assert_eq!(series_num_score(2.5, "2.5"), 100);
+        assert_eq!(series_num_score(2.5, "2"), 0);
+        assert_eq!(series_num_score(2.0, "2.5"), 0);
+        assert_eq!(series_num_score(2.5, "1-3"), 100);
+        assert_eq!(series_num_score(2.0, "2"), 100);
+    }
+
+    #[test]
+    fn test_list_item_matches_novella() {
+        let grab = Grab {
+            cost: Cost::Free,
+            ..Default::default()
+        };
+        let (_, mut meta, _, _) = candidate(1, Language::English, &grab);
+        meta.title = "The Expanse Books 2-3".to_string();
+        meta.series = vec![Series {
+            name: "The Expanse".to_string(),
+            entries: SeriesEntries::new(vec![SeriesEntry::Range(2.0, 3.0)]),
+        }];
+        let item = |num| list_item("Gods of Risk", "An Author", &[("The Expanse", num)]);
+        assert!(item(2.5).in_omnibus(&meta));
+        assert!(item(2.5).matches(&meta));
+        assert!(!item(3.5).matches(&meta));
+
+        meta.series[0].entries = SeriesEntries::new(vec![SeriesEntry::Num(2.5)]);
+        let novella = list_item("Gods of Risk", "An Author", &[("The Expanse", 2.5)]);
+        meta.title = "Gods of Risk".to_string();
+        assert!(novella.matches(&meta));
+    }
+
+    #[test]
+    fn test_match_window() {
+        let settings = MatchSettings {
+            min_score: 0,
+            window: 10,
+            require_series_match: false,
+        };
+        let scored = vec![("c", 85), ("a", 100), ("b", 91)];
+        assert_eq!(
+            settings.best_matches(scored.clone()).unwrap(),
+            vec!["a", "b"]
+        );
+        let settings = MatchSettings {
+            window: 0,
+            ..settings
+        };
+        assert_eq!(settings.best_matches(scored).unwrap(), vec!["a"]);
+        assert_eq!(
+            settings.best_matches::<&str>(vec![]).unwrap(),
+            Vec::<&str>::new()
+        );
+    }
+
+    #[test]
+    fn test_match_settings_override() {
+        let grabs = [Grab {
+            match_window: Some(20),
+            require_series_match: Some(false),
+            ..Default::default()
+        }];
+        let list = ListGrabber {
+            grab: &grabs,
+            prefer_format: None,
+            unsat_buffer: None,
+            wedge_buffer: None,
+            dry_run: true,
+            min_score: None,
+            require_author_match: false,
+            match_min_score: Some(50),
+            match_window: Some(30),
+            require_series_match: true,
+        };
+        assert_eq!(
+            list.match_settings(&grabs[0]),
+            MatchSettings {
+                min_score: 50,
+                window: 20,
+                require_series_match: false,
+            }
+        );
+        assert_eq!(
+            list.match_settings(&Grab::default()),
+            MatchSettings {
+                min_score: 50,
+                window: 30,
+                require_series_match: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_require_series_match() {
+        let series = vec![("The Expanse".to_string(), 1.0)];
+        let right = search_result(
+            1,
+            "Leviathan Wakes",
+            "James S. A. Corey",
+            Some("The Expanse"),
+        );
+        let wrong = search_result(2, "Leviathan Wakes", "Someone Else", Some("Leviathan"));
+        let none = search_result(3, "Leviathan Wakes", "Someone Else", None);
+        assert!(series_matches(&series, &right));
+        assert!(!series_matches(&series, &wrong));
+        assert!(!series_matches(&series, &none));
+        assert!(series_matches(&[], &none));
+    }
+
+    #[test]
+    fn test_language_priority_prefers_earlier_language() {
+        let grab = Grab {
+            cost: Cost::Free,
+            filter: TorrentFilter {
+                language_priority: vec![Language::English, Language::German],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut torrents = vec![
+            candidate(1, Language::German, &grab),
+            candidate(2, Language::English, &grab),
+        ];
+        rank_torrents(&mut torrents);
+        assert_eq!(torrents[0].1.language, Some(Language::English));
+        assert_eq!(torrents[1].1.language, Some(Language::German));
+    }
+
+    #[test]
+    fn test_language_priority_keeps_unlisted_languages() {
+        let grab = Grab {
+            cost: Cost::Free,
+            filter: TorrentFilter {
+                language_priority: vec![Language::English],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut torrents = vec![
+            candidate(1, Language::German, &grab),
+            candidate(2, Language::English, &grab),
+        ];
+        rank_torrents(&mut torrents);
+        assert_eq!(torrents.len(), 2);
+        assert_eq!(torrents[0].0.id, 2);
+    }
+
+    #[test]
+    fn test_preferred_narrator() {
+        let grab = Grab {
+            cost: Cost::Free,
+            preferred_narrators: vec!["Jefferson Mays".to_string()],
+            ..Default::default()
+        };
+        let mut torrents = vec![
+            candidate(1, Language::English, &grab),
+            candidate(2, Language::English, &grab),
+        ];
+        for (torrent, narrator) in torrents.iter_mut().zip(["Scott Brick", "jefferson mays"]) {
+            torrent.0.narrator_info = [(1, narrator.to_string())].into();
+            torrent.1.narrators = vec![narrator.to_string()];
+        }
+
+        rank_torrents(&mut torrents);
+        assert_eq!(torrents[0].0.id, 2);
+        assert_eq!(torrents[1].0.id, 1);
+    }
+
+    #[test]
+    fn test_preferred_narrator_needs_match_min_score() {
+        let config: Config = toml::from_str(r#"mam_id = "abc""#).unwrap();
+        let item = list_item("Leviathan Wakes", "James S. A. Corey", &[]);
+        let exact = search_result(1, "Leviathan Wakes", "James S. A. Corey", None);
+        let other = MaMTorrent {
+            narrator_info: [(1, "Jefferson Mays".to_string())].into(),
+            ..search_result(2, "Gods of Risk", "James S. A. Corey", None)
+        };
+        let other_score = match_score(&config, &item, &other);
+        assert!(other_score < match_score(&config, &item, &exact));
+
+        let settings = MatchSettings {
+            min_score: other_score + 1,
+            window: DEFAULT_MATCH_WINDOW as usize,
+            require_series_match: false,
+        };
+        let unmatched = settings
+            .best_matches(vec![(other, other_score)])
+            .unwrap_err();
+        assert_eq!(unmatched[0].0.id, 2);
+    }
+
+    #[test]
+    fn test_file_count_filter_keeps_tie_break() {
+        let grab = Grab {
+            cost: Cost::Free,
+            filter: TorrentFilter {
+                max_files: Some(10),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut torrents = vec![
+            candidate(1, Language::English, &grab),
+            candidate(2, Language::English, &grab),
+            candidate(3, Language::English, &grab),
+        ];
+        for (torrent, numfiles) in torrents.iter_mut().zip([8, 2, 200]) {
+            torrent.0.category = AudiobookCategory::GeneralFiction.to_id() as u64;
+            torrent.0.numfiles = numfiles;
+        }
+        let mut torrents = torrents
+            .into_iter()
+            .filter(|t| grab.filter.matches(&t.0))
+            .collect::<Vec<_>>();
+        rank_torrents(&mut torrents);
+        assert_eq!(
+            torrents.iter().map(|t| t.0.id).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+    }
+
+    #[test]
+    fn test_list_item_matches_omnibus() {
+        let grab = Grab {
+            cost: Cost::Free,
+            ..Default::default()
+        };
+        let (_, mut meta, _, _) = candidate(1, Language::English, &grab);
+        meta.title = "The Expanse Books 1-3".to_string();
+        meta.series = vec![Series {
+            name: "The Expanse".to_string(),
+            entries: SeriesEntries::new(vec![SeriesEntry::Range(1.0, 3.0)]),
+        }];
+        let item = |num: f64| ListItem {
+            guid: ("list".to_string(), num.to_string()),
+            list_id: "list".to_string(),
+            title: "Caliban's War".to_string(),
+            authors: vec!["An Author".to_string()],
+            series: vec![("The Expanse".to_string(), num)],
+            cover_url: String::new(),
+            book_url: None,
+            isbn: None,
+            prefer_format: None,
+            allow_audio: true,
+            audio_torrent: None,
+            allow_ebook: true,
+            ebook_torrent: None,
+            created_at: Timestamp::now(),
+            marked_done_at: None,
+            candidates: vec![],
+        };
+        assert!(item(2.0).matches(&meta));
+        assert!(!item(4.0).matches(&meta));
+    }
+
+    #[test]
+    fn test_satisfied_once() {
+        let grab = Grab {
+            cost: Cost::Free,
+            ..Default::default()
+        };
+        let (_, meta, _, _) = candidate(7, Language::English, &grab);
+        let torrent = library_torrent("abc", meta);
+        let mut item = ListItem {
+            guid: ("list".to_string(), "1".to_string()),
+            list_id: "list".to_string(),
+            title: "The Book".to_string(),
+            authors: vec!["An Author".to_string()],
+            series: vec![],
+            cover_url: String::new(),
+            book_url: None,
+            isbn: None,
+            prefer_format: Some(OldDbMainCat::Audio),
+            allow_audio: true,
+            audio_torrent: None,
+            allow_ebook: true,
+            ebook_torrent: None,
+            created_at: Timestamp::now(),
+            marked_done_at: None,
+            candidates: vec![],
+        };
+        assert_eq!(satisfied_by(&item, false), None);
+
+        let updated = set_existing(&mut item.audio_torrent, &torrent);
+        assert_eq!(satisfied_by(&item, updated), Some(7));
+
+        // The next import finds the same torrent again
+        let updated = set_existing(&mut item.audio_torrent, &torrent);
+        assert!(!updated);
+        assert_eq!(satisfied_by(&item, updated), None);
+
+        // Finding one format is not enough when both are wanted
+        item.prefer_format = None;
+        item.audio_torrent = None;
+        let updated = set_existing(&mut item.audio_torrent, &torrent);
+        assert!(updated);
+        assert_eq!(satisfied_by(&item, updated), None);
+    }
+
+    #[test]
+    fn test_collection_satisfies_items() {
+        let grab = Grab {
+            cost: Cost::Free,
+            ..Default::default()
+        };
+        let (_, mut meta, _, _) = candidate(9, Language::English, &grab);
+        meta.title = "The Expanse Collection".to_string();
+        meta.series = vec![Series {
+            name: "The Expanse".to_string(),
+            entries: SeriesEntries::new(vec![
+                SeriesEntry::Num(1.0),
+                SeriesEntry::Num(2.0),
+                SeriesEntry::Num(3.0),
+            ]),
+        }];
+        let torrent = library_torrent("abc", meta.clone());
+        let item = |title: &str, num: f64| ListItem {
+            guid: ("list".to_string(), title.to_string()),
+            list_id: "list".to_string(),
+            title: title.to_string(),
+            authors: vec!["An Author".to_string()],
+            series: vec![("The Expanse".to_string(), num)],
+            cover_url: String::new(),
+            book_url: None,
+            isbn: None,
+            prefer_format: Some(OldDbMainCat::Audio),
+            allow_audio: true,
+            audio_torrent: None,
+            allow_ebook: true,
+            ebook_torrent: None,
+            created_at: Timestamp::now(),
+            marked_done_at: None,
+            candidates: vec![],
+        };
+        let mut items = vec![
+            item("Leviathan Wakes", 1.0),
+            item("Caliban's War", 2.0),
+            item("Abaddon's Gate", 3.0),
+        ];
+
+        let config: Config = toml::from_str(r#"mam_id = "abc""#).unwrap();
+        assert!(items.iter().all(|item| !covers_item(&config, item, &meta)));
+
+        let config: Config = toml::from_str(
+            r#"
+            mam_id = "abc"
+            match_collections = true
+            "#,
+        )
+        .unwrap();
+        for item in &mut items {
+            assert!(covers_item(&config, item, &meta));
+            let updated = set_existing(&mut item.audio_torrent, &torrent);
+            assert_eq!(satisfied_by(item, updated), Some(9));
+        }
+        assert!(!covers_item(&config, &item("Cibola Burn", 4.0), &meta));
+    }
+
+    #[tokio::test]
+    async fn test_search_pages_stop_at_cap() {
+        let mut fetched = 0;
+        let results = fetch_search_pages(3, 500, |start| {
+            fetched += 1;
+            async move {
+                Ok(SearchResult {
+                    perpage: 100,
+                    start: start as usize,
+                    data: (0..100)
+                        .map(|i| MaMTorrent {
+                            id: start + i,
+                            ..Default::default()
+                        })
+                        .collect(),
+                    total: 500,
+                    found: 500,
+                })
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(fetched, 3);
+        assert_eq!(results.data.len(), 300);
+        assert_eq!(results.data.last().unwrap().id, 299);
+
+        let results = fetch_search_pages(5, 250, |start| async move {
+            Ok(SearchResult {
+                data: (0..100)
+                    .map(|i| MaMTorrent {
+                        id: start + i,
+                        ..Default::default()
+                    })
+                    .collect(),
+                found: 500,
+                ..Default::default()
+            })
+        })
+        .await
+        .unwrap();
+        assert_eq!(results.data.len(), 250);
+    }
 }
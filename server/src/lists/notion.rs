@@ -1,17 +1,22 @@
 use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
-use anyhow::{Context, Result};
-use mlm_db::{Torrent, TorrentKey};
-use mlm_mam::api::MaM;
+use anyhow::{Context, Result, bail};
+use itertools::Itertools as _;
+use mlm_db::{DatabaseExt as _, List, ListItem, Timestamp, Torrent, TorrentKey};
+use mlm_mam::{api::MaM, http};
+use mlm_parse::clean_value;
 use native_db::Database;
+use reqwest::RequestBuilder;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Value, json};
 use tokio::time::sleep;
 use tracing::{instrument, trace};
 
 use crate::{
     autograbber::select_torrents,
     config::{Config, NotionList},
+    lists::{ListGrabber, SERIES_POSITION, search_item},
+    stats::{ProgressReporter, ProgressTask, Stats},
 };
 
 static IMPORT_MUTEX: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
@@ -23,26 +28,63 @@ pub async fn run_notion_import(
     mam: Arc<MaM<'_>>,
     list: &NotionList,
     max_torrents: u64,
-) -> Result<()> {
+    stats: &Stats,
+    index: usize,
+) -> Result<Vec<(ListItem, Torrent)>> {
     // Make sure we are only running one import at a time
     let _guard = IMPORT_MUTEX.lock().await;
 
-    let content: DatasourceResponse = reqwest::ClientBuilder::new()
-        .build()?
-        .post(format!(
+    let data_source = fetch_data_source(list).await?;
+    check_mapping(list, &data_source)?;
+
+    let content: DatasourceResponse = http::send_with_retry(notion_request(
+        http::client().post(format!(
             "https://api.notion.com/v1/data_sources/{}/query",
             list.data_source,
-        ))
-        .header("Notion-Version", "2025-09-03")
-        .header("Authorization", format!("Bearer {}", list.token))
-        .send()
-        .await?
-        .json()
-        .await?;
+        )),
+        list,
+    ))
+    .await?
+    .json()
+    .await?;
 
     trace!("Scanning Notion list {}", list.name);
 
+    let list_id = list.list_id();
+    if list.title_property.is_some() && !list.dry_run {
+        let (_guard, rw) = db.rw_async().await?;
+        rw.upsert(List {
+            id: list_id.clone(),
+            title: list.name.clone(),
+            updated_at: Some(Timestamp::now()),
+            build_date: Some(Timestamp::now()),
+            etag: None,
+            last_modified: None,
+        })?;
+        rw.commit()?;
+    }
+
+    let grabber = ListGrabber {
+        grab: &list.grab,
+        prefer_format: None,
+        unsat_buffer: list.unsat_buffer,
+        wedge_buffer: list.wedge_buffer,
+        dry_run: list.dry_run,
+        min_score: None,
+        require_author_match: false,
+        match_min_score: None,
+        match_window: None,
+        require_series_match: false,
+    };
+
+    let mut satisfied = vec![];
+    let mut progress =
+        ProgressReporter::start(stats, ProgressTask::Import(index), content.results.len()).await;
     for item in content.results.into_iter() {
+        progress.advance().await;
+        if !is_wanted(list, &item) {
+            continue;
+        }
         let mam_ids = list
             .mam_fields
             .iter()
@@ -57,6 +99,61 @@ pub async fn run_notion_import(
             })
             .collect::<Vec<u64>>();
 
+        // Without a link to MaM the book is searched for like on other lists
+        if mam_ids.is_empty() {
+            let Some(item) = as_list_item(&item, &list_id, list) else {
+                continue;
+            };
+            let db_item = match db
+                .r_transaction()?
+                .get()
+                .primary::<ListItem>(item.guid.clone())?
+            {
+                Some(mut db_item) => {
+                    if db_item.title != item.title
+                        || db_item.authors != item.authors
+                        || db_item.series != item.series
+                    {
+                        db_item.title = item.title.clone();
+                        db_item.authors = item.authors.clone();
+                        db_item.series = item.series.clone();
+                        if !list.dry_run {
+                            let (_guard, rw) = db.rw_async().await?;
+                            rw.upsert(db_item.clone())?;
+                            rw.commit()?;
+                        }
+                    }
+                    if db_item.audio_torrent.is_some() && db_item.ebook_torrent.is_some() {
+                        continue;
+                    }
+                    db_item
+                }
+                None => {
+                    if !list.dry_run {
+                        let (_guard, rw) = db.rw_async().await?;
+                        rw.insert(item.clone())?;
+                        rw.commit()?;
+                    }
+                    item
+                }
+            };
+            trace!("Searching for book {} from Notion", db_item.title);
+            search_item(
+                &config,
+                &db,
+                &mam,
+                &grabber,
+                None,
+                db_item,
+                max_torrents,
+                &mut satisfied,
+            )
+            .await
+            .context("search notion book")?;
+            sleep(Duration::from_millis(400)).await;
+            continue;
+        }
+
         'torrent: for mam_id in mam_ids {
             let torrent = db
                 .r_transaction()?
@@ -75,7 +172,7 @@ pub async fn run_notion_import(
                     select_torrents(
                         &config,
                         &db,
-                        &mam,
+                        mam.as_ref(),
                         [torrent].into_iter(),
                         &grab.filter,
                         grab.cost,
@@ -85,6 +182,7 @@ pub async fn run_notion_import(
                         list.dry_run,
                         max_torrents,
                         None,
+                        None,
                     )
                     .await
                     .context("select_torrents")?;
@@ -95,9 +193,171 @@ pub async fn run_notion_import(
         }
     }
 
+    Ok(satisfied)
+}
+
+/// Sets `status_property` of the items' pages to `status`
+pub async fn update_notion_status(
+    list: &NotionList,
+    items: impl Iterator<Item = &ListItem>,
+    status: &str,
+) -> Result<()> {
+    let Some(status_property) = &list.status_property else {
+        return Ok(());
+    };
+    let data_source = fetch_data_source(list).await?;
+    check_mapping(list, &data_source)?;
+    let kind = &data_source.properties[status_property].kind;
+    let properties = json!({ status_property: status_value(kind, status) });
+    for item in items {
+        let (_, page_id) = &item.guid;
+        http::send_with_retry(
+            notion_request(
+                http::client().patch(format!("https://api.notion.com/v1/pages/{page_id}")),
+                list,
+            )
+            .json(&json!({ "properties": properties })),
+        )
+        .await?
+        .error_for_status()
+        .with_context(|| format!("update Notion page for {}", item.title))?;
+        trace!("Set Notion status of {} to {status}", item.title);
+        sleep(Duration::from_millis(400)).await;
+    }
+    Ok(())
+}
+
+async fn fetch_data_source(list: &NotionList) -> Result<DataSource> {
+    let data_source = http::send_with_retry(notion_request(
+        http::client().get(format!(
+            "https://api.notion.com/v1/data_sources/{}",
+            list.data_source,
+        )),
+        list,
+    ))
+    .await?
+    .error_for_status()
+    .context("Notion data source")?
+    .json()
+    .await?;
+    Ok(data_source)
+}
+
+/// The value of a status or select property with the option named `name`
+fn status_value(kind: &str, name: &str) -> Value {
+    match kind {
+        "status" => json!({ "status": { "name": name } }),
+        _ => json!({ "select": { "name": name } }),
+    }
+}
+
+fn notion_request(request: RequestBuilder, list: &NotionList) -> RequestBuilder {
+    request
+        .header("Notion-Version", "2025-09-03")
+        .header("Authorization", format!("Bearer {}", list.token))
+}
+
+/// Checks that the configured properties exist in the data source and have a
+/// type they can be read from
+fn check_mapping(list: &NotionList, data_source: &DataSource) -> Result<()> {
+    let mut problems = vec![];
+    for (setting, name, types) in list.property_mapping() {
+        match data_source.properties.get(name) {
+            None => problems.push(format!("{setting} {name:?} does not exist")),
+            Some(property) if !types.contains(&property.kind.as_str()) => problems.push(format!(
+                "{setting} {name:?} is a {} property, expected {}",
+                property.kind,
+                types.join(" or ")
+            )),
+            Some(_) => {}
+        }
+    }
+    if !problems.is_empty() {
+        bail!(
+            "Notion list {}: {}. Available properties: {}",
+            list.name,
+            problems.join(", "),
+            data_source
+                .properties
+                .iter()
+                .map(|(name, property)| format!("{name:?} ({})", property.kind))
+                .join(", ")
+        );
+    }
     Ok(())
 }
 
+fn is_wanted(list: &NotionList, item: &Item) -> bool {
+    let Some(status_property) = &list.status_property else {
+        return true;
+    };
+    item.properties.get(status_property).is_some_and(|status| {
+        status.texts().iter().any(|status| {
+            list.status_values
+                .iter()
+                .any(|value| value.eq_ignore_ascii_case(status))
+        })
+    })
+}
+
+/// The book the item is about, if the list has a `title_property` and the item
+/// has a title
+fn as_list_item(item: &Item, list_id: &str, list: &NotionList) -> Option<ListItem> {
+    let clean = |value: &str| clean_value(value).unwrap_or_else(|_| value.to_string());
+    let texts = |property: &Option<String>| {
+        property
+            .as_ref()
+            .and_then(|property| item.properties.get(property))
+            .map(Property::texts)
+            .unwrap_or_default()
+    };
+    let title = texts(&list.title_property).into_iter().next()?;
+    let authors = texts(&list.author_property)
+        .iter()
+        .flat_map(|authors| authors.split(','))
+        .map(|author| clean(author.trim()))
+        .filter(|author| !author.is_empty())
+        .collect();
+    let series = texts(&list.series_property)
+        .iter()
+        .flat_map(|series| series.split(';'))
+        .filter_map(|series| {
+            let (_, [name, num]) = SERIES_POSITION.captures(series.trim())?.extract();
+            Some((clean(name), num.parse().ok()?))
+        })
+        .collect();
+
+    Some(ListItem {
+        guid: (list_id.to_owned(), item.id.clone()),
+        list_id: list_id.to_owned(),
+        title: clean(&title),
+        authors,
+        series,
+        cover_url: String::new(),
+        book_url: item.url.clone(),
+        isbn: None,
+        prefer_format: None,
+        allow_audio: list.allow_audio(),
+        audio_torrent: None,
+        allow_ebook: list.allow_ebook(),
+        ebook_torrent: None,
+        created_at: Timestamp::now(),
+        marked_done_at: None,
+        candidates: vec![],
+    })
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataSource {
+    pub properties: BTreeMap<String, PropertySchema>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropertySchema {
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DatasourceResponse {
     pub results: Vec<Item>,
@@ -112,6 +372,7 @@ pub struct Item {
     pub last_edited_time: String,
     pub archived: bool,
     pub in_trash: bool,
+    pub url: Option<String>,
     pub properties: BTreeMap<String, Property>,
 }
 
@@ -130,6 +391,33 @@ pub enum Property {
     Url(Url),
 }
 
+impl Property {
+    /// The text of the property, one per option for selects
+    fn texts(&self) -> Vec<String> {
+        let texts = match self {
+            Property::Title(title) => vec![plain_text(&title.title)],
+            Property::RichText(text) => vec![plain_text(&text.rich_text)],
+            Property::Select(select) => select.select.iter().map(|s| s.name.clone()).collect(),
+            Property::MultiSelect(select) => {
+                select.multi_select.iter().map(|s| s.name.clone()).collect()
+            }
+            Property::Status(status) => status.status.iter().map(|s| s.name.clone()).collect(),
+            Property::Url(url) => url.url.iter().cloned().collect(),
+            _ => vec![],
+        };
+        texts.into_iter().filter(|text| !text.is_empty()).collect()
+    }
+}
+
+fn plain_text(values: &[Value]) -> String {
+    values
+        .iter()
+        .filter_map(|value| value.get("plain_text")?.as_str())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Date {
     pub id: String,
@@ -227,3 +515,126 @@ pub struct Title {
     pub id: String,
     pub title: Vec<Value>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list() -> NotionList {
+        toml::from_str(
+            r#"
+            data_source = "abc"
+            token = "secret"
+            name = "Reading list"
+            mam_fields = ["MaM"]
+            title_property = "Book Name"
+            author_property = "Writer"
+            series_property = "Saga"
+            status_property = "Reading state"
+            status_values = ["Want to read"]
+            [[grab]]
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn data_source() -> DataSource {
+        serde_json::from_str(
+            r#"{
+                "object": "data_source",
+                "id": "abc",
+                "properties": {
+                    "Book Name": { "id": "title", "name": "Book Name", "type": "title", "title": {} },
+                    "Writer": { "id": "a1", "name": "Writer", "type": "multi_select", "multi_select": {} },
+                    "Saga": { "id": "a2", "name": "Saga", "type": "rich_text", "rich_text": {} },
+                    "Reading state": { "id": "a3", "name": "Reading state", "type": "status", "status": {} },
+                    "MaM": { "id": "a4", "name": "MaM", "type": "url", "url": {} }
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_custom_properties() {
+        let list = list();
+        check_mapping(&list, &data_source()).unwrap();
+
+        let response: DatasourceResponse = serde_json::from_str(
+            r#"{
+                "object": "list",
+                "results": [{
+                    "object": "page",
+                    "id": "page-1",
+                    "created_time": "2025-01-01T00:00:00.000Z",
+                    "last_edited_time": "2025-01-01T00:00:00.000Z",
+                    "archived": false,
+                    "in_trash": false,
+                    "url": "https://www.notion.so/page-1",
+                    "properties": {
+                        "Book Name": { "id": "title", "type": "title", "title": [
+                            { "type": "text", "plain_text": "Caliban's War" }
+                        ] },
+                        "Writer": { "id": "a1", "type": "multi_select", "multi_select": [
+                            { "id": "o1", "name": "James S. A. Corey", "color": "blue" }
+                        ] },
+                        "Saga": { "id": "a2", "type": "rich_text", "rich_text": [
+                            { "type": "text", "plain_text": "The Expanse #2" }
+                        ] },
+                        "Reading state": { "id": "a3", "type": "status", "status":
+                            { "id": "s1", "name": "Want to read", "color": "green" }
+                        },
+                        "MaM": { "id": "a4", "type": "url", "url": null }
+                    }
+                }, {
+                    "object": "page",
+                    "id": "page-2",
+                    "created_time": "2025-01-01T00:00:00.000Z",
+                    "last_edited_time": "2025-01-01T00:00:00.000Z",
+                    "archived": false,
+                    "in_trash": false,
+                    "properties": {
+                        "Book Name": { "id": "title", "type": "title", "title": [
+                            { "type": "text", "plain_text": "Leviathan Wakes" }
+                        ] },
+                        "Reading state": { "id": "a3", "type": "status", "status":
+                            { "id": "s2", "name": "Read", "color": "gray" }
+                        }
+                    }
+                }],
+                "next_cursor": null,
+                "has_more": false
+            }"#,
+        )
+        .unwrap();
+
+        assert!(is_wanted(&list, &response.results[0]));
+        assert!(!is_wanted(&list, &response.results[1]));
+
+        let item = as_list_item(&response.results[0], &list.list_id(), &list).unwrap();
+        assert_eq!(item.guid, ("notion:abc".to_string(), "page-1".to_string()));
+        assert_eq!(item.title, "Caliban's War");
+        assert_eq!(item.authors, vec!["James S. A. Corey".to_string()]);
+        assert_eq!(item.series, vec![("The Expanse".to_string(), 2.0)]);
+        assert_eq!(
+            item.book_url.as_deref(),
+            Some("https://www.notion.so/page-1")
+        );
+    }
+
+    #[test]
+    fn test_wrong_mapping_lists_properties() {
+        let mut list = list();
+        list.author_property = Some("Author".to_string());
+        list.series_property = Some("Reading state".to_string());
+        let err = check_mapping(&list, &data_source())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(r#"author_property "Author" does not exist"#));
+        assert!(err.contains(
+            r#"series_property "Reading state" is a status property, expected rich_text or select or multi_select"#
+        ));
+        assert!(err.contains(r#""Book Name" (title)"#));
+        assert!(err.contains(r#""Writer" (multi_select)"#));
+    }
+}
@@ -0,0 +1,313 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use mlm_db::{DatabaseExt as _, List, ListItem, OldDbMainCat, Timestamp, Torrent};
+use mlm_mam::{api::MaM, http};
+use mlm_parse::clean_value;
+use native_db::Database;
+use serde::Deserialize;
+use tokio::time::sleep;
+use tracing::{instrument, trace};
+
+use crate::{
+    config::{Config, ReadarrList},
+    lists::{ListGrabber, SERIES_POSITION, search_item},
+    stats::{ProgressReporter, ProgressTask, Stats},
+};
+
+const PAGE_SIZE: u64 = 100;
+
+static IMPORT_MUTEX: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[instrument(skip_all)]
+pub async fn run_readarr_import(
+    config: Arc<Config>,
+    db: Arc<Database<'_>>,
+    mam: Arc<MaM<'_>>,
+    list: &ReadarrList,
+    max_torrents: u64,
+    stats: &Stats,
+    index: usize,
+) -> Result<Vec<(ListItem, Torrent)>> {
+    // Make sure we are only running one import at a time
+    let _guard = IMPORT_MUTEX.lock().await;
+    let mut satisfied = vec![];
+
+    let books = fetch_missing(&http::client(), list).await?;
+    trace!("Scanning Readarr list {}", list.url);
+
+    let list_id = list.list_id();
+
+    if !list.dry_run {
+        let (_guard, rw) = db.rw_async().await?;
+        rw.upsert(List {
+            id: list_id.clone(),
+            title: list.name.clone().unwrap_or_else(|| "Readarr".to_string()),
+            updated_at: Some(Timestamp::now()),
+            build_date: Some(Timestamp::now()),
+            etag: None,
+            last_modified: None,
+        })?;
+        rw.commit()?;
+    }
+
+    let grabber = ListGrabber {
+        grab: &list.grab,
+        prefer_format: list.prefer_format,
+        unsat_buffer: list.unsat_buffer,
+        wedge_buffer: list.wedge_buffer,
+        dry_run: list.dry_run,
+        min_score: list.min_score,
+        require_author_match: list.require_author_match,
+        match_min_score: list.match_min_score,
+        match_window: list.match_window,
+        require_series_match: list.require_series_match,
+    };
+
+    let mut progress =
+        ProgressReporter::start(stats, ProgressTask::Import(index), books.len()).await;
+    for book in books {
+        progress.advance().await;
+        let item = book.as_list_item(&list_id, list);
+        let db_item = match db
+            .r_transaction()?
+            .get()
+            .primary::<ListItem>(item.guid.clone())?
+        {
+            Some(mut db_item) => {
+                if db_item.prefer_format != list.prefer_format
+                    || db_item.allow_audio != list.allow_audio()
+                    || db_item.allow_ebook != list.allow_ebook()
+                    || db_item.title != item.title
+                    || db_item.series != item.series
+                {
+                    db_item.prefer_format = list.prefer_format;
+                    db_item.allow_audio = list.allow_audio();
+                    db_item.allow_ebook = list.allow_ebook();
+                    db_item.title = item.title.clone();
+                    db_item.series = item.series.clone();
+                    if !list.dry_run {
+                        let (_guard, rw) = db.rw_async().await?;
+                        rw.upsert(db_item.clone())?;
+                        rw.commit()?;
+                    }
+                }
+                if (db_item.audio_torrent.is_some() && db_item.ebook_torrent.is_some())
+                    || (list.prefer_format == Some(OldDbMainCat::Audio)
+                        && db_item.audio_torrent.is_some())
+                    || (list.prefer_format == Some(OldDbMainCat::Ebook)
+                        && db_item.ebook_torrent.is_some())
+                {
+                    continue;
+                }
+                db_item
+            }
+            None => {
+                if !list.dry_run {
+                    let (_guard, rw) = db.rw_async().await?;
+                    rw.insert(item.clone())?;
+                    rw.commit()?;
+                }
+                item
+            }
+        };
+        trace!("Searching for book {} from Readarr", db_item.title);
+        // Readarr uses Goodreads ids unless another metadata source is configured
+        let goodreads_id = book.foreign_book_id.parse().ok();
+        search_item(
+            &config,
+            &db,
+            &mam,
+            &grabber,
+            goodreads_id,
+            db_item,
+            max_torrents,
+            &mut satisfied,
+        )
+        .await
+        .context("search readarr book")?;
+        sleep(Duration::from_millis(400)).await;
+    }
+
+    Ok(satisfied)
+}
+
+async fn fetch_missing(client: &reqwest::Client, list: &ReadarrList) -> Result<Vec<Book>> {
+    let mut books = vec![];
+    for page in 1.. {
+        let request = client
+            .get(format!(
+                "{}/api/v1/wanted/missing",
+                list.url.trim_end_matches('/')
+            ))
+            .query(&[
+                ("page", page.to_string()),
+                ("pageSize", PAGE_SIZE.to_string()),
+                ("includeAuthor", "true".to_string()),
+                ("monitored", "true".to_string()),
+            ])
+            .header("X-Api-Key", &list.api_key);
+        let response: WantedResponse = http::send_with_retry(request)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let done = response.records.len() < PAGE_SIZE as usize
+            || page * PAGE_SIZE >= response.total_records;
+        books.extend(response.records);
+        if done {
+            break;
+        }
+    }
+    Ok(books)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WantedResponse {
+    total_records: u64,
+    records: Vec<Book>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Book {
+    id: u64,
+    title: String,
+    #[serde(default)]
+    series_title: Option<String>,
+    #[serde(default)]
+    foreign_book_id: String,
+    author: Option<Author>,
+    #[serde(default)]
+    images: Vec<Image>,
+    #[serde(default)]
+    links: Vec<Link>,
+    #[serde(default)]
+    editions: Vec<Edition>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Author {
+    author_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Image {
+    cover_type: String,
+    remote_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Link {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Edition {
+    isbn13: Option<String>,
+    #[serde(default)]
+    monitored: bool,
+}
+
+impl Book {
+    fn as_list_item(&self, list_id: &str, list: &ReadarrList) -> ListItem {
+        let clean = |value: &str| clean_value(value).unwrap_or_else(|_| value.to_string());
+        // Readarr joins multiple series with "; ", e.g. "The Expanse #2; Other Series #5"
+        let series = self
+            .series_title
+            .iter()
+            .flat_map(|series| series.split(';'))
+            .filter_map(|series| {
+                let (_, [name, num]) = SERIES_POSITION.captures(series.trim())?.extract();
+                Some((clean(name), num.parse().ok()?))
+            })
+            .collect();
+        let isbn = self
+            .editions
+            .iter()
+            .find(|e| e.monitored)
+            .or(self.editions.first())
+            .and_then(|e| e.isbn13.as_ref())
+            .and_then(|isbn| isbn.parse().ok());
+
+        ListItem {
+            guid: (list_id.to_owned(), self.id.to_string()),
+            list_id: list_id.to_owned(),
+            title: clean(&self.title),
+            authors: self.author.iter().map(|a| clean(&a.author_name)).collect(),
+            series,
+            cover_url: self
+                .images
+                .iter()
+                .find(|i| i.cover_type == "cover")
+                .and_then(|i| i.remote_url.clone())
+                .unwrap_or_default(),
+            book_url: self.links.first().map(|l| l.url.clone()),
+            isbn,
+            prefer_format: list.prefer_format,
+            allow_audio: list.allow_audio(),
+            audio_torrent: None,
+            allow_ebook: list.allow_ebook(),
+            ebook_torrent: None,
+            created_at: Timestamp::now(),
+            marked_done_at: None,
+            candidates: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wanted_response() {
+        let response: WantedResponse = serde_json::from_str(
+            r#"{
+                "page": 1,
+                "pageSize": 100,
+                "totalRecords": 1,
+                "records": [{
+                    "id": 12,
+                    "title": "Caliban's War",
+                    "seriesTitle": "The Expanse, #2; Expanse Universe #2.5",
+                    "foreignBookId": "12591698",
+                    "author": { "authorName": "James S. A. Corey" },
+                    "images": [{ "coverType": "cover", "url": "/MediaCover/1.jpg", "remoteUrl": "https://example.com/1.jpg" }],
+                    "links": [{ "url": "https://www.goodreads.com/work/editions/1", "name": "Goodreads Editions" }],
+                    "editions": [
+                        { "isbn13": "9780316129060", "monitored": false },
+                        { "isbn13": "9780316202275", "monitored": true }
+                    ],
+                    "monitored": true
+                }]
+            }"#,
+        )
+        .unwrap();
+        let list: ReadarrList = toml::from_str(
+            r#"
+            url = "http://readarr:8787/"
+            api_key = "key"
+            [[grab]]
+            "#,
+        )
+        .unwrap();
+        let item = response.records[0].as_list_item(&list.list_id(), &list);
+        assert_eq!(item.guid.0, "readarr:http://readarr:8787");
+        assert_eq!(item.guid.1, "12");
+        assert_eq!(item.authors, vec!["James S. A. Corey".to_string()]);
+        assert_eq!(
+            item.series,
+            vec![
+                ("The Expanse".to_string(), 2.0),
+                ("Expanse Universe".to_string(), 2.5)
+            ]
+        );
+        assert_eq!(item.isbn, Some(9780316202275));
+        assert_eq!(item.cover_url, "https://example.com/1.jpg");
+    }
+}
@@ -1,11 +1,22 @@
-use std::fmt::Display;
+use std::{env, fmt::Display};
 
 use anyhow::{Error, Result};
-use native_db::Database;
-use tracing::{error, warn};
+use native_db::{Database, transaction::RwTransaction};
+use once_cell::sync::Lazy;
+use tokio::sync::broadcast;
+use tracing::{Subscriber, error, warn};
+use tracing_subscriber::{
+    EnvFilter, Layer,
+    fmt::{MakeWriter, time::LocalTime},
+    registry::LookupSpan,
+};
 
 use mlm_db::{DatabaseExt, ErroredTorrent, ErroredTorrentId, Event, Timestamp, TorrentMeta};
 
+#[cfg(feature = "otlp")]
+use crate::config::OtlpConfig;
+use crate::config::{LogConfig, LogFormat, RetryConfig};
+
 #[derive(Debug)]
 pub struct TorrentMetaError(pub TorrentMeta, pub anyhow::Error);
 impl Display for TorrentMetaError {
@@ -19,8 +30,11 @@ impl std::error::Error for TorrentMetaError {
     }
 }
 
+/// Records a failed step for a torrent, with when it may be tried again, or
+/// clears the error when it succeeded
 pub async fn update_errored_torrent(
     db: &Database<'_>,
+    retry: &RetryConfig,
     id: ErroredTorrentId,
     torrent: String,
     result: Result<(), Error>,
@@ -38,13 +52,7 @@ pub async fn update_errored_torrent(
                 Ok(TorrentMetaError(meta, err)) => (err, Some(meta)),
                 Err(err) => (err, None),
             };
-            rw.upsert(ErroredTorrent {
-                id,
-                title: torrent,
-                error: format!("{err:?}"),
-                meta,
-                created_at: Timestamp::now(),
-            })?;
+            upsert_errored_torrent(&rw, retry, id, torrent, format!("{err:?}"), meta, false)?;
         } else if let Some(error) = rw.get().primary::<ErroredTorrent>(id)? {
             rw.remove(error)?;
         }
@@ -59,6 +67,91 @@ pub async fn update_errored_torrent(
     }
 }
 
+/// Records that a step for a torrent is waiting on something that has to change
+/// outside of MLM, like metadata on MaM. It is tried again like a failed step,
+/// but does not count towards `max_attempts` as it is not failing.
+/// Returns whether it was not already waiting for the same reason
+pub async fn update_pending_torrent(
+    db: &Database<'_>,
+    retry: &RetryConfig,
+    id: ErroredTorrentId,
+    torrent: String,
+    reason: String,
+    meta: Option<TorrentMeta>,
+) -> bool {
+    match db.rw_async().await.and_then(|(_guard, rw)| {
+        let changed = rw
+            .get()
+            .primary::<ErroredTorrent>(id.clone())?
+            .is_none_or(|error| error.error != reason);
+        upsert_errored_torrent(&rw, retry, id, torrent, reason, meta, true)?;
+        rw.commit()?;
+        Ok(changed)
+    }) {
+        Ok(changed) => changed,
+        Err(err) => {
+            error!("Error writing pending torrent to db: {err:?}");
+            false
+        }
+    }
+}
+
+fn upsert_errored_torrent(
+    rw: &RwTransaction<'_>,
+    retry: &RetryConfig,
+    id: ErroredTorrentId,
+    torrent: String,
+    error: String,
+    meta: Option<TorrentMeta>,
+    pending: bool,
+) -> Result<()> {
+    let existing = rw.get().primary::<ErroredTorrent>(id.clone())?;
+    let attempts = existing.as_ref().map_or(0, |error| error.attempts) + 1;
+    // Waiting is not failing, so only failures count towards max_attempts
+    let failures = existing.as_ref().map_or(0, |error| error.failures) + u64::from(!pending);
+    let now = Timestamp::now();
+    let next_retry_at = if pending {
+        Some(retry.next_check_at(attempts, now))
+    } else {
+        retry.next_retry_at(failures, now)
+    };
+    if next_retry_at.is_none() {
+        warn!("Giving up on {torrent} after {failures} failures, retry it from the errors page");
+    }
+    rw.upsert(ErroredTorrent {
+        id,
+        title: torrent,
+        error,
+        meta,
+        created_at: now,
+        attempts,
+        failures,
+        next_retry_at,
+    })?;
+    Ok(())
+}
+
+/// Whether a step should run for a torrent, false while it waits for its next
+/// retry or after it has given up
+pub fn should_retry(db: &Database<'_>, id: ErroredTorrentId) -> bool {
+    match db
+        .r_transaction()
+        .and_then(|r| r.get().primary::<ErroredTorrent>(id))
+    {
+        Ok(error) => error.is_none_or(|error| retry_due(&error, Timestamp::now())),
+        Err(err) => {
+            error!("Error reading errored torrent: {err:?}");
+            true
+        }
+    }
+}
+
+fn retry_due(error: &ErroredTorrent, now: Timestamp) -> bool {
+    error.next_retry_at.is_some_and(|at| at <= now)
+}
+
+static NEW_EVENTS: Lazy<broadcast::Sender<Event>> = Lazy::new(|| broadcast::channel(100).0);
+
 pub async fn write_event(db: &Database<'_>, event: Event) {
     if let Err(err) = db.rw_async().await.and_then(|(_guard, rw)| {
         rw.upsert(event.clone())?;
@@ -66,5 +159,296 @@ pub async fn write_event(db: &Database<'_>, event: Event) {
         Ok(())
     }) {
         error!("Error writing event: {err:?}, event: {event:?}");
+        return;
+    }
+    // Only fails when nobody is listening
+    NEW_EVENTS.send(event).ok();
+}
+
+/// Receives every event after it has been written
+pub fn subscribe_events() -> broadcast::Receiver<Event> {
+    NEW_EVENTS.subscribe()
+}
+
+/// A tracing layer that writes logs in the configured `log.format`
+pub fn log_layer<S, W>(log: &LogConfig, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_timer(LocalTime::rfc_3339())
+        .with_ansi(ansi)
+        .with_writer(writer);
+    match log.format {
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+/// Which logs to write, `log.targets` on top of the default of debug logs for
+/// MLM. `MLM_LOG` replaces the default and overrides the targets
+pub fn log_filter(log: &LogConfig) -> EnvFilter {
+    let env = env::var("MLM_LOG").ok().filter(|env| !env.is_empty());
+    let mut directives = vec![];
+    if env.is_none() {
+        directives.push("mlm=debug".to_string());
+    }
+    directives.extend(
+        log.targets
+            .iter()
+            .map(|(target, level)| format!("{target}={level}")),
+    );
+    directives.extend(env);
+    EnvFilter::builder().parse_lossy(directives.join(","))
+}
+
+/// A tracing layer that exports spans to `otlp.endpoint`. The provider has to
+/// be kept alive for as long as spans should be exported, it flushes them when
+/// dropped
+#[cfg(feature = "otlp")]
+pub fn otlp_layer<S>(
+    otlp: &OtlpConfig,
+) -> Result<(
+    Box<dyn Layer<S> + Send + Sync>,
+    opentelemetry_sdk::trace::SdkTracerProvider,
+)>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::WithExportConfig as _;
+
+    let endpoint = otlp.endpoint.trim_end_matches('/');
+    let endpoint = if endpoint.ends_with("/v1/traces") {
+        endpoint.to_string()
+    } else {
+        format!("{endpoint}/v1/traces")
+    };
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_resource(
+            opentelemetry_sdk::Resource::builder()
+                .with_service_name(otlp.service_name.clone())
+                .build(),
+        )
+        .with_batch_exporter(exporter)
+        .build();
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("mlm"))
+        .boxed();
+    Ok((layer, provider))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::info;
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_log_layer() {
+        let log: LogConfig = toml::from_str(
+            r#"
+            format = "json"
+            targets = { "mlm::linker" = "trace", "native_db" = "warn" }
+            "#,
+        )
+        .unwrap();
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(log_layer(&log, move || writer.clone(), false).with_filter(log_filter(&log)));
+        tracing::subscriber::with_default(subscriber, || {
+            info!(target: "mlm::linker", title = "Guards! Guards!", "linked");
+            tracing::trace!(target: "native_db", "hidden");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1, "{output}");
+        let line: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["target"], "mlm::linker");
+        assert_eq!(line["fields"]["message"], "linked");
+        assert_eq!(line["fields"]["title"], "Guards! Guards!");
+    }
+
+    #[test]
+    fn test_retry_cooldown() {
+        let retry = RetryConfig {
+            delay: 10,
+            max_delay: 60,
+            max_attempts: 4,
+        };
+        let now = Timestamp::now();
+        let minutes = |m| Timestamp(now.0 + time::Duration::minutes(m));
+        let mut error = ErroredTorrent {
+            id: ErroredTorrentId::Linker("abc".to_string()),
+            title: "Guards! Guards!".to_string(),
+            error: "failed".to_string(),
+            meta: None,
+            created_at: now,
+            attempts: 2,
+            failures: 2,
+            next_retry_at: retry.next_retry_at(2, now),
+        };
+
+        // Failed twice, so it waits twice the delay
+        assert!(!retry_due(&error, now));
+        assert!(!retry_due(&error, minutes(19)));
+        assert!(retry_due(&error, minutes(20)));
+
+        assert_eq!(retry.next_retry_at(1, now), Some(minutes(10)));
+        assert_eq!(retry.next_retry_at(3, now), Some(minutes(40)));
+        // Capped at max_delay
+        let capped = RetryConfig {
+            max_delay: 30,
+            ..retry.clone()
+        };
+        assert_eq!(capped.next_retry_at(3, now), Some(minutes(30)));
+
+        // Gives up after max_attempts
+        error.next_retry_at = retry.next_retry_at(4, now);
+        assert_eq!(error.next_retry_at, None);
+        assert!(!retry_due(&error, minutes(24 * 60)));
+    }
+
+    #[tokio::test]
+    async fn test_update_errored_torrent_gives_up() {
+        let db = native_db::Builder::new()
+            .create_in_memory(&mlm_db::MODELS)
+            .unwrap();
+        let retry = RetryConfig {
+            delay: 0,
+            max_delay: 0,
+            max_attempts: 2,
+        };
+        let id = ErroredTorrentId::Linker("abc".to_string());
+        let error = |db: &Database<'_>| {
+            db.r_transaction()
+                .unwrap()
+                .get()
+                .primary::<ErroredTorrent>(id.clone())
+                .unwrap()
+        };
+        assert!(should_retry(&db, id.clone()));
+
+        let failed = || Err(anyhow::Error::msg("failed"));
+        update_errored_torrent(&db, &retry, id.clone(), "Title".to_string(), failed()).await;
+        assert_eq!(error(&db).unwrap().attempts, 1);
+        // Without a delay it is due right away
+        assert!(should_retry(&db, id.clone()));
+
+        update_errored_torrent(&db, &retry, id.clone(), "Title".to_string(), failed()).await;
+        let given_up = error(&db).unwrap();
+        assert_eq!(given_up.attempts, 2);
+        assert_eq!(given_up.failures, 2);
+        assert_eq!(given_up.next_retry_at, None);
+        assert!(!should_retry(&db, id.clone()));
+
+        update_errored_torrent(&db, &retry, id.clone(), "Title".to_string(), Ok(())).await;
+        assert!(error(&db).is_none());
+        assert!(should_retry(&db, id));
+    }
+
+    #[tokio::test]
+    async fn test_pending_torrent_does_not_give_up() {
+        let db = native_db::Builder::new()
+            .create_in_memory(&mlm_db::MODELS)
+            .unwrap();
+        let retry = RetryConfig {
+            delay: 0,
+            max_delay: 0,
+            max_attempts: 2,
+        };
+        let id = ErroredTorrentId::Linker("abc".to_string());
+        let pending = |reason: &str| {
+            update_pending_torrent(
+                &db,
+                &retry,
+                id.clone(),
+                "Title".to_string(),
+                reason.to_string(),
+                None,
+            )
+        };
+        assert!(pending("missing author").await);
+        assert!(!pending("missing author").await);
+        assert!(!pending("missing author").await);
+        assert!(pending("missing series").await);
+
+        let error = db
+            .r_transaction()
+            .unwrap()
+            .get()
+            .primary::<ErroredTorrent>(id.clone())
+            .unwrap()
+            .unwrap();
+        assert_eq!(error.attempts, 4);
+        assert_eq!(error.failures, 0);
+        assert!(error.next_retry_at.is_some());
+        assert!(should_retry(&db, id));
+    }
+
+    #[tokio::test]
+    async fn test_failure_after_pending_is_retried() {
+        let db = native_db::Builder::new()
+            .create_in_memory(&mlm_db::MODELS)
+            .unwrap();
+        let retry = RetryConfig {
+            delay: 0,
+            max_delay: 0,
+            max_attempts: 2,
+        };
+        let id = ErroredTorrentId::Linker("abc".to_string());
+        for _ in 0..3 {
+            update_pending_torrent(
+                &db,
+                &retry,
+                id.clone(),
+                "Title".to_string(),
+                "missing author".to_string(),
+                None,
+            )
+            .await;
+        }
+        let failed = Err(anyhow::Error::msg("failed"));
+        update_errored_torrent(&db, &retry, id.clone(), "Title".to_string(), failed).await;
+
+        let error = db
+            .r_transaction()
+            .unwrap()
+            .get()
+            .primary::<ErroredTorrent>(id.clone())
+            .unwrap()
+            .unwrap();
+        assert_eq!(error.attempts, 4);
+        assert_eq!(error.failures, 1);
+        assert!(error.next_retry_at.is_some());
+        assert!(should_retry(&db, id));
     }
 }
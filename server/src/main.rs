@@ -1,17 +1,29 @@
 #![windows_subsystem = "windows"]
 
 mod audiobookshelf;
+mod audit;
 mod autograbber;
+mod chapters;
 mod cleaner;
 mod config;
 mod config_impl;
+mod config_schema;
+mod covers;
 mod exporter;
+mod hooks;
 mod linker;
 mod lists;
 mod logging;
+mod mam_sessions;
+mod metadata;
+mod paused_tasks;
 mod qbittorrent;
+mod rtorrent;
 mod snatchlist;
 mod stats;
+mod task_errors;
+#[cfg(test)]
+mod test_helpers;
 mod torrent_downloader;
 mod web;
 #[cfg(target_family = "windows")]
@@ -29,16 +41,19 @@ use std::{
 };
 
 use anyhow::{Context as _, Result};
-use audiobookshelf::match_torrents_to_abs;
+use audiobookshelf::{import_abs_library, match_torrents_to_abs};
+use audit::run_library_audit;
 use autograbber::run_autograbber;
 use cleaner::run_library_cleaner;
 use dirs::{config_dir, data_local_dir};
 use exporter::export_db;
 use figment::{
     Figment,
-    providers::{Env, Format, Toml},
+    providers::{Env, Format, Serialized, Toml},
+    value::{Dict, Value},
 };
-use mlm_mam::api::MaM;
+use mam_sessions::MamSessions;
+use mlm_mam::{http, meta::set_author_aliases};
 use stats::{Stats, Triggers};
 use time::OffsetDateTime;
 use tokio::{
@@ -46,21 +61,26 @@ use tokio::{
     sync::{Mutex, watch},
     time::sleep,
 };
-use torrent_downloader::grab_selected_torrents;
-use tracing::error;
+use torrent_downloader::{DownloadClient, grab_selected_torrents};
+use tracing::{debug, error, warn};
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
-use tracing_subscriber::{
-    EnvFilter, Layer as _, fmt::time::LocalTime, layer::SubscriberExt as _,
-    util::SubscriberInitExt as _,
-};
+use tracing_subscriber::{Layer as _, layer::SubscriberExt as _, util::SubscriberInitExt as _};
 use web::start_webserver;
 
 use crate::{
     config::Config,
     linker::link_torrents_to_library,
-    lists::{get_lists, run_list_import},
-    snatchlist::run_snatchlist_search,
+    lists::{get_lists, run_list_import, wait_for_list_change},
+    logging::{log_filter, log_layer},
+    paused_tasks::{autograb_task_key, is_paused, list_task_key, snatchlist_task_key},
+    qbittorrent::{ReconnectBackoff, import_qbittorrent},
+    rtorrent::Rtorrent,
+    snatchlist::{run_snatchlist_search, wait_for_snatchlist_change},
     stats::Context,
+    task_errors::{
+        AUDIOBOOKSHELF_TASK, AUDIT_TASK, CLEANER_TASK, DOWNLOADER_TASK, LINKER_TASK,
+        record_task_error,
+    },
 };
 
 #[tokio::main]
@@ -80,6 +100,28 @@ async fn main() {
 }
 
 async fn app_main() -> Result<()> {
+    for arg in env::args() {
+        match arg.as_str() {
+            "--print-config-schema" => {
+                print!("{}", config_schema::config_example());
+                return Ok(());
+            }
+            "--print-config-schema=json" => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&config_schema::config_schema())?
+                );
+                return Ok(());
+            }
+            #[cfg(feature = "graphql")]
+            "--print-graphql-schema" => {
+                print!("{}", web::graphql_schema_sdl());
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+
     let log_dir = env::var("MLM_LOG_DIR")
         .map(|path| {
             if path.is_empty() {
@@ -99,49 +141,6 @@ async fn app_main() -> Result<()> {
             )
         });
 
-    let stderr_layer = tracing_subscriber::fmt::layer()
-        .pretty()
-        .with_writer(io::stderr);
-
-    let file_layer = log_dir
-        .as_ref()
-        .map(|log_dir| {
-            Result::<_, anyhow::Error>::Ok(
-                tracing_subscriber::fmt::layer().pretty().with_writer(
-                    RollingFileAppender::builder()
-                        .rotation(Rotation::DAILY)
-                        .filename_prefix("mlm")
-                        .filename_suffix("log")
-                        .build(log_dir)?,
-                ),
-            )
-        })
-        .transpose()?;
-
-    tracing_subscriber::registry()
-        .with(
-            stderr_layer.with_timer(LocalTime::rfc_3339()).with_filter(
-                EnvFilter::builder()
-                    .with_default_directive("mlm=debug".parse()?)
-                    .with_env_var("MLM_LOG")
-                    .from_env_lossy(),
-            ),
-        )
-        .with(file_layer.map(|file_layer| {
-            file_layer
-                .with_timer(LocalTime::rfc_3339())
-                .with_ansi(false)
-                .with_filter(
-                    EnvFilter::builder()
-                        .with_default_directive("mlm=debug".parse().unwrap())
-                        .with_env_var("MLM_LOG")
-                        .from_env_lossy(),
-                )
-        }))
-        .try_init()?;
-    #[cfg(target_family = "windows")]
-    std::panic::set_hook(Box::new(tracing_panic::panic_hook));
-
     let config_file = env::var("MLM_CONFIG_FILE")
         .map(PathBuf::from)
         .unwrap_or_else(|_| {
@@ -176,8 +175,68 @@ async fn app_main() -> Result<()> {
     }
     let config: Result<Config, _> = Figment::new()
         .merge(Toml::file_exact(&config_file))
-        .merge(Env::prefixed("MLM_CONF_"))
+        .merge(Serialized::defaults(env_files(env::vars())?))
+        .merge(Env::prefixed("MLM_CONF_").filter(|key| !key.as_str().ends_with("_file")))
         .extract();
+
+    // Logging is set up once the config is read, an invalid config is logged
+    // with the default settings
+    let log = config
+        .as_ref()
+        .map(|config| config.log.clone())
+        .unwrap_or_default();
+    let file_layer = log_dir
+        .as_ref()
+        .map(|log_dir| {
+            Result::<_, anyhow::Error>::Ok(
+                RollingFileAppender::builder()
+                    .rotation(Rotation::DAILY)
+                    .filename_prefix("mlm")
+                    .filename_suffix("log")
+                    .build(log_dir)?,
+            )
+        })
+        .transpose()?;
+
+    let registry = tracing_subscriber::registry()
+        .with(log_layer(&log, io::stderr, true).with_filter(log_filter(&log)))
+        .with(
+            file_layer
+                .map(|file_layer| log_layer(&log, file_layer, false).with_filter(log_filter(&log))),
+        );
+    // Spans are exported with the same filter as logs, held until main returns
+    #[cfg(feature = "otlp")]
+    let (registry, _otlp_provider) = {
+        let otlp = config
+            .as_ref()
+            .ok()
+            .and_then(|config| config.otlp.as_ref())
+            .map(logging::otlp_layer)
+            .transpose()?;
+        let (layer, provider) = otlp.unzip();
+        (
+            registry.with(layer.map(|layer| layer.with_filter(log_filter(&log)))),
+            provider,
+        )
+    };
+    registry.try_init()?;
+    #[cfg(target_family = "windows")]
+    std::panic::set_hook(Box::new(tracing_panic::panic_hook));
+
+    if env::args().any(|arg| arg == "--check-config") {
+        let config =
+            config.with_context(|| format!("Failed to parse {}", config_file.display()))?;
+        let problems = config.validate();
+        if problems.is_empty() {
+            println!("{} is valid", config_file.display());
+            return Ok(());
+        }
+        eprintln!("Found problems in {}:", config_file.display());
+        for problem in problems {
+            eprintln!("  - {problem}");
+        }
+        process::exit(1);
+    }
     #[cfg(target_family = "windows")]
     if let Err(err) = &config {
         windows::error_window::ErrorWindow::create_and_run(
@@ -189,13 +248,30 @@ async fn app_main() -> Result<()> {
         return Ok(());
     }
     let config = config?;
+    for problem in config.validate() {
+        warn!("Config problem: {problem}");
+    }
+    http::configure(config.http_settings()?);
+    set_author_aliases(&config.author_aliases);
+    // Invalid title patterns fail the start, instead of being left out
+    config.title_patterns()?;
     let config = Arc::new(config);
 
     let db = native_db::Builder::new().create(&mlm_db::MODELS, database_file)?;
     mlm_db::migrate(&db)?;
 
     if env::args().any(|arg| arg == "--update-search-title") {
-        mlm_db::update_search_title(&db)?;
+        mlm_db::update_search_title(&db, config.title_rules())?;
+        return Ok(());
+    }
+
+    if env::args().any(|arg| arg == "--import-abs") {
+        let abs = config
+            .audiobookshelf
+            .as_ref()
+            .context("--import-abs needs [audiobookshelf] to be configured")?;
+        let imported = import_abs_library(abs, &db, config.title_rules()).await?;
+        println!("Imported {imported} books from Audiobookshelf");
         return Ok(());
     }
 
@@ -203,91 +279,127 @@ async fn app_main() -> Result<()> {
     // return Ok(());
     let db = Arc::new(db);
 
+    if let Some(arg) =
+        env::args().find(|arg| arg == "--import-qbit" || arg.starts_with("--import-qbit="))
+    {
+        let category = arg.strip_prefix("--import-qbit=");
+        let mam = MamSessions::connect(&config, db.clone())
+            .await
+            .get(None)
+            .context("--import-qbit needs to be logged in to MaM")?;
+        let imported = import_qbittorrent(config.clone(), db.clone(), mam, category).await?;
+        println!(
+            "Imported {} torrents from qBittorrent, {} found on MaM and {} from file names. Skipped {}, failed {}",
+            imported.from_mam + imported.from_files,
+            imported.from_mam,
+            imported.from_files,
+            imported.skipped,
+            imported.failed
+        );
+        return Ok(());
+    }
+
     #[cfg(target_family = "windows")]
     let _tray = windows::tray::start_tray_icon(log_dir, config_file.clone(), config.clone())?;
 
     let stats = Stats::new();
+    stats.set_maintenance(config.maintenance);
 
     let (mut search_tx, mut search_rx) = (BTreeMap::new(), BTreeMap::new());
     let (mut import_tx, mut import_rx) = (BTreeMap::new(), BTreeMap::new());
     let (linker_tx, linker_rx) = watch::channel(());
     let (downloader_tx, mut downloader_rx) = watch::channel(());
     let (audiobookshelf_tx, mut audiobookshelf_rx) = watch::channel(());
+    let (audit_tx, mut audit_rx) = watch::channel(());
 
-    let mam = if config.mam_id.is_empty() {
-        Err(anyhow::Error::msg("No mam_id set"))
-    } else {
-        MaM::new(&config.mam_id, db.clone()).await.map(Arc::new)
-    };
-    if let Ok(mam) = &mam {
+    let sessions = Arc::new(MamSessions::connect(&config, db.clone()).await);
+    if let Ok(mam) = sessions.get(None) {
         {
             let config = config.clone();
             let db = db.clone();
-            let mam = mam.clone();
+            let sessions = sessions.clone();
             let stats = stats.clone();
             tokio::spawn(async move {
-                if let Some(qbit_conf) = config.qbittorrent.first() {
-                    loop {
-                        let mut qbit: Option<qbit::Api> = None;
-                        if downloader_rx.changed().await.is_err() {
-                            break;
-                        }
-                        if qbit.is_none() {
-                            match qbit::Api::new_login_username_password(
-                                &qbit_conf.url,
-                                &qbit_conf.username,
-                                &qbit_conf.password,
-                            )
-                            .await
-                            {
-                                Ok(q) => qbit = Some(q),
-                                Err(err) => {
-                                    error!("Error logging in to qbit {}: {err}", qbit_conf.url);
-                                    stats
-                                        .update(|stats| {
-                                            stats.downloader_run_at =
-                                                Some(OffsetDateTime::now_utc());
-                                            stats.downloader_result = Some(Err(err.into()));
-                                        })
-                                        .await;
-                                }
-                            };
-                        }
-                        let Some(qbit) = &qbit else {
-                            continue;
-                        };
+                let watch_dir = config.watch_dir();
+                let qbit_conf = config.qbittorrent.first();
+                let rtorrent = config
+                    .rtorrent
+                    .first()
+                    .map(|rtorrent_conf| Rtorrent::new(rtorrent_conf, http::client()));
+                loop {
+                    if downloader_rx.changed().await.is_err() {
+                        break;
+                    }
+                    stats.wait_for_maintenance().await;
+                    let qbit;
+                    let client = if let Some(watch_dir) = watch_dir {
+                        DownloadClient::WatchDir(watch_dir)
+                    } else if let Some(qbit_conf) = qbit_conf {
+                        qbit = match qbit::Api::new_login_username_password(
+                            &qbit_conf.url,
+                            &qbit_conf.username,
+                            &qbit_conf.password,
+                        )
+                        .await
                         {
-                            stats
-                                .update(|stats| {
-                                    stats.downloader_run_at = Some(OffsetDateTime::now_utc());
-                                    stats.downloader_result = None;
-                                })
-                                .await;
-                        }
-                        let result =
-                            grab_selected_torrents(&config, &db, qbit, &qbit_conf.url, &mam)
-                                .await
-                                .context("grab_selected_torrents");
+                            Ok(qbit) => qbit,
+                            Err(err) => {
+                                error!("Error logging in to qbit {}: {err}", qbit_conf.url);
+                                let err = anyhow::Error::from(err);
+                                record_task_error(&db, DOWNLOADER_TASK, &err).await;
+                                stats
+                                    .update(|stats| {
+                                        stats.downloader_run_at = Some(OffsetDateTime::now_utc());
+                                        stats.downloader_result = Some(Err(err));
+                                    })
+                                    .await;
+                                continue;
+                            }
+                        };
+                        DownloadClient::Qbit(&qbit, &qbit_conf.url)
+                    } else if let Some(rtorrent) = &rtorrent {
+                        DownloadClient::Rtorrent(rtorrent)
+                    } else {
+                        break;
+                    };
+                    {
+                        stats
+                            .update(|stats| {
+                                stats.downloader_run_at = Some(OffsetDateTime::now_utc());
+                                stats.downloader_result = None;
+                            })
+                            .await;
+                    }
+                    let result = grab_selected_torrents(&config, &db, client, &sessions)
+                        .await
+                        .context("grab_selected_torrents");
 
-                        if let Err(err) = &result {
-                            error!("Error grabbing selected torrents: {err:?}");
-                        }
-                        {
-                            stats
-                                .update(|stats| {
-                                    stats.downloader_result = Some(result);
-                                })
-                                .await;
-                        }
+                    if let Err(err) = &result {
+                        error!("Error grabbing selected torrents: {err:?}");
+                        record_task_error(&db, DOWNLOADER_TASK, err).await;
+                    }
+                    {
+                        stats
+                            .update(|stats| {
+                                stats.downloader_result = Some(result);
+                            })
+                            .await;
                     }
                 }
             });
         }
 
         for (i, grab) in config.autograbs.iter().enumerate() {
+            let mam = match sessions.get(grab.account.as_deref()) {
+                Ok(mam) => mam,
+                Err(err) => {
+                    error!("Not starting autograb #{}: {err}", i + 1);
+                    continue;
+                }
+            };
+            let paused_key = autograb_task_key(grab, i);
             let config = config.clone();
             let db = db.clone();
-            let mam = mam.clone();
             let downloader_tx = downloader_tx.clone();
             let (tx, mut rx) = watch::channel(());
             search_tx.insert(i, tx);
@@ -320,6 +432,11 @@ async fn app_main() -> Result<()> {
                                 .await;
                         }
                     }
+                    stats.wait_for_maintenance().await;
+                    if is_paused(&db, &paused_key) {
+                        debug!("Skipping {paused_key}, it is paused");
+                        continue;
+                    }
                     {
                         stats
                             .update(|stats| {
@@ -335,6 +452,7 @@ async fn app_main() -> Result<()> {
                         db.clone(),
                         mam.clone(),
                         downloader_tx.clone(),
+                        &stats,
                         i,
                         grab.clone(),
                     )
@@ -342,6 +460,7 @@ async fn app_main() -> Result<()> {
                     .context("autograbbers");
                     if let Err(err) = &result {
                         error!("Error running autograbbers: {err:?}");
+                        record_task_error(&db, &paused_key, err).await;
                     }
                     {
                         stats
@@ -355,10 +474,18 @@ async fn app_main() -> Result<()> {
         }
 
         for (i, grab) in config.snatchlist.iter().enumerate() {
+            let mam = match sessions.get(grab.account()) {
+                Ok(mam) => mam,
+                Err(err) => {
+                    error!("Not starting snatchlist #{}: {err}", i + 1);
+                    continue;
+                }
+            };
+            let paused_key = snatchlist_task_key(grab, i);
             let i = i + config.autograbs.len();
             let config = config.clone();
             let db = db.clone();
-            let mam = mam.clone();
+            let downloader_tx = downloader_tx.clone();
             let (tx, mut rx) = watch::channel(());
             search_tx.insert(i, tx);
             search_rx.insert(i, rx.clone());
@@ -366,10 +493,11 @@ async fn app_main() -> Result<()> {
             let grab = Arc::new(grab.clone());
             tokio::spawn(async move {
                 loop {
-                    let interval = grab.search_interval.unwrap_or(config.search_interval);
+                    let interval = grab.search_interval().unwrap_or(config.search_interval);
                     if interval > 0 {
                         select! {
                             () = sleep(Duration::from_secs(60 * interval)) => {},
+                            () = wait_for_snatchlist_change(&grab) => {},
                             result = rx.changed() => {
                                 if let Err(err) = result {
                                     error!("Error listening on search_rx for snatchlist: {err:?}");
@@ -380,16 +508,23 @@ async fn app_main() -> Result<()> {
                             },
                         }
                     } else {
-                        let result = rx.changed().await;
-                        if let Err(err) = result {
-                            error!("Error listening on search_rx for snatchlist: {err:?}");
-                            stats
-                                .update(|stats| {
-                                    stats.autograbber_result.insert(i, Err(err.into()));
-                                })
-                                .await;
+                        select! {
+                            () = wait_for_snatchlist_change(&grab) => {},
+                            result = rx.changed() => {
+                                if let Err(err) = result {
+                                    error!("Error listening on search_rx for snatchlist: {err:?}");
+                                    stats.update(|stats| {
+                                        stats.autograbber_result.insert(i, Err(err.into()));
+                                    }).await;
+                                }
+                            },
                         }
                     }
+                    stats.wait_for_maintenance().await;
+                    if is_paused(&db, &paused_key) {
+                        debug!("Skipping {paused_key}, it is paused");
+                        continue;
+                    }
                     {
                         stats
                             .update(|stats| {
@@ -404,6 +539,8 @@ async fn app_main() -> Result<()> {
                         config.clone(),
                         db.clone(),
                         mam.clone(),
+                        downloader_tx.clone(),
+                        &stats,
                         i,
                         grab.clone(),
                     )
@@ -411,6 +548,7 @@ async fn app_main() -> Result<()> {
                     .context("snatchlist_search");
                     if let Err(err) = &result {
                         error!("Error running snatchlist_search: {err:?}");
+                        record_task_error(&db, &paused_key, err).await;
                     }
                     {
                         stats
@@ -424,9 +562,16 @@ async fn app_main() -> Result<()> {
         }
 
         for (i, list) in get_lists(&config).into_iter().enumerate() {
+            let mam = match sessions.get(list.account()) {
+                Ok(mam) => mam,
+                Err(err) => {
+                    error!("Not starting {} list #{}: {err}", list.list_type(), i + 1);
+                    continue;
+                }
+            };
+            let paused_key = list_task_key(&list, i);
             let config = config.clone();
             let db = db.clone();
-            let mam = mam.clone();
             let downloader_tx = downloader_tx.clone();
             let (tx, mut rx) = watch::channel(());
             import_tx.insert(i, tx);
@@ -439,6 +584,7 @@ async fn app_main() -> Result<()> {
                     if interval > 0 {
                         select! {
                             () = sleep(Duration::from_secs(60 * interval)) => {},
+                            () = wait_for_list_change(&list) => {},
                             result = rx.changed() => {
                                 if let Err(err) = result {
                                     error!("Error listening on import_rx: {err:?}");
@@ -449,16 +595,23 @@ async fn app_main() -> Result<()> {
                             },
                         }
                     } else {
-                        let result = rx.changed().await;
-                        if let Err(err) = result {
-                            error!("Error listening on import_rx: {err:?}");
-                            stats
-                                .update(|stats| {
-                                    stats.import_result.insert(i, Err(err.into()));
-                                })
-                                .await;
+                        select! {
+                            () = wait_for_list_change(&list) => {},
+                            result = rx.changed() => {
+                                if let Err(err) = result {
+                                    error!("Error listening on import_rx: {err:?}");
+                                    stats.update(|stats| {
+                                        stats.import_result.insert(i, Err(err.into()));
+                                    }).await;
+                                }
+                            },
                         }
                     }
+                    stats.wait_for_maintenance().await;
+                    if is_paused(&db, &paused_key) {
+                        debug!("Skipping {paused_key}, it is paused");
+                        continue;
+                    }
                     {
                         stats
                             .update(|stats| {
@@ -474,16 +627,19 @@ async fn app_main() -> Result<()> {
                         list.clone(),
                         i,
                         downloader_tx.clone(),
+                        &stats,
                     )
                     .await
                     .context("import");
                     if let Err(err) = &result {
                         error!("Error running import: {err:?}");
+                        record_task_error(&db, &paused_key, err).await;
                     }
                     {
                         stats
                             .update(|stats| {
                                 stats.import_result.insert(i, result);
+                                stats.import_progress.remove(&i);
                             })
                             .await;
                     }
@@ -499,9 +655,15 @@ async fn app_main() -> Result<()> {
                 let stats = stats.clone();
                 let mut linker_rx = linker_rx.clone();
                 tokio::spawn(async move {
+                    let link_interval = Duration::from_secs(60 * config.link_interval);
+                    let mut backoff = ReconnectBackoff::new(
+                        Duration::from_secs(qbit_conf.reconnect_delay),
+                        link_interval,
+                    );
+                    let mut delay = link_interval;
                     loop {
                         select! {
-                            () = sleep(Duration::from_secs(60 * config.link_interval)) => {},
+                            () = sleep(delay) => {},
                             result = linker_rx.changed() => {
                                 if let Err(err) = result {
                                     error!("Error listening on link_rx: {err:?}");
@@ -513,6 +675,7 @@ async fn app_main() -> Result<()> {
                                 }
                             },
                         }
+                        stats.wait_for_maintenance().await;
                         {
                             stats
                                 .update(|stats| {
@@ -521,24 +684,30 @@ async fn app_main() -> Result<()> {
                                 })
                                 .await;
                         }
-                        let qbit = match qbit::Api::new_login_username_password(
+                        let login = qbit::Api::new_login_username_password(
                             &qbit_conf.url,
                             &qbit_conf.username,
                             &qbit_conf.password,
                         )
-                        .await
-                        {
+                        .await;
+                        delay = backoff.next_delay(&login);
+                        let qbit = match login {
                             Ok(qbit) => qbit,
                             Err(err) => {
-                                error!("Error logging in to qbit {}: {err}", qbit_conf.url);
+                                error!(
+                                    "Error logging in to qbit {}: {err}, retrying in {}s",
+                                    qbit_conf.url,
+                                    delay.as_secs()
+                                );
+                                let err = anyhow::Error::msg(format!(
+                                    "Error logging in to qbit {}: {err}",
+                                    qbit_conf.url,
+                                ));
+                                record_task_error(&db, LINKER_TASK, &err).await;
                                 stats
                                     .update(|stats| {
                                         stats.linker_run_at = Some(OffsetDateTime::now_utc());
-                                        stats.linker_result =
-                                            Some(Err(anyhow::Error::msg(format!(
-                                                "Error logging in to qbit {}: {err}",
-                                                qbit_conf.url,
-                                            ))));
+                                        stats.linker_result = Some(Err(err));
                                     })
                                     .await;
                                 continue;
@@ -549,16 +718,19 @@ async fn app_main() -> Result<()> {
                             db.clone(),
                             (&qbit_conf, &qbit),
                             mam.clone(),
+                            &stats,
                         )
                         .await
                         .context("link_torrents_to_library");
                         if let Err(err) = &result {
                             error!("Error running linker: {err:?}");
+                            record_task_error(&db, LINKER_TASK, err).await;
                         }
                         {
                             stats
                                 .update(|stats| {
                                     stats.linker_result = Some(result);
+                                    stats.linker_progress = None;
                                     stats.cleaner_run_at = Some(OffsetDateTime::now_utc());
                                     stats.cleaner_result = None;
                                 })
@@ -569,6 +741,7 @@ async fn app_main() -> Result<()> {
                             .context("library_cleaner");
                         if let Err(err) = &result {
                             error!("Error running library_cleaner: {err:?}");
+                            record_task_error(&db, CLEANER_TASK, err).await;
                         }
                         {
                             stats
@@ -602,6 +775,7 @@ async fn app_main() -> Result<()> {
                         }
                     },
                 }
+                stats.wait_for_maintenance().await;
                 {
                     stats
                         .update(|stats| {
@@ -615,6 +789,7 @@ async fn app_main() -> Result<()> {
                     .context("audiobookshelf_matcher");
                 if let Err(err) = &result {
                     error!("Error running audiobookshelf matcher: {err:?}");
+                    record_task_error(&db, AUDIOBOOKSHELF_TASK, err).await;
                 }
                 {
                     stats
@@ -627,19 +802,72 @@ async fn app_main() -> Result<()> {
         });
     }
 
+    {
+        let config = config.clone();
+        let db = db.clone();
+        let stats = stats.clone();
+        tokio::spawn(async move {
+            loop {
+                // With no interval the audit only runs when started from the web UI,
+                // so a sleeping NAS isn't woken up
+                let result = if config.audit_interval > 0 {
+                    select! {
+                        () = sleep(Duration::from_secs(60 * config.audit_interval)) => Ok(()),
+                        result = audit_rx.changed() => result,
+                    }
+                } else {
+                    audit_rx.changed().await
+                };
+                if let Err(err) = result {
+                    error!("Error listening on audit_rx: {err:?}");
+                    stats
+                        .update(|stats| {
+                            stats.audit_result = Some(Err(err.into()));
+                        })
+                        .await;
+                    break;
+                }
+                stats.wait_for_maintenance().await;
+                {
+                    stats
+                        .update(|stats| {
+                            stats.audit_run_at = Some(OffsetDateTime::now_utc());
+                            stats.audit_result = None;
+                        })
+                        .await;
+                }
+                let result = run_library_audit(config.clone(), db.clone())
+                    .await
+                    .context("library_audit");
+                if let Err(err) = &result {
+                    error!("Error running library audit: {err:?}");
+                    record_task_error(&db, AUDIT_TASK, err).await;
+                }
+                {
+                    stats
+                        .update(|stats| {
+                            stats.audit_result = Some(result);
+                        })
+                        .await;
+                }
+            }
+        });
+    }
+
     let triggers = Triggers {
         search_tx,
         import_tx,
         linker_tx,
         downloader_tx,
         audiobookshelf_tx,
+        audit_tx,
     };
     #[cfg(target_family = "windows")]
-    let web_port = config.web_port;
+    let web_bind = config.web_bind();
     let context = Context {
         config: Arc::new(Mutex::new(config)),
         db,
-        mam: Arc::new(mam),
+        mam: sessions,
         stats,
         triggers,
     };
@@ -651,8 +879,8 @@ async fn app_main() -> Result<()> {
         windows::error_window::ErrorWindow::create_and_run(
             "MLM Webserver Error".to_string(),
             format!(
-                "{err}\r\n\r\nThis usually mean that your port is in use.\r\nConfigured port: {}",
-                web_port
+                "{err}\r\n\r\nThis usually mean that your port is in use.\r\nConfigured address: {}",
+                web_bind
             ),
             Some(config_file.clone()),
         )
@@ -663,3 +891,56 @@ async fn app_main() -> Result<()> {
 
     Ok(())
 }
+
+/// Reads config values from the files named by `MLM_CONF_<KEY>_FILE` env vars,
+/// for secrets passed as docker secrets
+fn env_files(vars: impl IntoIterator<Item = (String, String)>) -> Result<Dict> {
+    let mut values = Dict::new();
+    for (name, path) in vars {
+        let Some(key) = name
+            .strip_prefix("MLM_CONF_")
+            .and_then(|name| name.strip_suffix("_FILE"))
+        else {
+            continue;
+        };
+        let content =
+            fs::read_to_string(&path).with_context(|| format!("Failed to read {name}={path}"))?;
+        let value: Value = content.trim_end_matches(['\r', '\n']).parse().unwrap();
+        values.insert(key.to_lowercase(), value);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_files() {
+        let secret = env::temp_dir().join(format!("mlm-secret-{}", process::id()));
+        fs::write(&secret, "secret-mam-id\n").unwrap();
+        let vars = vec![
+            (
+                "MLM_CONF_MAM_ID_FILE".to_string(),
+                secret.to_string_lossy().into_owned(),
+            ),
+            ("OTHER_FILE".to_string(), "/does/not/exist".to_string()),
+        ];
+
+        let config: Config = Figment::new()
+            .merge(Toml::string(r#"mam_id = "from-toml""#))
+            .merge(Serialized::defaults(env_files(vars).unwrap()))
+            .extract()
+            .unwrap();
+        assert_eq!(config.mam_id, "secret-mam-id");
+
+        fs::remove_file(&secret).unwrap();
+        assert!(
+            env_files(vec![(
+                "MLM_CONF_MAM_ID_FILE".to_string(),
+                secret.to_string_lossy().into_owned(),
+            )])
+            .is_err()
+        );
+    }
+}
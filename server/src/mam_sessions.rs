@@ -0,0 +1,133 @@
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use mlm_mam::api::MaM;
+use native_db::Database;
+use tracing::error;
+
+use crate::config::Config;
+
+/// The MaM sessions for the main `mam_id` and the named `[[mam]]` accounts
+pub struct MamSessions<T = Arc<MaM<'static>>> {
+    /// `None` when no `mam_id` is set, the first account is the main one then
+    pub main: Option<Result<T>>,
+    pub accounts: Vec<(String, Result<T>)>,
+}
+
+impl MamSessions {
+    pub async fn connect(config: &Config, db: Arc<Database<'static>>) -> Self {
+        let main = if config.mam_id.is_empty() {
+            None
+        } else {
+            Some(MaM::new(&config.mam_id, db.clone()).await.map(Arc::new))
+        };
+        let mut accounts = vec![];
+        for account in &config.mam {
+            let session = MaM::new_account(&account.name, &account.mam_id, db.clone())
+                .await
+                .map(Arc::new);
+            if let Err(err) = &session {
+                error!("Error logging in to MaM account {}: {err:?}", account.name);
+            }
+            accounts.push((account.name.clone(), session));
+        }
+        Self { main, accounts }
+    }
+}
+
+impl<T: Clone> MamSessions<T> {
+    /// The session for the `account` of an autograb, snatchlist or list,
+    /// `None` is the main session
+    pub fn get(&self, account: Option<&str>) -> Result<T> {
+        let session = match account {
+            Some(account) => self
+                .accounts
+                .iter()
+                .find(|(name, _)| name == account)
+                .map(|(_, session)| session)
+                .ok_or_else(|| anyhow!("No [[mam]] account named {account:?}"))?,
+            None => match &self.main {
+                Some(session) => session,
+                None => self
+                    .accounts
+                    .first()
+                    .map(|(_, session)| session)
+                    .ok_or_else(|| anyhow!("No mam_id set"))?,
+            },
+        };
+        session
+            .as_ref()
+            .cloned()
+            .map_err(|err| anyhow!("mam_id error: {err}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sessions(config: &Config) -> MamSessions<&str> {
+        MamSessions {
+            main: (!config.mam_id.is_empty()).then_some(Ok(config.mam_id.as_str())),
+            accounts: config
+                .mam
+                .iter()
+                .map(|account| (account.name.clone(), Ok(account.mam_id.as_str())))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_grab_uses_named_session() {
+        let config: Config = toml::from_str(
+            r#"
+            mam_id = "main"
+
+            [[mam]]
+            name = "seedbox"
+            mam_id = "seedbox_id"
+
+            [[autograb]]
+            type = "freeleech"
+            account = "seedbox"
+
+            [[autograb]]
+            type = "bookmarks"
+            "#,
+        )
+        .unwrap();
+        let sessions = sessions(&config);
+
+        let grab = sessions.get(config.autograbs[0].account.as_deref());
+        assert_eq!(grab.unwrap(), "seedbox_id");
+        let grab = sessions.get(config.autograbs[1].account.as_deref());
+        assert_eq!(grab.unwrap(), "main");
+        assert!(sessions.get(Some("other")).is_err());
+    }
+
+    #[test]
+    fn test_first_account_is_main_without_mam_id() {
+        let config: Config = toml::from_str(
+            r#"
+            [[mam]]
+            name = "home"
+            mam_id = "home_id"
+
+            [[mam]]
+            name = "seedbox"
+            mam_id = "seedbox_id"
+            "#,
+        )
+        .unwrap();
+        let sessions = sessions(&config);
+
+        assert_eq!(sessions.get(None).unwrap(), "home_id");
+        assert!(config.validate().iter().all(|p| !p.contains("mam_id")));
+
+        let empty = MamSessions::<&str> {
+            main: None,
+            accounts: vec![],
+        };
+        assert!(empty.get(None).is_err());
+    }
+}
@@ -0,0 +1,354 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use anyhow::{Result, anyhow};
+use futures::{StreamExt as _, stream::FuturesUnordered};
+use mlm_db::{ASIN_ID, Event, EventType, ISBN_ID, Torrent, TorrentMeta};
+use mlm_mam::http::{self, HttpCache};
+use native_db::Database;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use tokio::{
+    sync::Semaphore,
+    time::{Instant, timeout_at},
+};
+use tracing::{debug, instrument};
+
+use crate::{config::MetadataConfig, logging::write_event};
+
+/// The ids that are looked up when a torrent is missing them
+const STANDARD_IDS: [&str; 2] = [ISBN_ID, ASIN_ID];
+
+/// Somewhere to look up the other standard ids of a book from the ones known
+pub trait IdProvider {
+    async fn lookup(&self, ids: &BTreeMap<String, String>) -> Result<BTreeMap<String, String>>;
+}
+
+pub struct MetadataService<P> {
+    provider: P,
+    /// Shared by every lookup of the service, so a bulk operation has at most
+    /// `max_concurrency` provider requests in flight
+    semaphore: Arc<Semaphore>,
+    /// How long a bulk operation may take in total
+    timeout: Option<Duration>,
+}
+
+impl MetadataService<OpenLibrary> {
+    pub fn open_library(config: &MetadataConfig) -> Self {
+        let cache = config.cache(&config.open_library);
+        Self::new(OpenLibrary::new(http::client(), cache), config)
+    }
+}
+
+impl<P: IdProvider> MetadataService<P> {
+    pub fn new(provider: P, config: &MetadataConfig) -> Self {
+        Self {
+            provider,
+            semaphore: Arc::new(Semaphore::new(config.max_concurrency.get())),
+            timeout: (config.timeout > 0).then(|| Duration::from_secs(config.timeout)),
+        }
+    }
+
+    /// Fills in the standard ids `meta` is missing from the ones it has,
+    /// nothing else is changed. Returns the ids that were added
+    pub async fn fill_missing_ids(
+        &self,
+        meta: &mut TorrentMeta,
+    ) -> Result<BTreeMap<String, String>> {
+        let mut added = BTreeMap::new();
+        if meta.ids.is_empty() || STANDARD_IDS.iter().all(|kind| meta.ids.contains_key(*kind)) {
+            return Ok(added);
+        }
+        let found = {
+            let _permit = self.semaphore.acquire().await?;
+            self.provider.lookup(&meta.ids).await?
+        };
+        for (kind, id) in found {
+            if STANDARD_IDS.contains(&kind.as_str()) && !meta.ids.contains_key(&kind) {
+                meta.ids.insert(kind.clone(), id.clone());
+                added.insert(kind, id);
+            }
+        }
+        Ok(added)
+    }
+
+    /// Looks up the missing ids of a torrent, saving and recording an event
+    /// for any that were found
+    #[instrument(skip_all)]
+    pub async fn verify_ids(&self, db: &Database<'_>, id: &str) -> Result<()> {
+        let Some(mut torrent) = db.r_transaction()?.get().primary::<Torrent>(id)? else {
+            return Err(anyhow!("Could not find torrent {id}"));
+        };
+        let added = self.fill_missing_ids(&mut torrent.meta).await?;
+        if added.is_empty() {
+            debug!("No new ids found for torrent {id}");
+            return Ok(());
+        }
+        {
+            let (_guard, rw) = db.rw_async().await?;
+            // Write the ids to the latest version, the lookup may have taken a while
+            let Some(mut current) = rw.get().primary::<Torrent>(id)? else {
+                return Err(anyhow!("Could not find torrent {id}"));
+            };
+            for (kind, value) in &added {
+                current.meta.ids.insert(kind.clone(), value.clone());
+            }
+            rw.upsert(current)?;
+            rw.commit()?;
+        }
+        write_event(
+            db,
+            Event::new(
+                Some(torrent.id),
+                Some(torrent.mam_id),
+                EventType::IdsAdded { ids: added },
+            ),
+        )
+        .await;
+        Ok(())
+    }
+
+    /// Verifies the ids of all the torrents at once, limited by the semaphore.
+    /// Returns the torrents that failed with their error, the ones still
+    /// running when the operation times out fail as timed out
+    pub async fn verify_all_ids(
+        &self,
+        db: &Database<'_>,
+        ids: &[String],
+    ) -> Vec<(String, anyhow::Error)> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut lookups = ids
+            .iter()
+            .map(|id| async move { (id, self.verify_ids(db, id).await) })
+            .collect::<FuturesUnordered<_>>();
+        let mut done = vec![];
+        let mut failed = vec![];
+        loop {
+            let next = match deadline {
+                Some(deadline) => match timeout_at(deadline, lookups.next()).await {
+                    Ok(next) => next,
+                    Err(_) => break,
+                },
+                None => lookups.next().await,
+            };
+            let Some((id, result)) = next else {
+                break;
+            };
+            done.push(id);
+            if let Err(err) = result {
+                failed.push((id.clone(), err));
+            }
+        }
+        if let Some(timeout) = self.timeout {
+            for id in ids.iter().filter(|id| !done.contains(id)) {
+                failed.push((
+                    id.clone(),
+                    anyhow!("timed out after {} seconds", timeout.as_secs()),
+                ));
+            }
+        }
+        failed
+    }
+}
+
+/// Looks up ids in the OpenLibrary search, which knows both the ISBNs and
+/// Amazon ids of its books
+pub struct OpenLibrary {
+    client: Client,
+    cache: Option<HttpCache>,
+}
+
+impl OpenLibrary {
+    pub fn new(client: Client, cache: Option<HttpCache>) -> Self {
+        Self { client, cache }
+    }
+}
+
+impl IdProvider for OpenLibrary {
+    async fn lookup(&self, ids: &BTreeMap<String, String>) -> Result<BTreeMap<String, String>> {
+        let query = if let Some(isbn) = ids.get(ISBN_ID) {
+            format!("isbn:{isbn}")
+        } else if let Some(asin) = ids.get(ASIN_ID) {
+            format!("id_amazon:{asin}")
+        } else {
+            return Ok(BTreeMap::new());
+        };
+        let mut url = Url::parse("https://openlibrary.org/search.json")?;
+        url.query_pairs_mut()
+            .append_pair("q", &query)
+            .append_pair("fields", "isbn,id_amazon")
+            .append_pair("limit", "1");
+        let body = match &self.cache {
+            Some(cache) => cache.get(&self.client, "open_library", url).await?,
+            None => {
+                http::send_with_retry(self.client.get(url))
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?
+            }
+        };
+        let response: OpenLibrarySearch = serde_json::from_slice(&body)?;
+        Ok(response
+            .docs
+            .into_iter()
+            .next()
+            .map(OpenLibraryDoc::ids)
+            .unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenLibrarySearch {
+    docs: Vec<OpenLibraryDoc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenLibraryDoc {
+    #[serde(default)]
+    isbn: Vec<String>,
+    #[serde(default)]
+    id_amazon: Vec<String>,
+}
+
+impl OpenLibraryDoc {
+    /// The ISBNs are of every edition of the book, ISBN-13 is preferred
+    fn ids(self) -> BTreeMap<String, String> {
+        let mut ids = BTreeMap::new();
+        let isbn = self
+            .isbn
+            .iter()
+            .find(|isbn| isbn.len() == 13)
+            .or(self.isbn.first());
+        if let Some(isbn) = isbn {
+            ids.insert(ISBN_ID.to_string(), isbn.clone());
+        }
+        if let Some(asin) = self.id_amazon.into_iter().find(|id| !id.is_empty()) {
+            ids.insert(ASIN_ID.to_string(), asin);
+        }
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::NonZeroUsize,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use futures::future::join_all;
+    use mlm_db::Size;
+
+    use super::*;
+    use crate::test_helpers::template_meta;
+
+    struct MockProvider;
+
+    impl IdProvider for MockProvider {
+        async fn lookup(&self, ids: &BTreeMap<String, String>) -> Result<BTreeMap<String, String>> {
+            assert_eq!(ids.get(ASIN_ID).map(String::as_str), Some("B004N3BNRU"));
+            Ok(BTreeMap::from([
+                (ISBN_ID.to_string(), "9780316129084".to_string()),
+                (ASIN_ID.to_string(), "B000000000".to_string()),
+                ("goodreads".to_string(), "8855321".to_string()),
+            ]))
+        }
+    }
+
+    /// Counts the lookups in flight, keeping the most seen at once
+    #[derive(Default)]
+    struct CountingProvider {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    impl IdProvider for CountingProvider {
+        async fn lookup(
+            &self,
+            _ids: &BTreeMap<String, String>,
+        ) -> Result<BTreeMap<String, String>> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(BTreeMap::new())
+        }
+    }
+
+    fn asin_meta() -> TorrentMeta {
+        TorrentMeta {
+            mam_id: 1,
+            main_cat: None,
+            num_files: 1,
+            size: Size::from_bytes(100),
+            title: "Leviathan Wakes".to_string(),
+            authors: vec!["James S. A. Corey".to_string()],
+            ids: BTreeMap::from([(ASIN_ID.to_string(), "B004N3BNRU".to_string())]),
+            ..template_meta()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fill_missing_ids() {
+        let mut meta = asin_meta();
+        let original = meta.clone();
+
+        let service = MetadataService::new(MockProvider, &MetadataConfig::default());
+        let added = service.fill_missing_ids(&mut meta).await.unwrap();
+        assert_eq!(
+            added,
+            BTreeMap::from([(ISBN_ID.to_string(), "9780316129084".to_string())])
+        );
+        assert_eq!(
+            meta.ids.get(ASIN_ID).map(String::as_str),
+            Some("B004N3BNRU")
+        );
+        assert_eq!(
+            TorrentMeta {
+                ids: original.ids.clone(),
+                ..meta.clone()
+            },
+            original
+        );
+
+        // Nothing is looked up once all standard ids are known
+        let added = service.fill_missing_ids(&mut meta).await.unwrap();
+        assert!(added.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrency() {
+        let service = MetadataService::new(
+            CountingProvider::default(),
+            &MetadataConfig {
+                max_concurrency: NonZeroUsize::new(3).unwrap(),
+                timeout: 0,
+                ..Default::default()
+            },
+        );
+        let mut metas = vec![asin_meta(); 20];
+        let results = join_all(metas.iter_mut().map(|meta| service.fill_missing_ids(meta))).await;
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(service.provider.in_flight.load(Ordering::SeqCst), 0);
+        assert_eq!(service.provider.max_in_flight.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_open_library_ids() {
+        let response: OpenLibrarySearch = serde_json::from_str(
+            r#"{"numFound": 1, "docs": [{
+                "isbn": ["0316129089", "9780316129084"],
+                "id_amazon": ["", "B004N3BNRU"]
+            }]}"#,
+        )
+        .unwrap();
+        let doc = response.docs.into_iter().next().unwrap();
+        assert_eq!(
+            doc.ids(),
+            BTreeMap::from([
+                (ASIN_ID.to_string(), "B004N3BNRU".to_string()),
+                (ISBN_ID.to_string(), "9780316129084".to_string()),
+            ])
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use native_db::Database;
+use tracing::{error, info};
+
+use crate::{
+    config::{Config, Snatchlist, TorrentSearch},
+    lists::{List, get_lists},
+};
+
+/// Paused tasks are stored in the db config table under this prefix, so they
+/// stay paused after a restart
+const PAUSED_PREFIX: &str = "paused_task:";
+
+/// The key of an autograb or snatchlist, by the index the search triggers use,
+/// with the snatchlists after the autograbs
+pub fn search_task_key(config: &Config, index: usize) -> Option<String> {
+    if let Some(grab) = config.autograbs.get(index) {
+        return Some(autograb_task_key(grab, index));
+    }
+    let index = index - config.autograbs.len();
+    let grab = config.snatchlist.get(index)?;
+    Some(snatchlist_task_key(grab, index))
+}
+
+pub fn autograb_task_key(grab: &TorrentSearch, index: usize) -> String {
+    task_key("autograb", grab.filter.name.as_deref(), index)
+}
+
+pub fn snatchlist_task_key(grab: &Snatchlist, index: usize) -> String {
+    task_key("snatchlist", grab.filter().name.as_deref(), index)
+}
+
+pub fn list_task_key(list: &List, index: usize) -> String {
+    task_key("list", list.name(), index)
+}
+
+/// Tasks with a name are keyed by it, so they stay paused when the config is
+/// reordered, others by their position. The two use different separators, so
+/// a task named "1" is not the second unnamed one
+fn task_key(kind: &str, name: Option<&str>, index: usize) -> String {
+    match name {
+        Some(name) => format!("{kind}:{name}"),
+        None => format!("{kind}-{index}"),
+    }
+}
+
+/// The keys of every task that can be paused
+pub fn task_keys(config: &Config) -> Vec<String> {
+    let searches = config.autograbs.len() + config.snatchlist.len();
+    (0..searches)
+        .filter_map(|i| search_task_key(config, i))
+        .chain(
+            get_lists(config)
+                .iter()
+                .enumerate()
+                .map(|(i, list)| list_task_key(list, i)),
+        )
+        .collect()
+}
+
+pub fn is_paused(db: &Database<'_>, key: &str) -> bool {
+    let db_key = format!("{PAUSED_PREFIX}{key}");
+    match db
+        .r_transaction()
+        .and_then(|r| r.get().primary::<mlm_db::Config>(db_key))
+    {
+        Ok(paused) => paused.is_some(),
+        Err(err) => {
+            error!("Error reading paused state of {key}: {err:?}");
+            false
+        }
+    }
+}
+
+pub fn paused_tasks(db: &Database<'_>) -> Result<BTreeSet<String>> {
+    let r = db.r_transaction()?;
+    let mut paused = BTreeSet::new();
+    for row in r.scan().primary::<mlm_db::Config>()?.all()? {
+        if let Some(key) = row?.key.strip_prefix(PAUSED_PREFIX) {
+            paused.insert(key.to_string());
+        }
+    }
+    Ok(paused)
+}
+
+/// Pauses or resumes a task, a paused task skips its runs until it is resumed
+pub async fn set_paused(db: &Database<'_>, key: &str, paused: bool) -> Result<()> {
+    let (_guard, rw) = db.rw_async().await?;
+    let db_key = format!("{PAUSED_PREFIX}{key}");
+    if paused {
+        rw.upsert(mlm_db::Config {
+            key: db_key,
+            value: "true".to_string(),
+        })?;
+    } else if let Some(row) = rw.get().primary::<mlm_db::Config>(db_key)? {
+        rw.remove(row)?;
+    }
+    rw.commit()?;
+    if paused {
+        info!("Paused {key}");
+    } else {
+        info!("Resumed {key}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_keys() {
+        let config: Config = toml::from_str(
+            r#"
+            mam_id = ""
+
+            [[autograb]]
+            type = "freeleech"
+            name = "Freeleech"
+
+            [[autograb]]
+            type = "bookmarks"
+
+            [[autograb]]
+            type = "freeleech"
+            name = "1"
+
+            [[snatchlist]]
+            file = "/config/mam_ids.txt"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            task_keys(&config),
+            vec![
+                "autograb:Freeleech",
+                "autograb-1",
+                "autograb:1",
+                "snatchlist-0"
+            ]
+        );
+        assert_eq!(search_task_key(&config, 4), None);
+    }
+}
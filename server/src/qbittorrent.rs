@@ -1,16 +1,31 @@
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
+use mlm_db::{
+    DatabaseExt as _, MediaType, MetadataSource, Size, Timestamp, Torrent as DbTorrent, TorrentKey,
+    TorrentMeta,
+};
+use mlm_mam::api::{MaM, RateLimitError};
+use mlm_parse::TitleRules;
+use native_db::Database;
 use once_cell::sync::Lazy;
 use qbit::{
     models::Torrent,
     parameters::{AddTorrent, TorrentListParams},
 };
-use tokio::sync::RwLock;
+use time::UtcDateTime;
+use tokio::{sync::RwLock, time::sleep};
+use tracing::{info, warn};
 
-use crate::config::{Config, QbitConfig};
+use crate::{
+    audiobookshelf::IMPORTED_MAM_ID_START,
+    config::{Config, ContentLayout, QbitConfig},
+    linker::link_torrents_to_library,
+    stats::Stats,
+};
 
 const CATEGORY_CACHE_TTL_SECS: u64 = 60;
 
@@ -95,6 +110,48 @@ pub async fn add_torrent_with_category(
     qbit.add_torrent(add_torrent).await.map_err(|e| anyhow::Error::new(e))
 }
 
+impl From<ContentLayout> for qbit::parameters::ContentLayout {
+    fn from(value: ContentLayout) -> Self {
+        match value {
+            ContentLayout::Original => qbit::parameters::ContentLayout::Original,
+            ContentLayout::Subfolder => qbit::parameters::ContentLayout::Subfolder,
+            ContentLayout::NoSubfolder => qbit::parameters::ContentLayout::NoSubfolder,
+        }
+    }
+}
+
+/// Schedules the runs of a task that logs in to qBittorrent, retrying a failed
+/// login sooner than the normal interval and backing off while it keeps failing
+pub struct ReconnectBackoff {
+    delay: Duration,
+    interval: Duration,
+    failures: u32,
+}
+
+impl ReconnectBackoff {
+    pub fn new(delay: Duration, interval: Duration) -> Self {
+        Self {
+            delay,
+            interval,
+            failures: 0,
+        }
+    }
+
+    /// How long to wait before the next run after a login
+    pub fn next_delay<T, E>(&mut self, login: &Result<T, E>) -> Duration {
+        if login.is_ok() {
+            self.failures = 0;
+            return self.interval;
+        }
+        let delay = self
+            .delay
+            .saturating_mul(2u32.saturating_pow(self.failures))
+            .min(self.interval);
+        self.failures = self.failures.saturating_add(1);
+        delay
+    }
+}
+
 pub async fn get_torrent<'a, 'b>(
     config: &'a Config,
     hash: &'b str,
@@ -124,3 +181,377 @@ pub async fn get_torrent<'a, 'b>(
     }
     Ok(None)
 }
+
+/// A finished torrent in qBittorrent, as far as [`import_torrents`] needs it
+pub struct ClientTorrent {
+    pub hash: String,
+    pub name: String,
+    pub category: String,
+    /// Paths of the files in the torrent
+    pub files: Vec<String>,
+}
+
+/// Where [`import_torrents`] looks up the metadata of a torrent by its hash
+pub trait MetaLookup {
+    async fn meta_by_hash(&self, hash: &str, rules: &TitleRules) -> Result<Option<TorrentMeta>>;
+}
+
+/// MaM is asked about one torrent after the other while importing, so the
+/// lookups are spaced out and wait out rate limiting like the other jobs
+impl MetaLookup for MaM<'_> {
+    async fn meta_by_hash(&self, hash: &str, rules: &TitleRules) -> Result<Option<TorrentMeta>> {
+        let mam_torrent = loop {
+            match self.get_torrent_info(hash).await {
+                Ok(mam_torrent) => break mam_torrent,
+                Err(err) => match err.downcast::<RateLimitError>() {
+                    Ok(_) => {
+                        warn!("Rate limited by MaM while importing, waiting before trying again");
+                        sleep(Duration::from_secs(30)).await;
+                    }
+                    Err(err) => return Err(err),
+                },
+            }
+        };
+        sleep(Duration::from_millis(400)).await;
+        let Some(mam_torrent) = mam_torrent else {
+            return Ok(None);
+        };
+        Ok(Some(mam_torrent.as_meta(rules)?))
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportedTorrents {
+    /// Found on MaM by their hash
+    pub from_mam: usize,
+    /// Not on MaM, imported with metadata from their names
+    pub from_files: usize,
+    /// Already known to MLM, or without any audio or ebook files
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+/// Imports the finished torrents of every qBittorrent client, only those in
+/// `category` when it is set, and links them into the libraries
+pub async fn import_qbittorrent(
+    config: Arc<Config>,
+    db: Arc<Database<'_>>,
+    mam: Arc<MaM<'_>>,
+    category: Option<&str>,
+) -> Result<ImportedTorrents> {
+    let mut imported = ImportedTorrents::default();
+    for qbit_conf in &config.qbittorrent {
+        let qbit = qbit::Api::new_login_username_password(
+            &qbit_conf.url,
+            &qbit_conf.username,
+            &qbit_conf.password,
+        )
+        .await
+        .with_context(|| format!("login to {}", qbit_conf.url))?;
+        let torrents = client_torrents(&qbit, category).await?;
+        let client = import_torrents(&config, &db, &*mam, torrents).await?;
+        link_torrents_to_library(
+            config.clone(),
+            db.clone(),
+            (qbit_conf, &qbit),
+            mam.clone(),
+            &Stats::new(),
+        )
+        .await
+        .context("link_torrents_to_library")?;
+        imported.from_mam += client.from_mam;
+        imported.from_files += client.from_files;
+        imported.skipped += client.skipped;
+        imported.failed += client.failed;
+    }
+    Ok(imported)
+}
+
+async fn client_torrents(qbit: &qbit::Api, category: Option<&str>) -> Result<Vec<ClientTorrent>> {
+    let mut torrents = vec![];
+    for torrent in qbit.torrents(Some(TorrentListParams::default())).await? {
+        if torrent.progress < 1.0 || category.is_some_and(|category| torrent.category != category) {
+            continue;
+        }
+        let files = qbit.files(&torrent.hash, None).await?;
+        torrents.push(ClientTorrent {
+            hash: torrent.hash,
+            name: torrent.name,
+            category: torrent.category,
+            files: files.into_iter().map(|file| file.name).collect(),
+        });
+    }
+    Ok(torrents)
+}
+
+/// Adds the torrents MLM doesn't know about yet. The ones MaM knows get its
+/// metadata, the others are imported with metadata from their names
+pub async fn import_torrents(
+    config: &Config,
+    db: &Database<'_>,
+    lookup: &impl MetaLookup,
+    torrents: Vec<ClientTorrent>,
+) -> Result<ImportedTorrents> {
+    let mut next_mam_id = IMPORTED_MAM_ID_START;
+    for torrent in db.r_transaction()?.scan().primary::<DbTorrent>()?.all()? {
+        next_mam_id = next_mam_id.max(torrent?.mam_id + 1);
+    }
+
+    let mut imported = ImportedTorrents::default();
+    for torrent in torrents {
+        if db
+            .r_transaction()?
+            .get()
+            .primary::<DbTorrent>(torrent.hash.clone())?
+            .is_some()
+        {
+            imported.skipped += 1;
+            continue;
+        }
+        let meta = match lookup
+            .meta_by_hash(&torrent.hash, config.title_rules())
+            .await
+        {
+            Ok(Some(meta)) => {
+                if db
+                    .r_transaction()?
+                    .get()
+                    .secondary::<DbTorrent>(TorrentKey::mam_id, meta.mam_id)?
+                    .is_some()
+                {
+                    imported.skipped += 1;
+                    continue;
+                }
+                imported.from_mam += 1;
+                meta
+            }
+            Ok(None) => {
+                let Some(meta) = file_meta(config, &torrent.name, &torrent.files, next_mam_id)
+                else {
+                    imported.skipped += 1;
+                    continue;
+                };
+                next_mam_id += 1;
+                imported.from_files += 1;
+                meta
+            }
+            Err(err) => {
+                warn!("Could not look up \"{}\" on MaM: {err:#}", torrent.name);
+                imported.failed += 1;
+                continue;
+            }
+        };
+        info!("Importing \"{}\" from qBittorrent", meta.title);
+        let (_guard, rw) = db.rw_async().await?;
+        rw.insert(DbTorrent {
+            category: (!torrent.category.is_empty()).then_some(torrent.category),
+            ..DbTorrent::new(torrent.hash, meta, config.title_rules())
+        })?;
+        rw.commit()?;
+    }
+    Ok(imported)
+}
+
+/// Minimal metadata for a torrent MaM doesn't know, from its name like
+/// "Author - Title". `None` when it has no audio or ebook files
+pub fn file_meta(
+    config: &Config,
+    name: &str,
+    files: &[String],
+    mam_id: u64,
+) -> Option<TorrentMeta> {
+    let filetypes = |types: &[String]| {
+        types
+            .iter()
+            .filter(|ext| {
+                let ext = format!(".{}", ext.to_lowercase());
+                files.iter().any(|file| file.to_lowercase().ends_with(&ext))
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let audio_types = filetypes(&config.audio_types);
+    let (media_type, filetypes) = if !audio_types.is_empty() {
+        (MediaType::Audiobook, audio_types)
+    } else {
+        let ebook_types = filetypes(&config.ebook_types);
+        if ebook_types.is_empty() {
+            return None;
+        }
+        (MediaType::Ebook, ebook_types)
+    };
+    // A torrent of a single file is named after the file
+    let name = if files.len() == 1 && files[0] == name {
+        Path::new(name)
+            .file_stem()
+            .map_or(name.to_string(), |stem| stem.to_string_lossy().into_owned())
+    } else {
+        name.to_string()
+    };
+    let (authors, title) = match name.split_once(" - ") {
+        Some((author, title)) => (vec![author.trim().to_string()], title.trim()),
+        None => (vec![], name.trim()),
+    };
+    let (title, edition) = config.title_rules().parse_edition(title, "");
+    Some(TorrentMeta {
+        mam_id,
+        vip_status: None,
+        cat: None,
+        media_type,
+        main_cat: None,
+        categories: vec![],
+        language: None,
+        flags: None,
+        filetypes,
+        num_files: files.len() as u64,
+        size: Size::from_bytes(0),
+        title,
+        edition,
+        authors,
+        original_authors: None,
+        narrators: vec![],
+        series: vec![],
+        description: None,
+        ids: Default::default(),
+        dramatized: false,
+        source: MetadataSource::File,
+        uploaded_at: Timestamp(UtcDateTime::UNIX_EPOCH),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::test_helpers::template_meta;
+
+    struct MockLookup;
+
+    impl MetaLookup for MockLookup {
+        async fn meta_by_hash(
+            &self,
+            hash: &str,
+            _rules: &TitleRules,
+        ) -> Result<Option<TorrentMeta>> {
+            match hash {
+                "on-mam" => Ok(Some(TorrentMeta {
+                    mam_id: 42,
+                    main_cat: None,
+                    filetypes: vec!["m4b".to_string()],
+                    num_files: 1,
+                    size: Size::from_bytes(1024),
+                    title: "Leviathan Wakes".to_string(),
+                    authors: vec!["James S. A. Corey".to_string()],
+                    ..template_meta()
+                })),
+                "broken" => Err(anyhow!("rate limited")),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    fn client_torrent(hash: &str, name: &str, files: &[&str]) -> ClientTorrent {
+        ClientTorrent {
+            hash: hash.to_string(),
+            name: name.to_string(),
+            category: "Audiobooks".to_string(),
+            files: files.iter().map(|file| file.to_string()).collect(),
+        }
+    }
+
+    fn client_listing() -> Vec<ClientTorrent> {
+        vec![
+            client_torrent("on-mam", "Leviathan Wakes", &["Leviathan Wakes.m4b"]),
+            client_torrent(
+                "local",
+                "Andy Weir - Project Hail Mary",
+                &["Project Hail Mary/01.mp3", "Project Hail Mary/02.mp3"],
+            ),
+            client_torrent("single", "Dune.epub", &["Dune.epub"]),
+            client_torrent("not-a-book", "Some Album", &["cover.jpg", "info.nfo"]),
+            client_torrent("broken", "Broken", &["Broken.m4b"]),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_import_torrents() {
+        let config: Config = toml::from_str(r#"mam_id = "abc""#).unwrap();
+        let db = native_db::Builder::new()
+            .create_in_memory(&mlm_db::MODELS)
+            .unwrap();
+
+        let imported = import_torrents(&config, &db, &MockLookup, client_listing())
+            .await
+            .unwrap();
+        assert_eq!(
+            imported,
+            ImportedTorrents {
+                from_mam: 1,
+                from_files: 2,
+                skipped: 1,
+                failed: 1,
+            }
+        );
+
+        let r = db.r_transaction().unwrap();
+        let get = |hash: &str| {
+            r.get()
+                .primary::<DbTorrent>(hash.to_string())
+                .unwrap()
+                .unwrap()
+        };
+        let on_mam = get("on-mam");
+        assert_eq!(on_mam.mam_id, 42);
+        assert_eq!(on_mam.meta.source, MetadataSource::Mam);
+        assert_eq!(on_mam.category.as_deref(), Some("Audiobooks"));
+        assert_eq!(on_mam.library_path, None);
+
+        let local = get("local");
+        assert_eq!(local.mam_id, IMPORTED_MAM_ID_START);
+        assert_eq!(local.meta.source, MetadataSource::File);
+        assert_eq!(local.meta.media_type, MediaType::Audiobook);
+        assert_eq!(local.meta.title, "Project Hail Mary");
+        assert_eq!(local.meta.authors, vec!["Andy Weir".to_string()]);
+        assert_eq!(local.meta.filetypes, vec!["mp3".to_string()]);
+
+        let single = get("single");
+        assert_eq!(single.mam_id, IMPORTED_MAM_ID_START + 1);
+        assert_eq!(single.meta.media_type, MediaType::Ebook);
+        assert_eq!(single.meta.title, "Dune");
+        assert!(single.meta.authors.is_empty());
+        drop(r);
+
+        // Running it again only retries the torrent that failed
+        let imported = import_torrents(&config, &db, &MockLookup, client_listing())
+            .await
+            .unwrap();
+        assert_eq!(
+            imported,
+            ImportedTorrents {
+                from_mam: 0,
+                from_files: 0,
+                skipped: 4,
+                failed: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let mut backoff = ReconnectBackoff::new(Duration::from_secs(30), Duration::from_secs(600));
+        let failed: Result<(), ()> = Err(());
+        let ok: Result<(), ()> = Ok(());
+
+        assert_eq!(backoff.next_delay(&failed), Duration::from_secs(30));
+        assert_eq!(backoff.next_delay(&failed), Duration::from_secs(60));
+        assert_eq!(backoff.next_delay(&failed), Duration::from_secs(120));
+        // The task keeps running and is back on its interval once logged in
+        assert_eq!(backoff.next_delay(&ok), Duration::from_secs(600));
+        assert_eq!(backoff.next_delay(&failed), Duration::from_secs(30));
+        for _ in 0..40 {
+            backoff.next_delay(&failed);
+        }
+        assert_eq!(backoff.next_delay(&failed), Duration::from_secs(600));
+    }
+}
@@ -0,0 +1,633 @@
+use std::fmt::Write as _;
+
+use anyhow::{Result, bail};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use mlm_mam::http;
+use quick_xml::{Reader, events::Event};
+use reqwest::Client;
+
+use crate::config::{Config, RtorrentConfig};
+
+/// A minimal XML-RPC client for rTorrent, as exposed by ruTorrent's `/RPC2` or a
+/// plain `scgi_port` behind a web server.
+#[derive(Clone)]
+pub struct Rtorrent {
+    client: Client,
+    url: String,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RtorrentTorrent {
+    pub hash: String,
+    pub name: String,
+    /// ruTorrent's label, stored URL-encoded in `d.custom1`
+    pub label: String,
+    pub ratio: f64,
+    pub complete: bool,
+    pub directory: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RtorrentFile {
+    /// Relative to the torrent's `directory`
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    String(String),
+    Int(i64),
+    Double(f64),
+    Bool(bool),
+    Base64(String),
+    Array(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+}
+
+impl Rtorrent {
+    pub fn new(config: &RtorrentConfig, client: Client) -> Self {
+        Self {
+            client,
+            url: config.url.clone(),
+            username: config.username.clone(),
+            password: config.password.clone(),
+        }
+    }
+
+    pub async fn torrents(&self) -> Result<Vec<RtorrentTorrent>> {
+        let response = self
+            .call(
+                "d.multicall2",
+                vec![
+                    Value::String(String::new()),
+                    Value::String("main".to_string()),
+                    Value::String("d.hash=".to_string()),
+                    Value::String("d.name=".to_string()),
+                    Value::String("d.custom1=".to_string()),
+                    Value::String("d.ratio=".to_string()),
+                    Value::String("d.complete=".to_string()),
+                    Value::String("d.directory=".to_string()),
+                ],
+            )
+            .await?;
+        let Value::Array(rows) = response else {
+            bail!("Unexpected d.multicall2 response: {response:?}");
+        };
+        rows.into_iter()
+            .map(|row| {
+                let Value::Array(fields) = row else {
+                    bail!("Unexpected d.multicall2 row: {row:?}");
+                };
+                let [hash, name, label, ratio, complete, directory] = fields.as_slice() else {
+                    bail!("Unexpected d.multicall2 row: {fields:?}");
+                };
+                Ok(RtorrentTorrent {
+                    hash: hash.as_str()?.to_lowercase(),
+                    name: name.as_str()?.to_string(),
+                    label: urlencoding::decode(label.as_str()?)?.into_owned(),
+                    // rTorrent reports the ratio in thousandths
+                    ratio: ratio.as_int()? as f64 / 1000.0,
+                    complete: complete.as_int()? == 1,
+                    directory: directory.as_str()?.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    pub async fn torrent(&self, hash: &str) -> Result<Option<RtorrentTorrent>> {
+        Ok(self
+            .torrents()
+            .await?
+            .into_iter()
+            .find(|t| t.hash.eq_ignore_ascii_case(hash)))
+    }
+
+    pub async fn files(&self, hash: &str) -> Result<Vec<RtorrentFile>> {
+        let response = self
+            .call(
+                "f.multicall",
+                vec![
+                    Value::String(hash.to_uppercase()),
+                    Value::String(String::new()),
+                    Value::String("f.path=".to_string()),
+                    Value::String("f.size_bytes=".to_string()),
+                ],
+            )
+            .await?;
+        let Value::Array(rows) = response else {
+            bail!("Unexpected f.multicall response: {response:?}");
+        };
+        rows.into_iter()
+            .map(|row| {
+                let Value::Array(fields) = row else {
+                    bail!("Unexpected f.multicall row: {row:?}");
+                };
+                let [path, size] = fields.as_slice() else {
+                    bail!("Unexpected f.multicall row: {fields:?}");
+                };
+                Ok(RtorrentFile {
+                    path: path.as_str()?.to_string(),
+                    size: size.as_int()? as u64,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn add_torrent(&self, data: &[u8], label: Option<&str>, stopped: bool) -> Result<()> {
+        let method = if stopped {
+            "load.raw_verbose"
+        } else {
+            "load.raw_start_verbose"
+        };
+        let mut params = vec![
+            Value::String(String::new()),
+            Value::Base64(STANDARD.encode(data)),
+        ];
+        if let Some(label) = label.filter(|l| !l.is_empty()) {
+            params.push(Value::String(format!(
+                "d.custom1.set={}",
+                urlencoding::encode(label)
+            )));
+        }
+        self.call(method, params).await?;
+        Ok(())
+    }
+
+    pub async fn set_label(&self, hash: &str, label: &str) -> Result<()> {
+        self.call(
+            "d.custom1.set",
+            vec![
+                Value::String(hash.to_uppercase()),
+                Value::String(urlencoding::encode(label).into_owned()),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Starts checking the data of a torrent against its hashes
+    pub async fn check_hash(&self, hash: &str) -> Result<()> {
+        self.call("d.check_hash", vec![Value::String(hash.to_uppercase())])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn start(&self, hash: &str) -> Result<()> {
+        self.call("d.start", vec![Value::String(hash.to_uppercase())])
+            .await?;
+        Ok(())
+    }
+
+    /// Whether the torrent is still being checked, and the fraction of its
+    /// data that is complete
+    pub async fn check_state(&self, hash: &str) -> Result<(bool, f64)> {
+        let hash = Value::String(hash.to_uppercase());
+        let hashing = self.call("d.hashing", vec![hash.clone()]).await?;
+        let completed = self.call("d.completed_bytes", vec![hash.clone()]).await?;
+        let size = self.call("d.size_bytes", vec![hash]).await?;
+        let size = size.as_int()?;
+        let progress = if size > 0 {
+            completed.as_int()? as f64 / size as f64
+        } else {
+            0.0
+        };
+        Ok((hashing.as_int()? != 0, progress))
+    }
+
+    async fn call(&self, method: &str, params: Vec<Value>) -> Result<Value> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "text/xml")
+            .body(method_call(method, &params));
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+        let body = request.send().await?.error_for_status()?.text().await?;
+        parse_response(&body)
+    }
+}
+
+/// Finds a torrent in any of the configured rTorrent clients
+pub async fn get_torrent(
+    config: &Config,
+    hash: &str,
+) -> Result<Option<(RtorrentTorrent, Rtorrent)>> {
+    for rtorrent_conf in config.rtorrent.iter() {
+        let rtorrent = Rtorrent::new(rtorrent_conf, http::client());
+        if let Some(torrent) = rtorrent.torrent(hash).await? {
+            return Ok(Some((torrent, rtorrent)));
+        }
+    }
+    Ok(None)
+}
+
+impl Value {
+    fn as_str(&self) -> Result<&str> {
+        match self {
+            Value::String(s) => Ok(s),
+            _ => bail!("Expected string, got {self:?}"),
+        }
+    }
+
+    fn as_int(&self) -> Result<i64> {
+        match self {
+            Value::Int(i) => Ok(*i),
+            Value::Bool(b) => Ok(*b as i64),
+            _ => bail!("Expected int, got {self:?}"),
+        }
+    }
+
+    fn write_xml(&self, out: &mut String) {
+        out.push_str("<value>");
+        match self {
+            Value::String(s) => {
+                let _ = write!(out, "<string>{}</string>", quick_xml::escape::escape(s));
+            }
+            Value::Int(i) => {
+                let _ = write!(out, "<i8>{i}</i8>");
+            }
+            Value::Double(d) => {
+                let _ = write!(out, "<double>{d}</double>");
+            }
+            Value::Bool(b) => {
+                let _ = write!(out, "<boolean>{}</boolean>", *b as u8);
+            }
+            Value::Base64(b) => {
+                let _ = write!(out, "<base64>{b}</base64>");
+            }
+            Value::Array(values) => {
+                out.push_str("<array><data>");
+                for value in values {
+                    value.write_xml(out);
+                }
+                out.push_str("</data></array>");
+            }
+            Value::Struct(members) => {
+                out.push_str("<struct>");
+                for (name, value) in members {
+                    let _ = write!(
+                        out,
+                        "<member><name>{}</name>",
+                        quick_xml::escape::escape(name)
+                    );
+                    value.write_xml(out);
+                    out.push_str("</member>");
+                }
+                out.push_str("</struct>");
+            }
+        }
+        out.push_str("</value>");
+    }
+}
+
+fn method_call(method: &str, params: &[Value]) -> String {
+    let mut out = String::from(r#"<?xml version="1.0"?><methodCall><methodName>"#);
+    out.push_str(method);
+    out.push_str("</methodName><params>");
+    for param in params {
+        out.push_str("<param>");
+        param.write_xml(&mut out);
+        out.push_str("</param>");
+    }
+    out.push_str("</params></methodCall>");
+    out
+}
+
+enum Frame {
+    Value {
+        tag: Option<String>,
+        text: String,
+        inner: Option<Value>,
+        closed: bool,
+    },
+    Array(Vec<Value>),
+    Struct(Vec<(String, Value)>, Option<String>),
+    Name(String),
+}
+
+fn parse_response(xml: &str) -> Result<Value> {
+    let mut reader = Reader::from_str(xml);
+
+    let mut stack: Vec<Frame> = vec![];
+    let mut result = None;
+    let mut is_fault = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match e.name().as_ref() {
+                b"fault" => is_fault = true,
+                b"value" => stack.push(Frame::Value {
+                    tag: None,
+                    text: String::new(),
+                    inner: None,
+                    closed: false,
+                }),
+                b"array" => stack.push(Frame::Array(vec![])),
+                b"struct" => stack.push(Frame::Struct(vec![], None)),
+                b"name" => stack.push(Frame::Name(String::new())),
+                tag => {
+                    if let Some(Frame::Value { tag: t, text, .. }) = stack.last_mut() {
+                        *t = Some(String::from_utf8_lossy(tag).into_owned());
+                        text.clear();
+                    }
+                }
+            },
+            Event::Empty(e) => match e.name().as_ref() {
+                b"value" => {
+                    finish_value(&mut stack, &mut result, Value::String(String::new()));
+                }
+                tag => {
+                    if let Some(Frame::Value {
+                        tag: t,
+                        text,
+                        closed,
+                        ..
+                    }) = stack.last_mut()
+                    {
+                        *t = Some(String::from_utf8_lossy(tag).into_owned());
+                        text.clear();
+                        *closed = true;
+                    }
+                }
+            },
+            Event::Text(e) => {
+                if let Some(text) = current_text(&mut stack) {
+                    text.push_str(&e.decode()?);
+                }
+            }
+            Event::GeneralRef(e) => {
+                let c = match e.resolve_char_ref()? {
+                    Some(c) => c,
+                    None => match e.decode()?.as_ref() {
+                        "amp" => '&',
+                        "lt" => '<',
+                        "gt" => '>',
+                        "quot" => '"',
+                        "apos" => '\'',
+                        other => bail!("Unknown XML entity &{other};"),
+                    },
+                };
+                if let Some(text) = current_text(&mut stack) {
+                    text.push(c);
+                }
+            }
+            Event::End(e) => match e.name().as_ref() {
+                b"value" => {
+                    let Some(Frame::Value {
+                        tag, text, inner, ..
+                    }) = stack.pop()
+                    else {
+                        bail!("Unbalanced XML-RPC response");
+                    };
+                    let value = match inner {
+                        Some(inner) => inner,
+                        None => scalar_value(tag.as_deref(), text)?,
+                    };
+                    finish_value(&mut stack, &mut result, value);
+                }
+                b"array" => {
+                    let Some(Frame::Array(values)) = stack.pop() else {
+                        bail!("Unbalanced XML-RPC response");
+                    };
+                    set_inner(&mut stack, Value::Array(values))?;
+                }
+                b"struct" => {
+                    let Some(Frame::Struct(members, _)) = stack.pop() else {
+                        bail!("Unbalanced XML-RPC response");
+                    };
+                    set_inner(&mut stack, Value::Struct(members))?;
+                }
+                b"name" => {
+                    let Some(Frame::Name(name)) = stack.pop() else {
+                        bail!("Unbalanced XML-RPC response");
+                    };
+                    if let Some(Frame::Struct(_, pending)) = stack.last_mut() {
+                        *pending = Some(name);
+                    }
+                }
+                _ => {
+                    if let Some(Frame::Value { closed, .. }) = stack.last_mut() {
+                        *closed = true;
+                    }
+                }
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let Some(result) = result else {
+        bail!("Empty XML-RPC response");
+    };
+    if is_fault {
+        let message = match &result {
+            Value::Struct(members) => members
+                .iter()
+                .find(|(name, _)| name == "faultString")
+                .and_then(|(_, v)| v.as_str().ok())
+                .unwrap_or("unknown fault")
+                .to_string(),
+            _ => format!("{result:?}"),
+        };
+        bail!("rTorrent error: {message}");
+    }
+    Ok(result)
+}
+
+fn scalar_value(tag: Option<&str>, text: String) -> Result<Value> {
+    Ok(match tag {
+        None | Some("string") => Value::String(text),
+        Some("i4" | "i8" | "int") => Value::Int(text.trim().parse()?),
+        Some("double") => Value::Double(text.trim().parse()?),
+        Some("boolean") => Value::Bool(text.trim() == "1"),
+        Some("base64") => Value::Base64(text),
+        Some(tag) => bail!("Unsupported XML-RPC type {tag}"),
+    })
+}
+
+/// The text node being collected, skipping whitespace after a scalar's closing tag
+fn current_text(stack: &mut [Frame]) -> Option<&mut String> {
+    match stack.last_mut() {
+        Some(Frame::Value {
+            text,
+            closed: false,
+            inner: None,
+            ..
+        }) => Some(text),
+        Some(Frame::Name(text)) => Some(text),
+        _ => None,
+    }
+}
+
+fn finish_value(stack: &mut [Frame], result: &mut Option<Value>, value: Value) {
+    match stack.last_mut() {
+        Some(Frame::Array(values)) => values.push(value),
+        Some(Frame::Struct(members, pending)) => {
+            members.push((pending.take().unwrap_or_default(), value));
+        }
+        _ => *result = Some(value),
+    }
+}
+
+fn set_inner(stack: &mut [Frame], value: Value) -> Result<()> {
+    let Some(Frame::Value { inner, .. }) = stack.last_mut() else {
+        bail!("Unbalanced XML-RPC response");
+    };
+    *inner = Some(value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Serves a single canned XML-RPC response and returns the request body
+    async fn mock_rpc(response: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/RPC2", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+            let mut buf = [0; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                if request.ends_with(b"</methodCall>") || n == 0 {
+                    break;
+                }
+            }
+            let reply = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                response.len()
+            );
+            socket.write_all(reply.as_bytes()).await.unwrap();
+            let request = String::from_utf8(request).unwrap();
+            request
+                .split_once("\r\n\r\n")
+                .map(|(_, body)| body.to_string())
+                .unwrap_or_default()
+        });
+        (url, handle)
+    }
+
+    fn client(url: String) -> Rtorrent {
+        Rtorrent::new(
+            &RtorrentConfig {
+                url,
+                username: None,
+                password: None,
+            },
+            http::client(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_list_torrents() {
+        let (url, request) = mock_rpc(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<methodResponse><params><param><value><array><data>
+<value><array><data>
+<value><string>ABCDEF0123</string></value>
+<value><string>Some Book &amp; More</string></value>
+<value><string>Audio%20Books</string></value>
+<value><i8>1500</i8></value>
+<value><i8>1</i8></value>
+<value><string>/downloads/Some Book</string></value>
+</data></array></value>
+</data></array></value></param></params></methodResponse>"#,
+        )
+        .await;
+
+        let torrents = client(url).torrents().await.unwrap();
+        assert_eq!(
+            torrents,
+            vec![RtorrentTorrent {
+                hash: "abcdef0123".to_string(),
+                name: "Some Book & More".to_string(),
+                label: "Audio Books".to_string(),
+                ratio: 1.5,
+                complete: true,
+                directory: "/downloads/Some Book".to_string(),
+            }]
+        );
+        let request = request.await.unwrap();
+        assert!(request.contains("<methodName>d.multicall2</methodName>"));
+        assert!(request.contains("<string>d.custom1=</string>"));
+    }
+
+    #[tokio::test]
+    async fn test_list_files() {
+        let (url, request) = mock_rpc(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<methodResponse><params><param><value><array><data>
+<value><array><data>
+<value><string>Some Book/01.mp3</string></value>
+<value><i8>1048576</i8></value>
+</data></array></value>
+<value><array><data>
+<value><string>Some Book/cover.jpg</string></value>
+<value><i8>2048</i8></value>
+</data></array></value>
+</data></array></value></param></params></methodResponse>"#,
+        )
+        .await;
+
+        let files = client(url).files("abcdef0123").await.unwrap();
+        assert_eq!(
+            files,
+            vec![
+                RtorrentFile {
+                    path: "Some Book/01.mp3".to_string(),
+                    size: 1048576,
+                },
+                RtorrentFile {
+                    path: "Some Book/cover.jpg".to_string(),
+                    size: 2048,
+                },
+            ]
+        );
+        let request = request.await.unwrap();
+        assert!(request.contains("<methodName>f.multicall</methodName>"));
+        assert!(request.contains("<string>ABCDEF0123</string>"));
+        assert!(request.contains("<string>f.path=</string>"));
+    }
+
+    #[tokio::test]
+    async fn test_add_torrent_with_label() {
+        let (url, request) = mock_rpc(
+            r#"<?xml version="1.0"?><methodResponse><params><param><value><i8>0</i8></value></param></params></methodResponse>"#,
+        )
+        .await;
+
+        client(url)
+            .add_torrent(b"foo", Some("Audio Books"), false)
+            .await
+            .unwrap();
+        let request = request.await.unwrap();
+        assert!(request.contains("<methodName>load.raw_start_verbose</methodName>"));
+        assert!(request.contains("<base64>Zm9v</base64>"));
+        assert!(request.contains("<string>d.custom1.set=Audio%20Books</string>"));
+    }
+
+    #[tokio::test]
+    async fn test_fault() {
+        let (url, _request) = mock_rpc(
+            r#"<?xml version="1.0"?><methodResponse><fault><value><struct>
+<member><name>faultCode</name><value><i4>-501</i4></value></member>
+<member><name>faultString</name><value><string>Could not find info-hash.</string></value></member>
+</struct></value></fault></methodResponse>"#,
+        )
+        .await;
+
+        let err = client(url).set_label("abc", "x").await.unwrap_err();
+        assert_eq!(err.to_string(), "rTorrent error: Could not find info-hash.");
+    }
+}
@@ -1,21 +1,31 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    future::pending,
+    iter,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use anyhow::{Context, Result, bail};
 use itertools::Itertools as _;
 use mlm_db::{
-    DatabaseExt, Event, EventType, MetadataSource, Timestamp, Torrent, TorrentKey, TorrentMeta,
-    VipStatus,
+    DatabaseExt, Event, EventType, MetadataSource, SelectedTorrent, Timestamp, Torrent, TorrentKey,
+    TorrentMeta, VipStatus,
 };
 use mlm_mam::{api::MaM, meta::MetaError, user_torrent::UserDetailsTorrent};
-use mlm_parse::normalize_title;
 use native_db::{Database, db_type, transaction::RwTransaction};
 use time::UtcDateTime;
-use tokio::{sync::MutexGuard, time::sleep};
+use tokio::{
+    fs,
+    sync::{MutexGuard, watch::Sender},
+    time::sleep,
+};
 use tracing::{Level, debug, enabled, info, instrument, trace, warn};
 use uuid::Uuid;
 
 use crate::{
-    config::{Config, Cost, SnatchlistSearch, TorrentFilter},
+    autograbber::select_torrents,
+    config::{Config, Cost, Snatchlist, SnatchlistFile, SnatchlistSearch, TorrentFilter},
     logging::write_event,
 };
 
@@ -24,8 +34,169 @@ pub async fn run_snatchlist_search(
     config: Arc<Config>,
     db: Arc<Database<'_>>,
     mam: Arc<MaM<'_>>,
+    autograb_trigger: Sender<()>,
+    index: usize,
+    snatchlist: Arc<Snatchlist>,
+) -> Result<()> {
+    match snatchlist.as_ref() {
+        Snatchlist::Search(snatchlist_config) => {
+            run_snatchlist_mam_search(&config, &db, &mam, index, snatchlist_config).await
+        }
+        Snatchlist::File(snatchlist_config) => {
+            run_snatchlist_file(&config, &db, &mam, index, snatchlist_config).await?;
+            if config.has_download_client() {
+                autograb_trigger.send(())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Resolves when the file of a file snatchlist has been modified, never
+/// resolves for other snatchlists.
+pub async fn wait_for_snatchlist_change(snatchlist: &Snatchlist) {
+    let Snatchlist::File(snatchlist_config) = snatchlist else {
+        return pending().await;
+    };
+    wait_for_file_change(&snatchlist_config.file).await;
+}
+
+/// Resolves when the file has been modified, checking every 30 seconds
+pub async fn wait_for_file_change(path: &Path) {
+    let modified = file_modified(path).await;
+    loop {
+        sleep(Duration::from_secs(30)).await;
+        if file_modified(path).await != modified {
+            debug!("file {} changed", path.display());
+            return;
+        }
+    }
+}
+
+async fn file_modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).await.and_then(|m| m.modified()).ok()
+}
+
+#[instrument(skip_all)]
+async fn run_snatchlist_file(
+    config: &Config,
+    db: &Database<'_>,
+    mam: &MaM<'_>,
+    index: usize,
+    snatchlist_config: &SnatchlistFile,
+) -> Result<()> {
+    let name = snatchlist_config
+        .filter
+        .name
+        .clone()
+        .unwrap_or_else(|| index.to_string());
+    let content = fs::read_to_string(&snatchlist_config.file)
+        .await
+        .with_context(|| format!("read {}", snatchlist_config.file.display()))?;
+    let mam_ids = parse_mam_ids(&content);
+    debug!("snatchlist {}, {} ids in file", name, mam_ids.len());
+
+    let user_info = mam.user_info().await?;
+    let unsat_buffer = snatchlist_config
+        .unsat_buffer
+        .unwrap_or(config.unsat_buffer.min());
+    let mut max_torrents = user_info
+        .unsat
+        .limit
+        .saturating_sub(user_info.unsat.count)
+        .saturating_sub(unsat_buffer);
+
+    for mam_id in mam_ids {
+        if max_torrents == 0
+            && snatchlist_config.cost != Cost::MetadataOnly
+            && snatchlist_config.cost != Cost::MetadataOnlyAdd
+        {
+            debug!("snatchlist {name} has no unsats left");
+            break;
+        }
+        {
+            let r = db.r_transaction()?;
+            if r.get()
+                .secondary::<Torrent>(TorrentKey::mam_id, mam_id)?
+                .is_some()
+                || r.get().primary::<SelectedTorrent>(mam_id)?.is_some()
+            {
+                trace!("Torrent {mam_id} is already selected or in library");
+                continue;
+            }
+        }
+
+        let Some(torrent) = mam.get_torrent_info_by_id(mam_id).await? else {
+            warn!("Torrent {mam_id} from snatchlist {name} was not found on MaM");
+            sleep(Duration::from_millis(400)).await;
+            continue;
+        };
+        if !snatchlist_config.filter.matches(&torrent) {
+            trace!("Torrent {mam_id} does not match snatchlist filter");
+            sleep(Duration::from_millis(400)).await;
+            continue;
+        }
+        let selected = select_torrents(
+            config,
+            db,
+            mam,
+            iter::once(torrent),
+            &snatchlist_config.filter,
+            snatchlist_config.cost,
+            snatchlist_config.unsat_buffer,
+            snatchlist_config.wedge_buffer,
+            snatchlist_config.category.clone(),
+            snatchlist_config.dry_run,
+            max_torrents.max(1),
+            None,
+            None,
+        )
+        .await
+        .context("select_torrents")?;
+        if selected > 0 {
+            max_torrents = max_torrents.saturating_sub(selected);
+            let cost = db
+                .r_transaction()?
+                .get()
+                .primary::<SelectedTorrent>(mam_id)?
+                .map(|t| t.cost);
+            write_event(
+                db,
+                Event::new(
+                    None,
+                    Some(mam_id),
+                    EventType::Selected {
+                        grabber: snatchlist_config.filter.name.clone(),
+                        cost,
+                    },
+                ),
+            )
+            .await;
+        }
+        sleep(Duration::from_millis(400)).await;
+    }
+
+    Ok(())
+}
+
+/// Reads mam ids from a newline or comma separated file. Anything that is not
+/// a number, like a CSV header or comments, is skipped.
+fn parse_mam_ids(content: &str) -> Vec<u64> {
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(|line| line.split([',', ';', '\t']))
+        .filter_map(|value| value.trim().trim_matches('"').parse().ok())
+        .unique()
+        .collect()
+}
+
+async fn run_snatchlist_mam_search(
+    config: &Config,
+    db: &Database<'_>,
+    mam: &MaM<'_>,
     index: usize,
-    snatchlist_config: Arc<SnatchlistSearch>,
+    snatchlist_config: &SnatchlistSearch,
 ) -> Result<()> {
     if !snatchlist_config.filter.languages.is_empty() {
         bail!("Language filtering is not supported in snatchlist searches");
@@ -48,7 +219,7 @@ pub async fn run_snatchlist_search(
         .unwrap_or_else(|| index.to_string());
     debug!("snatchlist {}", name);
 
-    search_and_update_torrents(&config, &db, &snatchlist_config, &mam)
+    search_and_update_torrents(config, db, snatchlist_config, mam)
         .await
         .context("search_torrents")?;
 
@@ -128,7 +299,7 @@ async fn update_torrents<T: Iterator<Item = UserDetailsTorrent>>(
             continue;
         }
 
-        let meta = match torrent.as_meta() {
+        let meta = match torrent.as_meta(config.title_rules()) {
             Ok(it) => it,
             Err(err) => match err {
                 MetaError::UnknownMediaType(_) => {
@@ -153,6 +324,7 @@ async fn update_torrents<T: Iterator<Item = UserDetailsTorrent>>(
             if let Some(old) = old_library {
                 if old.meta != meta {
                     update_torrent_meta(
+                        config,
                         db,
                         rw_opt.unwrap(),
                         &torrent,
@@ -168,7 +340,7 @@ async fn update_torrents<T: Iterator<Item = UserDetailsTorrent>>(
         }
         if cost == Cost::MetadataOnlyAdd {
             let mam_id = meta.mam_id;
-            add_metadata_only_torrent(rw_opt.unwrap(), torrent, meta)
+            add_metadata_only_torrent(config, rw_opt.unwrap(), torrent, meta)
                 .await
                 .or_else(|err| {
                     let err = err.downcast::<db_type::Error>()?;
@@ -194,6 +366,7 @@ async fn update_torrents<T: Iterator<Item = UserDetailsTorrent>>(
 
 #[instrument(skip_all)]
 async fn add_metadata_only_torrent(
+    config: &Config,
     (_guard, rw): (MutexGuard<'_, ()>, RwTransaction<'_>),
     torrent: UserDetailsTorrent,
     meta: TorrentMeta,
@@ -219,13 +392,14 @@ async fn add_metadata_only_torrent(
             category: None,
             selected_audio_format: None,
             selected_ebook_format: None,
-            title_search: normalize_title(&meta.title),
+            title_search: config.title_rules().normalize_title(&meta.title),
             meta,
             created_at: Timestamp::now(),
             replaced_with: None,
             request_matadata_update: false,
             library_mismatch: None,
             client_status: None,
+            note: None,
         })?;
         rw.commit()?;
     }
@@ -234,6 +408,7 @@ async fn add_metadata_only_torrent(
 }
 
 async fn update_torrent_meta(
+    config: &Config,
     db: &Database<'_>,
     (guard, rw): (MutexGuard<'_, ()>, RwTransaction<'_>),
     mam_torrent: &UserDetailsTorrent,
@@ -247,6 +422,8 @@ async fn update_torrent_meta(
     meta.language = torrent.meta.language;
     meta.num_files = torrent.meta.num_files;
     meta.uploaded_at = torrent.meta.uploaded_at;
+    meta.description = torrent.meta.description.clone();
+    meta.ids = torrent.meta.ids.clone();
 
     if torrent.meta.source != MetadataSource::Mam {
         // Update VIP status still
@@ -293,7 +470,7 @@ async fn update_torrent_meta(
             .join("\n")
     );
     torrent.meta = meta.clone();
-    torrent.title_search = normalize_title(&meta.title);
+    torrent.title_search = config.title_rules().normalize_title(&meta.title);
     rw.upsert(torrent.clone())?;
     rw.commit()?;
     drop(guard);
@@ -307,3 +484,26 @@ async fn update_torrent_meta(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mam_ids_lines() {
+        assert_eq!(parse_mam_ids("123\n456\n\n789\n"), vec![123, 456, 789]);
+    }
+
+    #[test]
+    fn test_parse_mam_ids_csv() {
+        assert_eq!(
+            parse_mam_ids("mam_id,title\n123,\"Some Book\"\n\"456\",Other Book\n"),
+            vec![123, 456]
+        );
+    }
+
+    #[test]
+    fn test_parse_mam_ids_skips_comments_and_duplicates() {
+        assert_eq!(parse_mam_ids("# wanted\n123\n123\n456"), vec![123, 456]);
+    }
+}
@@ -1,37 +1,49 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
-use mlm_db::Event;
-use mlm_mam::api::MaM;
+use mlm_db::{Event, Size};
+use mlm_mam::{api::MaM, user_data::UserResponse};
 use native_db::Database;
+use serde::Serialize;
 use time::{OffsetDateTime, UtcDateTime};
 use tokio::sync::{
     Mutex,
     watch::{self, Receiver, Sender},
 };
 
-use crate::config::Config;
+use crate::{config::Config, mam_sessions::MamSessions};
 
 #[derive(Default)]
 pub struct StatsValues {
     pub autograbber_run_at: BTreeMap<usize, OffsetDateTime>,
     pub autograbber_result: BTreeMap<usize, Result<()>>,
+    /// Why an autograbber stopped grabbing, e.g. its max_total_size was reached
+    pub autograbber_stopped: BTreeMap<usize, String>,
     pub import_run_at: BTreeMap<usize, OffsetDateTime>,
     pub import_result: BTreeMap<usize, Result<()>>,
+    pub import_progress: BTreeMap<usize, Progress>,
     pub linker_run_at: Option<OffsetDateTime>,
     pub linker_result: Option<Result<()>>,
+    pub linker_progress: Option<Progress>,
     pub cleaner_run_at: Option<OffsetDateTime>,
     pub cleaner_result: Option<Result<()>>,
     pub downloader_run_at: Option<OffsetDateTime>,
     pub downloader_result: Option<Result<()>>,
     pub audiobookshelf_run_at: Option<OffsetDateTime>,
     pub audiobookshelf_result: Option<Result<()>>,
+    pub audit_run_at: Option<OffsetDateTime>,
+    pub audit_result: Option<Result<()>>,
 }
 
 #[derive(Clone)]
 pub struct Stats {
     pub values: Arc<Mutex<StatsValues>>,
     values_updated: (Sender<UtcDateTime>, Receiver<UtcDateTime>),
+    maintenance: (Sender<bool>, Receiver<bool>),
 }
 
 impl Stats {
@@ -39,6 +51,7 @@ impl Stats {
         Self {
             values: Arc::new(Mutex::new(StatsValues::default())),
             values_updated: watch::channel(UtcDateTime::now()),
+            maintenance: watch::channel(false),
         }
     }
 
@@ -51,6 +64,154 @@ impl Stats {
     pub fn updates(&self) -> Receiver<UtcDateTime> {
         self.values_updated.1.clone()
     }
+
+    pub fn maintenance(&self) -> bool {
+        *self.maintenance.1.borrow()
+    }
+
+    pub fn set_maintenance(&self, maintenance: bool) {
+        self.maintenance.0.send_replace(maintenance);
+        self.values_updated.0.send(UtcDateTime::now()).unwrap();
+    }
+
+    /// Waits until maintenance mode is turned off. Background tasks call this
+    /// before starting a run, so a run that is already going is never interrupted
+    pub async fn wait_for_maintenance(&self) {
+        // The sender is kept in self, so this can't fail
+        let mut maintenance = self.maintenance.1.clone();
+        maintenance.wait_for(|maintenance| !maintenance).await.ok();
+    }
+}
+
+/// How often a running task may send its progress to the web ui
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How far the current run of a task has come
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    pub done: usize,
+    pub total: usize,
+    pub started_at: UtcDateTime,
+}
+
+impl Progress {
+    pub fn percent(&self) -> usize {
+        if self.total == 0 {
+            return 100;
+        }
+        self.done * 100 / self.total
+    }
+
+    /// Estimated time left, going by the rate of the run so far
+    pub fn eta(&self, now: UtcDateTime) -> Option<Duration> {
+        if self.done == 0 {
+            return None;
+        }
+        let elapsed = (now - self.started_at).unsigned_abs();
+        let left = self.total.saturating_sub(self.done) as u32;
+        Some(elapsed / self.done as u32 * left)
+    }
+}
+
+/// The task a [`ProgressReporter`] reports for
+#[derive(Clone, Copy, Debug)]
+pub enum ProgressTask {
+    Linker,
+    Import(usize),
+}
+
+/// Keeps the progress of a run in [`Stats`], only sending updates every
+/// [`PROGRESS_INTERVAL`] so long runs don't flood the watch channel. The
+/// progress is cleared when the result of the run is set
+pub struct ProgressReporter {
+    stats: Stats,
+    task: ProgressTask,
+    progress: Progress,
+    last_sent: Instant,
+}
+
+impl ProgressReporter {
+    /// Starts a new run, resetting the progress of the previous one
+    pub async fn start(stats: &Stats, task: ProgressTask, total: usize) -> Self {
+        let reporter = Self {
+            stats: stats.clone(),
+            task,
+            progress: Progress {
+                done: 0,
+                total,
+                started_at: UtcDateTime::now(),
+            },
+            last_sent: Instant::now(),
+        };
+        reporter.send().await;
+        reporter
+    }
+
+    pub async fn advance(&mut self) {
+        self.progress.done = (self.progress.done + 1).min(self.progress.total);
+        if self.progress.done == self.progress.total
+            || self.last_sent.elapsed() >= PROGRESS_INTERVAL
+        {
+            self.last_sent = Instant::now();
+            self.send().await;
+        }
+    }
+
+    async fn send(&self) {
+        let (task, progress) = (self.task, self.progress);
+        self.stats
+            .update(|stats| match task {
+                ProgressTask::Linker => stats.linker_progress = Some(progress),
+                ProgressTask::Import(i) => {
+                    stats.import_progress.insert(i, progress);
+                }
+            })
+            .await;
+    }
+}
+
+/// Ratio and unsat health of the MaM account
+#[derive(Clone, Debug, Serialize)]
+pub struct AccountStatus {
+    pub username: String,
+    /// None while nothing has been downloaded
+    pub ratio: Option<f64>,
+    pub uploaded: Size,
+    pub downloaded: Size,
+    /// How much can still be downloaded before dropping below min_ratio
+    pub buffer: Size,
+    pub bonus: i64,
+    pub wedges: u64,
+    pub unsat_count: u64,
+    pub unsat_limit: u64,
+    pub vip_until: Option<String>,
+    /// Ratio is below ratio_warning, or min_ratio if that isn't set
+    pub low_ratio: bool,
+    pub warning: bool,
+}
+
+impl AccountStatus {
+    pub fn new(config: &Config, user_info: &UserResponse) -> Self {
+        let ratio = (user_info.downloaded_bytes > 0.0)
+            .then(|| user_info.uploaded_bytes / user_info.downloaded_bytes);
+        let buffer =
+            ((user_info.uploaded_bytes - user_info.downloaded_bytes) / config.min_ratio).max(0.0);
+        let low_ratio = ratio.is_some_and(|r| r < config.ratio_warning.unwrap_or(config.min_ratio));
+        Self {
+            username: user_info.username.clone(),
+            ratio,
+            uploaded: Size::from_bytes(user_info.uploaded_bytes as u64),
+            downloaded: Size::from_bytes(user_info.downloaded_bytes as u64),
+            buffer: Size::from_bytes(buffer as u64),
+            bonus: user_info.seedbonus,
+            wedges: user_info.wedges,
+            unsat_count: user_info.unsat.count,
+            unsat_limit: user_info.unsat.limit,
+            vip_until: user_info.vip_until.clone(),
+            low_ratio,
+            warning: low_ratio || user_info.unsat.count >= user_info.unsat.limit,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -65,13 +226,14 @@ pub struct Triggers {
     pub linker_tx: Sender<()>,
     pub downloader_tx: Sender<()>,
     pub audiobookshelf_tx: Sender<()>,
+    pub audit_tx: Sender<()>,
 }
 
 #[derive(Clone)]
 pub struct Context {
     pub config: Arc<Mutex<Arc<Config>>>,
     pub db: Arc<Database<'static>>,
-    pub mam: Arc<Result<Arc<MaM<'static>>>>,
+    pub mam: Arc<MamSessions>,
     pub stats: Stats,
     // pub events: Events,
     pub triggers: Triggers,
@@ -83,10 +245,89 @@ impl Context {
     }
 
     pub fn mam(&self) -> Result<Arc<MaM<'static>>> {
-        let Ok(mam) = self.mam.as_ref() else {
-            return Err(anyhow::Error::msg("mam_id error"));
-        };
+        self.mam.get(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
 
-        Ok(mam.clone())
+    use tokio::time::timeout;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_for_maintenance() {
+        let stats = Stats::new();
+        timeout(Duration::from_millis(100), stats.wait_for_maintenance())
+            .await
+            .expect("should not wait outside maintenance");
+
+        stats.set_maintenance(true);
+        assert!(stats.maintenance());
+        let waiting = tokio::spawn({
+            let stats = stats.clone();
+            async move { stats.wait_for_maintenance().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        stats.set_maintenance(false);
+        timeout(Duration::from_millis(100), waiting)
+            .await
+            .expect("should stop waiting when maintenance ends")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_progress() {
+        let stats = Stats::new();
+        let linker_progress = async || stats.values.lock().await.linker_progress;
+
+        let mut reporter = ProgressReporter::start(&stats, ProgressTask::Linker, 3).await;
+        assert_eq!(linker_progress().await.map(|p| p.done), Some(0));
+        let mut last = 0;
+        for _ in 0..5 {
+            reporter.advance().await;
+            let done = reporter.progress.done;
+            assert!(done >= last);
+            last = done;
+        }
+        // Reaching the total is always sent, even inside the interval
+        assert_eq!(
+            linker_progress().await.map(|p| (p.done, p.total)),
+            Some((3, 3))
+        );
+        assert_eq!(linker_progress().await.unwrap().percent(), 100);
+
+        // A new run starts over
+        let mut reporter = ProgressReporter::start(&stats, ProgressTask::Linker, 10).await;
+        assert_eq!(
+            linker_progress().await.map(|p| (p.done, p.total)),
+            Some((0, 10))
+        );
+        reporter.advance().await;
+        // Throttled, the stats still show the start of the run
+        assert_eq!(linker_progress().await.map(|p| p.done), Some(0));
+        assert_eq!(reporter.progress.done, 1);
+    }
+
+    #[test]
+    fn test_progress_eta() {
+        let started_at = UtcDateTime::UNIX_EPOCH;
+        let progress = Progress {
+            done: 0,
+            total: 10,
+            started_at,
+        };
+        let now = started_at + Duration::from_secs(20);
+        assert_eq!(progress.eta(now), None);
+        let progress = Progress {
+            done: 4,
+            ..progress
+        };
+        assert_eq!(progress.eta(now), Some(Duration::from_secs(30)));
+        assert_eq!(progress.percent(), 40);
     }
 }
@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use mlm_db::{DatabaseExt as _, TaskError, Timestamp};
+use native_db::Database;
+use tracing::error;
+
+/// Keys of the tasks that are not configured per search or list, those are
+/// keyed like their paused state, see [`crate::paused_tasks`]
+pub const LINKER_TASK: &str = "linker";
+pub const CLEANER_TASK: &str = "cleaner";
+pub const DOWNLOADER_TASK: &str = "downloader";
+pub const AUDIOBOOKSHELF_TASK: &str = "audiobookshelf";
+pub const AUDIT_TASK: &str = "audit";
+
+/// Keeps the error of a failed run in the db, so why a task failed is still
+/// shown after a restart. Only the last error of each task is kept
+pub async fn record_task_error(db: &Database<'_>, task: &str, err: &anyhow::Error) {
+    if let Err(write_err) = db.rw_async().await.and_then(|(_guard, rw)| {
+        rw.upsert(TaskError {
+            task: task.to_string(),
+            created_at: Timestamp::now(),
+            message: format!("{err:?}"),
+        })?;
+        rw.commit()?;
+        Ok(())
+    }) {
+        error!("Error writing last error of {task}: {write_err:?}");
+    }
+}
+
+/// The last error of every task that has failed, by task key
+pub fn task_errors(db: &Database<'_>) -> Result<BTreeMap<String, TaskError>> {
+    let r = db.r_transaction()?;
+    let mut errors = BTreeMap::new();
+    for error in r.scan().primary::<TaskError>()?.all()? {
+        let error = error?;
+        errors.insert(error.task.clone(), error);
+    }
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use native_db::Builder;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_task_error() {
+        let db = Builder::new().create_in_memory(&mlm_db::MODELS).unwrap();
+        assert!(task_errors(&db).unwrap().is_empty());
+
+        record_task_error(&db, "autograb:Freeleech", &anyhow!("first")).await;
+        record_task_error(&db, LINKER_TASK, &anyhow!("could not log in")).await;
+        record_task_error(&db, "autograb:Freeleech", &anyhow!("rate limited")).await;
+
+        let errors = task_errors(&db).unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors["autograb:Freeleech"].message, "rate limited");
+        assert_eq!(errors[LINKER_TASK].message, "could not log in");
+    }
+}
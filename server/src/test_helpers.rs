@@ -0,0 +1,66 @@
+//! Fixtures shared by the tests of several modules, override the fields a test
+//! cares about with struct update syntax
+
+use mlm_db::{
+    MainCat, MediaType, MetadataSource, SelectedTorrent, Size, Timestamp, Torrent, TorrentCost,
+    TorrentMeta,
+};
+use mlm_parse::{TitleRules, normalize_title};
+
+/// A fiction audiobook with only a title and an author
+pub fn template_meta() -> TorrentMeta {
+    TorrentMeta {
+        mam_id: 0,
+        vip_status: None,
+        media_type: MediaType::Audiobook,
+        main_cat: Some(MainCat::Fiction),
+        categories: vec![],
+        cat: None,
+        language: None,
+        flags: None,
+        filetypes: vec![],
+        num_files: 0,
+        size: Size::from_bytes(0),
+        title: "The Title".to_string(),
+        edition: None,
+        authors: vec!["Author Name".to_string()],
+        original_authors: None,
+        narrators: vec![],
+        series: vec![],
+        description: None,
+        ids: Default::default(),
+        dramatized: false,
+        source: MetadataSource::Mam,
+        uploaded_at: Timestamp::now(),
+    }
+}
+
+/// A torrent in the library by its hash, that has not been linked yet
+pub fn library_torrent(id: &str, meta: TorrentMeta) -> Torrent {
+    Torrent::new(id.to_string(), meta, &TitleRules::default())
+}
+
+/// A torrent selected by hand, that has not been grabbed yet
+pub fn selected_torrent(meta: TorrentMeta) -> SelectedTorrent {
+    SelectedTorrent {
+        mam_id: meta.mam_id,
+        goodreads_id: None,
+        hash: None,
+        dl_link: String::new(),
+        unsat_buffer: None,
+        wedge_buffer: None,
+        cost: TorrentCost::Ratio,
+        category: None,
+        tags: vec![],
+        title_search: normalize_title(&meta.title),
+        meta,
+        grabber: None,
+        account: None,
+        priority: 0,
+        note: None,
+        created_at: Timestamp::now(),
+        started_at: None,
+        removed_at: None,
+        grab_skipped_at: None,
+    }
+}
@@ -1,35 +1,65 @@
-use std::time::Duration;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::{Result, anyhow};
 use bytes::Bytes;
 use lava_torrent::torrent::v1::Torrent;
 use mlm_db::{
-    DatabaseExt as _, ErroredTorrentId, Event, EventType, SelectedTorrent, Size, Timestamp,
-    TorrentCost,
+    ClientStatus, DatabaseExt as _, ErroredTorrentId, Event, EventType, SelectedTorrent, Size,
+    Timestamp, TorrentCost, TorrentMeta, VerifyOutcome,
+};
+use mlm_mam::{
+    api::{MaM, RateLimitError, WedgeBuyError},
+    search::MaMTorrent,
 };
-use mlm_mam::api::{MaM, RateLimitError, WedgeBuyError};
 use native_db::Database;
+use once_cell::sync::Lazy;
 use qbit::{
     models::Torrent as QbitTorrent,
     parameters::{AddTorrent, AddTorrentType, TorrentFile, TorrentListParams, TorrentState},
 };
-use tokio::time::sleep;
+use tokio::{fs, sync::Mutex, time::sleep};
 use tracing::{debug, info, instrument, trace, warn};
 
 use crate::{
-    config::Config,
-    logging::{TorrentMetaError, update_errored_torrent, write_event},
+    config::{Config, QbitConfig},
+    linker::find_category_library,
+    logging::{TorrentMetaError, should_retry, update_errored_torrent, write_event},
+    mam_sessions::MamSessions,
     qbittorrent::add_torrent_with_category,
+    rtorrent::Rtorrent,
 };
 
+/// The grabber name recorded on the event when a torrent is re-added to the client
+pub const READD_GRABBER: &str = "re-add";
+
+/// How long `verify_before_start` waits for the client to check a torrent
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const VERIFY_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The torrent client new torrents are added to
+#[derive(Clone, Copy)]
+pub enum DownloadClient<'a> {
+    Qbit(&'a qbit::Api, &'a str),
+    Rtorrent(&'a Rtorrent),
+    /// A dir watched by a client MLM has no API access to
+    WatchDir(&'a Path),
+}
+
 #[instrument(skip_all)]
 pub async fn grab_selected_torrents(
     config: &Config,
-    db: &Database<'_>,
-    qbit: &qbit::Api,
-    qbit_url: &str,
-    mam: &MaM<'_>,
+    db: &Arc<Database<'static>>,
+    client: DownloadClient<'_>,
+    sessions: &MamSessions,
 ) -> Result<()> {
+    if let Some(max_age_days) = config.selected_max_age_days {
+        expire_stale_selections(db, max_age_days).await?;
+    }
     let selected_torrents = {
         let r = db.r_transaction()?;
         r.scan()
@@ -43,6 +73,36 @@ pub async fn grab_selected_torrents(
         return Ok(());
     }
 
+    // Every account has its own unsats and ratio, and a torrent has to be
+    // grabbed by the account that selected it
+    let mut accounts = BTreeMap::<Option<String>, Vec<SelectedTorrent>>::new();
+    for torrent in selected_torrents {
+        accounts
+            .entry(torrent.account.clone())
+            .or_default()
+            .push(torrent);
+    }
+    let mut result = Ok(());
+    for (account, selected_torrents) in accounts {
+        let grabbed = match sessions.get(account.as_deref()) {
+            Ok(mam) => grab_account_torrents(config, db, client, &mam, selected_torrents).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = grabbed {
+            warn!("Error grabbing torrents for account {account:?}: {err:?}");
+            result = Err(err);
+        }
+    }
+    result
+}
+
+async fn grab_account_torrents(
+    config: &Config,
+    db: &Arc<Database<'static>>,
+    client: DownloadClient<'_>,
+    mam: &MaM<'_>,
+    selected_torrents: Vec<SelectedTorrent>,
+) -> Result<()> {
     let user_info = mam.user_info().await?;
     let max_torrents = user_info.unsat.limit.saturating_sub(user_info.unsat.count);
 
@@ -52,61 +112,282 @@ pub async fn grab_selected_torrents(
         .map(|t| t.meta.size.bytes() as f64)
         .sum();
 
-    let mut remaining_buffer = (user_info.uploaded_bytes - user_info.downloaded_bytes - downloading_size)
-        / config.min_ratio;
+    let remaining_buffer =
+        (user_info.uploaded_bytes - user_info.downloaded_bytes - downloading_size)
+            / config.min_ratio;
     debug!(
         "downloader, unsats: {:#?}; max_torrents: {max_torrents}; buffer: {}",
         user_info.unsat,
         Size::from_bytes(remaining_buffer as u64)
     );
 
-    let mut snatched_torrents = 0;
-    for torrent in selected_torrents
-        .into_iter()
-        .filter(|t| t.started_at.is_none())
-    {
-        let max_torrents = max_torrents
-            .saturating_sub(torrent.unsat_buffer.unwrap_or(config.unsat_buffer))
-            .saturating_sub(snatched_torrents);
-        if max_torrents == 0 {
+    let mut budget = GrabBudget {
+        max_torrents,
+        remaining_buffer,
+        snatched_torrents: 0,
+    };
+    let mut first_grab = true;
+    for mut torrent in grab_queue(selected_torrents) {
+        let Some(buffer_after) = budget.buffer_after(config, &torrent) else {
             continue;
-        }
-        let buffer_after = remaining_buffer - torrent.meta.size.bytes() as f64;
-        if buffer_after <= 0.0 {
+        };
+        let error_id = ErroredTorrentId::Grabber(torrent.mam_id);
+        if !should_retry(db, error_id.clone()) {
+            trace!("Waiting to retry grabbing torrent {}", torrent.mam_id);
             continue;
         }
+        if let Some(min_seeders) = config.recheck_seeders_before_grab {
+            let now = Timestamp::now();
+            if !seeders_check_due(torrent.grab_skipped_at, &now) {
+                trace!("Waiting to check seeders of torrent {}", torrent.mam_id);
+                continue;
+            }
+            match mam.get_torrent_info_by_id(torrent.mam_id).await {
+                Ok(Some(current)) => {
+                    if let Some(skipped) = seeders_skip(min_seeders, &current) {
+                        info!(
+                            "Not grabbing torrent \"{}\" yet, it has {} seeders",
+                            torrent.meta.title, current.seeders
+                        );
+                        // Only the first skip is an event, not every check after it
+                        let first_skip = torrent.grab_skipped_at.is_none();
+                        set_grab_skipped(db, &mut torrent, Some(now)).await?;
+                        if first_skip {
+                            write_event(
+                                db,
+                                Event::new(torrent.hash.clone(), Some(torrent.mam_id), skipped),
+                            )
+                            .await;
+                        }
+                        continue;
+                    }
+                    if torrent.grab_skipped_at.is_some() {
+                        set_grab_skipped(db, &mut torrent, None).await?;
+                    }
+                }
+                // Grabbing reports torrents that are gone from MaM
+                Ok(None) => {}
+                Err(err) => {
+                    warn!(
+                        "Error checking seeders of torrent {}, trying again next run: {err:?}",
+                        torrent.mam_id
+                    );
+                    continue;
+                }
+            }
+        }
 
-        let result = grab_torrent(config, db, qbit, qbit_url, mam, torrent.clone())
+        wait_for_grab_slot(config, first_grab).await;
+        first_grab = false;
+        let result = grab_torrent(config, db, client, mam, torrent.clone())
             .await
             .map_err(|err| anyhow::Error::new(TorrentMetaError(torrent.meta.clone(), err)));
 
         if result.is_ok() {
-            snatched_torrents += 1;
-            remaining_buffer = buffer_after;
+            budget.grabbed(buffer_after);
         }
 
-        update_errored_torrent(
+        update_errored_torrent(db, &config.retry, error_id, torrent.meta.title, result).await;
+    }
+    Ok(())
+}
+
+/// Unselects the torrents that have been selected for more than `max_age_days`
+/// without being grabbed, returning how many were unselected
+#[instrument(skip_all)]
+pub async fn expire_stale_selections(db: &Database<'_>, max_age_days: u64) -> Result<usize> {
+    let now = Timestamp::now();
+    let stale = {
+        let r = db.r_transaction()?;
+        r.scan()
+            .primary::<SelectedTorrent>()?
+            .all()?
+            .filter(|t| t.as_ref().is_ok_and(|t| is_stale(t, max_age_days, &now)))
+            .collect::<Result<Vec<_>, native_db::db_type::Error>>()
+    }?;
+    let count = stale.len();
+    for mut torrent in stale {
+        info!(
+            "Unselecting torrent \"{}\", not grabbed within {max_age_days} days",
+            torrent.meta.title
+        );
+        let (hash, mam_id) = (torrent.hash.clone(), torrent.mam_id);
+        {
+            let (_guard, rw) = db.rw_async().await?;
+            torrent.removed_at = Some(now);
+            rw.upsert(torrent)?;
+            rw.commit()?;
+        }
+        write_event(
             db,
-            ErroredTorrentId::Grabber(torrent.mam_id),
-            torrent.meta.title,
-            result,
+            Event::new(
+                hash,
+                Some(mam_id),
+                EventType::SelectionExpired { max_age_days },
+            ),
         )
         .await;
-
-        sleep(Duration::from_millis(1000)).await;
     }
+    Ok(count)
+}
+
+/// A selection that was never grabbed and is older than `max_age_days`
+fn is_stale(torrent: &SelectedTorrent, max_age_days: u64, now: &Timestamp) -> bool {
+    torrent.started_at.is_none()
+        && torrent.removed_at.is_none()
+        && now.0 - torrent.created_at.0 > time::Duration::days(max_age_days as i64)
+}
+
+/// The event to record when a torrent has dropped below `min_seeders` since it
+/// was selected, it stays selected to be tried again next run
+fn seeders_skip(min_seeders: u64, current: &MaMTorrent) -> Option<EventType> {
+    (current.seeders < min_seeders).then_some(EventType::GrabSkipped {
+        seeders: current.seeders,
+        min_seeders,
+    })
+}
+
+/// How long a torrent skipped for too few seeders waits before MaM is asked
+/// about its seeders again
+const SEEDERS_RECHECK_INTERVAL: time::Duration = time::Duration::hours(1);
+
+/// Whether to ask MaM about the seeders of a torrent again, it is only asked
+/// once an hour while the torrent has too few
+fn seeders_check_due(grab_skipped_at: Option<Timestamp>, now: &Timestamp) -> bool {
+    grab_skipped_at.is_none_or(|at| now.0 - at.0 >= SEEDERS_RECHECK_INTERVAL)
+}
+
+async fn set_grab_skipped(
+    db: &Database<'_>,
+    torrent: &mut SelectedTorrent,
+    grab_skipped_at: Option<Timestamp>,
+) -> Result<()> {
+    torrent.grab_skipped_at = grab_skipped_at;
+    let (_guard, rw) = db.rw_async().await?;
+    rw.upsert(torrent.clone())?;
+    rw.commit()?;
     Ok(())
 }
 
+/// Selected torrents that have not been started yet, in the order they should be grabbed
+fn grab_queue(selected_torrents: Vec<SelectedTorrent>) -> Vec<SelectedTorrent> {
+    let mut queue = selected_torrents
+        .into_iter()
+        .filter(|t| t.started_at.is_none())
+        .collect::<Vec<_>>();
+    queue.sort_by(SelectedTorrent::queue_cmp);
+    queue
+}
+
+/// Unsats and ratio buffer left for grabbing selected torrents this run
+struct GrabBudget {
+    max_torrents: u64,
+    remaining_buffer: f64,
+    snatched_torrents: u64,
+}
+
+impl GrabBudget {
+    /// The ratio buffer left after grabbing the torrent, if it fits in the budget
+    fn buffer_after(&self, config: &Config, torrent: &SelectedTorrent) -> Option<f64> {
+        let max_torrents = self
+            .max_torrents
+            .saturating_sub(
+                torrent
+                    .unsat_buffer
+                    .unwrap_or(config.unsat_buffer.for_media_type(torrent.meta.media_type)),
+            )
+            .saturating_sub(self.snatched_torrents);
+        if max_torrents == 0 {
+            return None;
+        }
+        let buffer_after = self.remaining_buffer - torrent.meta.size.bytes() as f64;
+        if buffer_after <= 0.0 {
+            return None;
+        }
+        Some(buffer_after)
+    }
+
+    fn grabbed(&mut self, buffer_after: f64) {
+        self.snatched_torrents += 1;
+        self.remaining_buffer = buffer_after;
+    }
+}
+
+/// Token bucket limiting how often torrents are grabbed from MaM
+struct GrabLimiter {
+    per_minute: u64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+/// Shared by all downloader runs, so back-to-back triggers can't exceed the rate
+static GRAB_LIMITER: Lazy<Mutex<Option<GrabLimiter>>> = Lazy::new(|| Mutex::new(None));
+
+impl GrabLimiter {
+    fn new(per_minute: u64, now: Instant) -> Self {
+        let capacity = per_minute.clamp(1, 5) as f64;
+        Self {
+            per_minute,
+            capacity,
+            tokens: capacity,
+            last: now,
+        }
+    }
+
+    /// Takes a token and returns how long to wait before using it
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let refill_per_sec = self.per_minute.max(1) as f64 / 60.0;
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * refill_per_sec).min(self.capacity);
+        self.last = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / refill_per_sec)
+        }
+    }
+}
+
+async fn wait_for_grab_slot(config: &Config, first_grab: bool) {
+    let wait = {
+        let mut guard = GRAB_LIMITER.lock().await;
+        let now = Instant::now();
+        let limiter = match guard.take() {
+            Some(l) if l.per_minute == config.max_grabs_per_minute => guard.insert(l),
+            _ => guard.insert(GrabLimiter::new(config.max_grabs_per_minute, now)),
+        };
+        limiter.reserve(now)
+    };
+    let wait = grab_wait(wait, config.grab_delay_ms, first_grab);
+    if wait > Duration::ZERO {
+        trace!("waiting {wait:?} before next grab");
+        sleep(wait).await;
+    }
+}
+
+/// `grab_delay_ms` only spaces out grabs, so the first grab of a run only
+/// waits for the limiter
+fn grab_wait(limiter_wait: Duration, grab_delay_ms: u64, first_grab: bool) -> Duration {
+    if first_grab {
+        limiter_wait
+    } else {
+        limiter_wait.max(Duration::from_millis(grab_delay_ms))
+    }
+}
+
 #[instrument(skip_all)]
 async fn grab_torrent(
     config: &Config,
-    db: &Database<'_>,
-    qbit: &qbit::Api,
-    qbit_url: &str,
+    db: &Arc<Database<'static>>,
+    client: DownloadClient<'_>,
     mam: &MaM<'_>,
-    torrent: SelectedTorrent,
+    mut torrent: SelectedTorrent,
 ) -> Result<()> {
+    torrent.category = config
+        .category_map
+        .qbit_category(&torrent.meta, torrent.category.as_deref());
     info!(
         "Grabbing torrent \"{}\", with category {:?} and tags {:?}",
         torrent.meta.title, torrent.category, torrent.tags,
@@ -117,17 +398,7 @@ async fn grab_torrent(
     let torrent_file = Torrent::read_from_bytes(torrent_file_bytes.clone())?;
     let hash = torrent_file.info_hash();
 
-    if let Some(qbit_torrent) = get_existing_qbit_torrent(config, qbit, qbit_url, &hash).await {
-        let is_completed = matches!(
-            qbit_torrent.state,
-            TorrentState::Uploading
-                | TorrentState::StoppedUploading
-                | TorrentState::QueuedUploading
-                | TorrentState::StalledUploading
-                | TorrentState::CheckingUploading
-                | TorrentState::ForcedUploading
-        );
-
+    if let Some(is_completed) = get_existing_torrent(config, client, &torrent, &hash).await {
         let (_guard, rw) = db.rw_async().await?;
 
         if is_completed {
@@ -151,6 +422,7 @@ async fn grab_torrent(
                     request_matadata_update: false,
                     library_mismatch: None,
                     client_status: None,
+                    note: torrent.note.clone(),
                 })?;
             }
             rw.remove(torrent)?;
@@ -224,29 +496,51 @@ async fn grab_torrent(
     }
 
     mam.add_unsats(1).await;
-    add_torrent_with_category(
-        qbit,
-        qbit_url,
-        AddTorrent {
-            torrents: AddTorrentType::Files(vec![TorrentFile {
-                filename: format!("{}.torrent", torrent.mam_id),
-                data: torrent_file_bytes.iter().copied().collect(),
-            }]),
-            stopped: config.add_torrents_stopped,
-            category: torrent.category.clone(),
-            tags: if torrent.tags.is_empty() {
-                None
-            } else {
-                Some(torrent.tags.clone())
-            },
-            ..Default::default()
-        },
-    )
-    .await?;
+    match client {
+        DownloadClient::Qbit(qbit, qbit_url) => {
+            add_torrent_with_category(
+                qbit,
+                qbit_url,
+                with_library_layout(
+                    config,
+                    AddTorrent {
+                        torrents: AddTorrentType::Files(vec![TorrentFile {
+                            filename: format!("{}.torrent", torrent.mam_id),
+                            data: torrent_file_bytes.iter().copied().collect(),
+                        }]),
+                        stopped: config.add_torrents_stopped || config.verify_before_start,
+                        category: torrent.category.clone(),
+                        tags: if torrent.tags.is_empty() {
+                            None
+                        } else {
+                            Some(torrent.tags.clone())
+                        },
+                        ..Default::default()
+                    },
+                    &torrent.meta,
+                ),
+            )
+            .await?;
+        }
+        DownloadClient::Rtorrent(rtorrent) => {
+            // rTorrent has a single label per torrent, so tags are not carried over
+            rtorrent
+                .add_torrent(
+                    &torrent_file_bytes,
+                    torrent.category.as_deref(),
+                    config.add_torrents_stopped || config.verify_before_start,
+                )
+                .await?;
+        }
+        DownloadClient::WatchDir(watch_dir) => {
+            add_to_watch_dir(watch_dir, torrent.mam_id, &torrent_file_bytes).await?;
+        }
+    }
 
     let mam_id = torrent.mam_id;
     let cost = Some(torrent.cost);
     let grabber = torrent.grabber.clone();
+    let title = torrent.meta.title.clone();
     {
         let (_guard, rw) = db.rw_async().await?;
         rw.upsert(mlm_db::Torrent {
@@ -268,6 +562,7 @@ async fn grab_torrent(
             request_matadata_update: false,
             library_mismatch: None,
             client_status: None,
+            note: torrent.note.clone(),
         })?;
         let mut torrent = torrent;
         torrent.hash = Some(hash.clone());
@@ -279,7 +574,7 @@ async fn grab_torrent(
     write_event(
         db,
         Event::new(
-            Some(hash),
+            Some(hash.clone()),
             Some(mam_id),
             EventType::Grabbed {
                 grabber,
@@ -290,9 +585,383 @@ async fn grab_torrent(
     )
     .await;
 
+    if config.verify_before_start {
+        spawn_verify(config, db, client, hash, mam_id, title);
+    }
+
+    Ok(())
+}
+
+/// The torrent client connection a detached verification owns
+enum VerifyConnection {
+    Qbit(QbitConfig),
+    Rtorrent(Rtorrent),
+}
+
+/// Checks the data of a grabbed torrent in the background, so a slow check
+/// does not hold up the rest of the downloader run
+fn spawn_verify(
+    config: &Config,
+    db: &Arc<Database<'static>>,
+    client: DownloadClient<'_>,
+    hash: String,
+    mam_id: u64,
+    title: String,
+) {
+    let connection = match client {
+        DownloadClient::Qbit(_, qbit_url) => {
+            let Some(qbit_conf) = config.qbittorrent.iter().find(|q| q.url == qbit_url) else {
+                warn!(
+                    "Error checking torrent \"{title}\", left stopped: no qbittorrent {qbit_url}"
+                );
+                return;
+            };
+            VerifyConnection::Qbit(qbit_conf.clone())
+        }
+        DownloadClient::Rtorrent(rtorrent) => VerifyConnection::Rtorrent(rtorrent.clone()),
+        DownloadClient::WatchDir(_) => return,
+    };
+    let start = !config.add_torrents_stopped;
+    let db = db.clone();
+    tokio::spawn(async move {
+        let result = match &connection {
+            VerifyConnection::Qbit(qbit_conf) => {
+                match qbit::Api::new_login_username_password(
+                    &qbit_conf.url,
+                    &qbit_conf.username,
+                    &qbit_conf.password,
+                )
+                .await
+                {
+                    Ok(qbit) => {
+                        verify_torrent(
+                            &DownloadClient::Qbit(&qbit, &qbit_conf.url),
+                            &hash,
+                            start,
+                            VERIFY_TIMEOUT,
+                            VERIFY_POLL_INTERVAL,
+                        )
+                        .await
+                    }
+                    Err(err) => Err(err.into()),
+                }
+            }
+            VerifyConnection::Rtorrent(rtorrent) => {
+                verify_torrent(
+                    &DownloadClient::Rtorrent(rtorrent),
+                    &hash,
+                    start,
+                    VERIFY_TIMEOUT,
+                    VERIFY_POLL_INTERVAL,
+                )
+                .await
+            }
+        };
+        match result {
+            Ok(outcome) => {
+                info!("Checked torrent \"{title}\": {outcome}");
+                write_event(
+                    &db,
+                    Event::new(Some(hash), Some(mam_id), EventType::Verified { outcome }),
+                )
+                .await;
+            }
+            Err(err) => warn!("Error checking torrent \"{title}\", left stopped: {err:?}"),
+        }
+    });
+}
+
+/// The state of a torrent after it was told to check its data
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CheckState {
+    /// Being checked, or waiting in the queue to be checked
+    Checking,
+    /// All data is there and matched
+    Complete,
+    /// None of the data is there yet
+    Empty,
+    /// Only part of the data matched
+    Partial,
+}
+
+/// The calls `verify_before_start` needs from a torrent client
+trait VerifyClient {
+    async fn recheck(&self, hash: &str) -> Result<()>;
+    async fn check_state(&self, hash: &str) -> Result<Option<CheckState>>;
+    async fn start(&self, hash: &str) -> Result<()>;
+}
+
+impl VerifyClient for DownloadClient<'_> {
+    async fn recheck(&self, hash: &str) -> Result<()> {
+        match self {
+            DownloadClient::Qbit(qbit, _) => qbit.recheck(vec![hash]).await?,
+            DownloadClient::Rtorrent(rtorrent) => rtorrent.check_hash(hash).await?,
+            DownloadClient::WatchDir(_) => {
+                return Err(anyhow!("Can not check torrents in a watch dir"));
+            }
+        }
+        Ok(())
+    }
+
+    async fn check_state(&self, hash: &str) -> Result<Option<CheckState>> {
+        match self {
+            DownloadClient::Qbit(qbit, _) => {
+                let torrent = qbit
+                    .torrents(Some(TorrentListParams {
+                        hashes: Some(vec![hash.to_string()]),
+                        ..TorrentListParams::default()
+                    }))
+                    .await?
+                    .into_iter()
+                    .next();
+                Ok(torrent.map(|t| qbit_check_state(&t.state, t.progress)))
+            }
+            DownloadClient::Rtorrent(rtorrent) => {
+                let (hashing, progress) = rtorrent.check_state(hash).await?;
+                Ok(Some(match progress {
+                    _ if hashing => CheckState::Checking,
+                    progress if progress >= 1.0 => CheckState::Complete,
+                    progress if progress <= 0.0 => CheckState::Empty,
+                    _ => CheckState::Partial,
+                }))
+            }
+            DownloadClient::WatchDir(_) => Ok(None),
+        }
+    }
+
+    async fn start(&self, hash: &str) -> Result<()> {
+        match self {
+            DownloadClient::Qbit(qbit, _) => qbit.start(vec![hash]).await?,
+            DownloadClient::Rtorrent(rtorrent) => rtorrent.start(hash).await?,
+            DownloadClient::WatchDir(_) => {
+                return Err(anyhow!("Can not start torrents in a watch dir"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// qBittorrent queues rechecks, so a queued torrent has not been checked yet
+fn qbit_check_state(state: &TorrentState, progress: f64) -> CheckState {
+    match state {
+        TorrentState::CheckingUploading
+        | TorrentState::CheckingDownloading
+        | TorrentState::CheckingResumeData
+        | TorrentState::QueuedUploading
+        | TorrentState::QueuedDownloading => CheckState::Checking,
+        _ if progress >= 1.0 => CheckState::Complete,
+        _ if progress <= 0.0 => CheckState::Empty,
+        _ => CheckState::Partial,
+    }
+}
+
+/// Checks the data of a torrent that was added stopped and starts it if `start`
+/// is set, unless only part of the existing data matched or checking did not
+/// finish in time
+async fn verify_torrent(
+    client: &impl VerifyClient,
+    hash: &str,
+    start: bool,
+    timeout: Duration,
+    poll_interval: Duration,
+) -> Result<VerifyOutcome> {
+    client.recheck(hash).await?;
+    let started = Instant::now();
+    loop {
+        sleep(poll_interval).await;
+        let outcome = match client.check_state(hash).await? {
+            Some(CheckState::Complete) => VerifyOutcome::Complete,
+            Some(CheckState::Empty) => VerifyOutcome::NoData,
+            Some(CheckState::Partial) => return Ok(VerifyOutcome::Mismatch),
+            Some(CheckState::Checking) | None => {
+                if started.elapsed() >= timeout {
+                    return Ok(VerifyOutcome::TimedOut);
+                }
+                continue;
+            }
+        };
+        if start {
+            client.start(hash).await?;
+        }
+        return Ok(outcome);
+    }
+}
+
+/// Sets the save path and content layout of the library the torrent will be
+/// linked into, so it lands where the linker looks for it
+fn with_library_layout(config: &Config, mut add: AddTorrent, meta: &TorrentMeta) -> AddTorrent {
+    let tags = add.tags.clone().unwrap_or_default();
+    if let Some(library) = find_category_library(config, add.category.as_deref(), &tags, meta) {
+        if let Some(save_path) = &library.save_path {
+            add.savepath = Some(save_path.to_string_lossy().to_string());
+        }
+        if let Some(content_layout) = library.content_layout {
+            add.content_layout = Some(content_layout.into());
+        }
+    }
+    add
+}
+
+/// Downloads the torrent file for a torrent that is no longer in the download client and adds
+/// it back, if the torrent still exists on MaM
+#[instrument(skip_all)]
+pub async fn readd_torrent(
+    config: &Config,
+    db: &Database<'_>,
+    client: DownloadClient<'_>,
+    mam: &MaM<'_>,
+    mut torrent: mlm_db::Torrent,
+) -> Result<()> {
+    let Some(mam_torrent) = mam.get_torrent_info_by_id(torrent.mam_id).await? else {
+        warn!(
+            "Torrent \"{}\" ({}) no longer exists on MaM, can not re-add it",
+            torrent.meta.title, torrent.mam_id
+        );
+        if torrent.client_status != Some(ClientStatus::RemovedFromMam) {
+            let (_guard, rw) = db.rw_async().await?;
+            torrent.client_status = Some(ClientStatus::RemovedFromMam);
+            let id = torrent.id.clone();
+            let mam_id = torrent.mam_id;
+            rw.upsert(torrent)?;
+            rw.commit()?;
+            write_event(
+                db,
+                Event::new(Some(id), Some(mam_id), EventType::RemovedFromMam),
+            )
+            .await;
+        }
+        return Ok(());
+    };
+    let Some(dl_link) = mam_torrent.dl else {
+        return Err(anyhow!("No download link for torrent {}", torrent.mam_id));
+    };
+
+    info!(
+        "Re-adding torrent \"{}\", with category {:?}",
+        torrent.meta.title, torrent.category
+    );
+    let torrent_file_bytes = get_mam_torrent_file(mam, &dl_link).await?;
+    let torrent_file = Torrent::read_from_bytes(torrent_file_bytes.clone())?;
+    let hash = torrent_file.info_hash();
+    if torrent.id_is_hash && hash != torrent.id {
+        return Err(anyhow!(
+            "Torrent file from MaM has hash {hash}, expected {}",
+            torrent.id
+        ));
+    }
+
+    match client {
+        DownloadClient::Qbit(qbit, qbit_url) => {
+            add_torrent_with_category(
+                qbit,
+                qbit_url,
+                with_library_layout(
+                    config,
+                    AddTorrent {
+                        torrents: AddTorrentType::Files(vec![TorrentFile {
+                            filename: format!("{}.torrent", torrent.mam_id),
+                            data: torrent_file_bytes.iter().copied().collect(),
+                        }]),
+                        stopped: config.add_torrents_stopped,
+                        category: torrent.category.clone(),
+                        ..Default::default()
+                    },
+                    &torrent.meta,
+                ),
+            )
+            .await?;
+        }
+        DownloadClient::Rtorrent(rtorrent) => {
+            rtorrent
+                .add_torrent(
+                    &torrent_file_bytes,
+                    torrent.category.as_deref(),
+                    config.add_torrents_stopped,
+                )
+                .await?;
+        }
+        DownloadClient::WatchDir(watch_dir) => {
+            add_to_watch_dir(watch_dir, torrent.mam_id, &torrent_file_bytes).await?;
+        }
+    }
+
+    let id = torrent.id.clone();
+    let mam_id = torrent.mam_id;
+    {
+        let (_guard, rw) = db.rw_async().await?;
+        torrent.client_status = None;
+        rw.upsert(torrent)?;
+        rw.commit()?;
+    }
+
+    write_event(
+        db,
+        Event::new(
+            Some(id),
+            Some(mam_id),
+            EventType::Grabbed {
+                grabber: Some(READD_GRABBER.to_string()),
+                cost: None,
+                wedged: false,
+            },
+        ),
+    )
+    .await;
+
     Ok(())
 }
 
+/// Looks for the torrent in the download client, returning whether it has completed
+async fn get_existing_torrent(
+    config: &Config,
+    client: DownloadClient<'_>,
+    torrent: &SelectedTorrent,
+    hash: &str,
+) -> Option<bool> {
+    match client {
+        DownloadClient::Qbit(qbit, qbit_url) => {
+            let qbit_torrent = get_existing_qbit_torrent(config, qbit, qbit_url, hash).await?;
+            Some(matches!(
+                qbit_torrent.state,
+                TorrentState::Uploading
+                    | TorrentState::StoppedUploading
+                    | TorrentState::QueuedUploading
+                    | TorrentState::StalledUploading
+                    | TorrentState::CheckingUploading
+                    | TorrentState::ForcedUploading
+            ))
+        }
+        DownloadClient::Rtorrent(rtorrent) => {
+            let existing = rtorrent.torrent(hash).await.ok()??;
+            debug!(
+                "Torrent \"{}\" already in rTorrent at {} with ratio {}",
+                existing.name, existing.directory, existing.ratio
+            );
+            if let Some(category) = &torrent.category
+                && existing.label != *category
+                && let Err(err) = rtorrent.set_label(hash, category).await
+            {
+                warn!("Failed setting rTorrent label on {hash}: {err}");
+            }
+            Some(existing.complete)
+        }
+        // Whatever picks up the watch dir is not visible to MLM
+        DownloadClient::WatchDir(_) => None,
+    }
+}
+
+/// Saves the torrent file in the watch dir, named by its MaM id. It is written
+/// under another name first so the client never picks up a partial file
+async fn add_to_watch_dir(watch_dir: &Path, mam_id: u64, torrent_file: &[u8]) -> Result<PathBuf> {
+    let path = watch_dir.join(format!("{mam_id}.torrent"));
+    let partial = watch_dir.join(format!("{mam_id}.torrent.part"));
+    fs::create_dir_all(watch_dir).await?;
+    fs::write(&partial, torrent_file).await?;
+    fs::rename(&partial, &path).await?;
+    debug!("Saved torrent file {}", path.display());
+    Ok(path)
+}
+
 async fn get_existing_qbit_torrent(
     config: &Config,
     qbit: &qbit::Api,
@@ -351,3 +1020,420 @@ pub(crate) async fn get_mam_torrent_file(mam: &MaM<'_>, dl_link: &str) -> Result
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use mlm_db::MediaType;
+    use time::UtcDateTime;
+
+    use super::*;
+    use crate::test_helpers::{selected_torrent, template_meta};
+
+    fn selected(mam_id: u64, priority: i32, created_at: Timestamp) -> SelectedTorrent {
+        let meta = TorrentMeta {
+            mam_id,
+            main_cat: None,
+            num_files: 1,
+            size: Size::from_bytes(100),
+            title: format!("Torrent {mam_id}"),
+            authors: vec![],
+            uploaded_at: created_at,
+            ..template_meta()
+        };
+        SelectedTorrent {
+            unsat_buffer: Some(0),
+            priority,
+            created_at,
+            ..selected_torrent(meta)
+        }
+    }
+
+    #[test]
+    fn test_grab_queue_priority() {
+        let config: Config = toml::from_str(r#"mam_id = "abc""#).unwrap();
+        let old = Timestamp::from(UtcDateTime::UNIX_EPOCH);
+        let new = Timestamp::now();
+        let mut started = selected(4, 10, old);
+        started.started_at = Some(new);
+        let queue = grab_queue(vec![
+            selected(1, 0, old),
+            selected(2, 5, new),
+            selected(3, 0, new),
+            started,
+        ]);
+        assert_eq!(
+            queue.iter().map(|t| t.mam_id).collect::<Vec<_>>(),
+            vec![2, 1, 3]
+        );
+
+        // Only room for one torrent, the highest priority one should get it
+        let mut budget = GrabBudget {
+            max_torrents: 1,
+            remaining_buffer: 1000.0,
+            snatched_torrents: 0,
+        };
+        let mut grabbed = vec![];
+        for torrent in &queue {
+            if let Some(buffer_after) = budget.buffer_after(&config, torrent) {
+                budget.grabbed(buffer_after);
+                grabbed.push(torrent.mam_id);
+            }
+        }
+        assert_eq!(grabbed, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_expire_stale_selections() {
+        let db = native_db::Builder::new()
+            .create_in_memory(&mlm_db::MODELS)
+            .unwrap();
+        let old = Timestamp::from(UtcDateTime::now() - time::Duration::days(31));
+        let recent = Timestamp::from(UtcDateTime::now() - time::Duration::days(29));
+        let mut grabbed = selected(3, 0, old);
+        grabbed.started_at = Some(recent);
+        {
+            let rw = db.rw_transaction().unwrap();
+            rw.insert(selected(1, 0, old)).unwrap();
+            rw.insert(selected(2, 0, recent)).unwrap();
+            rw.insert(grabbed).unwrap();
+            rw.commit().unwrap();
+        }
+
+        assert_eq!(expire_stale_selections(&db, 30).await.unwrap(), 1);
+        let r = db.r_transaction().unwrap();
+        let removed = |mam_id: u64| {
+            r.get()
+                .primary::<SelectedTorrent>(mam_id)
+                .unwrap()
+                .unwrap()
+                .removed_at
+                .is_some()
+        };
+        assert!(removed(1));
+        assert!(!removed(2));
+        assert!(!removed(3));
+        drop(r);
+
+        // Already unselected torrents are left alone
+        assert_eq!(expire_stale_selections(&db, 30).await.unwrap(), 0);
+    }
+
+    #[test]
+    fn test_grab_limiter_min_time() {
+        let start = Instant::now();
+        let mut limiter = GrabLimiter::new(20, start);
+        // Request 25 grabs at once: 5 fit in the burst, the other 20 need a
+        // full minute of refill at 20 per minute
+        let mut now = start;
+        for _ in 0..25 {
+            now += limiter.reserve(now);
+        }
+        assert!(now - start >= Duration::from_secs(60));
+        assert!(now - start < Duration::from_secs(61));
+    }
+
+    #[test]
+    fn test_grab_limiter_refills() {
+        let start = Instant::now();
+        let mut limiter = GrabLimiter::new(60, start);
+        for _ in 0..5 {
+            assert_eq!(limiter.reserve(start), Duration::ZERO);
+        }
+        assert!(limiter.reserve(start) > Duration::ZERO);
+        let later = start + Duration::from_secs(120);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_grab_wait() {
+        assert_eq!(grab_wait(Duration::ZERO, 1000, true), Duration::ZERO);
+        assert_eq!(
+            grab_wait(Duration::ZERO, 1000, false),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            grab_wait(Duration::from_secs(3), 1000, true),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            grab_wait(Duration::from_secs(3), 1000, false),
+            Duration::from_secs(3)
+        );
+    }
+
+    /// Reports the torrent as checking for a number of polls after it was
+    /// told to recheck, and then as `result`
+    struct MockClient {
+        checking_polls: u32,
+        result: CheckState,
+        polls: Cell<u32>,
+        rechecked: Cell<bool>,
+        started: Cell<bool>,
+    }
+
+    impl MockClient {
+        fn new(checking_polls: u32, result: CheckState) -> Self {
+            MockClient {
+                checking_polls,
+                result,
+                polls: Cell::new(0),
+                rechecked: Cell::new(false),
+                started: Cell::new(false),
+            }
+        }
+
+        async fn verify(&self) -> VerifyOutcome {
+            self.verify_and_start(true).await
+        }
+
+        async fn verify_and_start(&self, start: bool) -> VerifyOutcome {
+            verify_torrent(
+                self,
+                "abc",
+                start,
+                Duration::from_millis(50),
+                Duration::from_millis(1),
+            )
+            .await
+            .unwrap()
+        }
+    }
+
+    impl VerifyClient for MockClient {
+        async fn recheck(&self, _hash: &str) -> Result<()> {
+            self.rechecked.set(true);
+            Ok(())
+        }
+
+        async fn check_state(&self, _hash: &str) -> Result<Option<CheckState>> {
+            assert!(self.rechecked.get());
+            let polls = self.polls.get() + 1;
+            self.polls.set(polls);
+            if polls <= self.checking_polls {
+                Ok(Some(CheckState::Checking))
+            } else {
+                Ok(Some(self.result))
+            }
+        }
+
+        async fn start(&self, _hash: &str) -> Result<()> {
+            self.started.set(true);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_starts_matching_torrent() {
+        let client = MockClient::new(3, CheckState::Complete);
+        assert_eq!(client.verify().await, VerifyOutcome::Complete);
+        assert_eq!(client.polls.get(), 4);
+        assert!(client.started.get());
+
+        let client = MockClient::new(0, CheckState::Empty);
+        assert_eq!(client.verify().await, VerifyOutcome::NoData);
+        assert!(client.started.get());
+    }
+
+    #[tokio::test]
+    async fn test_add_to_watch_dir() {
+        let dir = std::env::temp_dir().join(format!("mlm-watch-dir-{}", std::process::id()));
+        let config: Config = toml::from_str(&format!(
+            r#"
+            mam_id = ""
+            grab_method = "watchdir"
+            watch_dir = {:?}
+            "#,
+            dir.display().to_string()
+        ))
+        .unwrap();
+        let watch_dir = config.watch_dir().unwrap();
+        let torrent = selected(1234, 0, Timestamp::now());
+
+        let path = add_to_watch_dir(watch_dir, torrent.mam_id, b"d4:infode")
+            .await
+            .unwrap();
+        assert_eq!(path, dir.join("1234.torrent"));
+        assert_eq!(std::fs::read(&path).unwrap(), b"d4:infode");
+        assert!(!dir.join("1234.torrent.part").exists());
+        // Nothing can be looked up in a watch dir, so it is always grabbed
+        assert_eq!(
+            get_existing_torrent(
+                &config,
+                DownloadClient::WatchDir(watch_dir),
+                &torrent,
+                "hash"
+            )
+            .await,
+            None
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_verify_leaves_mismatch_stopped() {
+        let client = MockClient::new(2, CheckState::Partial);
+        assert_eq!(client.verify().await, VerifyOutcome::Mismatch);
+        assert!(!client.started.get());
+
+        let client = MockClient::new(u32::MAX, CheckState::Complete);
+        assert_eq!(client.verify().await, VerifyOutcome::TimedOut);
+        assert!(!client.started.get());
+    }
+
+    #[tokio::test]
+    async fn test_verify_respects_add_torrents_stopped() {
+        let client = MockClient::new(1, CheckState::Complete);
+        assert_eq!(
+            client.verify_and_start(false).await,
+            VerifyOutcome::Complete
+        );
+        assert!(!client.started.get());
+    }
+
+    #[test]
+    fn test_qbit_queued_check_is_pending() {
+        assert_eq!(
+            qbit_check_state(&TorrentState::QueuedDownloading, 0.0),
+            CheckState::Checking
+        );
+        assert_eq!(
+            qbit_check_state(&TorrentState::QueuedUploading, 1.0),
+            CheckState::Checking
+        );
+        assert_eq!(
+            qbit_check_state(&TorrentState::CheckingResumeData, 0.5),
+            CheckState::Checking
+        );
+        assert_eq!(
+            qbit_check_state(&TorrentState::StoppedUploading, 0.0),
+            CheckState::Empty
+        );
+        assert_eq!(
+            qbit_check_state(&TorrentState::StoppedUploading, 1.0),
+            CheckState::Complete
+        );
+    }
+
+    #[test]
+    fn test_add_torrent_uses_library_save_path() {
+        let config: Config = toml::from_str(
+            r#"
+            mam_id = ""
+
+            [[library]]
+            category = "Audiobooks"
+            library_dir = "/library/audiobooks"
+            save_path = "/downloads/audiobooks"
+            content_layout = "no_subfolder"
+
+            [[library]]
+            category = "Ebooks"
+            library_dir = "/library/ebooks"
+            "#,
+        )
+        .unwrap();
+        let torrent = selected(1, 0, Timestamp::now());
+
+        let add = with_library_layout(
+            &config,
+            AddTorrent {
+                category: Some("Audiobooks".to_string()),
+                ..Default::default()
+            },
+            &torrent.meta,
+        );
+        assert_eq!(add.savepath.as_deref(), Some("/downloads/audiobooks"));
+        assert!(matches!(
+            add.content_layout,
+            Some(qbit::parameters::ContentLayout::NoSubfolder)
+        ));
+
+        let add = with_library_layout(
+            &config,
+            AddTorrent {
+                category: Some("Ebooks".to_string()),
+                ..Default::default()
+            },
+            &torrent.meta,
+        );
+        assert_eq!(add.savepath, None);
+        assert!(add.content_layout.is_none());
+    }
+
+    #[test]
+    fn test_category_map() {
+        let config: Config = toml::from_str(
+            r#"
+            mam_id = ""
+
+            [category_map]
+            default = "mlm"
+            [category_map.media_type]
+            radio = "radio"
+            "#,
+        )
+        .unwrap();
+        let mut radio = selected(1, 0, Timestamp::now());
+        radio.meta.media_type = MediaType::Radio;
+        assert_eq!(
+            config
+                .category_map
+                .qbit_category(&radio.meta, radio.category.as_deref())
+                .as_deref(),
+            Some("radio")
+        );
+
+        let mut audiobook = selected(2, 0, Timestamp::now());
+        assert_eq!(
+            config
+                .category_map
+                .qbit_category(&audiobook.meta, audiobook.category.as_deref())
+                .as_deref(),
+            Some("mlm")
+        );
+        // A category from the grabber or a [[tag]] is kept
+        audiobook.category = Some("Audiobooks".to_string());
+        assert_eq!(
+            config
+                .category_map
+                .qbit_category(&audiobook.meta, audiobook.category.as_deref())
+                .as_deref(),
+            Some("Audiobooks")
+        );
+    }
+
+    #[test]
+    fn test_seeders_skip() {
+        let dropped = MaMTorrent {
+            id: 1,
+            seeders: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            seeders_skip(3, &dropped),
+            Some(EventType::GrabSkipped {
+                seeders: 1,
+                min_seeders: 3
+            })
+        ));
+
+        let seeded = MaMTorrent {
+            seeders: 3,
+            ..dropped
+        };
+        assert!(seeders_skip(3, &seeded).is_none());
+    }
+
+    #[test]
+    fn test_seeders_check_due() {
+        let now = Timestamp::now();
+        let ago = |minutes| Some(Timestamp::from(now.0 - time::Duration::minutes(minutes)));
+        assert!(seeders_check_due(None, &now));
+        assert!(!seeders_check_due(ago(0), &now));
+        assert!(!seeders_check_due(ago(59), &now));
+        assert!(seeders_check_due(ago(60), &now));
+    }
+}
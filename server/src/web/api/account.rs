@@ -0,0 +1,16 @@
+use axum::{Json, extract::State};
+
+use crate::{
+    stats::{AccountStatus, Context},
+    web::AppError,
+};
+
+pub async fn account_api(
+    State(context): State<Context>,
+) -> std::result::Result<Json<AccountStatus>, AppError> {
+    let config = context.config().await;
+    let mam = context.mam()?;
+    let user_info = mam.user_info().await?;
+
+    Ok::<_, AppError>(Json(AccountStatus::new(&config, &user_info)))
+}
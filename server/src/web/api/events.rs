@@ -0,0 +1,151 @@
+use std::{convert::Infallible, time::Duration};
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::{
+        Sse,
+        sse::{Event as SseEvent, KeepAlive},
+    },
+};
+use futures::Stream;
+use mlm_db::{Event, EventKey};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio_stream::{StreamExt as _, wrappers::BroadcastStream};
+
+use crate::{
+    logging::subscribe_events,
+    stats::Context,
+    web::{AppError, pages::events::event_kind},
+};
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Events, newest first
+pub async fn events_api(
+    State(context): State<Context>,
+    Query(query): Query<EventsApiQuery>,
+) -> std::result::Result<Json<Value>, AppError> {
+    let page_size = query
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let r = context.db.r_transaction()?;
+    let mut total = 0;
+    let mut events = vec![];
+    for event in r
+        .scan()
+        .secondary::<Event>(EventKey::created_at)?
+        .all()?
+        .rev()
+    {
+        let event = event?;
+        if !query.matches(&event) {
+            continue;
+        }
+        if total >= query.from && events.len() < page_size {
+            events.push(event_json(&event));
+        }
+        total += 1;
+    }
+
+    Ok::<_, AppError>(Json(json!({
+        "from": query.from,
+        "page_size": page_size,
+        "total": total,
+        "events": events,
+    })))
+}
+
+/// Sends new events as they are written, filtered the same way as the events
+/// API. With `ping=true` only "update" is sent, for clients that fetch the
+/// events themselves
+pub async fn events_updates(
+    Query(query): Query<EventsApiQuery>,
+) -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
+    let stream = BroadcastStream::new(subscribe_events()).filter_map(move |event| {
+        // A lagging client missed some events, have it fetch them again
+        let Ok(event) = event else {
+            return Some(Ok(SseEvent::default().event("lagged").data("update")));
+        };
+        if !query.matches(&event) {
+            return None;
+        }
+        Some(Ok(if query.ping {
+            SseEvent::default().data("update")
+        } else {
+            SseEvent::default().data(event_json(&event).to_string())
+        }))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(10)))
+}
+
+fn event_json(event: &Event) -> Value {
+    json!({
+        "id": event.id,
+        "type": event_kind(&event.event),
+        "torrent_id": event.torrent_id,
+        "mam_id": event.mam_id,
+        "created_at": event.created_at.0.unix_timestamp(),
+        "event": event.event,
+    })
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EventsApiQuery {
+    #[serde(default)]
+    from: usize,
+    page_size: Option<usize>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    /// Torrent id or MaM id
+    torrent_id: Option<String>,
+    #[serde(default)]
+    ping: bool,
+}
+
+impl EventsApiQuery {
+    fn matches(&self, event: &Event) -> bool {
+        if let Some(kind) = &self.kind
+            && event_kind(&event.event) != kind
+        {
+            return false;
+        }
+        if let Some(id) = &self.torrent_id
+            && event.torrent_id.as_ref() != Some(id)
+            && event.mam_id.is_none_or(|mam_id| mam_id.to_string() != *id)
+        {
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mlm_db::EventType;
+
+    use super::*;
+
+    #[test]
+    fn test_events_query_matches() {
+        let event = Event::new(
+            Some("abc".to_string()),
+            Some(123),
+            EventType::RemovedFromMam,
+        );
+        let query = |kind: Option<&str>, torrent_id: Option<&str>| EventsApiQuery {
+            kind: kind.map(str::to_string),
+            torrent_id: torrent_id.map(str::to_string),
+            ..Default::default()
+        };
+        assert!(query(None, None).matches(&event));
+        assert!(query(Some("removed"), None).matches(&event));
+        assert!(!query(Some("linker"), None).matches(&event));
+        assert!(query(None, Some("abc")).matches(&event));
+        assert!(query(Some("removed"), Some("123")).matches(&event));
+        assert!(!query(None, Some("other")).matches(&event));
+    }
+}
@@ -0,0 +1,70 @@
+use axum::{Json, extract::State};
+use axum_extra::extract::Form;
+use mlm_mam::search::{SearchQuery, Tor};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::TorrentFilter, config_impl::FilterClause, stats::Context, web::AppError};
+
+/// Runs a filter against the latest uploads in its categories and languages,
+/// showing which conditions each torrent passed. Nothing is grabbed
+pub async fn filter_preview_api(
+    State(context): State<Context>,
+    Form(form): Form<FilterPreviewForm>,
+) -> std::result::Result<Json<FilterPreviewResponse>, AppError> {
+    let mam = context.mam()?;
+    let filter: TorrentFilter = toml::from_str(&form.toml)?;
+    let result = mam
+        .search(&SearchQuery {
+            perpage: form.perpage.unwrap_or(100).clamp(5, 100),
+            tor: Tor {
+                main_cat: filter.categories.get_main_cats(),
+                cat: filter.categories.get_cats(),
+                browse_lang: filter.languages.iter().map(|l| l.to_id()).collect(),
+                sort_type: "dateDesc".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await?;
+
+    let torrents = result
+        .data
+        .into_iter()
+        .map(|torrent| {
+            let clauses = filter.clauses(&torrent);
+            FilterPreviewTorrent {
+                id: torrent.id,
+                title: torrent.title,
+                added: torrent.added,
+                matches: clauses.iter().all(|c| c.passed),
+                clauses,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Ok::<_, AppError>(Json(FilterPreviewResponse {
+        matched: torrents.iter().filter(|t| t.matches).count(),
+        torrents,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FilterPreviewForm {
+    toml: String,
+    perpage: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct FilterPreviewResponse {
+    matched: usize,
+    torrents: Vec<FilterPreviewTorrent>,
+}
+
+#[derive(Serialize)]
+pub struct FilterPreviewTorrent {
+    id: u64,
+    title: String,
+    added: String,
+    matches: bool,
+    clauses: Vec<FilterClause>,
+}
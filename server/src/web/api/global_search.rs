@@ -0,0 +1,208 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use mlm_db::{ListItem, SelectedTorrent, Torrent};
+use mlm_mam::search::{SearchQuery, Tor};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    stats::Context,
+    web::{AppError, list_url, pages::torrents::score},
+};
+
+/// How many results each group returns at most
+const GROUP_LIMIT: usize = 10;
+
+/// Searches the library, selected torrents, list items and MaM at once, for
+/// the search box in the header
+pub async fn global_search_api(
+    State(context): State<Context>,
+    Query(query): Query<GlobalSearchQuery>,
+) -> std::result::Result<Json<GlobalSearchResponse>, AppError> {
+    let q = query.q.trim();
+    if q.is_empty() {
+        return Ok::<_, AppError>(Json(GlobalSearchResponse::default()));
+    }
+
+    let (library, selected, lists) = {
+        let r = context.db.r_transaction()?;
+        let mut library = vec![];
+        for torrent in r.scan().primary::<Torrent>()?.all()? {
+            let torrent = torrent?;
+            if let Some(score) = match_score(q, &torrent.meta.title, &torrent.meta.authors) {
+                library.push((
+                    GlobalSearchResult {
+                        url: format!("/torrents/{}", torrent.id),
+                        title: torrent.meta.title,
+                        authors: torrent.meta.authors,
+                    },
+                    score,
+                ));
+            }
+        }
+        let mut selected = vec![];
+        for torrent in r.scan().primary::<SelectedTorrent>()?.all()? {
+            let torrent = torrent?;
+            if torrent.removed_at.is_some() {
+                continue;
+            }
+            if let Some(score) = match_score(q, &torrent.meta.title, &torrent.meta.authors) {
+                selected.push((
+                    GlobalSearchResult {
+                        url: format!("/torrents/{}", torrent.mam_id),
+                        title: torrent.meta.title,
+                        authors: torrent.meta.authors,
+                    },
+                    score,
+                ));
+            }
+        }
+        let mut lists = vec![];
+        for item in r.scan().primary::<ListItem>()?.all()? {
+            let item = item?;
+            if let Some(score) = match_score(q, &item.title, &item.authors) {
+                lists.push((
+                    GlobalSearchResult {
+                        url: list_url(&item.list_id),
+                        title: item.title,
+                        authors: item.authors,
+                    },
+                    score,
+                ));
+            }
+        }
+        (library, selected, lists)
+    };
+
+    Ok::<_, AppError>(Json(GlobalSearchResponse {
+        library: GlobalSearchGroup::ranked(library),
+        selected: GlobalSearchGroup::ranked(selected),
+        lists: GlobalSearchGroup::ranked(lists),
+        mam: search_mam(&context, q).await,
+    }))
+}
+
+/// A failing MaM search is reported in its group, so the local results are
+/// still shown
+async fn search_mam(context: &Context, q: &str) -> GlobalSearchGroup {
+    let config = context.config().await;
+    let result = match context.mam() {
+        Ok(mam) => {
+            mam.search(&SearchQuery {
+                perpage: GROUP_LIMIT as u64,
+                tor: Tor {
+                    text: q.to_string(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .await
+        }
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(result) => GlobalSearchGroup {
+            count: result.found,
+            results: result
+                .data
+                .into_iter()
+                .map(|torrent| {
+                    let (title, authors) = match torrent.as_meta(config.title_rules()) {
+                        Ok(meta) => (meta.title, meta.authors),
+                        Err(_) => (torrent.title, vec![]),
+                    };
+                    GlobalSearchResult {
+                        url: format!("/torrents/{}", torrent.id),
+                        title,
+                        authors,
+                    }
+                })
+                .collect(),
+            error: None,
+        },
+        Err(err) => GlobalSearchGroup {
+            error: Some(format!("{err:#}")),
+            ..Default::default()
+        },
+    }
+}
+
+/// Scores the title and authors the same way the torrents page does, `None`
+/// when it is not a match
+fn match_score(q: &str, title: &str, authors: &[String]) -> Option<isize> {
+    let total = score(q, title) + authors.iter().map(|a| score(q, a)).sum::<isize>();
+    (total >= 10).then_some(total)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GlobalSearchQuery {
+    #[serde(default)]
+    q: String,
+}
+
+#[derive(Default, Serialize)]
+pub struct GlobalSearchResponse {
+    library: GlobalSearchGroup,
+    selected: GlobalSearchGroup,
+    lists: GlobalSearchGroup,
+    mam: GlobalSearchGroup,
+}
+
+#[derive(Default, Serialize)]
+pub struct GlobalSearchGroup {
+    /// How many matched in total, there are at most [`GROUP_LIMIT`] results
+    count: usize,
+    results: Vec<GlobalSearchResult>,
+    error: Option<String>,
+}
+
+impl GlobalSearchGroup {
+    /// Keeps the best scoring results
+    fn ranked(mut results: Vec<(GlobalSearchResult, isize)>) -> Self {
+        let count = results.len();
+        results.sort_by_key(|(_, score)| -*score);
+        results.truncate(GROUP_LIMIT);
+        Self {
+            count,
+            results: results.into_iter().map(|(result, _)| result).collect(),
+            error: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GlobalSearchResult {
+    title: String,
+    authors: Vec<String>,
+    url: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranked() {
+        let authors = vec!["Frank Herbert".to_string()];
+        assert!(match_score("dune", "Dune Messiah", &authors).is_some());
+        assert!(match_score("herbert", "Dune Messiah", &authors).is_some());
+        assert_eq!(match_score("foundation", "Dune Messiah", &authors), None);
+
+        let results = (0..15)
+            .map(|i| {
+                let result = GlobalSearchResult {
+                    title: format!("Book {i}"),
+                    authors: vec![],
+                    url: format!("/torrents/{i}"),
+                };
+                (result, i)
+            })
+            .collect();
+        let group = GlobalSearchGroup::ranked(results);
+        assert_eq!(group.count, 15);
+        assert_eq!(group.results.len(), GROUP_LIMIT);
+        assert_eq!(group.results[0].title, "Book 14");
+        assert_eq!(group.results[9].title, "Book 5");
+    }
+}
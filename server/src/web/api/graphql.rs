@@ -0,0 +1,648 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, InputObject, Json as GraphQLJson, Object,
+    OutputType, Result, Schema, SimpleObject, http::GraphiQLSource,
+};
+use axum::{Json, extract::State, response::Html};
+use mlm_db::{Event, EventKey, SelectedTorrent, Timestamp, Torrent, TorrentKey, TorrentMeta};
+use native_db::Database;
+
+use crate::web::pages::events::event_kind;
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+/// Deepest selection a query may have, `series { items { books { torrent {
+/// events { kind } } } } }` is as deep as it gets
+const MAX_DEPTH: usize = 6;
+/// Pages count each of their items, so a query with events on a full page of
+/// torrents is turned away before it scans the events once per torrent
+const MAX_COMPLEXITY: usize = 20_000;
+/// What the events of a torrent count as, they are a scan of their own
+const EVENTS_COMPLEXITY: usize = 100;
+
+pub type MlmSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+pub fn schema(db: Arc<Database<'static>>) -> MlmSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(db)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// The schema in GraphQL SDL, for `--print-graphql-schema`
+pub fn schema_sdl() -> String {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .finish()
+        .sdl()
+}
+
+pub async fn graphql_api(
+    State(schema): State<MlmSchema>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    Json(schema.execute(request).await)
+}
+
+pub async fn graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    async fn torrent(&self, ctx: &Context<'_>, id: String) -> Result<Option<TorrentObject>> {
+        let r = db(ctx)?.r_transaction()?;
+        let torrent = match id.parse::<u64>() {
+            Ok(mam_id) => r.get().secondary::<Torrent>(TorrentKey::mam_id, mam_id)?,
+            Err(_) => r.get().primary::<Torrent>(id)?,
+        };
+        Ok(torrent.map(TorrentObject))
+    }
+
+    /// Torrents, newest first
+    #[graphql(complexity = "page_complexity(page_size, child_complexity)")]
+    async fn torrents(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: TorrentsFilter,
+        #[graphql(default)] from: usize,
+        page_size: Option<usize>,
+    ) -> Result<Page<TorrentObject>> {
+        let r = db(ctx)?.r_transaction()?;
+        let mut page = Page::new(from, page_size);
+        for torrent in r
+            .scan()
+            .secondary::<Torrent>(TorrentKey::created_at)?
+            .all()?
+            .rev()
+        {
+            let torrent = torrent?;
+            if filter.matches(&torrent) {
+                page.push(TorrentObject(torrent));
+            }
+        }
+        Ok(page)
+    }
+
+    /// Torrents selected for download, highest priority first
+    #[graphql(complexity = "page_complexity(page_size, child_complexity)")]
+    async fn selected_torrents(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] include_removed: bool,
+        #[graphql(default)] from: usize,
+        page_size: Option<usize>,
+    ) -> Result<Page<SelectedTorrentObject>> {
+        let r = db(ctx)?.r_transaction()?;
+        let mut torrents = r
+            .scan()
+            .primary::<SelectedTorrent>()?
+            .all()?
+            .collect::<Result<Vec<_>, _>>()?;
+        torrents.sort_by(|a, b| {
+            b.priority
+                .cmp(&a.priority)
+                .then(a.created_at.cmp(&b.created_at))
+        });
+        let mut page = Page::new(from, page_size);
+        for torrent in torrents {
+            if include_removed || torrent.removed_at.is_none() {
+                page.push(SelectedTorrentObject(torrent));
+            }
+        }
+        Ok(page)
+    }
+
+    /// Events, newest first
+    #[graphql(complexity = "page_complexity(page_size, child_complexity)")]
+    async fn events(
+        &self,
+        ctx: &Context<'_>,
+        kind: Option<String>,
+        #[graphql(desc = "Torrent id or MaM id")] torrent_id: Option<String>,
+        #[graphql(default)] from: usize,
+        page_size: Option<usize>,
+    ) -> Result<Page<EventObject>> {
+        let r = db(ctx)?.r_transaction()?;
+        let mut page = Page::new(from, page_size);
+        for event in r
+            .scan()
+            .secondary::<Event>(EventKey::created_at)?
+            .all()?
+            .rev()
+        {
+            let event = event?;
+            if kind
+                .as_deref()
+                .is_some_and(|kind| event_kind(&event.event) != kind)
+            {
+                continue;
+            }
+            if let Some(id) = &torrent_id
+                && event.torrent_id.as_ref() != Some(id)
+                && event.mam_id.is_none_or(|mam_id| mam_id.to_string() != *id)
+            {
+                continue;
+            }
+            page.push(EventObject(event));
+        }
+        Ok(page)
+    }
+
+    /// Series of the torrents in the database, by name
+    #[graphql(complexity = "page_complexity(page_size, child_complexity)")]
+    async fn series(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "Only series whose name contains this, ignoring case")] name: Option<
+            String,
+        >,
+        #[graphql(default)] from: usize,
+        page_size: Option<usize>,
+    ) -> Result<Page<SeriesObject>> {
+        let r = db(ctx)?.r_transaction()?;
+        let name = name.map(|name| name.to_lowercase());
+        let mut series = BTreeMap::<String, Vec<SeriesBook>>::new();
+        for torrent in r.scan().primary::<Torrent>()?.all()? {
+            let torrent = torrent?;
+            for s in &torrent.meta.series {
+                if name
+                    .as_ref()
+                    .is_some_and(|name| !s.name.to_lowercase().contains(name))
+                {
+                    continue;
+                }
+                series.entry(s.name.clone()).or_default().push(SeriesBook {
+                    entries: s.entries.to_string(),
+                    torrent: TorrentObject(torrent.clone()),
+                });
+            }
+        }
+        let mut page = Page::new(from, page_size);
+        for (name, books) in series {
+            page.push(SeriesObject { name, books });
+        }
+        Ok(page)
+    }
+}
+
+fn page_complexity(page_size: Option<usize>, child_complexity: usize) -> usize {
+    page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE)
+        .saturating_mul(child_complexity)
+}
+
+fn db<'a>(ctx: &Context<'a>) -> Result<&'a Arc<Database<'static>>> {
+    ctx.data::<Arc<Database<'static>>>()
+}
+
+#[derive(SimpleObject)]
+#[graphql(concrete(name = "TorrentPage", params(TorrentObject)))]
+#[graphql(concrete(name = "SelectedTorrentPage", params(SelectedTorrentObject)))]
+#[graphql(concrete(name = "EventPage", params(EventObject)))]
+#[graphql(concrete(name = "SeriesPage", params(SeriesObject)))]
+pub struct Page<T: OutputType> {
+    from: usize,
+    page_size: usize,
+    /// Number of matches across all pages
+    total: usize,
+    items: Vec<T>,
+}
+
+impl<T: OutputType> Page<T> {
+    fn new(from: usize, page_size: Option<usize>) -> Self {
+        Self {
+            from,
+            page_size: page_size
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+            total: 0,
+            items: vec![],
+        }
+    }
+
+    fn push(&mut self, item: T) {
+        if self.total >= self.from && self.items.len() < self.page_size {
+            self.items.push(item);
+        }
+        self.total += 1;
+    }
+}
+
+/// Filters for `torrents`, text is matched ignoring case
+#[derive(Default, InputObject)]
+#[graphql(name = "TorrentFilter")]
+pub struct TorrentsFilter {
+    /// Title contains this
+    title: Option<String>,
+    author: Option<String>,
+    /// Name of the series contains this
+    series: Option<String>,
+    /// Name of the library that linked the torrent
+    linker: Option<String>,
+    category: Option<String>,
+    media_type: Option<String>,
+    linked: Option<bool>,
+    replaced: Option<bool>,
+}
+
+impl TorrentsFilter {
+    fn matches(&self, torrent: &Torrent) -> bool {
+        let contains =
+            |value: &str, wanted: &str| value.to_lowercase().contains(&wanted.to_lowercase());
+        let meta = &torrent.meta;
+        if let Some(title) = &self.title
+            && !contains(&meta.title, title)
+        {
+            return false;
+        }
+        if let Some(author) = &self.author
+            && !meta.authors.iter().any(|a| contains(a, author))
+        {
+            return false;
+        }
+        if let Some(series) = &self.series
+            && !meta.series.iter().any(|s| contains(&s.name, series))
+        {
+            return false;
+        }
+        if self.linker.is_some() && torrent.linker != self.linker {
+            return false;
+        }
+        if self.category.is_some() && torrent.category != self.category {
+            return false;
+        }
+        if let Some(media_type) = &self.media_type
+            && !meta.media_type.as_str().eq_ignore_ascii_case(media_type)
+        {
+            return false;
+        }
+        if self
+            .linked
+            .is_some_and(|linked| linked != torrent.library_path.is_some())
+        {
+            return false;
+        }
+        if self
+            .replaced
+            .is_some_and(|replaced| replaced != torrent.replaced_with.is_some())
+        {
+            return false;
+        }
+        true
+    }
+}
+
+pub struct TorrentObject(Torrent);
+
+#[Object(name = "Torrent")]
+impl TorrentObject {
+    async fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    async fn mam_id(&self) -> u64 {
+        self.0.mam_id
+    }
+
+    async fn abs_id(&self) -> Option<&str> {
+        self.0.abs_id.as_deref()
+    }
+
+    async fn goodreads_id(&self) -> Option<u64> {
+        self.0.goodreads_id
+    }
+
+    async fn library_path(&self) -> Option<String> {
+        self.0
+            .library_path
+            .as_ref()
+            .map(|path| path.to_string_lossy().into_owned())
+    }
+
+    async fn library_files(&self) -> Vec<String> {
+        self.0
+            .library_files
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    /// Name of the library that linked the torrent
+    async fn linker(&self) -> Option<&str> {
+        self.0.linker.as_deref()
+    }
+
+    async fn category(&self) -> Option<&str> {
+        self.0.category.as_deref()
+    }
+
+    async fn selected_audio_format(&self) -> Option<&str> {
+        self.0.selected_audio_format.as_deref()
+    }
+
+    async fn selected_ebook_format(&self) -> Option<&str> {
+        self.0.selected_ebook_format.as_deref()
+    }
+
+    async fn meta(&self) -> MetaObject<'_> {
+        MetaObject(&self.0.meta)
+    }
+
+    async fn created_at(&self) -> i64 {
+        unix_time(&self.0.created_at)
+    }
+
+    /// Id of the torrent this was replaced with
+    async fn replaced_with(&self) -> Option<&str> {
+        self.0.replaced_with.as_ref().map(|(id, _)| id.as_str())
+    }
+
+    async fn library_mismatch(&self) -> Option<GraphQLJson<serde_json::Value>> {
+        self.0
+            .library_mismatch
+            .as_ref()
+            .map(|mismatch| GraphQLJson(serde_json::json!(mismatch)))
+    }
+
+    async fn client_status(&self) -> Option<GraphQLJson<serde_json::Value>> {
+        self.0
+            .client_status
+            .as_ref()
+            .map(|status| GraphQLJson(serde_json::json!(status)))
+    }
+
+    /// Events of the torrent, newest first
+    #[graphql(complexity = "EVENTS_COMPLEXITY + child_complexity")]
+    async fn events(&self, ctx: &Context<'_>) -> Result<Vec<EventObject>> {
+        let events = db(ctx)?
+            .r_transaction()?
+            .scan()
+            .secondary::<Event>(EventKey::mam_id)?;
+        let events = events.range(Some(self.0.mam_id)..=Some(self.0.mam_id))?;
+        let mut events = events.collect::<Result<Vec<_>, _>>()?;
+        events.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(events.into_iter().map(EventObject).collect())
+    }
+}
+
+pub struct SelectedTorrentObject(SelectedTorrent);
+
+#[Object(name = "SelectedTorrent")]
+impl SelectedTorrentObject {
+    async fn mam_id(&self) -> u64 {
+        self.0.mam_id
+    }
+
+    async fn hash(&self) -> Option<&str> {
+        self.0.hash.as_deref()
+    }
+
+    async fn goodreads_id(&self) -> Option<u64> {
+        self.0.goodreads_id
+    }
+
+    async fn cost(&self) -> &str {
+        self.0.cost.as_str()
+    }
+
+    async fn category(&self) -> Option<&str> {
+        self.0.category.as_deref()
+    }
+
+    async fn tags(&self) -> &[String] {
+        &self.0.tags
+    }
+
+    async fn meta(&self) -> MetaObject<'_> {
+        MetaObject(&self.0.meta)
+    }
+
+    async fn grabber(&self) -> Option<&str> {
+        self.0.grabber.as_deref()
+    }
+
+    async fn account(&self) -> Option<&str> {
+        self.0.account.as_deref()
+    }
+
+    async fn priority(&self) -> i32 {
+        self.0.priority
+    }
+
+    async fn created_at(&self) -> i64 {
+        unix_time(&self.0.created_at)
+    }
+
+    async fn started_at(&self) -> Option<i64> {
+        self.0.started_at.as_ref().map(unix_time)
+    }
+
+    async fn removed_at(&self) -> Option<i64> {
+        self.0.removed_at.as_ref().map(unix_time)
+    }
+}
+
+pub struct EventObject(Event);
+
+#[Object(name = "Event")]
+impl EventObject {
+    async fn id(&self) -> String {
+        serde_json::json!(self.0.id)
+            .as_str()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// The same kinds as the events page filters on
+    async fn kind(&self) -> &str {
+        event_kind(&self.0.event)
+    }
+
+    async fn torrent_id(&self) -> Option<&str> {
+        self.0.torrent_id.as_deref()
+    }
+
+    async fn mam_id(&self) -> Option<u64> {
+        self.0.mam_id
+    }
+
+    async fn created_at(&self) -> i64 {
+        unix_time(&self.0.created_at)
+    }
+
+    /// The details of the event, shaped as in the events API
+    async fn event(&self) -> GraphQLJson<serde_json::Value> {
+        GraphQLJson(serde_json::json!(self.0.event))
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Series")]
+pub struct SeriesObject {
+    name: String,
+    books: Vec<SeriesBook>,
+}
+
+#[derive(SimpleObject)]
+pub struct SeriesBook {
+    /// The numbers of the book in the series, like "1-3"
+    entries: String,
+    torrent: TorrentObject,
+}
+
+pub struct MetaObject<'a>(&'a TorrentMeta);
+
+#[Object(name = "TorrentMeta")]
+impl MetaObject<'_> {
+    async fn mam_id(&self) -> u64 {
+        self.0.mam_id
+    }
+
+    async fn title(&self) -> &str {
+        &self.0.title
+    }
+
+    async fn edition(&self) -> Option<&str> {
+        self.0.edition.as_ref().map(|(edition, _)| edition.as_str())
+    }
+
+    async fn authors(&self) -> &[String] {
+        &self.0.authors
+    }
+
+    async fn narrators(&self) -> &[String] {
+        &self.0.narrators
+    }
+
+    async fn series(&self) -> Vec<SeriesEntryObject> {
+        self.0
+            .series
+            .iter()
+            .map(|s| SeriesEntryObject {
+                name: s.name.clone(),
+                entries: s.entries.to_string(),
+            })
+            .collect()
+    }
+
+    async fn media_type(&self) -> &str {
+        self.0.media_type.as_str()
+    }
+
+    async fn main_cat(&self) -> Option<&str> {
+        self.0.main_cat.as_ref().map(|cat| cat.as_str())
+    }
+
+    async fn categories(&self) -> Vec<&str> {
+        self.0.categories.iter().map(|cat| cat.as_str()).collect()
+    }
+
+    async fn language(&self) -> Option<&str> {
+        self.0.language.map(|language| language.to_str())
+    }
+
+    async fn filetypes(&self) -> &[String] {
+        &self.0.filetypes
+    }
+
+    async fn num_files(&self) -> u64 {
+        self.0.num_files
+    }
+
+    /// Size in bytes
+    async fn size(&self) -> u64 {
+        self.0.size.bytes()
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    async fn uploaded_at(&self) -> i64 {
+        unix_time(&self.0.uploaded_at)
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "SeriesEntry")]
+pub struct SeriesEntryObject {
+    name: String,
+    entries: String,
+}
+
+fn unix_time(timestamp: &Timestamp) -> i64 {
+    timestamp.0.unix_timestamp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page() {
+        let mut page = Page::new(2, Some(2));
+        for i in 0..5 {
+            page.push(SeriesEntryObject {
+                name: i.to_string(),
+                entries: String::new(),
+            });
+        }
+        assert_eq!(page.total, 5);
+        let names = page
+            .items
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["2", "3"]);
+
+        assert_eq!(Page::<SeriesObject>::new(0, Some(0)).page_size, 1);
+        assert_eq!(
+            Page::<SeriesObject>::new(0, None).page_size,
+            DEFAULT_PAGE_SIZE
+        );
+    }
+
+    #[test]
+    fn test_schema_sdl() {
+        let sdl = schema_sdl();
+        for name in [
+            "type Torrent ",
+            "type SelectedTorrent ",
+            "type Event ",
+            "type Series ",
+        ] {
+            assert!(sdl.contains(name), "{name} missing from schema");
+        }
+        assert!(sdl.contains("torrents(filter: TorrentFilter!"));
+    }
+
+    #[tokio::test]
+    async fn test_schema_limits() {
+        let db = native_db::Builder::new()
+            .create_in_memory(&mlm_db::MODELS)
+            .unwrap();
+        let schema = schema(Arc::new(db));
+
+        let response = schema
+            .execute("{ torrents(pageSize: 10) { total items { id events { kind } } } }")
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+        let response = schema
+            .execute("{ series { items { books { torrent { meta { series { name } } } } } } }")
+            .await;
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(response.errors[0].message, "Query is nested too deep.");
+
+        let response = schema
+            .execute("{ torrents(pageSize: 1000) { items { id events { kind } } } }")
+            .await;
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(response.errors[0].message, "Query is too complex.");
+    }
+}
@@ -0,0 +1,41 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::info;
+
+use crate::{stats::Context, web::AppError};
+
+pub async fn maintenance_api(
+    State(context): State<Context>,
+) -> std::result::Result<Json<Value>, AppError> {
+    Ok::<_, AppError>(Json(json!({ "maintenance": context.stats.maintenance() })))
+}
+
+/// Turns maintenance mode on or off, toggling it if `enabled` is not given
+pub async fn maintenance_api_post(
+    State(context): State<Context>,
+    Query(query): Query<MaintenanceApiQuery>,
+) -> std::result::Result<Json<Value>, AppError> {
+    let maintenance = query
+        .enabled
+        .unwrap_or_else(|| !context.stats.maintenance());
+    set_maintenance(&context, maintenance);
+    Ok::<_, AppError>(Json(json!({ "maintenance": maintenance })))
+}
+
+pub fn set_maintenance(context: &Context, maintenance: bool) {
+    if maintenance {
+        info!("Maintenance mode on, background tasks pause after their current run");
+    } else {
+        info!("Maintenance mode off");
+    }
+    context.stats.set_maintenance(maintenance);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceApiQuery {
+    enabled: Option<bool>,
+}
@@ -1,2 +1,10 @@
+pub mod account;
+pub mod events;
+pub mod filter;
+pub mod global_search;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod maintenance;
 pub mod search;
+pub mod tasks;
 pub mod torrent;
@@ -1,47 +1,124 @@
-use std::{fs::File, path::PathBuf};
+use std::{fmt::Display, fs::File, path::PathBuf, str::FromStr};
 
 use anyhow::Result;
 use axum::{
     Json,
     extract::{Query, State},
+    response::{IntoResponse, Response},
 };
 use axum_extra::extract::Form;
-use mlm_mam::search::{MaMTorrent, SearchFields};
-use serde::{Deserialize, Serialize};
+use mlm_db::{Language, Size, Torrent, TorrentCost, TorrentKey, TorrentMeta};
+use mlm_mam::{
+    enums::{SearchKind, SearchTarget},
+    search::{MaMTorrent, SearchFields, SearchQuery, Tor},
+};
+use serde::{Deserialize, Deserializer, Serialize};
 use tokio::fs::create_dir_all;
 
 use crate::{
     autograbber::{mark_removed_torrents, search_torrents, select_torrents},
     config::{Cost, TorrentSearch},
     stats::Context,
-    web::{AppError, MaMState},
+    web::AppError,
 };
 
 pub async fn search_api(
-    State(mam): State<MaMState>,
+    State(context): State<Context>,
     Query(query): Query<SearchApiQuery>,
-) -> std::result::Result<Json<SearchApiResponse>, AppError> {
-    let Ok(mam) = mam.as_ref() else {
-        return Err(anyhow::Error::msg("mam_id error").into());
-    };
-    let search: TorrentSearch = toml::from_str(&query.toml)?;
-    let torrents = search_torrents(
-        &search,
-        SearchFields {
-            description: true,
-            isbn: true,
-            media_info: true,
+) -> std::result::Result<Response, AppError> {
+    let mam = context.mam()?;
+    if let Some(toml) = &query.toml {
+        let search: TorrentSearch = toml::from_str(toml)?;
+        let torrents = search_torrents(
+            &search,
+            SearchFields {
+                description: true,
+                isbn: true,
+                media_info: true,
+                ..Default::default()
+            },
+            mam.as_ref(),
+        )
+        .await?
+        .collect::<Vec<_>>();
+
+        return Ok(Json(SearchApiResponse {
+            torrents: Some(torrents),
             ..Default::default()
-        },
-        mam,
-    )
-    .await?
-    .collect::<Vec<_>>();
+        })
+        .into_response());
+    }
 
-    Ok::<_, AppError>(Json(SearchApiResponse {
-        torrents: Some(torrents),
-        ..Default::default()
-    }))
+    let kind = query.cost.map(cost_search_kind).transpose()?.flatten();
+    let result = mam
+        .search(&SearchQuery {
+            fields: SearchFields {
+                media_info: true,
+                ..Default::default()
+            },
+            perpage: query.perpage.unwrap_or(100).clamp(5, 100),
+            tor: Tor {
+                target: query.uploader.map(SearchTarget::Uploader),
+                kind,
+                text: query.q.clone(),
+                cat: query.categories.clone(),
+                browse_lang: query.languages.iter().map(|l| l.to_id()).collect(),
+                min_size: query.min_size.map_or(0, |s| s.bytes()),
+                max_size: query.max_size.map_or(0, |s| s.bytes()),
+                unit: query.min_size.or(query.max_size).map_or(0, |_| 1),
+                start_number: query.from.unwrap_or_default(),
+                ..Default::default()
+            },
+        })
+        .await?;
+
+    let config = context.config().await;
+    let r = context.db.r_transaction()?;
+    let torrents = result
+        .data
+        .into_iter()
+        .map(|mam_torrent| {
+            let meta = mam_torrent.as_meta(config.title_rules())?;
+            let torrent = r
+                .get()
+                .secondary::<Torrent>(TorrentKey::mam_id, meta.mam_id)?;
+            let selected = r
+                .get()
+                .primary::<mlm_db::SelectedTorrent>(mam_torrent.id)?
+                .is_some_and(|t| t.removed_at.is_none());
+            let cost = if mam_torrent.vip {
+                TorrentCost::Vip
+            } else if mam_torrent.personal_freeleech {
+                TorrentCost::PersonalFreeleech
+            } else if mam_torrent.free {
+                TorrentCost::GlobalFreeleech
+            } else {
+                TorrentCost::Ratio
+            };
+
+            Ok(SearchApiTorrent {
+                id: mam_torrent.id,
+                title: meta.title.clone(),
+                cost: cost.as_str(),
+                vip_expire: mam_torrent.vip.then_some(mam_torrent.vip_expire),
+                seeders: mam_torrent.seeders,
+                leechers: mam_torrent.leechers,
+                snatches: mam_torrent.times_completed,
+                library_id: torrent.map(|t| t.id),
+                selected,
+                meta,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Json(SearchApiResults {
+        start: result.start,
+        perpage: result.perpage,
+        total: result.total,
+        found: result.found,
+        torrents,
+    })
+    .into_response())
 }
 
 pub async fn search_api_post(
@@ -62,7 +139,7 @@ pub async fn search_api_post(
                 && search.cost != Cost::MetadataOnlyAdd,
             ..Default::default()
         },
-        &mam,
+        mam.as_ref(),
     )
     .await?
     .collect::<Vec<_>>();
@@ -91,7 +168,7 @@ pub async fn search_api_post(
         select_torrents(
             &config,
             &context.db,
-            &mam,
+            mam.as_ref(),
             torrents.into_iter(),
             &search.filter,
             search.cost,
@@ -101,6 +178,7 @@ pub async fn search_api_post(
             search.dry_run,
             u64::MAX,
             None,
+            None,
         )
         .await?;
         return Ok::<_, AppError>(Json(SearchApiResponse {
@@ -128,7 +206,25 @@ pub struct SearchApiForm {
 
 #[derive(Deserialize)]
 pub struct SearchApiQuery {
-    toml: String,
+    toml: Option<String>,
+    #[serde(default)]
+    q: String,
+    uploader: Option<u64>,
+    cost: Option<Cost>,
+    #[serde(default)]
+    #[serde(deserialize_with = "comma_separated")]
+    categories: Vec<u8>,
+    #[serde(default)]
+    #[serde(deserialize_with = "comma_separated")]
+    languages: Vec<Language>,
+    #[serde(default)]
+    #[serde(deserialize_with = "parse_opt_size")]
+    min_size: Option<Size>,
+    #[serde(default)]
+    #[serde(deserialize_with = "parse_opt_size")]
+    max_size: Option<Size>,
+    from: Option<u64>,
+    perpage: Option<u64>,
 }
 
 #[derive(Default, Serialize)]
@@ -136,3 +232,98 @@ pub struct SearchApiResponse {
     torrents: Option<Vec<MaMTorrent>>,
     added: Option<bool>,
 }
+
+#[derive(Serialize)]
+pub struct SearchApiResults {
+    start: usize,
+    perpage: usize,
+    total: usize,
+    found: usize,
+    torrents: Vec<SearchApiTorrent>,
+}
+
+#[derive(Serialize)]
+pub struct SearchApiTorrent {
+    id: u64,
+    title: String,
+    cost: &'static str,
+    vip_expire: Option<u64>,
+    seeders: u64,
+    leechers: u64,
+    snatches: u64,
+    library_id: Option<String>,
+    selected: bool,
+    meta: TorrentMeta,
+}
+
+/// The MaM search type for `cost`. MaM can only narrow a search down to
+/// freeleech or VIP torrents, `ratio` searches all of them and the other costs
+/// are rejected rather than silently ignored
+fn cost_search_kind(cost: Cost) -> Result<Option<SearchKind>, AppError> {
+    match cost {
+        Cost::Free | Cost::Vip => Ok(cost.search_kind()),
+        Cost::Ratio => Ok(None),
+        Cost::PersonalFreeleech
+        | Cost::Wedge
+        | Cost::TryWedge
+        | Cost::MetadataOnly
+        | Cost::MetadataOnlyAdd => Err(AppError::BadRequest(format!(
+            "Can't search MaM by cost {cost:?}"
+        ))),
+    }
+}
+
+fn comma_separated<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    T: FromStr,
+    T::Err: Display,
+    D: Deserializer<'de>,
+{
+    let v: String = Deserialize::deserialize(deserializer)?;
+    v.split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+fn parse_opt_size<'de, D>(deserializer: D) -> Result<Option<Size>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let v: Option<String> = Deserialize::deserialize(deserializer)?;
+    v.filter(|v| !v.is_empty())
+        .map(|v| v.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_search_kind() {
+        let kind = |cost| cost_search_kind(cost).map_err(|err| err.to_string());
+        assert_eq!(kind(Cost::Free), Ok(Some(SearchKind::Free)));
+        assert_eq!(kind(Cost::Vip), Ok(Some(SearchKind::Vip)));
+        assert_eq!(kind(Cost::Ratio), Ok(None));
+        for cost in [
+            Cost::PersonalFreeleech,
+            Cost::Wedge,
+            Cost::TryWedge,
+            Cost::MetadataOnly,
+            Cost::MetadataOnlyAdd,
+        ] {
+            assert_eq!(
+                kind(cost),
+                Err(format!("Bad Request: Can't search MaM by cost {cost:?}"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_cost_rejected_with_bad_request() {
+        let response = cost_search_kind(Cost::Wedge).unwrap_err().into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::anyhow;
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::{
+    paused_tasks::{is_paused, paused_tasks, set_paused, task_keys},
+    stats::Context,
+    web::AppError,
+};
+
+pub async fn tasks_api(
+    State(context): State<Context>,
+) -> std::result::Result<Json<Value>, AppError> {
+    let config = context.config().await;
+    let paused = paused_tasks(&context.db)?;
+    let tasks = task_keys(&config)
+        .into_iter()
+        .map(|key| json!({ "paused": paused.contains(&key), "key": key }))
+        .collect::<Vec<_>>();
+    Ok::<_, AppError>(Json(json!({ "tasks": tasks })))
+}
+
+/// Pauses or resumes an autograb, snatchlist or list, toggling it if `paused`
+/// is not given
+pub async fn tasks_api_post(
+    State(context): State<Context>,
+    Query(query): Query<TasksApiQuery>,
+) -> std::result::Result<Json<Value>, AppError> {
+    let config = context.config().await;
+    if !task_keys(&config).contains(&query.key) {
+        return Err(anyhow!("Unknown task {:?}", query.key).into());
+    }
+    let paused = query
+        .paused
+        .unwrap_or_else(|| !is_paused(&context.db, &query.key));
+    set_paused(&context.db, &query.key, paused).await?;
+    context.stats.update(|_| ()).await;
+    Ok::<_, AppError>(Json(json!({ "key": query.key, "paused": paused })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TasksApiQuery {
+    key: String,
+    paused: Option<bool>,
+}
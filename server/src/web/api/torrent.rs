@@ -39,7 +39,8 @@ async fn torrent_api_mam_id(
     let Some(mam_torrent) = mam.get_torrent_info_by_id(mam_id).await? else {
         return Err(AppError::NotFound);
     };
-    let meta = mam_torrent.as_meta()?;
+    let config = context.config().await;
+    let meta = mam_torrent.as_meta(config.title_rules())?;
 
     Ok::<_, AppError>(Json(json!({
         "mam_torrent": mam_torrent,
@@ -0,0 +1,301 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, HeaderValue, Method, Request, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+use crate::{config::AuthConfig, stats::Context};
+
+#[derive(Debug, PartialEq, Eq)]
+enum AuthResult {
+    Allowed,
+    Denied(&'static str),
+}
+
+pub async fn require_auth(
+    State(context): State<Context>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let config = context.config().await;
+    let Some(auth) = &config.auth else {
+        return next.run(request).await;
+    };
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    match authorize(
+        auth,
+        request.method(),
+        request.uri().path(),
+        request.headers(),
+        ip,
+    ) {
+        AuthResult::Allowed => next.run(request).await,
+        AuthResult::Denied(challenge) => {
+            let mut response = (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(challenge),
+            );
+            response
+        }
+    }
+}
+
+fn authorize(
+    auth: &AuthConfig,
+    method: &Method,
+    path: &str,
+    headers: &HeaderMap,
+    ip: Option<IpAddr>,
+) -> AuthResult {
+    if let Some(ip) = ip
+        && auth.allow_cidrs.iter().any(|cidr| cidr.contains(ip))
+    {
+        return AuthResult::Allowed;
+    }
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+
+    // Opening /graphql in a browser gives the GraphiQL page, only the queries
+    // it sends need the token
+    let is_graphql = path == "/graphql" || path.starts_with("/graphql/");
+    let is_api = path.starts_with("/api/") || (is_graphql && method != Method::GET);
+    let basic = auth.username.as_deref().zip(auth.password.as_deref());
+    let token = auth.api_token.as_deref().filter(|_| is_api);
+
+    if basic.is_none() && token.is_none() {
+        return AuthResult::Allowed;
+    }
+    if let Some(expected) = token
+        && let Some(given) = authorization.and_then(|a| a.strip_prefix("Bearer "))
+        && constant_time_eq(given.trim().as_bytes(), expected.as_bytes())
+    {
+        return AuthResult::Allowed;
+    }
+    if let Some((username, password)) = basic
+        && let Some(given) = authorization.and_then(|a| a.strip_prefix("Basic "))
+        && let Ok(given) = STANDARD.decode(given.trim())
+        && constant_time_eq(&given, format!("{username}:{password}").as_bytes())
+    {
+        return AuthResult::Allowed;
+    }
+
+    if basic.is_some() {
+        AuthResult::Denied("Basic realm=\"MLM\", charset=\"UTF-8\"")
+    } else {
+        AuthResult::Denied("Bearer realm=\"MLM\"")
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth(toml: &str) -> AuthConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(authorization).unwrap(),
+        );
+        headers
+    }
+
+    fn basic(credentials: &str) -> HeaderMap {
+        headers(&format!("Basic {}", STANDARD.encode(credentials)))
+    }
+
+    #[test]
+    fn test_basic_auth() {
+        let auth = auth(
+            r#"
+            username = "mouse"
+            password = "cheese"
+            "#,
+        );
+        assert_eq!(
+            authorize(&auth, &Method::GET, "/", &basic("mouse:cheese"), None),
+            AuthResult::Allowed
+        );
+        assert_eq!(
+            authorize(
+                &auth,
+                &Method::GET,
+                "/api/search",
+                &basic("mouse:cheese"),
+                None
+            ),
+            AuthResult::Allowed
+        );
+        assert!(matches!(
+            authorize(&auth, &Method::GET, "/", &basic("mouse:cheddar"), None),
+            AuthResult::Denied(challenge) if challenge.starts_with("Basic ")
+        ));
+        assert!(matches!(
+            authorize(&auth, &Method::GET, "/torrents", &HeaderMap::new(), None),
+            AuthResult::Denied(_)
+        ));
+        assert!(matches!(
+            authorize(
+                &auth,
+                &Method::GET,
+                "/",
+                &headers("Basic not base64!"),
+                None
+            ),
+            AuthResult::Denied(_)
+        ));
+    }
+
+    #[test]
+    fn test_api_token() {
+        let auth = auth(r#"api_token = "secret""#);
+        assert_eq!(
+            authorize(
+                &auth,
+                &Method::GET,
+                "/api/search",
+                &headers("Bearer secret"),
+                None
+            ),
+            AuthResult::Allowed
+        );
+        assert!(matches!(
+            authorize(&auth, &Method::GET, "/api/search", &headers("Bearer wrong"), None),
+            AuthResult::Denied(challenge) if challenge.starts_with("Bearer ")
+        ));
+        assert!(matches!(
+            authorize(&auth, &Method::GET, "/api/search", &HeaderMap::new(), None),
+            AuthResult::Denied(_)
+        ));
+        // The token only guards the API
+        assert_eq!(
+            authorize(&auth, &Method::GET, "/", &HeaderMap::new(), None),
+            AuthResult::Allowed
+        );
+        assert_eq!(
+            authorize(
+                &auth,
+                &Method::GET,
+                "/search/global",
+                &HeaderMap::new(),
+                None
+            ),
+            AuthResult::Allowed
+        );
+    }
+
+    #[test]
+    fn test_api_token_graphql() {
+        let auth = auth(r#"api_token = "secret""#);
+        assert!(matches!(
+            authorize(&auth, &Method::POST, "/graphql", &HeaderMap::new(), None),
+            AuthResult::Denied(challenge) if challenge.starts_with("Bearer ")
+        ));
+        assert_eq!(
+            authorize(
+                &auth,
+                &Method::POST,
+                "/graphql",
+                &headers("Bearer secret"),
+                None
+            ),
+            AuthResult::Allowed
+        );
+        // The GraphiQL page, which sends its queries with the token set in it
+        assert_eq!(
+            authorize(&auth, &Method::GET, "/graphql", &HeaderMap::new(), None),
+            AuthResult::Allowed
+        );
+        assert_eq!(
+            authorize(&auth, &Method::GET, "/graphqlish", &HeaderMap::new(), None),
+            AuthResult::Allowed
+        );
+    }
+
+    #[test]
+    fn test_token_and_basic() {
+        let auth = auth(
+            r#"
+            username = "mouse"
+            password = "cheese"
+            api_token = "secret"
+            "#,
+        );
+        assert_eq!(
+            authorize(
+                &auth,
+                &Method::GET,
+                "/api/search",
+                &headers("Bearer secret"),
+                None
+            ),
+            AuthResult::Allowed
+        );
+        assert!(matches!(
+            authorize(&auth, &Method::GET, "/", &headers("Bearer secret"), None),
+            AuthResult::Denied(_)
+        ));
+    }
+
+    #[test]
+    fn test_allow_cidrs() {
+        let auth = auth(
+            r#"
+            username = "mouse"
+            password = "cheese"
+            allow_cidrs = ["192.168.1.0/24", "::1"]
+            "#,
+        );
+        assert_eq!(
+            authorize(
+                &auth,
+                &Method::GET,
+                "/",
+                &HeaderMap::new(),
+                Some("192.168.1.10".parse().unwrap())
+            ),
+            AuthResult::Allowed
+        );
+        assert_eq!(
+            authorize(
+                &auth,
+                &Method::GET,
+                "/",
+                &HeaderMap::new(),
+                Some("::1".parse().unwrap())
+            ),
+            AuthResult::Allowed
+        );
+        assert!(matches!(
+            authorize(
+                &auth,
+                &Method::GET,
+                "/",
+                &HeaderMap::new(),
+                Some("10.0.0.1".parse().unwrap())
+            ),
+            AuthResult::Denied(_)
+        ));
+        assert!(matches!(
+            authorize(&auth, &Method::GET, "/", &HeaderMap::new(), None),
+            AuthResult::Denied(_)
+        ));
+    }
+}
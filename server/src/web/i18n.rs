@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+
+use crate::config::UiLanguage;
+
+/// The UI strings of each language by key, embedded from `server/locales`
+static LOCALES: Lazy<HashMap<UiLanguage, HashMap<String, String>>> = Lazy::new(|| {
+    [
+        (UiLanguage::En, include_str!("../../locales/en.json")),
+        (UiLanguage::Sv, include_str!("../../locales/sv.json")),
+    ]
+    .into_iter()
+    .map(|(language, json)| (language, serde_json::from_str(json).unwrap()))
+    .collect()
+});
+
+/// The UI string for `key`, in English when the language doesn't have it and
+/// the key itself when English doesn't have it either
+pub fn translate(language: UiLanguage, key: &str) -> &str {
+    [language, UiLanguage::En]
+        .iter()
+        .find_map(|language| LOCALES.get(language)?.get(key))
+        .map_or(key, String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use askama::Template;
+
+    use super::*;
+    use crate::web::Page;
+
+    #[derive(Template)]
+    #[template(source = "<button>{{ t(\"action.run_now\") }}</button>", ext = "html")]
+    struct LabelTemplate {
+        language: UiLanguage,
+    }
+
+    impl Page for LabelTemplate {
+        fn language(&self) -> UiLanguage {
+            self.language
+        }
+    }
+
+    #[test]
+    fn test_translate() {
+        let en = LabelTemplate {
+            language: UiLanguage::En,
+        };
+        let sv = LabelTemplate {
+            language: UiLanguage::Sv,
+        };
+        assert_eq!(en.render().unwrap(), "<button>run now</button>");
+        assert_eq!(sv.render().unwrap(), "<button>kör nu</button>");
+
+        assert_eq!(translate(UiLanguage::Sv, "nav.home"), "Hem");
+        assert_eq!(translate(UiLanguage::Sv, "missing.key"), "missing.key");
+    }
+
+    #[test]
+    fn test_locale_keys_exist_in_english() {
+        let en = &LOCALES[&UiLanguage::En];
+        for (language, strings) in LOCALES.iter() {
+            for key in strings.keys() {
+                assert!(
+                    en.contains_key(key),
+                    "{key} of {language:?} is missing in en"
+                );
+            }
+        }
+    }
+}
@@ -1,8 +1,13 @@
 mod api;
+mod auth;
+mod i18n;
 mod pages;
 mod tables;
 
-use std::{path::PathBuf, sync::Arc};
+#[cfg(feature = "graphql")]
+pub use api::graphql::schema_sdl as graphql_schema_sdl;
+
+use std::{net::SocketAddr, path::PathBuf};
 
 use anyhow::Result;
 use askama::{Template, filters::HtmlSafe};
@@ -17,31 +22,37 @@ use axum::{
 use itertools::Itertools;
 use mlm_db::{
     AudiobookCategory, Category, EbookCategory, Flags, SelectedTorrent, Series, Timestamp, Torrent,
-    TorrentMeta,
+    TorrentMeta, TorrentMetaDiff,
 };
-use mlm_mam::{api::MaM, meta::MetaError, search::MaMTorrent, serde::DATE_FORMAT};
+use mlm_mam::{meta::MetaError, search::MaMTorrent, serde::DATE_FORMAT};
 use once_cell::sync::Lazy;
 use pages::{
     config::{config_page, config_page_post},
-    duplicate::{duplicate_page, duplicate_torrents_page_post},
+    duplicate::{duplicate_compare_page, duplicate_page, duplicate_torrents_page_post},
     errors::{errors_page, errors_page_post},
     events::event_page,
     index::{index_page, index_page_post},
+    library_duplicates::{library_duplicates_page, library_duplicates_page_post},
     list::{list_page, list_page_post},
     lists::lists_page,
     replaced::{replaced_torrents_page, replaced_torrents_page_post},
+    rss::rss_feed,
     selected::{selected_page, selected_torrents_page_post},
-    torrent::{torrent_file, torrent_page, torrent_page_post},
+    series_gaps::series_gaps_page,
+    torrent::{torrent_cover, torrent_file, torrent_page, torrent_page_post},
     torrent_edit::{torrent_edit_page, torrent_edit_page_post},
-    torrents::{torrents_page, torrents_page_post},
+    torrents::{torrents_csv, torrents_page, torrents_page_post},
+    unmatched::{unmatched_page, unmatched_page_post},
+    untracked_files::{untracked_files_page, untracked_files_page_post},
 };
 use reqwest::header;
 use serde::Serialize;
 use tables::{ItemFilter, ItemFilters, Key};
 use time::{
     Date, UtcDateTime, UtcOffset,
-    format_description::{self, OwnedFormatItem},
+    format_description::{self, OwnedFormatItem, well_known::Rfc2822},
 };
+use time_tz::{OffsetDateTimeExt as _, Tz};
 use tokio::sync::watch::error::SendError;
 use tower::ServiceBuilder;
 use tracing::warn;
@@ -49,11 +60,17 @@ use tracing::warn;
 use tower_http::services::{ServeDir, ServeFile};
 
 use crate::{
-    config::{SearchConfig, TorrentFilter},
+    config::{SearchConfig, TorrentFilter, UiLanguage, WebBind},
     stats::Context,
     web::{
         api::{
+            account::account_api,
+            events::{events_api, events_updates},
+            filter::filter_preview_api,
+            global_search::global_search_api,
+            maintenance::{maintenance_api, maintenance_api_post},
             search::{search_api, search_api_post},
+            tasks::{tasks_api, tasks_api_post},
             torrent::torrent_api,
         },
         pages::{
@@ -63,8 +80,6 @@ use crate::{
     },
 };
 
-pub type MaMState = Arc<Result<Arc<MaM<'static>>>>;
-
 pub async fn start_webserver(context: Context) -> Result<()> {
     let config = context.config().await;
     let assets_dir = resolve_assets_dir();
@@ -84,6 +99,10 @@ pub async fn start_webserver(context: Context) -> Result<()> {
             "/torrents",
             post(torrents_page_post).with_state(context.clone()),
         )
+        .route(
+            "/torrents.csv",
+            get(torrents_csv).with_state(context.clone()),
+        )
         .route(
             "/torrents/{id}",
             get(torrent_page).with_state(context.clone()),
@@ -94,7 +113,11 @@ pub async fn start_webserver(context: Context) -> Result<()> {
         )
         .route(
             "/torrents/{id}/edit",
-            get(torrent_edit_page).with_state(context.db.clone()),
+            get(torrent_edit_page).with_state(context.clone()),
+        )
+        .route(
+            "/torrents/{id}/cover",
+            get(torrent_cover).with_state(context.clone()),
         )
         .route(
             "/torrents/{id}/edit",
@@ -104,8 +127,13 @@ pub async fn start_webserver(context: Context) -> Result<()> {
             "/torrents/{id}/{filename}",
             get(torrent_file).with_state(context.clone()),
         )
-        .route("/events", get(event_page).with_state(context.db.clone()))
+        .route("/events", get(event_page).with_state(context.clone()))
         .route("/search", get(search_page).with_state(context.clone()))
+        // The header search box, outside /api so it is guarded like the pages
+        .route(
+            "/search/global",
+            get(global_search_api).with_state(context.clone()),
+        )
         .route(
             "/search",
             post(search_page_post).with_state(context.clone()),
@@ -113,21 +141,21 @@ pub async fn start_webserver(context: Context) -> Result<()> {
         .route("/lists", get(lists_page).with_state(context.clone()))
         .route(
             "/lists/{list_id}",
-            get(list_page).with_state(context.db.clone()),
+            get(list_page).with_state(context.clone()),
         )
         .route(
             "/lists/{list_id}",
             post(list_page_post).with_state(context.db.clone()),
         )
-        .route("/errors", get(errors_page).with_state(context.db.clone()))
+        .route("/errors", get(errors_page).with_state(context.clone()))
         .route(
             "/errors",
-            post(errors_page_post).with_state(context.db.clone()),
+            post(errors_page_post).with_state(context.clone()),
         )
         .route("/selected", get(selected_page).with_state(context.clone()))
         .route(
             "/selected",
-            post(selected_torrents_page_post).with_state(context.db.clone()),
+            post(selected_torrents_page_post).with_state(context.clone()),
         )
         .route(
             "/replaced",
@@ -145,23 +173,103 @@ pub async fn start_webserver(context: Context) -> Result<()> {
             "/duplicate",
             post(duplicate_torrents_page_post).with_state(context.clone()),
         )
-        .route("/config", get(config_page).with_state(config.clone()))
+        .route(
+            "/duplicate/compare",
+            get(duplicate_compare_page).with_state(context.clone()),
+        )
+        .route(
+            "/library-duplicates",
+            get(library_duplicates_page).with_state(context.clone()),
+        )
+        .route(
+            "/library-duplicates",
+            post(library_duplicates_page_post).with_state(context.clone()),
+        )
+        .route("/rss", get(rss_feed).with_state(context.clone()))
+        .route(
+            "/series-gaps",
+            get(series_gaps_page).with_state(context.clone()),
+        )
+        .route(
+            "/unmatched-books",
+            get(unmatched_page).with_state(context.clone()),
+        )
+        .route(
+            "/unmatched-books",
+            post(unmatched_page_post).with_state(context.clone()),
+        )
+        .route(
+            "/untracked-files",
+            get(untracked_files_page).with_state(context.clone()),
+        )
+        .route(
+            "/untracked-files",
+            post(untracked_files_page_post).with_state(context.clone()),
+        )
+        .route("/config", get(config_page).with_state(context.clone()))
         .route(
             "/config",
             post(config_page_post).with_state(context.clone()),
         )
+        .route(
+            "/api/account",
+            get(account_api).with_state(context.clone()),
+        )
+        .route(
+            "/api/events",
+            get(events_api).with_state(context.clone()),
+        )
+        .route("/api/events/updates", get(events_updates))
+        .route(
+            "/api/filter/preview",
+            post(filter_preview_api).with_state(context.clone()),
+        )
+        .route(
+            "/api/maintenance",
+            get(maintenance_api).with_state(context.clone()),
+        )
+        .route(
+            "/api/maintenance",
+            post(maintenance_api_post).with_state(context.clone()),
+        )
+        .route(
+            "/api/search/global",
+            get(global_search_api).with_state(context.clone()),
+        )
         .route(
             "/api/search",
-            get(search_api).with_state(context.mam.clone()),
+            get(search_api).with_state(context.clone()),
         )
         .route(
             "/api/search",
             post(search_api_post).with_state(context.clone()),
         )
+        .route(
+            "/api/tasks",
+            get(tasks_api).with_state(context.clone()),
+        )
+        .route(
+            "/api/tasks",
+            post(tasks_api_post).with_state(context.clone()),
+        )
         .route(
             "/api/torrents/{id}",
             get(torrent_api).with_state(context.clone()),
-        )
+        );
+
+    #[cfg(feature = "graphql")]
+    let app = app.route(
+        "/graphql",
+        get(api::graphql::graphiql)
+            .post(api::graphql::graphql_api)
+            .with_state(api::graphql::schema(context.db.clone())),
+    );
+
+    let app = app
+        .layer(middleware::from_fn_with_state(
+            context.clone(),
+            auth::require_auth,
+        ))
         .nest_service(
             "/assets",
             ServiceBuilder::new()
@@ -179,9 +287,34 @@ pub async fn start_webserver(context: Context) -> Result<()> {
             )),
     );
 
-    let listener =
-        tokio::net::TcpListener::bind((config.web_host.clone(), config.web_port)).await?;
-    axum::serve(listener, app).await?;
+    match config.web_bind() {
+        WebBind::Tcp { host, port } => {
+            let listener = tokio::net::TcpListener::bind((host, port)).await?;
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await?;
+        }
+        #[cfg(unix)]
+        WebBind::Unix(path) => {
+            use std::os::unix::fs::FileTypeExt as _;
+
+            // A socket left behind by a previous run would make bind fail
+            if std::fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_socket()) {
+                std::fs::remove_file(&path)?;
+            }
+            let listener = tokio::net::UnixListener::bind(&path)?;
+            axum::serve(listener, app).await?;
+        }
+        #[cfg(not(unix))]
+        WebBind::Unix(path) => {
+            anyhow::bail!(
+                "Can not bind to {}, unix sockets are not supported on this platform",
+                path.display()
+            );
+        }
+    }
 
     Ok(())
 }
@@ -221,6 +354,25 @@ pub trait Page {
         env!("DATE")
     }
 
+    /// The `language` from the config the page was rendered with
+    fn language(&self) -> UiLanguage;
+
+    /// A UI label in the configured language
+    fn t<'a>(&self, key: &'a str) -> &'a str {
+        i18n::translate(self.language(), key)
+    }
+
+    /// The `timezone` from the config the page was rendered with, only pages
+    /// that show times need it
+    fn timezone(&self) -> Option<&'static Tz> {
+        None
+    }
+
+    /// A time in the configured timezone
+    fn time(&self, time: &Timestamp) -> String {
+        format_time(time, self.timezone())
+    }
+
     fn item_path(&self) -> &'static str {
         ""
     }
@@ -319,12 +471,25 @@ pub static TIME_FORMAT: Lazy<OwnedFormatItem> = Lazy::new(|| {
     format_description::parse_owned::<2>("[year]-[month]-[day] [hour]:[minute]:[second]").unwrap()
 });
 
-fn time(time: &Timestamp) -> String {
+/// Formats a time in `timezone`, without one the local timezone of the system
+/// is used, which is usually UTC in containers
+fn format_time(time: &Timestamp, timezone: Option<&Tz>) -> String {
+    let time = time.0.to_offset(UtcOffset::UTC);
+    match timezone {
+        Some(timezone) => time.to_timezone(timezone),
+        None => time.to_offset(UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC)),
+    }
+    .replace_nanosecond(0)
+    .unwrap()
+    .format(&TIME_FORMAT)
+    .unwrap_or_default()
+}
+
+/// Formats a time for RSS feeds, which always use RFC 2822 in UTC
+fn rss_time(time: &Timestamp) -> String {
     time.0
-        .to_offset(UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC))
-        .replace_nanosecond(0)
-        .unwrap()
-        .format(&TIME_FORMAT)
+        .to_offset(UtcOffset::UTC)
+        .format(&Rfc2822)
         .unwrap_or_default()
 }
 
@@ -404,6 +569,23 @@ pub fn flag_icons(meta: &TorrentMeta) -> FlagIconsTemplate {
     }
 }
 
+#[derive(Template)]
+#[template(path = "partials/meta_diff.html")]
+pub struct MetaDiffTemplate<'a> {
+    fields: &'a [TorrentMetaDiff],
+}
+impl<'a> HtmlSafe for MetaDiffTemplate<'a> {}
+
+/// The old and new value of each field an update changed
+pub fn meta_diff(fields: &[TorrentMetaDiff]) -> MetaDiffTemplate<'_> {
+    MetaDiffTemplate { fields }
+}
+
+/// The page of a list, encoded as the ids of some lists are URLs
+pub fn list_url(list_id: &str) -> String {
+    format!("/lists/{}", urlencoding::encode(list_id))
+}
+
 #[derive(Template)]
 #[template(path = "partials/cost_icon.html")]
 pub struct CostIconTemplate<'a> {
@@ -433,6 +615,26 @@ struct MaMTorrentsTemplate {
 }
 impl HtmlSafe for MaMTorrentsTemplate {}
 
+impl MaMTorrentsTemplate {
+    /// Why a result can't be grabbed, if it already has been
+    fn grab_status(
+        &self,
+        torrent: &Option<Torrent>,
+        selected_torrent: &Option<SelectedTorrent>,
+    ) -> Option<&'static str> {
+        if torrent.is_some() {
+            return Some("Downloaded");
+        }
+        match selected_torrent {
+            Some(selected) if selected.removed_at.is_none() => Some(match selected.started_at {
+                Some(_) => "Downloading",
+                None => "Queued",
+            }),
+            _ => None,
+        }
+    }
+}
+
 /// ```askama
 /// {% match template %}
 /// {% when Some(template) %}{{ template | safe }}
@@ -474,6 +676,8 @@ enum AppError {
     Toml(#[from] toml::de::Error),
     #[error("Error: {0:?}")]
     Generic(#[from] anyhow::Error),
+    #[error("Bad Request: {0}")]
+    BadRequest(String),
     #[error("Page Not Found")]
     NotFound,
 }
@@ -492,7 +696,10 @@ impl IntoResponse for AppError {
             _ => {}
         }
 
-        let status = StatusCode::INTERNAL_SERVER_ERROR;
+        let status = match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
         let tmpl = Tmpl { error: self };
         if let Ok(body) = tmpl.render() {
             (status, Html(body)).into_response()
@@ -501,3 +708,74 @@ impl IntoResponse for AppError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+    use time_tz::timezones;
+
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_format_time() {
+        let stockholm = timezones::get_by_name("Europe/Stockholm").unwrap();
+        let summer = Timestamp::from(datetime!(2024-07-01 12:30:15.5 UTC));
+        let winter = Timestamp::from(datetime!(2024-01-01 12:30:15 UTC));
+        assert_eq!(format_time(&summer, Some(stockholm)), "2024-07-01 14:30:15");
+        assert_eq!(format_time(&winter, Some(stockholm)), "2024-01-01 13:30:15");
+        assert_eq!(
+            format_time(&winter, timezones::get_by_name("America/New_York")),
+            "2024-01-01 07:30:15"
+        );
+    }
+
+    #[derive(Template)]
+    #[template(source = "{{ time(at) }}", ext = "html")]
+    struct TimeTemplate {
+        config: Config,
+        at: Timestamp,
+    }
+
+    impl Page for TimeTemplate {
+        fn language(&self) -> UiLanguage {
+            self.config.language
+        }
+
+        fn timezone(&self) -> Option<&'static Tz> {
+            self.config.timezone()
+        }
+    }
+
+    #[test]
+    fn test_page_time_uses_config_timezone() {
+        let at = Timestamp::from(datetime!(2024-07-01 12:30:15 UTC));
+        let config = |timezone| {
+            toml::from_str::<Config>(&format!("mam_id = \"abc\"\ntimezone = \"{timezone}\""))
+                .unwrap()
+        };
+        let page = |config| TimeTemplate { config, at }.render().unwrap();
+        assert_eq!(page(config("Europe/Stockholm")), "2024-07-01 14:30:15");
+        // Read from the config each page is rendered with, not the first one
+        assert_eq!(page(config("America/New_York")), "2024-07-01 08:30:15");
+    }
+
+    #[test]
+    fn test_list_url() {
+        assert_eq!(list_url("1234:to-read"), "/lists/1234%3Ato-read");
+        assert_eq!(
+            list_url("readarr:http://readarr:8787"),
+            "/lists/readarr%3Ahttp%3A%2F%2Freadarr%3A8787"
+        );
+        assert_eq!(
+            list_url("file:/data/lists/wanted.md"),
+            "/lists/file%3A%2Fdata%2Flists%2Fwanted.md"
+        );
+    }
+
+    #[test]
+    fn test_rss_time() {
+        let time = Timestamp::from(datetime!(2024-07-01 12:30:15.5 UTC));
+        assert_eq!(rss_time(&time), "Mon, 01 Jul 2024 12:30:15 +0000");
+    }
+}
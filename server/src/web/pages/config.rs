@@ -15,17 +15,20 @@ use tracing::{info, warn};
 
 use crate::{
     autograbber::update_torrent_meta,
-    config::{Config, Cost, Library, TorrentSearch, Type},
+    config::{Config, Cost, DownloadDirs, Library, TorrentSearch, Type, UiLanguage},
+    linker::update_library_mismatches,
     qbittorrent::ensure_category_exists,
     stats::Context,
     web::{AppError, Page, filter, yaml_items, yaml_nums},
 };
 
 pub async fn config_page(
-    State(config): State<Arc<Config>>,
+    State(context): State<Context>,
     Query(query): Query<ConfigPageQuery>,
 ) -> std::result::Result<Html<String>, AppError> {
+    let config = context.config().await;
     let template = ConfigPageTemplate {
+        problems: config.validate(),
         config,
         show_apply_tags: query.show_apply_tags.unwrap_or_default(),
     };
@@ -74,7 +77,7 @@ pub async fn config_page_post(
                             warn!("could not get torrent from mam");
                             continue;
                         };
-                        let new_meta = mam_torrent.as_meta()?;
+                        let new_meta = mam_torrent.as_meta(config.title_rules())?;
                         if new_meta != torrent.meta {
                             update_torrent_meta(
                                 &config,
@@ -116,6 +119,11 @@ pub async fn config_page_post(
                 }
             }
         }
+        "relink" => {
+            let mismatched = update_library_mismatches(&config, &context.db).await?;
+            info!("{mismatched} linked torrents differ from their library options");
+            return Ok(Redirect::to("/torrents?library_mismatch="));
+        }
         action => {
             eprintln!("unknown action: {action}");
         }
@@ -140,10 +148,15 @@ pub struct ConfigPageForm {
 #[template(path = "pages/config.html")]
 struct ConfigPageTemplate {
     config: Arc<Config>,
+    problems: Vec<String>,
     show_apply_tags: bool,
 }
 
-impl Page for ConfigPageTemplate {}
+impl Page for ConfigPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.config.language
+    }
+}
 
 impl TorrentSearch {
     fn mam_search(&self) -> String {
@@ -168,6 +181,7 @@ impl TorrentSearch {
             let search_type = match (self.kind, self.cost) {
                 (Type::Freeleech, _) => "fl",
                 (_, Cost::Free) => "fl-VIP",
+                (_, Cost::Vip) => "VIP",
                 _ => "all",
             };
             query.append_pair("tor[searchType]", search_type);
@@ -204,12 +218,9 @@ impl TorrentSearch {
                 query.append_pair("tor[browse_lang][]", &lang.to_id().to_string());
             }
 
-            let (flags_is_hide, flags) = self.filter.flags.as_search_bitfield();
-            if !flags.is_empty() {
-                query.append_pair(
-                    "tor[browseFlagsHideVsShow]",
-                    if flags_is_hide { "0" } else { "1" },
-                );
+            let (hide_vs_show, flags) = self.filter.flags.as_search_params();
+            if let Some(hide_vs_show) = hide_vs_show {
+                query.append_pair("tor[browseFlagsHideVsShow]", &hide_vs_show.to_string());
             }
             for flag in flags {
                 query.append_pair("tor[browseFlags][]", &flag.to_string());
@@ -5,20 +5,24 @@ use axum::{
     response::{Html, Redirect},
 };
 use axum_extra::extract::Form;
+use itertools::Itertools as _;
 use mlm_db::{
-    DatabaseExt as _, DuplicateTorrent, SelectedTorrent, Timestamp, Torrent, TorrentCost,
+    DatabaseExt as _, DuplicateTorrent, Flags, SelectedTorrent, Timestamp, Torrent, TorrentCost,
+    TorrentKey, TorrentMeta, impls::format_serie,
 };
-use mlm_parse::normalize_title;
+use mlm_mam::search::MaMTorrent;
 use serde::{Deserialize, Serialize};
+use time::UtcDateTime;
+use time_tz::Tz;
 use tracing::info;
 
 use crate::{
     cleaner::clean_torrent,
+    config::UiLanguage,
     stats::Context,
     web::{
-        AppError, Page,
+        AppError, Page, format_time,
         tables::{Key, SortOn, Sortable, table_styles_rows},
-        time,
     },
 };
 
@@ -81,6 +85,8 @@ pub async fn duplicate_page(
         torrents.push((torrent, duplicate));
     }
     let template = DuplicatePageTemplate {
+        language: config.language,
+        timezone: config.timezone(),
         abs_url: config.audiobookshelf.as_ref().map(|abs| abs.url.clone()),
         sort,
         torrents,
@@ -120,8 +126,8 @@ pub async fn duplicate_torrents_page_post(
                     );
                 };
 
-                let meta = mam_torrent.as_meta()?;
-                let title_search = normalize_title(&meta.title);
+                let meta = mam_torrent.as_meta(config.title_rules())?;
+                let title_search = config.title_rules().normalize_title(&meta.title);
                 let tags: Vec<_> = config
                     .tags
                     .iter()
@@ -171,9 +177,13 @@ pub async fn duplicate_torrents_page_post(
                         title_search,
                         meta,
                         grabber: None,
+                        account: None,
+                        priority: 0,
+                        note: None,
                         created_at: Timestamp::now(),
                         started_at: None,
                         removed_at: None,
+                        grab_skipped_at: None,
                     })?;
                     rw.remove(duplicate_torrent)?;
                     rw.commit()?;
@@ -199,6 +209,193 @@ pub async fn duplicate_torrents_page_post(
     Ok(Redirect::to(&uri.to_string()))
 }
 
+pub async fn duplicate_compare_page(
+    State(context): State<Context>,
+    Query(query): Query<DuplicateCompareQuery>,
+) -> std::result::Result<Html<String>, AppError> {
+    let config = context.config().await;
+    let a = compare_torrent(&context, &query.a).await?;
+    let b = compare_torrent(&context, &query.b).await?;
+    let template = DuplicateComparePageTemplate {
+        language: config.language,
+        abs_url: config.audiobookshelf.as_ref().map(|abs| abs.url.clone()),
+        rows: compare_rows(&a, &b, config.timezone()),
+        a,
+        b,
+    };
+    Ok::<_, AppError>(Html(template.to_string()))
+}
+
+/// Looks up a torrent by its id or MaM id, falling back to the duplicates
+/// that were not grabbed, along with its current data on MaM if available
+async fn compare_torrent(context: &Context, id: &str) -> Result<CompareTorrent, AppError> {
+    let (torrent, meta, created_at) = {
+        let r = context.db.r_transaction()?;
+        let mut torrent = r.get().primary::<Torrent>(id.to_string())?;
+        if torrent.is_none()
+            && let Ok(mam_id) = id.parse::<u64>()
+        {
+            torrent = r.get().secondary::<Torrent>(TorrentKey::mam_id, mam_id)?;
+        }
+        match torrent {
+            Some(torrent) => {
+                let meta = torrent.meta.clone();
+                let created_at = torrent.created_at;
+                (Some(torrent), meta, created_at)
+            }
+            None => {
+                let Ok(mam_id) = id.parse::<u64>() else {
+                    return Err(AppError::NotFound);
+                };
+                let Some(duplicate) = r.get().primary::<DuplicateTorrent>(mam_id)? else {
+                    return Err(AppError::NotFound);
+                };
+                (None, duplicate.meta, duplicate.created_at)
+            }
+        }
+    };
+    let mam_torrent = match context.mam() {
+        Ok(mam) => mam.get_torrent_info_by_id(meta.mam_id).await.ok().flatten(),
+        Err(_) => None,
+    };
+    Ok(CompareTorrent {
+        torrent,
+        meta,
+        created_at,
+        mam_torrent,
+    })
+}
+
+/// The fields shown side by side, rows where both torrents have no value are
+/// left out
+fn compare_rows(a: &CompareTorrent, b: &CompareTorrent, timezone: Option<&Tz>) -> Vec<CompareRow> {
+    fn mam(t: &CompareTorrent, value: fn(&MaMTorrent) -> String) -> String {
+        t.mam_torrent.as_ref().map(value).unwrap_or_default()
+    }
+    let row = |field, value: &dyn Fn(&CompareTorrent) -> String| CompareRow {
+        field,
+        a: value(a),
+        b: value(b),
+    };
+    [
+        row("Type", &|t| t.meta.media_type.to_string()),
+        row("Title", &|t| t.meta.title.clone()),
+        row("Edition", &|t| {
+            t.meta
+                .edition
+                .as_ref()
+                .map(|(edition, _)| edition.clone())
+                .unwrap_or_default()
+        }),
+        row("Authors", &|t| t.meta.authors.join(", ")),
+        row("Narrators", &|t| t.meta.narrators.join(", ")),
+        row("Series", &|t| {
+            t.meta.series.iter().map(format_serie).join(", ")
+        }),
+        row("Language", &|t| {
+            t.meta
+                .language
+                .map(|language| language.to_str().to_string())
+                .unwrap_or_default()
+        }),
+        row("Flags", &|t| {
+            t.meta
+                .flags
+                .map(|flags| Flags::from(flags).to_string())
+                .unwrap_or_default()
+        }),
+        row("Filetypes", &|t| t.meta.filetypes.join(", ")),
+        row("Files", &|t| t.meta.num_files.to_string()),
+        row("Size", &|t| t.meta.size.to_string()),
+        row("Audio", &|t| {
+            mam(t, |m| {
+                m.media_info
+                    .as_ref()
+                    .map(|info| {
+                        format!(
+                            "{} {} {}",
+                            info.audio.format, info.audio.bitrate, info.audio.mode
+                        )
+                        .trim()
+                        .to_string()
+                    })
+                    .unwrap_or_default()
+            })
+        }),
+        row("Duration", &|t| {
+            mam(t, |m| {
+                m.media_info
+                    .as_ref()
+                    .map(|info| info.general.duration.clone())
+                    .unwrap_or_default()
+            })
+        }),
+        row("Seeders", &|t| mam(t, |m| m.seeders.to_string())),
+        row("Snatches", &|t| mam(t, |m| m.times_completed.to_string())),
+        row("Uploaded", &|t| {
+            if t.meta.uploaded_at.0 == UtcDateTime::UNIX_EPOCH {
+                mam(t, |m| m.added.clone())
+            } else {
+                format_time(&t.meta.uploaded_at, timezone)
+            }
+        }),
+        row("Added", &|t| format_time(&t.created_at, timezone)),
+        row("Library", &|t| {
+            t.torrent
+                .as_ref()
+                .and_then(|t| t.library_path.as_ref())
+                .map(|path| path.display().to_string())
+                .unwrap_or_default()
+        }),
+    ]
+    .into_iter()
+    .filter(|row| !row.a.is_empty() || !row.b.is_empty())
+    .collect()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DuplicateCompareQuery {
+    a: String,
+    b: String,
+}
+
+/// A torrent in the library, or a duplicate that was not grabbed when
+/// `torrent` is `None`
+struct CompareTorrent {
+    torrent: Option<Torrent>,
+    meta: TorrentMeta,
+    created_at: Timestamp,
+    mam_torrent: Option<MaMTorrent>,
+}
+
+struct CompareRow {
+    field: &'static str,
+    a: String,
+    b: String,
+}
+
+impl CompareRow {
+    fn differs(&self) -> bool {
+        self.a != self.b
+    }
+}
+
+#[derive(Template)]
+#[template(path = "pages/duplicate_compare.html")]
+struct DuplicateComparePageTemplate {
+    language: UiLanguage,
+    abs_url: Option<String>,
+    a: CompareTorrent,
+    b: CompareTorrent,
+    rows: Vec<CompareRow>,
+}
+
+impl Page for DuplicateComparePageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct TorrentsPageForm {
     action: String,
@@ -209,12 +406,22 @@ pub struct TorrentsPageForm {
 #[derive(Template)]
 #[template(path = "pages/duplicate.html")]
 struct DuplicatePageTemplate {
+    language: UiLanguage,
+    timezone: Option<&'static Tz>,
     abs_url: Option<String>,
     sort: SortOn<DuplicatePageSort>,
     torrents: Vec<(DuplicateTorrent, Torrent)>,
 }
 
-impl Page for DuplicatePageTemplate {}
+impl Page for DuplicatePageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+
+    fn timezone(&self) -> Option<&'static Tz> {
+        self.timezone
+    }
+}
 
 #[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -253,3 +460,53 @@ impl Sortable for DuplicatePageTemplate {
         self.sort
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mlm_db::{Language, Size};
+
+    use super::*;
+    use crate::test_helpers::template_meta;
+
+    fn side(size: u64, edition: Option<(&str, u64)>) -> CompareTorrent {
+        let created_at = Timestamp(UtcDateTime::UNIX_EPOCH);
+        CompareTorrent {
+            torrent: None,
+            meta: TorrentMeta {
+                mam_id: 1,
+                main_cat: None,
+                language: Some(Language::English),
+                filetypes: vec!["m4b".to_string()],
+                num_files: 1,
+                size: Size::from_bytes(size),
+                title: "Title".to_string(),
+                edition: edition.map(|(edition, n)| (edition.to_string(), n)),
+                authors: vec!["Author".to_string()],
+                uploaded_at: created_at,
+                ..template_meta()
+            },
+            created_at,
+            mam_torrent: None,
+        }
+    }
+
+    #[test]
+    fn test_compare_rows() {
+        let a = side(1024 * 1024, None);
+        let b = side(2048 * 1024, Some(("2nd Edition", 2)));
+        let rows = compare_rows(&a, &b, None);
+
+        let differs = rows
+            .iter()
+            .filter(|row| row.differs())
+            .map(|row| row.field)
+            .collect::<Vec<_>>();
+        assert_eq!(differs, vec!["Edition", "Size"]);
+
+        let fields = rows.iter().map(|row| row.field).collect::<Vec<_>>();
+        assert!(fields.contains(&"Title"));
+        assert!(!fields.contains(&"Narrators"));
+        assert!(!fields.contains(&"Seeders"));
+        assert!(!fields.contains(&"Uploaded"));
+    }
+}
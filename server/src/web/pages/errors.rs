@@ -1,7 +1,4 @@
-use std::{
-    cell::{Ref, RefCell},
-    sync::Arc,
-};
+use std::cell::{Ref, RefCell};
 
 use askama::Template;
 use axum::{
@@ -9,21 +6,29 @@ use axum::{
     response::{Html, Redirect},
 };
 use axum_extra::extract::Form;
-use mlm_db::{DatabaseExt as _, ErroredTorrent, ErroredTorrentId, ErroredTorrentKey};
-use native_db::Database;
+use mlm_db::{
+    DatabaseExt as _, ErroredTorrent, ErroredTorrentId, ErroredTorrentKey, SelectedTorrent,
+    Timestamp,
+};
 use serde::{Deserialize, Serialize};
-
-use crate::web::{
-    AppError, Page,
-    tables::{self, Flex, HidableColumns, Key, SortOn, Sortable},
-    time,
+use time_tz::Tz;
+
+use crate::{
+    config::UiLanguage,
+    stats::Context,
+    web::{
+        AppError, Page,
+        tables::{self, Flex, HidableColumns, Key, SortOn, Sortable},
+    },
 };
 
 pub async fn errors_page(
-    State(db): State<Arc<Database<'static>>>,
+    State(context): State<Context>,
     Query(sort): Query<SortOn<ErrorsPageSort>>,
     Query(filter): Query<Vec<(ErrorsPageFilter, String)>>,
 ) -> std::result::Result<Html<String>, AppError> {
+    let config = context.config().await;
+    let db = &context.db;
     let mut errored_torrents = db
         .r_transaction()?
         .scan()
@@ -54,12 +59,16 @@ pub async fn errors_page(
                 ErrorsPageSort::Step => step(&a.id).cmp(step(&b.id)),
                 ErrorsPageSort::Title => a.title.cmp(&b.title),
                 ErrorsPageSort::Error => a.error.cmp(&b.error),
+                ErrorsPageSort::Attempts => a.attempts.cmp(&b.attempts),
+                ErrorsPageSort::NextRetryAt => a.next_retry_at.cmp(&b.next_retry_at),
                 ErrorsPageSort::CreatedAt => a.created_at.cmp(&b.created_at),
             };
             if sort.asc { ord.reverse() } else { ord }
         });
     }
     let template = ErrorsPageTemplate {
+        language: config.language,
+        timezone: config.timezone(),
         sort,
         cols: Default::default(),
         errors: errored_torrents,
@@ -68,7 +77,7 @@ pub async fn errors_page(
 }
 
 pub async fn errors_page_post(
-    State(db): State<Arc<Database<'static>>>,
+    State(context): State<Context>,
     uri: OriginalUri,
     Form(form): Form<ErrorPageForm>,
 ) -> Result<Redirect, AppError> {
@@ -78,7 +87,7 @@ pub async fn errors_page_post(
                 let Ok(error) = serde_json::from_str::<ErroredTorrentId>(&error) else {
                     return Err(anyhow::Error::msg("Could not parse error").into());
                 };
-                let (_guard, rw) = db.rw_async().await?;
+                let (_guard, rw) = context.db.rw_async().await?;
                 let Some(error) = rw.get().primary::<ErroredTorrent>(error)? else {
                     return Err(anyhow::Error::msg("Could not find error").into());
                 };
@@ -86,6 +95,53 @@ pub async fn errors_page_post(
                 rw.commit()?;
             }
         }
+        "retry" => {
+            // The error row is cleared or updated by the step itself when it runs again
+            let mut run_downloader = false;
+            let mut run_linker = false;
+            for error in form.errors {
+                let Ok(error) = serde_json::from_str::<ErroredTorrentId>(&error) else {
+                    return Err(anyhow::Error::msg("Could not parse error").into());
+                };
+                {
+                    let (_guard, rw) = context.db.rw_async().await?;
+                    if let Some(mut row) = rw.get().primary::<ErroredTorrent>(error.clone())? {
+                        // A retry by hand gets the full number of attempts again
+                        row.attempts = 0;
+                        row.failures = 0;
+                        row.next_retry_at = Some(Timestamp::now());
+                        rw.upsert(row)?;
+                        rw.commit()?;
+                    }
+                }
+                match error {
+                    ErroredTorrentId::Grabber(mam_id) => {
+                        let selected = context
+                            .db
+                            .r_transaction()?
+                            .get()
+                            .primary::<SelectedTorrent>(mam_id)?;
+                        if selected.is_none_or(|t| t.removed_at.is_some()) {
+                            return Err(anyhow::Error::msg(format!(
+                                "Torrent {mam_id} is no longer selected, select it again to retry"
+                            ))
+                            .into());
+                        }
+                        run_downloader = true;
+                    }
+                    // The cleaner runs right after the linker
+                    ErroredTorrentId::Linker(_) | ErroredTorrentId::Cleaner(_) => {
+                        run_linker = true;
+                    }
+                }
+            }
+            if run_downloader {
+                context.triggers.downloader_tx.send(())?;
+            }
+            if run_linker {
+                context.triggers.linker_tx.send(())?;
+            }
+        }
         action => {
             eprintln!("unknown action: {action}");
         }
@@ -104,12 +160,31 @@ pub struct ErrorPageForm {
 #[derive(Template)]
 #[template(path = "pages/errors.html")]
 struct ErrorsPageTemplate {
+    language: UiLanguage,
+    timezone: Option<&'static Tz>,
     sort: SortOn<ErrorsPageSort>,
     cols: RefCell<Vec<Box<dyn tables::Size>>>,
     errors: Vec<ErroredTorrent>,
 }
 
-impl Page for ErrorsPageTemplate {}
+impl ErrorsPageTemplate {
+    fn steps(&self) -> Vec<&'static str> {
+        let mut steps = self.errors.iter().map(|e| step(&e.id)).collect::<Vec<_>>();
+        steps.sort_unstable();
+        steps.dedup();
+        steps
+    }
+}
+
+impl Page for ErrorsPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+
+    fn timezone(&self) -> Option<&'static Tz> {
+        self.timezone
+    }
+}
 
 #[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -117,6 +192,8 @@ pub enum ErrorsPageSort {
     Step,
     Title,
     Error,
+    Attempts,
+    NextRetryAt,
     CreatedAt,
 }
 
@@ -151,7 +228,7 @@ impl HidableColumns for ErrorsPageTemplate {
     }
 }
 
-pub fn step(id: &ErroredTorrentId) -> &str {
+pub fn step(id: &ErroredTorrentId) -> &'static str {
     match id {
         ErroredTorrentId::Grabber(_) => "auto grabber",
         ErroredTorrentId::Linker(_) => "library linker",
@@ -1,26 +1,29 @@
-use std::sync::Arc;
-
 use askama::Template;
 use axum::{
     extract::{OriginalUri, Query, State},
     response::{Html, IntoResponse, Response},
 };
 use mlm_db::{Event, EventKey, EventType, Torrent, TorrentCost, TorrentKey};
-use native_db::Database;
 use serde::{Deserialize, Serialize};
+use time_tz::Tz;
 
-use crate::web::{
-    AppError, Conditional, Page, TorrentLink,
-    tables::{Key, Pagination, PaginationParams, table_styles},
-    time,
+use crate::{
+    config::UiLanguage,
+    stats::Context,
+    web::{
+        AppError, Conditional, Page, TorrentLink, meta_diff,
+        tables::{Key, Pagination, PaginationParams, table_styles},
+    },
 };
 
 pub async fn event_page(
-    State(db): State<Arc<Database<'static>>>,
+    State(context): State<Context>,
     uri: OriginalUri,
     Query(filter): Query<Vec<(EventPageFilter, String)>>,
     Query(paging): Query<PaginationParams>,
 ) -> std::result::Result<Response, AppError> {
+    let config = context.config().await;
+    let db = &context.db;
     let r = db.r_transaction()?;
     let events = r.scan().secondary::<Event>(EventKey::created_at)?;
     let event_count = r.len().secondary::<Event>(EventKey::created_at)?;
@@ -31,15 +34,10 @@ pub async fn event_page(
         };
         for (field, value) in filter.iter() {
             let ok = match field {
-                EventPageFilter::Show => match t.event {
-                    EventType::Grabbed { .. } => value == "grabber",
-                    EventType::Linked { .. } => value == "linker",
-                    EventType::Cleaned { .. } => value == "cleaner",
-                    EventType::Updated { .. } => value == "updated",
-                    EventType::RemovedFromMam { .. } => value == "removed",
-                },
+                EventPageFilter::Show => event_kind(&t.event) == value,
                 EventPageFilter::Grabber => match t.event {
-                    EventType::Grabbed { ref grabber, .. } => {
+                    EventType::Selected { ref grabber, .. }
+                    | EventType::Grabbed { ref grabber, .. } => {
                         if value.is_empty() {
                             grabber.is_none()
                         } else {
@@ -150,6 +148,8 @@ pub async fn event_page(
             events.collect()
         };
     let template = EventPageTemplate {
+        language: config.language,
+        timezone: config.timezone(),
         paging: paging.unwrap_or_default(),
         show: filter.iter().find_map(|f| {
             if f.0 == EventPageFilter::Show {
@@ -163,16 +163,45 @@ pub async fn event_page(
     Ok::<_, AppError>(Html(template.to_string()).into_response())
 }
 
+/// The name used for a type of event in the `show` filter and the events API
+pub fn event_kind(event: &EventType) -> &'static str {
+    match event {
+        EventType::Selected { .. } | EventType::SelectionExpired { .. } => "selected",
+        EventType::Grabbed { .. } | EventType::Verified { .. } | EventType::GrabSkipped { .. } => {
+            "grabber"
+        }
+        EventType::Linked { .. }
+        | EventType::Converted { .. }
+        | EventType::MetadataPending { .. } => "linker",
+        EventType::Cleaned { .. } => "cleaner",
+        EventType::Updated { .. } | EventType::IdsAdded { .. } => "updated",
+        EventType::RemovedFromMam => "removed",
+        EventType::AbsUpdated { .. } => "abs",
+        EventType::FilesMissing { .. } => "audit",
+        EventType::HookFailed { .. } => "hook",
+    }
+}
+
 type EventWithTorrent = (Event, Option<Torrent>, Option<Torrent>);
 #[derive(Template)]
 #[template(path = "pages/events.html")]
 struct EventPageTemplate<'a> {
+    language: UiLanguage,
+    timezone: Option<&'static Tz>,
     paging: Pagination,
     show: Option<&'a str>,
     events: Vec<EventWithTorrent>,
 }
 
-impl<'a> Page for EventPageTemplate<'a> {}
+impl<'a> Page for EventPageTemplate<'a> {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+
+    fn timezone(&self) -> Option<&'static Tz> {
+        self.timezone
+    }
+}
 
 impl<'a> EventPageTemplate<'a> {
     fn torrent_title(&'a self, torrent: &'a Option<Torrent>) -> Conditional<TorrentLink<'a>> {
@@ -1,4 +1,9 @@
-use std::{collections::BTreeMap, convert::Infallible, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::Infallible,
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Result;
 use askama::Template;
@@ -11,32 +16,67 @@ use axum::{
 };
 use axum_extra::extract::Form;
 use futures::Stream;
-use mlm_db::Timestamp;
+use mlm_db::{TaskError, Timestamp};
 use serde::Deserialize;
+use time::UtcDateTime;
+use time_tz::Tz;
 use tokio_stream::{StreamExt as _, wrappers::WatchStream};
 
 use crate::{
-    config::{Config, TorrentFilter},
+    config::{Config, TorrentFilter, UiLanguage},
     lists::{List, get_lists},
-    stats::Context,
-    web::{AppError, Page, time},
+    paused_tasks::{list_task_key, paused_tasks, search_task_key, set_paused},
+    stats::{AccountStatus, Context, Progress},
+    task_errors::task_errors,
+    web::{AppError, Page, api::maintenance::set_maintenance},
 };
 
 pub async fn index_page(
     State(context): State<Context>,
 ) -> std::result::Result<Html<String>, AppError> {
-    let stats = context.stats.values.lock().await;
-    let username = match context.mam.as_ref() {
-        Ok(mam) => mam.cached_user_info().await.map(|u| u.username),
+    let config = context.config().await;
+    let mam = context.mam();
+    let account = match &mam {
+        Ok(mam) => mam
+            .user_info()
+            .await
+            .ok()
+            .map(|user_info| AccountStatus::new(&config, &user_info)),
         Err(_) => None,
     };
-    let config = context.config.lock().await;
+    let paused = paused_tasks(&context.db)?;
+    let lists = get_lists(&config);
+    let paused_searches = (0..config.autograbs.len() + config.snatchlist.len())
+        .filter(|&i| search_task_key(&config, i).is_some_and(|key| paused.contains(&key)))
+        .collect();
+    let paused_imports = lists
+        .iter()
+        .enumerate()
+        .filter(|(i, list)| paused.contains(&list_task_key(list, *i)))
+        .map(|(i, _)| i)
+        .collect();
+    let mut task_errors = task_errors(&context.db)?;
+    let search_errors = (0..config.autograbs.len() + config.snatchlist.len())
+        .filter_map(|i| Some((i, task_errors.remove(&search_task_key(&config, i)?)?)))
+        .collect();
+    let import_errors = lists
+        .iter()
+        .enumerate()
+        .filter_map(|(i, list)| Some((i, task_errors.remove(&list_task_key(list, i))?)))
+        .collect();
+    let stats = context.stats.values.lock().await;
     let template = IndexPageTemplate {
         config: config.clone(),
-        lists: get_lists(&config),
-        mam_error: context.mam.as_ref().as_ref().err().map(|e| format!("{e}")),
-        has_no_qbits: config.qbittorrent.is_empty(),
-        username,
+        lists,
+        mam_error: mam.as_ref().err().map(|e| format!("{e}")),
+        has_no_clients: !config.has_download_client(),
+        maintenance: context.stats.maintenance(),
+        account,
+        paused_searches,
+        paused_imports,
+        search_errors,
+        import_errors,
+        task_errors,
         autograbber_run_at: stats
             .autograbber_run_at
             .iter()
@@ -47,6 +87,7 @@ pub async fn index_page(
             .iter()
             .map(|(i, r)| (*i, r.as_ref().map(|_| ()).map_err(|e| format!("{e:?}"))))
             .collect(),
+        autograbber_stopped: stats.autograbber_stopped.clone(),
         import_run_at: stats
             .import_run_at
             .iter()
@@ -57,11 +98,13 @@ pub async fn index_page(
             .iter()
             .map(|(i, r)| (*i, r.as_ref().map(|_| ()).map_err(|e| format!("{e:?}"))))
             .collect(),
+        import_progress: stats.import_progress.clone(),
         linker_run_at: stats.linker_run_at.map(Into::into),
         linker_result: stats
             .linker_result
             .as_ref()
             .map(|r| r.as_ref().map(|_| ()).map_err(|e| format!("{e:?}"))),
+        linker_progress: stats.linker_progress,
         cleaner_run_at: stats.cleaner_run_at.map(Into::into),
         cleaner_result: stats
             .cleaner_result
@@ -77,6 +120,11 @@ pub async fn index_page(
             .audiobookshelf_result
             .as_ref()
             .map(|r| r.as_ref().map(|_| ()).map_err(|e| format!("{e:?}"))),
+        audit_run_at: stats.audit_run_at.map(Into::into),
+        audit_result: stats
+            .audit_result
+            .as_ref()
+            .map(|r| r.as_ref().map(|_| ()).map_err(|e| format!("{e:?}"))),
     };
     Ok::<_, AppError>(Html(template.to_string()))
 }
@@ -126,6 +174,30 @@ pub async fn index_page_post(
         "run_abs_matcher" => {
             context.triggers.audiobookshelf_tx.send(())?;
         }
+        "run_audit" => {
+            context.triggers.audit_tx.send(())?;
+        }
+        "pause_search" | "resume_search" => {
+            let config = context.config().await;
+            let key = form
+                .index
+                .and_then(|i| search_task_key(&config, i))
+                .ok_or_else(|| anyhow::Error::msg("Invalid index"))?;
+            set_paused(&context.db, &key, form.action == "pause_search").await?;
+            context.stats.update(|_| ()).await;
+        }
+        "pause_import" | "resume_import" => {
+            let config = context.config().await;
+            let lists = get_lists(&config);
+            let key = form
+                .index
+                .and_then(|i| lists.get(i).map(|list| list_task_key(list, i)))
+                .ok_or_else(|| anyhow::Error::msg("Invalid index"))?;
+            set_paused(&context.db, &key, form.action == "pause_import").await?;
+            context.stats.update(|_| ()).await;
+        }
+        "start_maintenance" => set_maintenance(&context, true),
+        "stop_maintenance" => set_maintenance(&context, false),
         action => {
             eprintln!("unknown action: {action}");
         }
@@ -140,23 +212,43 @@ struct IndexPageTemplate {
     config: Arc<Config>,
     lists: Vec<List>,
     mam_error: Option<String>,
-    has_no_qbits: bool,
-    username: Option<String>,
+    has_no_clients: bool,
+    maintenance: bool,
+    account: Option<AccountStatus>,
+    paused_searches: BTreeSet<usize>,
+    paused_imports: BTreeSet<usize>,
+    /// The last errors kept in the db, by index or by task for the others
+    search_errors: BTreeMap<usize, TaskError>,
+    import_errors: BTreeMap<usize, TaskError>,
+    task_errors: BTreeMap<String, TaskError>,
     autograbber_run_at: BTreeMap<usize, Timestamp>,
     autograbber_result: BTreeMap<usize, Result<(), String>>,
+    autograbber_stopped: BTreeMap<usize, String>,
     import_run_at: BTreeMap<usize, Timestamp>,
     import_result: BTreeMap<usize, Result<(), String>>,
+    import_progress: BTreeMap<usize, Progress>,
     linker_run_at: Option<Timestamp>,
     linker_result: Option<Result<(), String>>,
+    linker_progress: Option<Progress>,
     cleaner_run_at: Option<Timestamp>,
     cleaner_result: Option<Result<(), String>>,
     downloader_run_at: Option<Timestamp>,
     downloader_result: Option<Result<(), String>>,
     audiobookshelf_run_at: Option<Timestamp>,
     audiobookshelf_result: Option<Result<(), String>>,
+    audit_run_at: Option<Timestamp>,
+    audit_result: Option<Result<(), String>>,
 }
 
-impl Page for IndexPageTemplate {}
+impl Page for IndexPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.config.language
+    }
+
+    fn timezone(&self) -> Option<&'static Tz> {
+        self.config.timezone()
+    }
+}
 
 #[derive(Debug, Deserialize)]
 pub struct IndexPageForm {
@@ -169,3 +261,17 @@ impl TorrentFilter {
         self.name.clone().unwrap_or_else(|| format!("{i}"))
     }
 }
+
+/// How far a running task has come and roughly how long it has left
+fn progress_text(progress: &Progress) -> String {
+    let mut text = format!("{}/{}", progress.done, progress.total);
+    if let Some(eta) = progress.eta(UtcDateTime::now()) {
+        let minutes = eta.as_secs().div_ceil(60);
+        if minutes > 1 {
+            text.push_str(&format!(", about {minutes} minutes left"));
+        } else {
+            text.push_str(", less than a minute left");
+        }
+    }
+    text
+}
@@ -0,0 +1,92 @@
+use askama::Template;
+use axum::{
+    extract::{OriginalUri, State},
+    response::{Html, Redirect},
+};
+use axum_extra::extract::Form;
+use mlm_db::Torrent;
+use serde::Deserialize;
+
+use crate::{
+    cleaner::{LibraryDuplicates, find_library_duplicates, replace_torrents},
+    config::UiLanguage,
+    stats::Context,
+    web::{AppError, Page},
+};
+
+pub async fn library_duplicates_page(
+    State(context): State<Context>,
+) -> std::result::Result<Html<String>, AppError> {
+    let config = context.config().await;
+    let duplicates = find_library_duplicates(&config, &context.db)?;
+    let template = LibraryDuplicatesPageTemplate {
+        language: config.language,
+        abs_url: config.audiobookshelf.as_ref().map(|abs| abs.url.clone()),
+        duplicates,
+    };
+    Ok::<_, AppError>(Html(template.to_string()))
+}
+
+pub async fn library_duplicates_page_post(
+    State(context): State<Context>,
+    uri: OriginalUri,
+    Form(form): Form<LibraryDuplicatesPageForm>,
+) -> Result<Redirect, AppError> {
+    let config = context.config().await;
+    match form.action.as_str() {
+        "keep-best" => {
+            let duplicates = find_library_duplicates(&config, &context.db)?;
+            for group in duplicates {
+                if !group
+                    .torrents
+                    .first()
+                    .is_some_and(|(t, _)| form.groups.contains(&t.id))
+                {
+                    continue;
+                }
+                let mut keep: Option<Torrent> = None;
+                let mut remove = vec![];
+                for (torrent, best) in group.torrents {
+                    if best {
+                        if let Some(keep) = keep.take() {
+                            replace_torrents(&config, &context.db, &keep, remove).await;
+                        }
+                        keep = Some(torrent);
+                        remove = vec![];
+                    } else {
+                        remove.push(torrent);
+                    }
+                }
+                if let Some(keep) = keep {
+                    replace_torrents(&config, &context.db, &keep, remove).await;
+                }
+            }
+        }
+        action => {
+            eprintln!("unknown action: {action}");
+        }
+    }
+
+    Ok(Redirect::to(&uri.to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LibraryDuplicatesPageForm {
+    action: String,
+    #[serde(default, rename = "group")]
+    groups: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/library_duplicates.html")]
+struct LibraryDuplicatesPageTemplate {
+    language: UiLanguage,
+    abs_url: Option<String>,
+    duplicates: Vec<LibraryDuplicates>,
+}
+
+impl Page for LibraryDuplicatesPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+}
@@ -13,14 +13,21 @@ use mlm_db::{
 use native_db::Database;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
+use time_tz::Tz;
 
-use crate::web::{AppError, Page, time};
+use crate::{
+    config::UiLanguage,
+    stats::Context,
+    web::{AppError, Page},
+};
 
 pub async fn list_page(
-    State(db): State<Arc<Database<'static>>>,
+    State(context): State<Context>,
     Path(list_id): Path<String>,
     Query(filter): Query<Vec<(ListPageFilter, String)>>,
 ) -> std::result::Result<Html<String>, AppError> {
+    let config = context.config().await;
+    let db = &context.db;
     let Some(list) = db.r_transaction()?.get().primary::<List>(list_id)? else {
         return Err(AppError::NotFound);
     };
@@ -53,6 +60,8 @@ pub async fn list_page(
         })
         .collect::<Result<Vec<_>, native_db::db_type::Error>>()?;
     let template = ListPageTemplate {
+        language: config.language,
+        timezone: config.timezone(),
         show: filter.iter().find_map(|f| {
             if f.0 == ListPageFilter::Show {
                 Some(f.1.as_str())
@@ -99,12 +108,22 @@ pub struct ListPageForm {
 #[derive(Template)]
 #[template(path = "pages/list.html")]
 struct ListPageTemplate<'a> {
+    language: UiLanguage,
+    timezone: Option<&'static Tz>,
     show: Option<&'a str>,
     list: List,
     items: Vec<ListItem>,
 }
 
-impl<'a> Page for ListPageTemplate<'a> {}
+impl<'a> Page for ListPageTemplate<'a> {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+
+    fn timezone(&self) -> Option<&'static Tz> {
+        self.timezone
+    }
+}
 
 #[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -1,12 +1,13 @@
 use askama::Template;
 use axum::{extract::State, response::Html};
 use itertools::Itertools as _;
-use mlm_db::{List, ListKey};
+use mlm_db::{List, ListItem, ListKey};
+use time_tz::Tz;
 
 use crate::{
-    config::GoodreadsList,
+    config::UiLanguage,
     stats::Context,
-    web::{AppError, Page, time},
+    web::{AppError, Page, list_url},
 };
 
 pub async fn lists_page(
@@ -23,27 +24,65 @@ pub async fn lists_page(
         .collect::<Result<Vec<_>, native_db::db_type::Error>>()?;
     let mut lists = vec![];
 
-    for list in config.goodreads_lists.iter() {
-        let id = list.list_id()?;
+    let list_ids = config
+        .goodreads_lists
+        .iter()
+        .map(|list| Ok((list.name.clone(), list.list_id()?)))
+        .chain(
+            config
+                .notion_lists
+                .iter()
+                .filter(|list| list.title_property.is_some())
+                .map(|list| Ok((Some(list.name.clone()), list.list_id()))),
+        )
+        .chain(
+            config
+                .readarr_lists
+                .iter()
+                .map(|list| Ok((list.name.clone(), list.list_id()))),
+        )
+        .chain(
+            config
+                .file_lists
+                .iter()
+                .map(|list| Ok((list.name.clone(), list.list_id()))),
+        )
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    for (name, id) in list_ids {
         if let Some((index, _)) = db_lists.iter().find_position(|db_list| db_list.id == id) {
             let db_list = db_lists.remove(index);
-            lists.push((list.clone(), db_list));
+            lists.push((name, db_list));
         } else {
             lists.push((
-                list.clone(),
+                name,
                 List {
                     id: id.clone(),
                     title: id,
                     updated_at: None,
                     build_date: None,
+                    etag: None,
+                    last_modified: None,
                 },
             ));
         }
     }
 
+    let r = context.db.r_transaction()?;
+    let mut unmatched = 0;
+    for item in r.scan().primary::<ListItem>()?.all()? {
+        let item = item?;
+        if !item.candidates.is_empty() && (item.want_audio() || item.want_ebook()) {
+            unmatched += 1;
+        }
+    }
+
     let template = ListsPageTemplate {
+        language: config.language,
+        timezone: config.timezone(),
         lists,
         inactive_lists: db_lists,
+        unmatched,
     };
     Ok::<_, AppError>(Html(template.to_string()))
 }
@@ -51,8 +90,19 @@ pub async fn lists_page(
 #[derive(Template)]
 #[template(path = "pages/lists.html")]
 struct ListsPageTemplate {
-    lists: Vec<(GoodreadsList, List)>,
+    language: UiLanguage,
+    timezone: Option<&'static Tz>,
+    lists: Vec<(Option<String>, List)>,
     inactive_lists: Vec<List>,
+    unmatched: usize,
 }
 
-impl Page for ListsPageTemplate {}
+impl Page for ListsPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+
+    fn timezone(&self) -> Option<&'static Tz> {
+        self.timezone
+    }
+}
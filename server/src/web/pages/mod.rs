@@ -3,11 +3,16 @@ pub mod duplicate;
 pub mod errors;
 pub mod events;
 pub mod index;
+pub mod library_duplicates;
 pub mod list;
 pub mod lists;
 pub mod replaced;
+pub mod rss;
 pub mod search;
 pub mod selected;
+pub mod series_gaps;
 pub mod torrent;
 pub mod torrent_edit;
 pub mod torrents;
+pub mod unmatched;
+pub mod untracked_files;
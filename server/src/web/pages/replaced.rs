@@ -12,7 +12,9 @@ use axum::{
 use axum_extra::extract::Form;
 use mlm_db::{Language, Torrent, TorrentKey};
 use serde::{Deserialize, Serialize};
+use time_tz::Tz;
 
+use crate::config::UiLanguage;
 use crate::stats::Context;
 use crate::web::{Page, tables};
 use crate::{
@@ -20,7 +22,6 @@ use crate::{
     web::{
         AppError,
         tables::{Flex, HidableColumns, Key, Pagination, PaginationParams, SortOn, Sortable},
-        time,
     },
 };
 use mlm_db::DatabaseExt as _;
@@ -121,6 +122,8 @@ pub async fn replaced_torrents_page(
     }
 
     let template = ReplacedTorrentsPageTemplate {
+        language: config.language,
+        timezone: config.timezone(),
         abs_url: config.audiobookshelf.as_ref().map(|abs| abs.url.clone()),
         paging: paging.unwrap_or_default(),
         sort,
@@ -178,6 +181,8 @@ pub struct TorrentsPageForm {
 #[derive(Template)]
 #[template(path = "pages/replaced.html")]
 struct ReplacedTorrentsPageTemplate {
+    language: UiLanguage,
+    timezone: Option<&'static Tz>,
     abs_url: Option<String>,
     paging: Pagination,
     sort: SortOn<TorrentsPageSort>,
@@ -186,7 +191,15 @@ struct ReplacedTorrentsPageTemplate {
     torrents: Vec<(Torrent, Torrent)>,
 }
 
-impl Page for ReplacedTorrentsPageTemplate {}
+impl Page for ReplacedTorrentsPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+
+    fn timezone(&self) -> Option<&'static Tz> {
+        self.timezone
+    }
+}
 
 #[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
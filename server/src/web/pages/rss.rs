@@ -0,0 +1,168 @@
+use std::str::FromStr;
+
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, header},
+    response::IntoResponse,
+};
+use mlm_db::{MediaType, Torrent, TorrentKey};
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    stats::Context,
+    web::{AppError, rss_time},
+};
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+/// RSS feed of the most recently added torrents
+pub async fn rss_feed(
+    State(context): State<Context>,
+    headers: HeaderMap,
+    Query(query): Query<RssQuery>,
+) -> std::result::Result<impl IntoResponse, AppError> {
+    let config = context.config().await;
+    let media_type = query
+        .media_type
+        .as_deref()
+        .map(MediaType::from_str)
+        .transpose()
+        .map_err(anyhow::Error::msg)?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let r = context.db.r_transaction()?;
+    let mut torrents = vec![];
+    for torrent in r
+        .scan()
+        .secondary::<Torrent>(TorrentKey::created_at)?
+        .all()?
+        .rev()
+    {
+        let torrent = torrent?;
+        if media_type.is_some_and(|media_type| torrent.meta.media_type != media_type) {
+            continue;
+        }
+        if let Some(library) = &query.library
+            && !in_library(&config, library, &torrent)
+        {
+            continue;
+        }
+        torrents.push(torrent);
+        if torrents.len() >= limit {
+            break;
+        }
+    }
+
+    let template = RssTemplate {
+        base_url: base_url(&headers),
+        torrents,
+    };
+    Ok::<_, AppError>((
+        [(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
+        template.to_string(),
+    ))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RssQuery {
+    media_type: Option<String>,
+    library: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Whether the torrent is linked to the library with the given name or library_dir
+fn in_library(config: &Config, library: &str, torrent: &Torrent) -> bool {
+    let Some(library_path) = &torrent.library_path else {
+        return false;
+    };
+    config.libraries.iter().any(|l| {
+        (l.tag_filters().name.as_deref() == Some(library)
+            || l.library_dir().to_string_lossy() == library)
+            && library_path.starts_with(l.library_dir())
+    })
+}
+
+/// Links in a feed have to be absolute, so they are built from the host the feed
+/// was requested on
+fn base_url(headers: &HeaderMap) -> String {
+    let get = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let proto = get("x-forwarded-proto").unwrap_or("http");
+    let host = get("x-forwarded-host")
+        .or_else(|| get(header::HOST.as_str()))
+        .unwrap_or("localhost");
+    format!("{proto}://{host}")
+}
+
+#[derive(Template)]
+#[template(path = "pages/rss.xml")]
+struct RssTemplate {
+    base_url: String,
+    torrents: Vec<Torrent>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use mlm_db::{Series, SeriesEntries, Size, TorrentMeta};
+
+    use super::*;
+    use crate::test_helpers::{library_torrent, template_meta};
+
+    fn torrent(title: &str, library_path: Option<&str>) -> Torrent {
+        let meta = TorrentMeta {
+            mam_id: 1,
+            main_cat: None,
+            num_files: 1,
+            size: Size::from_bytes(100),
+            title: title.to_string(),
+            authors: vec!["Author <One>".to_string()],
+            series: vec![Series {
+                name: "Tom & Jerry".to_string(),
+                entries: SeriesEntries::new(vec![]),
+            }],
+            ..template_meta()
+        };
+        Torrent {
+            library_path: library_path.map(PathBuf::from),
+            ..library_torrent("abc", meta)
+        }
+    }
+
+    #[test]
+    fn test_in_library() {
+        let config: Config = toml::from_str(
+            r#"
+            mam_id = "abc"
+
+            [[library]]
+            name = "Audiobooks"
+            download_dir = "/downloads"
+            library_dir = "/library/audiobooks"
+            "#,
+        )
+        .unwrap();
+        let linked = torrent("Linked", Some("/library/audiobooks/Author/Linked"));
+        assert!(in_library(&config, "Audiobooks", &linked));
+        assert!(in_library(&config, "/library/audiobooks", &linked));
+        assert!(!in_library(&config, "Ebooks", &linked));
+        let unlinked = torrent("Unlinked", None);
+        assert!(!in_library(&config, "Audiobooks", &unlinked));
+    }
+
+    #[test]
+    fn test_rss_escapes_xml() {
+        let template = RssTemplate {
+            base_url: "http://localhost".to_string(),
+            torrents: vec![torrent("Cats & Dogs <Unabridged>", None)],
+        };
+        let xml = template.to_string();
+        assert!(xml.contains("<title>Cats &amp; Dogs &lt;Unabridged&gt;</title>"));
+        assert!(xml.contains("Author &lt;One&gt;"));
+        assert!(xml.contains("Tom &amp; Jerry"));
+        assert!(!xml.contains("Cats & Dogs"));
+    }
+}
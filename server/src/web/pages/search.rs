@@ -5,16 +5,20 @@ use axum::{
     response::{Html, Redirect},
 };
 use axum_extra::extract::Form;
-use mlm_db::{DatabaseExt as _, SelectedTorrent, Timestamp, Torrent, TorrentCost, TorrentKey};
+use mlm_db::{
+    DatabaseExt as _, Flags, SearchHistory, SearchHistoryKey, SelectedTorrent, Timestamp, Torrent,
+    TorrentCost, TorrentKey,
+};
 use mlm_mam::{
     enums::SearchTarget,
     search::{SearchFields, SearchQuery, Tor},
 };
-use mlm_parse::normalize_title;
 use serde::Deserialize;
+use time_tz::Tz;
 use tracing::info;
 
 use crate::{
+    config::UiLanguage,
     stats::Context,
     web::{AppError, MaMTorrentsTemplate, Page},
 };
@@ -24,6 +28,7 @@ pub async fn search_page(
     Query(query): Query<SearchPageQuery>,
 ) -> std::result::Result<Html<String>, AppError> {
     let mam = context.mam()?;
+    let (browse_flags_hide_vs_show, browse_flags) = query.hidden_flags().as_search_params();
     let result = mam
         .search(&SearchQuery {
             fields: SearchFields {
@@ -33,18 +38,31 @@ pub async fn search_page(
             tor: Tor {
                 target: query.uploader.map(SearchTarget::Uploader),
                 text: query.q.clone(),
+                browse_flags_hide_vs_show,
+                browse_flags,
                 ..Default::default()
             },
             ..Default::default()
         })
         .await?;
 
+    if !query.q.is_empty() || query.uploader.is_some() {
+        record_search(&context, &query).await?;
+    }
+
+    let config = context.config().await;
     let r = context.db.r_transaction()?;
+    let history = r
+        .scan()
+        .secondary::<SearchHistory>(SearchHistoryKey::created_at)?
+        .all()?
+        .rev()
+        .collect::<Result<Vec<_>, _>>()?;
     let mut torrents = result
         .data
         .into_iter()
         .map(|mam_torrent| {
-            let meta = mam_torrent.as_meta()?;
+            let meta = mam_torrent.as_meta(config.title_rules())?;
             let torrent = r
                 .get()
                 .secondary::<Torrent>(TorrentKey::mam_id, meta.mam_id)?;
@@ -63,9 +81,12 @@ pub async fn search_page(
     }
 
     let template = SearchPageTemplate {
+        language: config.language,
+        timezone: config.timezone(),
         query,
+        history,
         torrents: MaMTorrentsTemplate {
-            config: context.config().await.search.clone(),
+            config: config.search.clone(),
             torrents,
         },
     };
@@ -79,7 +100,24 @@ pub async fn search_page_post(
 ) -> Result<Redirect, AppError> {
     match form.action.as_str() {
         "select" | "wedge" => {
-            select_torrent(&context, form.mam_id, form.action == "wedge").await?;
+            let Some(mam_id) = form.mam_id else {
+                return Err(anyhow::Error::msg("Missing mam_id").into());
+            };
+            select_torrent(&context, mam_id, form.action == "wedge").await?;
+        }
+        "clear_history" => {
+            let (_guard, rw) = context.db.rw_async().await?;
+            let history = rw
+                .scan()
+                .primary::<SearchHistory>()?
+                .all()?
+                .collect::<Result<Vec<_>, _>>()?;
+            for search in history {
+                rw.remove(search)?;
+            }
+            rw.commit()?;
+            // Going back to the same search would record it again
+            return Ok(Redirect::to("/search"));
         }
         action => {
             eprintln!("unknown action: {action}");
@@ -92,17 +130,67 @@ pub async fn search_page_post(
 #[derive(Debug, Deserialize)]
 pub struct SearchPageForm {
     action: String,
-    mam_id: u64,
+    mam_id: Option<u64>,
 }
 
 #[derive(Template)]
 #[template(path = "pages/search.html")]
 struct SearchPageTemplate {
+    language: UiLanguage,
+    timezone: Option<&'static Tz>,
     query: SearchPageQuery,
+    history: Vec<SearchHistory>,
     torrents: MaMTorrentsTemplate,
 }
 
-impl Page for SearchPageTemplate {}
+impl SearchPageTemplate {
+    fn history_url(&self, search: &SearchHistory) -> String {
+        let mut url = format!("/search?q={}", urlencoding::encode(&search.query));
+        if !search.sort.is_empty() {
+            url.push_str(&format!("&sort={}", urlencoding::encode(&search.sort)));
+        }
+        if let Some(uploader) = search.uploader {
+            url.push_str(&format!("&uploader={uploader}"));
+        }
+        url
+    }
+}
+
+impl Page for SearchPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+
+    fn timezone(&self) -> Option<&'static Tz> {
+        self.timezone
+    }
+}
+
+const MAX_SEARCH_HISTORY: usize = 20;
+
+/// Saves the search, skipping repeats of the last one, and drops the oldest entries
+async fn record_search(context: &Context, query: &SearchPageQuery) -> Result<()> {
+    let search = SearchHistory::new(query.q.clone(), query.sort.clone(), query.uploader);
+    let (_guard, rw) = context.db.rw_async().await?;
+    let history = rw
+        .scan()
+        .secondary::<SearchHistory>(SearchHistoryKey::created_at)?
+        .all()?
+        .rev()
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(last) = history.first().filter(|last| last.same_search(&search)) {
+        let mut last = last.clone();
+        last.created_at = Timestamp::now();
+        rw.upsert(last)?;
+    } else {
+        rw.insert(search)?;
+        for old in history.into_iter().skip(MAX_SEARCH_HISTORY - 1) {
+            rw.remove(old)?;
+        }
+    }
+    rw.commit()?;
+    Ok(())
+}
 
 #[derive(Deserialize)]
 pub struct SearchPageQuery {
@@ -112,16 +200,58 @@ pub struct SearchPageQuery {
     sort: String,
     #[serde(default)]
     uploader: Option<u64>,
+    #[serde(default)]
+    hide_crude_language: bool,
+    #[serde(default)]
+    hide_violence: bool,
+    #[serde(default)]
+    hide_some_explicit: bool,
+    #[serde(default)]
+    hide_explicit: bool,
+    #[serde(default)]
+    hide_abridged: bool,
+    #[serde(default)]
+    hide_lgbt: bool,
+}
+
+impl SearchPageQuery {
+    /// Content flags to hide, passed on to MaM so they don't take up the page
+    fn hidden_flags(&self) -> Flags {
+        let hide = |hide: bool| hide.then_some(false);
+        Flags {
+            crude_language: hide(self.hide_crude_language),
+            violence: hide(self.hide_violence),
+            some_explicit: hide(self.hide_some_explicit),
+            explicit: hide(self.hide_explicit),
+            abridged: hide(self.hide_abridged),
+            lgbt: hide(self.hide_lgbt),
+        }
+    }
 }
 
 pub async fn select_torrent(context: &Context, mam_id: u64, wedge: bool) -> Result<(), AppError> {
+    {
+        let r = context.db.r_transaction()?;
+        if r.get()
+            .secondary::<Torrent>(TorrentKey::mam_id, mam_id)?
+            .is_some()
+        {
+            return Err(Error::msg(format!("torrent {mam_id} is already downloaded")).into());
+        }
+        if r.get()
+            .primary::<SelectedTorrent>(mam_id)?
+            .is_some_and(|t| t.removed_at.is_none())
+        {
+            return Err(Error::msg(format!("torrent {mam_id} is already selected")).into());
+        }
+    }
     let mam = context.mam()?;
     let Some(torrent) = mam.get_torrent_info_by_id(mam_id).await? else {
         return Err(AppError::NotFound);
     };
 
-    let meta = torrent.as_meta()?;
     let config = context.config().await;
+    let meta = torrent.as_meta(config.title_rules())?;
 
     let tags: Vec<_> = config
         .tags
@@ -147,7 +277,8 @@ pub async fn select_torrent(context: &Context, mam_id: u64, wedge: bool) -> Resu
     );
     {
         let (_guard, rw) = context.db.rw_async().await?;
-        rw.insert(SelectedTorrent {
+        // Replaces a selection that was removed earlier
+        rw.upsert(SelectedTorrent {
             mam_id: torrent.id,
             goodreads_id: None,
             hash: None,
@@ -160,12 +291,16 @@ pub async fn select_torrent(context: &Context, mam_id: u64, wedge: bool) -> Resu
             cost,
             category,
             tags,
-            title_search: normalize_title(&meta.title),
+            title_search: config.title_rules().normalize_title(&meta.title),
             meta,
             grabber: None,
+            account: None,
+            priority: 0,
+            note: None,
             created_at: Timestamp::now(),
             started_at: None,
             removed_at: None,
+            grab_skipped_at: None,
         })?;
         rw.commit()?;
     }
@@ -1,7 +1,6 @@
 use std::{
     cell::{Ref, RefCell},
     str::FromStr as _,
-    sync::Arc,
 };
 
 use anyhow::Result;
@@ -13,16 +12,17 @@ use axum::{
 use axum_extra::extract::Form;
 use mlm_db::{DatabaseExt as _, Flags, Language, OldCategory, SelectedTorrent, Size, Timestamp};
 use mlm_mam::user_data::UserResponse;
-use native_db::Database;
 use serde::{Deserialize, Serialize};
+use time_tz::Tz;
 use tracing::info;
 
 use crate::{
+    config::{UiLanguage, UnsatBuffer},
     stats::Context,
+    torrent_downloader::expire_stale_selections,
     web::{
         AppError, Page, flag_icons,
         tables::{self, Flex, HidableColumns, Key, SortOn, Sortable},
-        time,
     },
 };
 
@@ -125,14 +125,20 @@ pub async fn selected_page(
                 SelectedPageSort::Cost => a.cost.cmp(&b.cost),
                 SelectedPageSort::Buffer => a
                     .unsat_buffer
-                    .unwrap_or(config.unsat_buffer)
-                    .cmp(&b.unsat_buffer.unwrap_or(config.unsat_buffer)),
+                    .unwrap_or(config.unsat_buffer.for_media_type(a.meta.media_type))
+                    .cmp(
+                        &b.unsat_buffer
+                            .unwrap_or(config.unsat_buffer.for_media_type(b.meta.media_type)),
+                    ),
                 SelectedPageSort::Grabber => a.grabber.cmp(&b.grabber),
+                SelectedPageSort::Priority => a.priority.cmp(&b.priority),
                 SelectedPageSort::CreatedAt => a.created_at.cmp(&b.created_at),
                 SelectedPageSort::StartedAt => a.started_at.cmp(&b.started_at),
             };
             if sort.asc { ord.reverse() } else { ord }
         });
+    } else {
+        torrents.sort_by(SelectedTorrent::queue_cmp);
     }
     let downloading_size: f64 = context
         .db
@@ -150,7 +156,7 @@ pub async fn selected_page(
         })
         .map(|t| t.meta.size.bytes() as f64)
         .sum();
-    let user_info = match context.mam.as_ref() {
+    let user_info = match context.mam() {
         Ok(mam) => mam.user_info().await.ok(),
         _ => None,
     };
@@ -162,9 +168,12 @@ pub async fn selected_page(
         )
     });
     let template = SelectedPageTemplate {
+        language: config.language,
+        timezone: config.timezone(),
         user_info,
         remaining_buffer,
         unsat_buffer: config.unsat_buffer,
+        selected_max_age_days: config.selected_max_age_days,
         sort,
         show,
         cols: Default::default(),
@@ -174,10 +183,11 @@ pub async fn selected_page(
 }
 
 pub async fn selected_torrents_page_post(
-    State(db): State<Arc<Database<'static>>>,
+    State(context): State<Context>,
     uri: OriginalUri,
     Form(form): Form<TorrentsPageForm>,
 ) -> Result<Redirect, AppError> {
+    let db = &context.db;
     match form.action.as_str() {
         "remove" => {
             for torrent in form.torrents {
@@ -195,6 +205,22 @@ pub async fn selected_torrents_page_post(
                 rw.commit()?;
             }
         }
+        "raise_priority" | "lower_priority" => {
+            let change = if form.action == "raise_priority" {
+                1
+            } else {
+                -1
+            };
+            for torrent in form.torrents {
+                let (_guard, rw) = db.rw_async().await?;
+                let Some(mut torrent) = rw.get().primary::<SelectedTorrent>(torrent)? else {
+                    return Err(anyhow::Error::msg("Could not find torrent").into());
+                };
+                torrent.priority = torrent.priority.saturating_add(change);
+                rw.upsert(torrent)?;
+                rw.commit()?;
+            }
+        }
         "update" => {
             for torrent in form.torrents {
                 let (_guard, rw) = db.rw_async().await?;
@@ -207,6 +233,25 @@ pub async fn selected_torrents_page_post(
                 rw.commit()?;
             }
         }
+        "note" => {
+            let note = form.note.trim();
+            for torrent in form.torrents {
+                let (_guard, rw) = db.rw_async().await?;
+                let Some(mut torrent) = rw.get().primary::<SelectedTorrent>(torrent)? else {
+                    return Err(anyhow::Error::msg("Could not find torrent").into());
+                };
+                torrent.note = (!note.is_empty()).then(|| note.to_string());
+                rw.upsert(torrent)?;
+                rw.commit()?;
+            }
+        }
+        "expire" => {
+            let config = context.config().await;
+            let Some(max_age_days) = config.selected_max_age_days else {
+                return Err(anyhow::Error::msg("selected_max_age_days is not configured").into());
+            };
+            expire_stale_selections(db, max_age_days).await?;
+        }
         action => {
             eprintln!("unknown action: {action}");
         }
@@ -219,6 +264,8 @@ pub async fn selected_torrents_page_post(
 pub struct TorrentsPageForm {
     action: String,
     unsats: Option<u64>,
+    #[serde(default)]
+    note: String,
     #[serde(default, rename = "torrent")]
     torrents: Vec<u64>,
 }
@@ -226,9 +273,12 @@ pub struct TorrentsPageForm {
 #[derive(Template)]
 #[template(path = "pages/selected.html")]
 struct SelectedPageTemplate {
+    language: UiLanguage,
+    timezone: Option<&'static Tz>,
     user_info: Option<UserResponse>,
     remaining_buffer: Option<Size>,
-    unsat_buffer: u64,
+    unsat_buffer: UnsatBuffer,
+    selected_max_age_days: Option<u64>,
     sort: SortOn<SelectedPageSort>,
     show: TorrentsPageColumns,
     cols: RefCell<Vec<Box<dyn tables::Size>>>,
@@ -251,7 +301,15 @@ impl SelectedPageTemplate {
     }
 }
 
-impl Page for SelectedPageTemplate {}
+impl Page for SelectedPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+
+    fn timezone(&self) -> Option<&'static Tz> {
+        self.timezone
+    }
+}
 
 #[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -266,6 +324,7 @@ pub enum SelectedPageSort {
     Cost,
     Buffer,
     Grabber,
+    Priority,
     CreatedAt,
     StartedAt,
 }
@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use askama::Template;
+use axum::{extract::State, response::Html};
+use mlm_db::{SeriesEntries, SeriesEntry, Torrent};
+use native_db::Database;
+
+use crate::{
+    config::UiLanguage,
+    stats::Context,
+    web::{AppError, Page},
+};
+
+pub async fn series_gaps_page(
+    State(context): State<Context>,
+) -> std::result::Result<Html<String>, AppError> {
+    let config = context.config().await;
+    let template = SeriesGapsPageTemplate {
+        language: config.language,
+        series: find_series_gaps(&context.db)?,
+    };
+    Ok::<_, AppError>(Html(template.to_string()))
+}
+
+pub struct SeriesGaps {
+    pub name: String,
+    pub author: String,
+    pub have: SeriesEntries,
+    pub missing: Vec<u32>,
+}
+
+impl SeriesGaps {
+    /// MaM search for one missing book of the series
+    pub fn search_query(&self, num: u32) -> String {
+        let mut query = format!("@series \"{}\" {num}", self.name);
+        if !self.author.is_empty() {
+            query.push_str(&format!(" @author \"{}\"", self.author));
+        }
+        query
+    }
+}
+
+/// Series in the library that are missing whole numbered books below the
+/// highest one you have. Series with the same name by different authors are
+/// kept apart
+pub fn find_series_gaps(db: &Database<'_>) -> Result<Vec<SeriesGaps>> {
+    let mut groups: BTreeMap<(String, String), SeriesGaps> = BTreeMap::new();
+    let r = db.r_transaction()?;
+    for torrent in r.scan().primary::<Torrent>()?.all()? {
+        let torrent = torrent?;
+        if torrent.library_path.is_none() || torrent.replaced_with.is_some() {
+            continue;
+        }
+        let author = torrent.meta.authors.first().cloned().unwrap_or_default();
+        for series in &torrent.meta.series {
+            let group = groups
+                .entry((series.name.to_lowercase(), author.to_lowercase()))
+                .or_insert_with(|| SeriesGaps {
+                    name: series.name.clone(),
+                    author: author.clone(),
+                    have: SeriesEntries::new(vec![]),
+                    missing: vec![],
+                });
+            group.have.0.extend(series.entries.0.iter().cloned());
+        }
+    }
+
+    let mut gaps = vec![];
+    for mut group in groups.into_values() {
+        group.have.0.sort();
+        group.have.0.dedup();
+        group.missing = missing_entries(&group.have);
+        if !group.missing.is_empty() {
+            gaps.push(group);
+        }
+    }
+    Ok(gaps)
+}
+
+/// Whole numbers from 1 up to the highest entry that are not covered by any
+/// entry. Novellas like 2.5 count towards the highest entry but are never
+/// reported missing themselves
+fn missing_entries(entries: &SeriesEntries) -> Vec<u32> {
+    let max = entries
+        .0
+        .iter()
+        .map(|entry| match *entry {
+            SeriesEntry::Num(num) => num,
+            SeriesEntry::Range(_, end) => end,
+            SeriesEntry::Part(num, _) => num,
+        })
+        .fold(0.0, f32::max)
+        .floor() as u32;
+    (1..=max)
+        .filter(|num| !entries.contains(*num as f32))
+        .collect()
+}
+
+#[derive(Template)]
+#[template(path = "pages/series_gaps.html")]
+struct SeriesGapsPageTemplate {
+    language: UiLanguage,
+    series: Vec<SeriesGaps>,
+}
+
+impl Page for SeriesGapsPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use mlm_db::{Series, TorrentMeta};
+
+    use super::*;
+    use crate::test_helpers::{library_torrent, template_meta};
+
+    fn linked(id: &str, series: &str, num: f32, author: &str) -> Torrent {
+        let meta = TorrentMeta {
+            title: format!("{series} {num}"),
+            authors: vec![author.to_string()],
+            series: vec![Series {
+                name: series.to_string(),
+                entries: SeriesEntries::new(vec![SeriesEntry::Num(num)]),
+            }],
+            ..template_meta()
+        };
+        Torrent {
+            mam_id: id.parse().unwrap(),
+            library_path: Some(PathBuf::from(format!("/library/{id}"))),
+            ..library_torrent(id, meta)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_find_series_gaps_by_author() {
+        let db = native_db::Builder::new()
+            .create_in_memory(&mlm_db::MODELS)
+            .unwrap();
+        {
+            let (_guard, rw) = db.rw_async().await.unwrap();
+            rw.insert(linked("1", "Legacy", 1.0, "Author One")).unwrap();
+            rw.insert(linked("2", "Legacy", 3.0, "Author One")).unwrap();
+            rw.insert(linked("3", "Legacy", 2.0, "Author Two")).unwrap();
+            rw.commit().unwrap();
+        }
+
+        let gaps = find_series_gaps(&db).unwrap();
+        let found = gaps
+            .iter()
+            .map(|gap| (gap.author.as_str(), gap.missing.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![("Author One", vec![2]), ("Author Two", vec![1])]
+        );
+        assert_eq!(
+            gaps[0].search_query(2),
+            r#"@series "Legacy" 2 @author "Author One""#
+        );
+    }
+
+    #[test]
+    fn test_missing_entries() {
+        let missing = |entries: Vec<SeriesEntry>| missing_entries(&SeriesEntries::new(entries));
+        assert_eq!(
+            missing(vec![
+                SeriesEntry::Num(1.0),
+                SeriesEntry::Num(2.0),
+                SeriesEntry::Num(4.0)
+            ]),
+            vec![3]
+        );
+        assert_eq!(
+            missing(vec![SeriesEntry::Num(1.0), SeriesEntry::Num(3.5)]),
+            vec![2, 3]
+        );
+        assert_eq!(
+            missing(vec![SeriesEntry::Range(1.0, 3.0), SeriesEntry::Num(5.0)]),
+            vec![4]
+        );
+        assert_eq!(
+            missing(vec![SeriesEntry::Part(2.0, 1.0), SeriesEntry::Num(3.0)]),
+            vec![1]
+        );
+        assert!(missing(vec![SeriesEntry::Num(0.0), SeriesEntry::Num(1.0)]).is_empty());
+        assert!(missing(vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_novella_order() {
+        let mut have = SeriesEntries::new(vec![
+            SeriesEntry::Num(3.0),
+            SeriesEntry::Num(2.5),
+            SeriesEntry::Range(4.0, 5.0),
+            SeriesEntry::Num(1.0),
+            SeriesEntry::Num(2.5),
+        ]);
+        have.0.sort();
+        have.0.dedup();
+        assert_eq!(have.to_string(), "1, 2.5, 3, 4-5");
+        assert_eq!(missing_entries(&have), vec![2]);
+    }
+}
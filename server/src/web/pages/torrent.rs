@@ -5,17 +5,19 @@ use askama::Template;
 use axum::{
     body::Body,
     extract::{OriginalUri, Path, State},
-    response::{Html, IntoResponse, Redirect},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
 };
 use axum_extra::extract::Form;
 use itertools::Itertools;
 use mlm_db::{
-    ClientStatus, DatabaseExt as _, Event, EventKey, EventType, Size, Torrent, TorrentCost,
-    TorrentKey, TorrentMeta,
+    ClientStatus, DatabaseExt as _, Event, EventKey, EventType, MediaType, SeriesEntries, Size,
+    Torrent, TorrentCost, TorrentKey, TorrentMeta,
 };
 use mlm_mam::{
     api::MaM,
     enums::SearchIn,
+    http,
     search::{MaMTorrent, SearchFields, SearchQuery, Tor},
 };
 use native_db::Database;
@@ -26,24 +28,30 @@ use qbit::{
 use regex::Regex;
 use reqwest::header;
 use serde::Deserialize;
+use serde_json::{Value, json};
 use time::UtcDateTime;
+use time_tz::Tz;
 use tokio_util::io::ReaderStream;
 
 use crate::{
     audiobookshelf::{Abs, LibraryItemMinified},
     cleaner::clean_torrent,
-    config::Config,
+    config::{Config, UiLanguage},
+    covers::get_cover,
     linker::{find_library, library_dir, map_path, refresh_metadata, refresh_metadata_relink},
     qbittorrent::{self, ensure_category_exists},
+    rtorrent,
     stats::Context,
     web::{
-        AppError, Conditional, MaMTorrentsTemplate, Page, TorrentLink, flag_icons,
+        AppError, Conditional, MaMTorrentsTemplate, Page, TorrentLink, flag_icons, meta_diff,
         pages::{search::select_torrent, torrents::TorrentsPageFilter},
         tables::table_styles,
-        time,
     },
 };
 
+/// How many related library torrents to show on the torrent page
+const MAX_RELATED_TORRENTS: usize = 20;
+
 pub async fn torrent_file(
     State(context): State<Context>,
     Path((id, filename)): Path<(String, String)>,
@@ -68,6 +76,13 @@ pub async fn torrent_file(
             .into_iter()
             .find(|f| f.name == filename)
             .map(|file| map_path(&qbit_config.path_mapping, &torrent.save_path).join(&file.name))
+    } else if let Some((torrent, rtorrent)) = rtorrent::get_torrent(&config, &torrent.id).await? {
+        rtorrent
+            .files(&torrent.hash)
+            .await?
+            .into_iter()
+            .find(|f| f.path == filename)
+            .map(|file| PathBuf::from(&torrent.directory).join(&file.path))
     } else {
         None
     }) else {
@@ -91,6 +106,25 @@ pub async fn torrent_file(
     Ok((headers, body))
 }
 
+pub async fn torrent_cover(
+    State(context): State<Context>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let config = context.config().await;
+    let Some(torrent) = context.db.r_transaction()?.get().primary::<Torrent>(id)? else {
+        return Err(AppError::NotFound);
+    };
+    let mam = context.mam().ok();
+    let Some(cover) = get_cover(&config, mam.as_deref(), &torrent).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let headers = [
+        (header::CONTENT_TYPE, cover.content_type),
+        (header::CACHE_CONTROL, "max-age=86400"),
+    ];
+    Ok((headers, cover.data).into_response())
+}
+
 pub async fn torrent_page(
     State(context): State<Context>,
     Path(id_or_mam_id): Path<String>,
@@ -119,14 +153,16 @@ async fn torrent_page_mam_id(
     let Some(mam_torrent) = mam.get_torrent_info_by_id(mam_id).await? else {
         return Err(AppError::NotFound);
     };
-    let meta = mam_torrent.as_meta()?;
+    let config = context.config.lock().await.clone();
+    let meta = mam_torrent.as_meta(config.title_rules())?;
 
     println!("mam_torrent: {:?}", mam_torrent);
     println!("mam_meta: {:?}", meta);
-    let config = context.config.lock().await.clone();
     let other_torrents = other_torrents(&config, &context.db, &mam, &meta).await?;
 
     let template = TorrentMamPageTemplate {
+        language: config.language,
+        timezone: config.timezone(),
         mam_torrent,
         meta,
         other_torrents,
@@ -139,7 +175,10 @@ async fn torrent_page_id(
     Path(id): Path<String>,
 ) -> std::result::Result<Html<String>, AppError> {
     let config = context.config().await;
-    let abs = config.audiobookshelf.as_ref().map(Abs::new);
+    let abs = config
+        .audiobookshelf
+        .as_ref()
+        .map(|abs_config| Abs::new(abs_config, http::client()));
     let Some(mut torrent) = context.db.r_transaction()?.get().primary::<Torrent>(id)? else {
         return Err(AppError::NotFound);
     };
@@ -163,7 +202,7 @@ async fn torrent_page_id(
         rw.commit()?;
     }
     let book = match abs {
-        Some(abs) => abs?.get_book(&torrent).await?,
+        Some(abs) => abs.get_book(&torrent).await?,
         None => None,
     };
 
@@ -178,7 +217,10 @@ async fn torrent_page_id(
 
     let mam = context.mam()?;
     let mam_torrent = mam.get_torrent_info_by_id(torrent.mam_id).await?;
-    let mam_meta = mam_torrent.as_ref().map(|t| t.as_meta()).transpose()?;
+    let mam_meta = mam_torrent
+        .as_ref()
+        .map(|t| t.as_meta(config.title_rules()))
+        .transpose()?;
 
     if let Some(mam_meta) = &mam_meta
         && torrent.meta.uploaded_at.0 == UtcDateTime::UNIX_EPOCH
@@ -191,7 +233,7 @@ async fn torrent_page_id(
 
     let mut qbit_data = None;
     let mut wanted_path = None;
-    let mut qbit_files = vec![];
+    let mut files = vec![];
     if torrent.id_is_hash
         && let Some((qbit_torrent, qbit, _)) =
             qbittorrent::get_torrent(&config, &torrent.id).await?
@@ -201,13 +243,14 @@ async fn torrent_page_id(
         categories.sort_by(|a, b| a.name.cmp(&b.name));
         let tags = qbit.tags().await?;
 
-        wanted_path = find_library(&config, &qbit_torrent).and_then(|library| {
-            library_dir(
-                config.exclude_narrator_in_library_dir,
-                library,
-                &torrent.meta,
-            )
-        });
+        wanted_path =
+            find_library(&config, &qbit_torrent, Some(&torrent.meta)).and_then(|library| {
+                library_dir(
+                    library.exclude_narrator_in_library_dir(&config),
+                    library,
+                    &torrent.meta,
+                )
+            });
 
         qbit_data = Some(QbitData {
             torrent_tags: qbit_torrent.tags.split(", ").map(str::to_string).collect(),
@@ -217,7 +260,21 @@ async fn torrent_page_id(
             tags,
         });
 
-        qbit_files = qbit.files(&torrent.id, None).await?;
+        files = qbit
+            .files(&torrent.id, None)
+            .await?
+            .into_iter()
+            .map(|file| file.name)
+            .collect();
+    } else if torrent.id_is_hash
+        && let Some((_, rtorrent)) = rtorrent::get_torrent(&config, &torrent.id).await?
+    {
+        files = rtorrent
+            .files(&torrent.id)
+            .await?
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
     }
 
     println!("book: {:?}", book);
@@ -239,8 +296,11 @@ async fn torrent_page_id(
         rw.commit()?;
     }
     let other_torrents = other_torrents(&config, &context.db, &mam, &torrent.meta).await?;
+    let related_torrents = related_torrents(&context.db, &torrent, MAX_RELATED_TORRENTS)?;
 
     let template = TorrentPageTemplate {
+        language: config.language,
+        timezone: config.timezone(),
         abs_url: config
             .audiobookshelf
             .as_ref()
@@ -254,8 +314,9 @@ async fn torrent_page_id(
         mam_meta,
         qbit_data,
         wanted_path,
-        qbit_files,
+        files,
         other_torrents,
+        related_torrents,
     };
     Ok::<_, AppError>(Html(template.to_string()))
 }
@@ -352,6 +413,9 @@ pub async fn torrent_page_post_id(
             };
             qbit.stop(vec![&id]).await?;
         }
+        "note" => {
+            set_torrent_note(&context.db, id, &form.note).await?;
+        }
         "clear-replacement" => {
             let (_guard, rw) = context.db.rw_async().await?;
             let Some(mut torrent) = rw.get().primary::<Torrent>(id)? else {
@@ -429,12 +493,29 @@ pub struct TorrentPageForm {
     category: String,
     #[serde(default)]
     tags: Vec<String>,
+    #[serde(default)]
+    note: String,
     mam_id: Option<u64>,
 }
 
+/// Sets the note of a torrent, an empty note removes it
+async fn set_torrent_note(db: &Database<'_>, id: String, note: &str) -> Result<()> {
+    let (_guard, rw) = db.rw_async().await?;
+    let Some(mut torrent) = rw.get().primary::<Torrent>(id)? else {
+        return Err(anyhow::Error::msg("Could not find torrent"));
+    };
+    let note = note.trim();
+    torrent.note = (!note.is_empty()).then(|| note.to_string());
+    rw.upsert(torrent)?;
+    rw.commit()?;
+    Ok(())
+}
+
 #[derive(Template)]
 #[template(path = "pages/torrent.html")]
 struct TorrentPageTemplate {
+    language: UiLanguage,
+    timezone: Option<&'static Tz>,
     abs_url: String,
     torrent: Torrent,
     replacement_torrent: Option<Torrent>,
@@ -444,8 +525,9 @@ struct TorrentPageTemplate {
     mam_meta: Option<TorrentMeta>,
     qbit_data: Option<QbitData>,
     wanted_path: Option<PathBuf>,
-    qbit_files: Vec<qbit::models::TorrentContent>,
+    files: Vec<String>,
     other_torrents: MaMTorrentsTemplate,
+    related_torrents: Vec<(Relation, Torrent)>,
 }
 
 impl TorrentPageTemplate {
@@ -459,7 +541,69 @@ impl TorrentPageTemplate {
     }
 }
 
+impl TorrentPageTemplate {
+    fn json_ld(&self) -> String {
+        let isbn = self.mam_torrent.as_ref().and_then(|t| t.isbn.as_deref());
+        json_ld(&self.torrent.meta, isbn)
+    }
+}
+
+/// schema.org Book metadata for the torrent, safe to embed in a script tag
+fn json_ld(meta: &TorrentMeta, isbn: Option<&str>) -> String {
+    let is_audiobook = matches!(
+        meta.media_type,
+        MediaType::Audiobook | MediaType::PeriodicalAudiobook
+    );
+    let people = |names: &[String]| {
+        names
+            .iter()
+            .map(|name| json!({ "@type": "Person", "name": name }))
+            .collect::<Vec<_>>()
+    };
+    let mut book = json!({
+        "@context": "https://schema.org",
+        "@type": if is_audiobook { "Audiobook" } else { "Book" },
+        "name": meta.title,
+        "bookFormat": if is_audiobook {
+            "https://schema.org/AudiobookFormat"
+        } else {
+            "https://schema.org/EBook"
+        },
+    });
+    if !meta.authors.is_empty() {
+        book["author"] = Value::from(people(&meta.authors));
+    }
+    if is_audiobook && !meta.narrators.is_empty() {
+        book["readBy"] = Value::from(people(&meta.narrators));
+    }
+    if let Some(language) = meta.language {
+        book["inLanguage"] = Value::from(language.to_str());
+    }
+    if let Some((edition, _)) = &meta.edition {
+        book["bookEdition"] = Value::from(edition.as_str());
+    }
+    if let Some(isbn) = isbn.map(str::trim).filter(|isbn| !isbn.is_empty()) {
+        book["isbn"] = Value::from(isbn);
+    }
+    if let Some(series) = meta.series.first() {
+        let mut part_of = json!({ "@type": "BookSeries", "name": series.name });
+        if !series.entries.0.is_empty() {
+            part_of["position"] = Value::from(series.entries.to_string());
+        }
+        book["isPartOf"] = part_of;
+    }
+    book.to_string().replace('<', "\\u003c")
+}
+
 impl Page for TorrentPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+
+    fn timezone(&self) -> Option<&'static Tz> {
+        self.timezone
+    }
+
     fn item_path(&self) -> &'static str {
         "/torrents"
     }
@@ -468,12 +612,22 @@ impl Page for TorrentPageTemplate {
 #[derive(Template)]
 #[template(path = "pages/torrent_mam.html")]
 struct TorrentMamPageTemplate {
+    language: UiLanguage,
+    timezone: Option<&'static Tz>,
     mam_torrent: MaMTorrent,
     meta: TorrentMeta,
     other_torrents: MaMTorrentsTemplate,
 }
 
 impl Page for TorrentMamPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+
+    fn timezone(&self) -> Option<&'static Tz> {
+        self.timezone
+    }
+
     fn item_path(&self) -> &'static str {
         "/torrents"
     }
@@ -508,6 +662,72 @@ fn duration(seconds: f64) -> String {
     duration.join(" ")
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Relation {
+    Series,
+    Author,
+}
+
+fn relation(meta: &TorrentMeta, other: &TorrentMeta) -> Option<Relation> {
+    if series_entries(meta, other).is_some() {
+        Some(Relation::Series)
+    } else if other
+        .authors
+        .iter()
+        .any(|a| meta.authors.iter().any(|b| a.eq_ignore_ascii_case(b)))
+    {
+        Some(Relation::Author)
+    } else {
+        None
+    }
+}
+
+/// The entries of `other` in the first series it shares with `meta`
+fn series_entries<'a>(meta: &TorrentMeta, other: &'a TorrentMeta) -> Option<&'a SeriesEntries> {
+    other
+        .series
+        .iter()
+        .find(|s| {
+            meta.series
+                .iter()
+                .any(|m| m.name.eq_ignore_ascii_case(&s.name))
+        })
+        .map(|s| &s.entries)
+}
+
+/// Other library torrents in the same series, in series order, followed by
+/// other books by the same authors. There is no index on authors or series, so
+/// this is a single scan that only keeps the matches
+fn related_torrents(
+    db: &Database<'_>,
+    torrent: &Torrent,
+    limit: usize,
+) -> Result<Vec<(Relation, Torrent)>> {
+    if torrent.meta.authors.is_empty() && torrent.meta.series.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut related = vec![];
+    for other in db.r_transaction()?.scan().primary::<Torrent>()?.all()? {
+        let other = other?;
+        if other.id == torrent.id || other.replaced_with.is_some() {
+            continue;
+        }
+        if let Some(relation) = relation(&torrent.meta, &other.meta) {
+            related.push((relation, other));
+        }
+    }
+    related.sort_by(|(a_relation, a), (b_relation, b)| {
+        a_relation
+            .cmp(b_relation)
+            .then_with(|| {
+                series_entries(&torrent.meta, &a.meta).cmp(&series_entries(&torrent.meta, &b.meta))
+            })
+            .then_with(|| a.meta.title.cmp(&b.meta.title))
+    });
+    related.truncate(limit);
+    Ok(related)
+}
+
 async fn other_torrents(
     config: &Config,
     db: &Database<'_>,
@@ -564,7 +784,7 @@ async fn other_torrents(
         .into_iter()
         .filter(|t| t.id != meta.mam_id)
         .map(|mam_torrent| {
-            let meta = mam_torrent.as_meta()?;
+            let meta = mam_torrent.as_meta(config.title_rules())?;
             let torrent = r
                 .get()
                 .secondary::<Torrent>(TorrentKey::mam_id, meta.mam_id)?;
@@ -579,3 +799,84 @@ async fn other_torrents(
         torrents,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use mlm_db::{Language, Series, SeriesEntries, SeriesEntry};
+
+    use super::*;
+    use crate::test_helpers::template_meta;
+
+    fn meta() -> TorrentMeta {
+        TorrentMeta {
+            mam_id: 1,
+            media_type: MediaType::Ebook,
+            main_cat: None,
+            authors: vec![],
+            ..template_meta()
+        }
+    }
+
+    #[test]
+    fn test_json_ld_minimal() {
+        let value: Value = serde_json::from_str(&json_ld(&meta(), Some(" "))).unwrap();
+        assert_eq!(value["@type"], "Book");
+        assert_eq!(value["name"], "The Title");
+        assert!(value.get("author").is_none());
+        assert!(value.get("isbn").is_none());
+        assert!(value.get("inLanguage").is_none());
+    }
+
+    #[test]
+    fn test_json_ld_audiobook() {
+        let mut meta = meta();
+        meta.media_type = MediaType::Audiobook;
+        meta.title = "</script><b>".to_string();
+        meta.authors = vec!["Author".to_string()];
+        meta.narrators = vec!["Narrator".to_string()];
+        meta.language = Some(Language::English);
+        meta.series = vec![Series {
+            name: "Series".to_string(),
+            entries: SeriesEntries::new(vec![SeriesEntry::Num(2.0)]),
+        }];
+        let json = json_ld(&meta, Some("9780000000000"));
+        assert!(!json.contains('<'));
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["@type"], "Audiobook");
+        assert_eq!(value["name"], "</script><b>");
+        assert_eq!(value["author"][0]["name"], "Author");
+        assert_eq!(value["readBy"][0]["name"], "Narrator");
+        assert_eq!(value["inLanguage"], "English");
+        assert_eq!(value["isbn"], "9780000000000");
+        assert_eq!(value["isPartOf"]["name"], "Series");
+        assert_eq!(value["isPartOf"]["position"], "2");
+    }
+
+    #[test]
+    fn test_relation() {
+        let series = |name: &str, num: f32| Series {
+            name: name.to_string(),
+            entries: SeriesEntries::new(vec![SeriesEntry::Num(num)]),
+        };
+        let mut current = meta();
+        current.authors = vec!["James S. A. Corey".to_string()];
+        current.series = vec![series("The Expanse", 1.0)];
+
+        let mut next = meta();
+        next.authors = vec!["Someone Else".to_string()];
+        next.series = vec![series("the expanse", 2.0)];
+        assert_eq!(relation(&current, &next), Some(Relation::Series));
+        assert_eq!(
+            series_entries(&current, &next),
+            Some(&SeriesEntries::new(vec![SeriesEntry::Num(2.0)]))
+        );
+
+        let mut standalone = meta();
+        standalone.authors = vec!["Someone Else".to_string(), "james s. a. corey".to_string()];
+        assert_eq!(relation(&current, &standalone), Some(Relation::Author));
+        assert_eq!(series_entries(&current, &standalone), None);
+
+        assert_eq!(relation(&current, &meta()), None);
+        assert!(Relation::Series < Relation::Author);
+    }
+}
@@ -1,5 +1,3 @@
-use std::sync::Arc;
-
 use askama::Template;
 use axum::{
     extract::{Path, State},
@@ -11,24 +9,27 @@ use mlm_db::{
     AudiobookCategory, DatabaseExt as _, EbookCategory, FlagBits, Flags, Language, MetadataSource,
     OldCategory, Series, Torrent, TorrentMeta, impls::format_serie,
 };
-use native_db::Database;
 use serde::Deserialize;
 
 use crate::{
     autograbber::update_torrent_meta,
+    config::UiLanguage,
     stats::Context,
     web::{AppError, Page},
 };
 
 pub async fn torrent_edit_page(
-    State(db): State<Arc<Database<'static>>>,
+    State(context): State<Context>,
     Path(hash): Path<String>,
 ) -> std::result::Result<Html<String>, AppError> {
+    let config = context.config().await;
+    let db = &context.db;
     let Some(torrent) = db.r_transaction()?.get().primary::<Torrent>(hash)? else {
         return Err(AppError::NotFound);
     };
 
     let template = TorrentPageTemplate {
+        language: config.language,
         flags: Flags::from_bitfield(torrent.meta.flags.map_or(0, |f| f.0)),
         torrent,
     };
@@ -98,6 +99,7 @@ pub async fn torrent_edit_page_post(
         language: Some(language),
         flags: Some(FlagBits::new(flags.as_bitfield())),
         authors,
+        original_authors: None,
         narrators,
         series,
         source: MetadataSource::Manual,
@@ -147,6 +149,7 @@ pub struct TorrentPageForm {
 #[derive(Template)]
 #[template(path = "pages/torrent_edit.html")]
 struct TorrentPageTemplate {
+    language: UiLanguage,
     torrent: Torrent,
     flags: Flags,
 }
@@ -158,6 +161,10 @@ impl TorrentPageTemplate {
 }
 
 impl Page for TorrentPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+
     fn item_path(&self) -> &'static str {
         "/torrents"
     }
@@ -1,5 +1,6 @@
 use std::cell::Ref;
 use std::cell::RefCell;
+use std::io;
 use std::mem;
 use std::str::FromStr;
 
@@ -7,28 +8,41 @@ use anyhow::Result;
 use askama::Template;
 use axum::response::{IntoResponse, Response};
 use axum::{
+    body::Body,
     extract::{OriginalUri, Query, State},
+    http::header,
     response::{Html, Redirect},
 };
 use axum_extra::extract::Form;
+use bytes::Bytes;
 use mlm_db::{Language, LibraryMismatch, Torrent, TorrentKey};
+use mlm_mam::http;
+use native_db::Database;
 use serde::{Deserialize, Serialize};
 use sublime_fuzzy::FuzzySearch;
+use time_tz::Tz;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::warn;
 
 use crate::{
     cleaner::clean_torrent,
+    config::{Config, UiLanguage},
     linker::{refresh_metadata, refresh_metadata_relink},
+    metadata::MetadataService,
+    qbittorrent::{self, ensure_category_exists},
+    rtorrent::Rtorrent,
     stats::Context,
+    torrent_downloader::{DownloadClient, readd_torrent},
     web::{
         AppError,
         tables::{Flex, HidableColumns, Key, Pagination, PaginationParams, SortOn, Sortable},
-        time,
     },
-    web::{Page, flag_icons, tables},
+    web::{Page, flag_icons, format_time, tables},
 };
 use mlm_db::{
     Category, ClientStatus, DatabaseExt as _, Flags, MediaType, MetadataSource, OldCategory,
-    Series, SeriesEntry,
+    Series, SeriesEntry, impls::format_serie,
 };
 
 pub async fn torrents_page(
@@ -39,34 +53,16 @@ pub async fn torrents_page(
     Query(show): Query<TorrentsPageColumnsQuery>,
     Query(paging): Query<PaginationParams>,
 ) -> std::result::Result<Response, AppError> {
+    let config = context.config().await;
     let r = context.db.r_transaction()?;
 
     let torrent_count = r.len().secondary::<Torrent>(TorrentKey::created_at)?;
     let torrents = r.scan().secondary::<Torrent>(TorrentKey::created_at)?;
-    let mut filter = filter;
-    let query_pos = filter
-        .iter()
-        .position(|(field, _)| field == &TorrentsPageFilter::Query);
-    let query = query_pos
-        .map(|i| filter.remove(i))
-        .and_then(|(_, value)| if value.is_empty() { None } else { Some(value) });
-    let metadata_pos = filter
-        .iter()
-        .position(|(field, _)| field == &TorrentsPageFilter::Metadata);
-    let metadata = metadata_pos
-        .map(|i| filter.remove(i))
-        .and_then(|(_, value)| if value.is_empty() { None } else { Some(value) });
-    filter.retain(|(field, _)| {
-        !matches!(
-            field,
-            TorrentsPageFilter::SortBy
-                | TorrentsPageFilter::Asc
-                | TorrentsPageFilter::Show
-                | TorrentsPageFilter::From
-                | TorrentsPageFilter::PageSize
-        )
-    });
-    let show = show.show.unwrap_or_default();
+    let filter = TorrentsFilter::new(filter, show.show.unwrap_or_default());
+    let csv_url = match uri.query() {
+        Some(query) => format!("/torrents.csv?{query}"),
+        None => "/torrents.csv".to_string(),
+    };
 
     let torrents = torrents.all()?.rev();
 
@@ -74,8 +70,139 @@ pub async fn torrents_page(
         let Ok(t) = t else {
             return Some(t.map(|t| (t, 0)));
         };
+        let score = filter.score(&t)?;
+        Some(Ok((t, score)))
+    });
+
+    let mut paging = match paging.default_page_size(uri, 500, torrent_count as usize) {
+        Ok(paging) => paging,
+        Err(redirect) => return Ok(redirect.into_response()),
+    };
+
+    let mut torrents: Vec<Torrent> = if filter.query.is_some() || sort.sort_by.is_some() {
+        let mut torrents = torrents.collect::<Result<Vec<_>, native_db::db_type::Error>>()?;
+        sort_torrents(&mut torrents, &sort);
+        if filter.metadata.is_none()
+            && let Some(paging) = &mut paging
+        {
+            paging.total = torrents.len();
+            let torrents: Vec<_> = torrents
+                .into_iter()
+                .map(|(t, _)| t)
+                .skip(paging.from)
+                .take(paging.page_size)
+                .collect();
+            torrents
+        } else {
+            torrents.into_iter().map(|(t, _)| t).collect()
+        }
+    } else if filter.metadata.is_none()
+        && let Some(paging) = &mut paging
+    {
+        if filter.filters.is_empty() {
+            torrents
+                .map(|t| t.map(|(t, _)| t))
+                .skip(paging.from)
+                .take(paging.page_size)
+                .collect::<Result<_, native_db::db_type::Error>>()?
+        } else {
+            let mut torrent_count = 0;
+            let mut new_torrents = vec![];
+            for torrent in torrents.map(|t| t.map(|(t, _)| t)) {
+                torrent_count += 1;
+                if torrent_count >= paging.from && new_torrents.len() < paging.page_size {
+                    new_torrents.push(torrent?);
+                }
+            }
+            paging.total = torrent_count;
+            new_torrents
+        }
+    } else {
+        torrents
+            .map(|t| t.map(|(t, _)| t))
+            .collect::<Result<_, native_db::db_type::Error>>()?
+    };
+
+    if let Some(metadata) = &filter.metadata {
+        torrents = metadata_torrents(metadata, torrents)?;
+        if let Some(paging) = &mut paging {
+            paging.total = torrents.len();
+            torrents = torrents
+                .into_iter()
+                .skip(paging.from)
+                .take(paging.page_size)
+                .collect();
+        }
+    }
+
+    let template = TorrentsPageTemplate {
+        language: config.language,
+        timezone: config.timezone(),
+        abs_url: context
+            .config
+            .lock()
+            .await
+            .audiobookshelf
+            .as_ref()
+            .map(|abs| abs.url.clone()),
+        paging: paging.unwrap_or_default(),
+        sort,
+        show: filter.show,
+        cols: Default::default(),
+        query: filter.query.as_deref().unwrap_or("").to_owned(),
+        csv_url,
+        torrents,
+    };
+    Ok::<_, AppError>(Html(template.to_string()).into_response())
+}
+
+/// The filters and search query of a torrents page url
+struct TorrentsFilter {
+    filters: Vec<(TorrentsPageFilter, String)>,
+    query: Option<String>,
+    query_lowercase: Option<String>,
+    metadata: Option<String>,
+    show: TorrentsPageColumns,
+}
+
+impl TorrentsFilter {
+    fn new(mut filter: Vec<(TorrentsPageFilter, String)>, show: TorrentsPageColumns) -> Self {
+        let query_pos = filter
+            .iter()
+            .position(|(field, _)| field == &TorrentsPageFilter::Query);
+        let query = query_pos
+            .map(|i| filter.remove(i))
+            .and_then(|(_, value)| if value.is_empty() { None } else { Some(value) });
+        let metadata_pos = filter
+            .iter()
+            .position(|(field, _)| field == &TorrentsPageFilter::Metadata);
+        let metadata = metadata_pos
+            .map(|i| filter.remove(i))
+            .and_then(|(_, value)| if value.is_empty() { None } else { Some(value) });
+        filter.retain(|(field, _)| {
+            !matches!(
+                field,
+                TorrentsPageFilter::SortBy
+                    | TorrentsPageFilter::Asc
+                    | TorrentsPageFilter::Show
+                    | TorrentsPageFilter::From
+                    | TorrentsPageFilter::PageSize
+            )
+        });
+        let query_lowercase = query.as_deref().map(str::to_lowercase);
+        TorrentsFilter {
+            filters: filter,
+            query,
+            query_lowercase,
+            metadata,
+            show,
+        }
+    }
+
+    /// The search score of a torrent, `None` when it is filtered out
+    fn score(&self, t: &Torrent) -> Option<isize> {
         let mut torrent_score = 0;
-        for (field, value) in filter.iter() {
+        for (field, value) in self.filters.iter() {
             let ok = match field {
                 TorrentsPageFilter::Kind => t.meta.media_type.as_str() == value,
                 TorrentsPageFilter::Category => {
@@ -178,6 +305,10 @@ pub async fn torrents_page(
                                 value == "new_path" || value.as_str() == path.to_string_lossy()
                             }
                             Some(LibraryMismatch::NoLibrary) => value == "no_library",
+                            Some(LibraryMismatch::MissingFiles(ref files)) => {
+                                value == "missing_files"
+                                    || files.iter().any(|f| value.as_str() == f.to_string_lossy())
+                            }
                             None => false,
                         }
                     }
@@ -191,8 +322,13 @@ pub async fn torrents_page(
                 TorrentsPageFilter::Source => match value.as_str() {
                     "mam" => t.meta.source == MetadataSource::Mam,
                     "manual" => t.meta.source == MetadataSource::Manual,
+                    "file" => t.meta.source == MetadataSource::File,
                     _ => false,
                 },
+                TorrentsPageFilter::Unmatched => {
+                    // Without provider ids, anything still on raw MaM metadata is unmatched
+                    (t.meta.source == MetadataSource::Mam) == (value == "true")
+                }
                 TorrentsPageFilter::Query => true,
                 TorrentsPageFilter::Metadata => true,
                 TorrentsPageFilter::SortBy => true,
@@ -205,418 +341,483 @@ pub async fn torrents_page(
                 return None;
             }
         }
-        if let Some(value) = query.as_deref() {
+        if let Some(value) = self.query.as_deref() {
             torrent_score += score(value, &t.meta.title);
-            if show.authors {
+            if self.show.authors {
                 for author in &t.meta.authors {
                     torrent_score += score(value, author);
                 }
             }
-            if show.narrators {
+            if self.show.narrators {
                 for narrator in &t.meta.narrators {
                     torrent_score += score(value, narrator);
                 }
             }
-            if show.series {
+            if self.show.series {
                 for s in &t.meta.series {
                     torrent_score += score(value, &s.name);
                 }
             }
+            // Descriptions are long, only fuzzy score the ones that contain the query
+            if self.show.description
+                && let Some(description) = &t.meta.description
+                && let Some(query_lowercase) = &self.query_lowercase
+                && description.to_lowercase().contains(query_lowercase)
+            {
+                torrent_score += score(value, description);
+            }
+            if let Some(note) = &t.note {
+                torrent_score += score(value, note);
+            }
             if torrent_score < 10 {
                 return None;
             }
         }
-        Some(Ok((t, torrent_score)))
-    });
-
-    let mut paging = match paging.default_page_size(uri, 500, torrent_count as usize) {
-        Ok(paging) => paging,
-        Err(redirect) => return Ok(redirect.into_response()),
-    };
+        Some(torrent_score)
+    }
+}
 
-    let mut torrents: Vec<Torrent> = if query.is_some() || sort.sort_by.is_some() {
-        let mut torrents = torrents.collect::<Result<Vec<_>, native_db::db_type::Error>>()?;
-        if let Some(sort_by) = &sort.sort_by {
-            torrents.sort_by(|(a, _), (b, _)| {
-                let ord = match sort_by {
-                    TorrentsPageSort::Kind => a.meta.media_type.cmp(&b.meta.media_type),
-                    TorrentsPageSort::Category => a
-                        .meta
-                        .cat
-                        .partial_cmp(&b.meta.cat)
-                        .unwrap_or(std::cmp::Ordering::Less),
-                    TorrentsPageSort::Title => a.meta.title.cmp(&b.meta.title),
-                    TorrentsPageSort::Edition => a
-                        .meta
-                        .edition
-                        .as_ref()
-                        .map(|e| e.1)
-                        .cmp(&b.meta.edition.as_ref().map(|e| e.1))
-                        .then(a.meta.edition.cmp(&b.meta.edition)),
-                    TorrentsPageSort::Authors => a.meta.authors.cmp(&b.meta.authors),
-                    TorrentsPageSort::Narrators => a.meta.narrators.cmp(&b.meta.narrators),
-                    TorrentsPageSort::Series => a
-                        .meta
-                        .series
-                        .cmp(&b.meta.series)
-                        .then(a.meta.media_type.cmp(&b.meta.media_type)),
-                    TorrentsPageSort::Language => a.meta.language.cmp(&b.meta.language),
-                    TorrentsPageSort::Size => a.meta.size.cmp(&b.meta.size),
-                    TorrentsPageSort::Linker => a.linker.cmp(&b.linker),
-                    TorrentsPageSort::QbitCategory => a.category.cmp(&b.category),
-                    TorrentsPageSort::Linked => a.library_path.cmp(&b.library_path),
-                    TorrentsPageSort::CreatedAt => a.created_at.cmp(&b.created_at),
-                    TorrentsPageSort::UploadedAt => a.meta.uploaded_at.cmp(&b.meta.uploaded_at),
-                };
-                if sort.asc { ord.reverse() } else { ord }
-            });
-        } else {
-            torrents.sort_by_key(|(_, score)| -*score);
-        }
-        if metadata.is_none()
-            && let Some(paging) = &mut paging
-        {
-            paging.total = torrents.len();
-            let torrents: Vec<_> = torrents
-                .into_iter()
-                .map(|(t, _)| t)
-                .skip(paging.from)
-                .take(paging.page_size)
-                .collect();
-            torrents
-        } else {
-            torrents.into_iter().map(|(t, _)| t).collect()
-        }
-    } else if metadata.is_none()
-        && let Some(paging) = &mut paging
-    {
-        if filter.is_empty() {
-            torrents
-                .map(|t| t.map(|(t, _)| t))
-                .skip(paging.from)
-                .take(paging.page_size)
-                .collect::<Result<_, native_db::db_type::Error>>()?
-        } else {
-            let mut torrent_count = 0;
-            let mut new_torrents = vec![];
-            for torrent in torrents.map(|t| t.map(|(t, _)| t)) {
-                torrent_count += 1;
-                if torrent_count >= paging.from && new_torrents.len() < paging.page_size {
-                    new_torrents.push(torrent?);
-                }
-            }
-            paging.total = torrent_count;
-            new_torrents
-        }
+fn sort_torrents(torrents: &mut [(Torrent, isize)], sort: &SortOn<TorrentsPageSort>) {
+    if let Some(sort_by) = &sort.sort_by {
+        torrents.sort_by(|(a, _), (b, _)| {
+            let ord = match sort_by {
+                TorrentsPageSort::Kind => a.meta.media_type.cmp(&b.meta.media_type),
+                TorrentsPageSort::Category => a
+                    .meta
+                    .cat
+                    .partial_cmp(&b.meta.cat)
+                    .unwrap_or(std::cmp::Ordering::Less),
+                TorrentsPageSort::Title => a.meta.title.cmp(&b.meta.title),
+                TorrentsPageSort::Edition => a
+                    .meta
+                    .edition
+                    .as_ref()
+                    .map(|e| e.1)
+                    .cmp(&b.meta.edition.as_ref().map(|e| e.1))
+                    .then(a.meta.edition.cmp(&b.meta.edition)),
+                TorrentsPageSort::Authors => a.meta.authors.cmp(&b.meta.authors),
+                TorrentsPageSort::Narrators => a.meta.narrators.cmp(&b.meta.narrators),
+                TorrentsPageSort::Series => a
+                    .meta
+                    .series
+                    .cmp(&b.meta.series)
+                    .then(a.meta.media_type.cmp(&b.meta.media_type)),
+                TorrentsPageSort::Language => a.meta.language.cmp(&b.meta.language),
+                TorrentsPageSort::Size => a.meta.size.cmp(&b.meta.size),
+                TorrentsPageSort::Linker => a.linker.cmp(&b.linker),
+                TorrentsPageSort::QbitCategory => a.category.cmp(&b.category),
+                TorrentsPageSort::Linked => a.library_path.cmp(&b.library_path),
+                TorrentsPageSort::CreatedAt => a.created_at.cmp(&b.created_at),
+                TorrentsPageSort::UploadedAt => a.meta.uploaded_at.cmp(&b.meta.uploaded_at),
+            };
+            if sort.asc { ord.reverse() } else { ord }
+        });
     } else {
-        torrents
-            .map(|t| t.map(|(t, _)| t))
-            .collect::<Result<_, native_db::db_type::Error>>()?
-    };
+        torrents.sort_by_key(|(_, score)| -*score);
+    }
+}
 
-    if let Some(metadata) = metadata {
-        match metadata.as_str() {
-            "title" => {
-                torrents.sort_by(|a, b| {
-                    a.title_search
-                        .cmp(&b.title_search)
-                        .then_with(|| a.meta.authors.cmp(&b.meta.authors))
-                });
-                let mut batch: Vec<Torrent> = vec![];
-                let mut new_torrents: Vec<Torrent> = vec![];
-                for torrent in torrents {
-                    if let Some(current) = batch.first() {
-                        if current.title_search != torrent.title_search
-                            || current
-                                .meta
-                                .authors
-                                .iter()
-                                .all(|a| !torrent.meta.authors.contains(a))
+/// Only keeps torrents that look like they have inconsistent metadata, like
+/// the same book with different titles or authors
+fn metadata_torrents(metadata: &str, mut torrents: Vec<Torrent>) -> Result<Vec<Torrent>> {
+    match metadata.as_str() {
+        "title" => {
+            torrents.sort_by(|a, b| {
+                a.title_search
+                    .cmp(&b.title_search)
+                    .then_with(|| a.meta.authors.cmp(&b.meta.authors))
+            });
+            let mut batch: Vec<Torrent> = vec![];
+            let mut new_torrents: Vec<Torrent> = vec![];
+            for torrent in torrents {
+                if let Some(current) = batch.first() {
+                    if current.title_search != torrent.title_search
+                        || current
+                            .meta
+                            .authors
+                            .iter()
+                            .all(|a| !torrent.meta.authors.contains(a))
+                    {
+                        if batch.len() > 1
+                            && !batch.iter().all(|t| t.meta.title == current.meta.title)
                         {
-                            if batch.len() > 1
-                                && !batch.iter().all(|t| t.meta.title == current.meta.title)
-                            {
-                                new_torrents.extend(mem::take(&mut batch));
-                            } else {
-                                batch.clear();
-                            }
+                            new_torrents.extend(mem::take(&mut batch));
+                        } else {
+                            batch.clear();
                         }
-                        batch.push(torrent);
-                    } else {
-                        batch.push(torrent);
                     }
+                    batch.push(torrent);
+                } else {
+                    batch.push(torrent);
                 }
-                torrents = new_torrents;
-            }
-            "authors" => {
-                torrents.sort_by(|a, b| a.title_search.cmp(&b.title_search));
-                let mut batch: Vec<Torrent> = vec![];
-                let mut new_torrents: Vec<Torrent> = vec![];
-                for torrent in torrents {
-                    if let Some(current) = batch.first() {
-                        if current.title_search != torrent.title_search {
-                            if batch.len() > 1
-                                && !batch.iter().all(|t| t.meta.authors == current.meta.authors)
-                            {
-                                new_torrents.extend(mem::take(&mut batch));
-                            } else {
-                                batch.clear();
-                            }
+            }
+            torrents = new_torrents;
+        }
+        "authors" => {
+            torrents.sort_by(|a, b| a.title_search.cmp(&b.title_search));
+            let mut batch: Vec<Torrent> = vec![];
+            let mut new_torrents: Vec<Torrent> = vec![];
+            for torrent in torrents {
+                if let Some(current) = batch.first() {
+                    if current.title_search != torrent.title_search {
+                        if batch.len() > 1
+                            && !batch.iter().all(|t| t.meta.authors == current.meta.authors)
+                        {
+                            new_torrents.extend(mem::take(&mut batch));
+                        } else {
+                            batch.clear();
                         }
-                        batch.push(torrent);
-                    } else {
-                        batch.push(torrent);
                     }
+                    batch.push(torrent);
+                } else {
+                    batch.push(torrent);
                 }
-                torrents = new_torrents;
-            }
-            "series" => {
-                torrents.sort_by(|a, b| a.title_search.cmp(&b.title_search));
-                let mut batch: Vec<Torrent> = vec![];
-                let mut new_torrents: Vec<Torrent> = vec![];
-                for torrent in torrents {
-                    if let Some(current) = batch.first() {
-                        if current.title_search != torrent.title_search {
-                            if batch.len() > 1
-                                && !batch.iter().all(|t| t.meta.series == current.meta.series)
-                            {
-                                new_torrents.extend(mem::take(&mut batch));
-                            } else {
-                                batch.clear();
-                            }
+            }
+            torrents = new_torrents;
+        }
+        "series" => {
+            torrents.sort_by(|a, b| a.title_search.cmp(&b.title_search));
+            let mut batch: Vec<Torrent> = vec![];
+            let mut new_torrents: Vec<Torrent> = vec![];
+            for torrent in torrents {
+                if let Some(current) = batch.first() {
+                    if current.title_search != torrent.title_search {
+                        if batch.len() > 1
+                            && !batch.iter().all(|t| t.meta.series == current.meta.series)
+                        {
+                            new_torrents.extend(mem::take(&mut batch));
+                        } else {
+                            batch.clear();
                         }
-                        batch.push(torrent);
-                    } else {
-                        batch.push(torrent);
                     }
+                    batch.push(torrent);
+                } else {
+                    batch.push(torrent);
                 }
-                torrents = new_torrents;
-            }
-            "leading" => {
-                fn remove_leading(title: &str) -> &str {
-                    title
-                        .strip_prefix("the ")
-                        .or_else(|| title.strip_prefix("a "))
-                        .unwrap_or(title)
-                }
-                torrents.sort_by(|a, b| {
-                    remove_leading(&a.title_search).cmp(remove_leading(&b.title_search))
-                });
-                let mut batch: Vec<Torrent> = vec![];
-                let mut new_torrents: Vec<Torrent> = vec![];
-                for torrent in torrents {
-                    if let Some(current) = batch.first() {
-                        if remove_leading(&current.title_search)
-                            != remove_leading(&torrent.title_search)
+            }
+            torrents = new_torrents;
+        }
+        "leading" => {
+            fn remove_leading(title: &str) -> &str {
+                title
+                    .strip_prefix("the ")
+                    .or_else(|| title.strip_prefix("a "))
+                    .unwrap_or(title)
+            }
+            torrents.sort_by(|a, b| {
+                remove_leading(&a.title_search).cmp(remove_leading(&b.title_search))
+            });
+            let mut batch: Vec<Torrent> = vec![];
+            let mut new_torrents: Vec<Torrent> = vec![];
+            for torrent in torrents {
+                if let Some(current) = batch.first() {
+                    if remove_leading(&current.title_search)
+                        != remove_leading(&torrent.title_search)
+                    {
+                        if batch.len() > 1
+                            && !batch.iter().all(|t| t.meta.title == current.meta.title)
                         {
-                            if batch.len() > 1
-                                && !batch.iter().all(|t| t.meta.title == current.meta.title)
-                            {
-                                new_torrents.extend(mem::take(&mut batch));
-                            } else {
-                                batch.clear();
-                            }
+                            new_torrents.extend(mem::take(&mut batch));
+                        } else {
+                            batch.clear();
                         }
-                        batch.push(torrent);
-                    } else {
-                        batch.push(torrent);
                     }
+                    batch.push(torrent);
+                } else {
+                    batch.push(torrent);
                 }
-                torrents = new_torrents;
-            }
-            "subtitle" => {
-                fn subtitle(title: &str) -> &str {
-                    let Some((title, _subtitle)) = title.split_once(':') else {
-                        return title;
-                    };
-                    title
-                }
-                torrents.sort_by(|a, b| subtitle(&a.title_search).cmp(subtitle(&b.title_search)));
-                let mut batch: Vec<Torrent> = vec![];
-                let mut new_torrents: Vec<Torrent> = vec![];
-                for torrent in torrents {
-                    if let Some(current) = batch.first() {
-                        if subtitle(&current.title_search) != subtitle(&torrent.title_search) {
-                            if batch.len() > 1
-                                && !batch.iter().all(|t| t.meta.title == current.meta.title)
-                            {
-                                new_torrents.extend(mem::take(&mut batch));
-                            } else {
-                                batch.clear();
-                            }
+            }
+            torrents = new_torrents;
+        }
+        "subtitle" => {
+            fn subtitle(title: &str) -> &str {
+                let Some((title, _subtitle)) = title.split_once(':') else {
+                    return title;
+                };
+                title
+            }
+            torrents.sort_by(|a, b| subtitle(&a.title_search).cmp(subtitle(&b.title_search)));
+            let mut batch: Vec<Torrent> = vec![];
+            let mut new_torrents: Vec<Torrent> = vec![];
+            for torrent in torrents {
+                if let Some(current) = batch.first() {
+                    if subtitle(&current.title_search) != subtitle(&torrent.title_search) {
+                        if batch.len() > 1
+                            && !batch.iter().all(|t| t.meta.title == current.meta.title)
+                        {
+                            new_torrents.extend(mem::take(&mut batch));
+                        } else {
+                            batch.clear();
                         }
-                        batch.push(torrent);
-                    } else {
-                        batch.push(torrent);
                     }
+                    batch.push(torrent);
+                } else {
+                    batch.push(torrent);
                 }
-                torrents = new_torrents;
-            }
-            "missing_ebook" => {
-                torrents.sort_by(|a, b| a.title_search.cmp(&b.title_search));
-                let mut batch: Vec<Torrent> = vec![];
-                let mut new_torrents: Vec<Torrent> = vec![];
-                for torrent in torrents {
-                    if let Some(current) = batch.first() {
-                        if current.title_search != torrent.title_search {
-                            if batch
+            }
+            torrents = new_torrents;
+        }
+        "missing_ebook" => {
+            torrents.sort_by(|a, b| a.title_search.cmp(&b.title_search));
+            let mut batch: Vec<Torrent> = vec![];
+            let mut new_torrents: Vec<Torrent> = vec![];
+            for torrent in torrents {
+                if let Some(current) = batch.first() {
+                    if current.title_search != torrent.title_search {
+                        if batch
+                            .iter()
+                            .any(|t| t.meta.media_type.matches(MediaType::Audiobook))
+                            && !batch
                                 .iter()
-                                .any(|t| t.meta.media_type.matches(MediaType::Audiobook))
-                                && !batch
-                                    .iter()
-                                    .any(|t| t.meta.media_type.matches(MediaType::Ebook))
-                            {
-                                new_torrents.extend(mem::take(&mut batch));
-                            } else {
-                                batch.clear();
-                            }
+                                .any(|t| t.meta.media_type.matches(MediaType::Ebook))
+                        {
+                            new_torrents.extend(mem::take(&mut batch));
+                        } else {
+                            batch.clear();
                         }
-                        batch.push(torrent);
-                    } else {
-                        batch.push(torrent);
                     }
+                    batch.push(torrent);
+                } else {
+                    batch.push(torrent);
                 }
-                torrents = new_torrents;
-            }
-            "initials" => {
-                fn bunched_initials(name: &str) -> bool {
-                    let mut capital = false;
-                    for char in name.chars() {
-                        if capital && char.is_uppercase() {
-                            return true;
-                        }
-                        capital = char.is_uppercase()
+            }
+            torrents = new_torrents;
+        }
+        "initials" => {
+            fn bunched_initials(name: &str) -> bool {
+                let mut capital = false;
+                for char in name.chars() {
+                    if capital && char.is_uppercase() {
+                        return true;
                     }
-                    false
-                }
-                torrents.retain(|t| {
-                    t.meta.authors.iter().any(|name| bunched_initials(name))
-                        || t.meta.narrators.iter().any(|name| bunched_initials(name))
-                });
-            }
-            "series_with_holes" => {
-                fn first_series(series: &[Series]) -> Option<&Series> {
-                    series.iter().find(|s| !s.entries.0.is_empty())
+                    capital = char.is_uppercase()
                 }
-                fn series_name(series: &[Series]) -> &str {
-                    first_series(series)
-                        .map(|s| s.name.as_str())
-                        .unwrap_or_default()
-                }
-                torrents
-                    .sort_by(|a, b| series_name(&a.meta.series).cmp(series_name(&b.meta.series)));
-                let mut batch: Vec<(Torrent, Series)> = vec![];
-                let mut new_torrents: Vec<Torrent> = vec![];
-                for torrent in torrents {
-                    let Some(series) = first_series(&torrent.meta.series) else {
-                        continue;
-                    };
-                    if let Some(current) = batch.first() {
-                        if current.1.name != series.name {
-                            if batch.iter().any(|t| t.0.library_path.is_some()) {
-                                batch.sort_by(|a, b| a.1.entries.cmp(&b.1.entries));
-                                let last = batch
+                false
+            }
+            torrents.retain(|t| {
+                t.meta.authors.iter().any(|name| bunched_initials(name))
+                    || t.meta.narrators.iter().any(|name| bunched_initials(name))
+            });
+        }
+        "series_with_holes" => {
+            fn first_series(series: &[Series]) -> Option<&Series> {
+                series.iter().find(|s| !s.entries.0.is_empty())
+            }
+            fn series_name(series: &[Series]) -> &str {
+                first_series(series)
+                    .map(|s| s.name.as_str())
+                    .unwrap_or_default()
+            }
+            torrents.sort_by(|a, b| series_name(&a.meta.series).cmp(series_name(&b.meta.series)));
+            let mut batch: Vec<(Torrent, Series)> = vec![];
+            let mut new_torrents: Vec<Torrent> = vec![];
+            for torrent in torrents {
+                let Some(series) = first_series(&torrent.meta.series) else {
+                    continue;
+                };
+                if let Some(current) = batch.first() {
+                    if current.1.name != series.name {
+                        if batch.iter().any(|t| t.0.library_path.is_some()) {
+                            batch.sort_by(|a, b| a.1.entries.cmp(&b.1.entries));
+                            let last = batch
+                                .iter()
+                                .flat_map(|s| &s.1.entries.0)
+                                .map(|s| match s {
+                                    SeriesEntry::Num(n) => *n,
+                                    SeriesEntry::Range(_start, end) => *end,
+                                    SeriesEntry::Part(n, _) => *n,
+                                } as i32)
+                                .max()
+                                .unwrap_or_default();
+                            for i in 1..=last {
+                                if !batch
                                     .iter()
-                                    .flat_map(|s| &s.1.entries.0)
-                                    .map(|s| match s {
-                                        SeriesEntry::Num(n) => *n,
-                                        SeriesEntry::Range(_start, end) => *end,
-                                        SeriesEntry::Part(n, _) => *n,
-                                    } as i32)
-                                    .max()
-                                    .unwrap_or_default();
-                                for i in 1..=last {
-                                    if !batch
-                                        .iter()
-                                        .any(|(_, series)| series.entries.contains(i as f32))
-                                    {
-                                        new_torrents.extend(
-                                            mem::take(&mut batch).into_iter().map(|(t, _)| t),
-                                        );
-                                        break;
-                                    }
+                                    .any(|(_, series)| series.entries.contains(i as f32))
+                                {
+                                    new_torrents
+                                        .extend(mem::take(&mut batch).into_iter().map(|(t, _)| t));
+                                    break;
                                 }
                             }
-                            batch.clear();
                         }
-                        let series = series.clone();
-                        batch.push((torrent, series));
-                    } else {
-                        let series = series.clone();
-                        batch.push((torrent, series));
+                        batch.clear();
                     }
+                    let series = series.clone();
+                    batch.push((torrent, series));
+                } else {
+                    let series = series.clone();
+                    batch.push((torrent, series));
                 }
-                torrents = new_torrents;
             }
-            "series_authors" => {
-                fn first_series(series: &[Series]) -> Option<&Series> {
-                    series.iter().find(|s| !s.entries.0.is_empty())
-                }
-                fn series_name(series: &[Series]) -> &str {
-                    first_series(series)
-                        .map(|s| s.name.as_str())
-                        .unwrap_or_default()
-                }
-                torrents
-                    .sort_by(|a, b| series_name(&a.meta.series).cmp(series_name(&b.meta.series)));
-                let mut batch: Vec<(Torrent, Series)> = vec![];
-                let mut new_torrents: Vec<Torrent> = vec![];
-                for torrent in torrents {
-                    let Some(series) = first_series(&torrent.meta.series) else {
-                        continue;
-                    };
-                    if let Some(current) = batch.first() {
-                        if current.1.name != series.name {
-                            if batch.len() > 1
-                                && !batch
-                                    .iter()
-                                    .all(|t| t.0.meta.authors == current.0.meta.authors)
-                            {
-                                new_torrents
-                                    .extend(mem::take(&mut batch).into_iter().map(|(t, _)| t));
-                            }
-                            batch.clear();
+            torrents = new_torrents;
+        }
+        "series_authors" => {
+            fn first_series(series: &[Series]) -> Option<&Series> {
+                series.iter().find(|s| !s.entries.0.is_empty())
+            }
+            fn series_name(series: &[Series]) -> &str {
+                first_series(series)
+                    .map(|s| s.name.as_str())
+                    .unwrap_or_default()
+            }
+            torrents.sort_by(|a, b| series_name(&a.meta.series).cmp(series_name(&b.meta.series)));
+            let mut batch: Vec<(Torrent, Series)> = vec![];
+            let mut new_torrents: Vec<Torrent> = vec![];
+            for torrent in torrents {
+                let Some(series) = first_series(&torrent.meta.series) else {
+                    continue;
+                };
+                if let Some(current) = batch.first() {
+                    if current.1.name != series.name {
+                        if batch.len() > 1
+                            && !batch
+                                .iter()
+                                .all(|t| t.0.meta.authors == current.0.meta.authors)
+                        {
+                            new_torrents.extend(mem::take(&mut batch).into_iter().map(|(t, _)| t));
                         }
-                        let series = series.clone();
-                        batch.push((torrent, series));
-                    } else {
-                        let series = series.clone();
-                        batch.push((torrent, series));
+                        batch.clear();
                     }
+                    let series = series.clone();
+                    batch.push((torrent, series));
+                } else {
+                    let series = series.clone();
+                    batch.push((torrent, series));
                 }
-                torrents = new_torrents;
             }
-            _ => return Err(anyhow::Error::msg("Unknown metadata filter").into()),
+            torrents = new_torrents;
         }
-        if let Some(paging) = &mut paging {
-            paging.total = torrents.len();
-            torrents = torrents
-                .into_iter()
-                .skip(paging.from)
-                .take(paging.page_size)
-                .collect();
+        _ => return Err(anyhow::Error::msg("Unknown metadata filter")),
+    }
+    Ok(torrents)
+}
+
+/// Downloads the torrents matching the filters and search query of the
+/// torrents page as CSV, in the same order
+pub async fn torrents_csv(
+    State(context): State<Context>,
+    Query(sort): Query<SortOn<TorrentsPageSort>>,
+    Query(filter): Query<Vec<(TorrentsPageFilter, String)>>,
+    Query(show): Query<TorrentsPageColumnsQuery>,
+) -> std::result::Result<Response, AppError> {
+    let filter = TorrentsFilter::new(filter, show.show.unwrap_or_default());
+    let timezone = context.config().await.timezone();
+    let db = context.db.clone();
+    let (tx, rx) = mpsc::channel(8);
+    tokio::task::spawn_blocking(move || {
+        let out = BodyWriter(tx.clone());
+        if let Err(err) = export_torrents_csv(&db, &filter, &sort, timezone, out) {
+            warn!("Error exporting torrents: {err:#}");
+            // Aborts the download so a partial file is not taken for a full one
+            let _ = tx.blocking_send(Err(io::Error::other(err.to_string())));
         }
+    });
+
+    let headers = [
+        (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+        (
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"torrents.csv\"",
+        ),
+    ];
+    Ok::<_, AppError>((headers, Body::from_stream(ReceiverStream::new(rx))).into_response())
+}
+
+fn export_torrents_csv(
+    db: &Database<'_>,
+    filter: &TorrentsFilter,
+    sort: &SortOn<TorrentsPageSort>,
+    timezone: Option<&Tz>,
+    out: impl io::Write,
+) -> Result<()> {
+    let r = db.r_transaction()?;
+    let torrents = r.scan().secondary::<Torrent>(TorrentKey::created_at)?;
+    let torrents = torrents.all()?.rev().filter_map(|t| match t {
+        Ok(t) => filter.score(&t).map(|score| Ok((t, score))),
+        Err(err) => Some(Err(err)),
+    });
+    if filter.query.is_none() && sort.sort_by.is_none() && filter.metadata.is_none() {
+        // Nothing to sort, so rows are written while reading the library
+        return write_torrents_csv(torrents.map(|t| t.map(|(t, _)| t)), timezone, out);
     }
 
-    let template = TorrentsPageTemplate {
-        abs_url: context
-            .config
-            .lock()
-            .await
-            .audiobookshelf
-            .as_ref()
-            .map(|abs| abs.url.clone()),
-        paging: paging.unwrap_or_default(),
-        sort,
-        show,
-        cols: Default::default(),
-        query: query.as_deref().unwrap_or("").to_owned(),
-        torrents,
-    };
-    Ok::<_, AppError>(Html(template.to_string()).into_response())
+    let mut torrents = torrents.collect::<Result<Vec<_>, native_db::db_type::Error>>()?;
+    if filter.query.is_some() || sort.sort_by.is_some() {
+        sort_torrents(&mut torrents, sort);
+    }
+    let mut torrents = torrents.into_iter().map(|(t, _)| t).collect();
+    if let Some(metadata) = &filter.metadata {
+        torrents = metadata_torrents(metadata, torrents)?;
+    }
+    write_torrents_csv(torrents.into_iter().map(Ok), timezone, out)
 }
 
-fn score(query: &str, target: &str) -> isize {
+fn write_torrents_csv(
+    torrents: impl Iterator<Item = Result<Torrent, native_db::db_type::Error>>,
+    timezone: Option<&Tz>,
+    out: impl io::Write,
+) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record([
+        "title",
+        "authors",
+        "narrators",
+        "series",
+        "size",
+        "language",
+        "filetypes",
+        "path",
+        "created_at",
+    ])?;
+    for torrent in torrents {
+        let torrent = torrent?;
+        let series = torrent
+            .meta
+            .series
+            .iter()
+            .map(format_serie)
+            .collect::<Vec<_>>();
+        writer.write_record([
+            torrent.meta.title,
+            torrent.meta.authors.join(", "),
+            torrent.meta.narrators.join(", "),
+            series.join(", "),
+            torrent.meta.size.to_string(),
+            torrent
+                .meta
+                .language
+                .map(Language::to_str)
+                .unwrap_or_default()
+                .to_string(),
+            torrent.meta.filetypes.join(", "),
+            torrent
+                .library_path
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            format_time(&torrent.created_at, timezone),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Sends everything written to it as chunks of a response body
+struct BodyWriter(mpsc::Sender<io::Result<Bytes>>);
+
+impl io::Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn score(query: &str, target: &str) -> isize {
     FuzzySearch::new(query, target)
         .case_insensitive()
         .best_match()
@@ -656,6 +857,98 @@ pub async fn torrents_page_post(
                 .await?;
             }
         }
+        "verify-ids" => {
+            let service = MetadataService::open_library(&config.metadata);
+            let mut failed = vec![];
+            for (torrent, err) in service.verify_all_ids(&context.db, &form.torrents).await {
+                warn!("Failed to verify ids of torrent {torrent}: {err}");
+                failed.push(format!("{torrent}: {err}"));
+            }
+            if !failed.is_empty() {
+                return Err(anyhow::Error::msg(format!(
+                    "Failed to verify ids of {} of {} torrents: {}",
+                    failed.len(),
+                    form.torrents.len(),
+                    failed.join(", ")
+                ))
+                .into());
+            }
+        }
+        "readd" => {
+            let mam = context.mam()?;
+            let qbit = match config.qbittorrent.first() {
+                Some(qbit_conf) => Some((
+                    qbit::Api::new_login_username_password(
+                        &qbit_conf.url,
+                        &qbit_conf.username,
+                        &qbit_conf.password,
+                    )
+                    .await?,
+                    qbit_conf.url.as_str(),
+                )),
+                None => None,
+            };
+            let rtorrent = config
+                .rtorrent
+                .first()
+                .map(|rtorrent_conf| Rtorrent::new(rtorrent_conf, http::client()));
+            let client = match (&qbit, &rtorrent) {
+                (Some((qbit, qbit_url)), _) => DownloadClient::Qbit(qbit, qbit_url),
+                (None, Some(rtorrent)) => DownloadClient::Rtorrent(rtorrent),
+                (None, None) => {
+                    return Err(anyhow::Error::msg("No torrent client configured").into());
+                }
+            };
+            for torrent in form.torrents {
+                let Some(torrent) = context
+                    .db
+                    .r_transaction()?
+                    .get()
+                    .primary::<Torrent>(torrent)?
+                else {
+                    return Err(anyhow::Error::msg("Could not find torrent").into());
+                };
+                if torrent.client_status != Some(ClientStatus::NotInClient) {
+                    continue;
+                }
+                readd_torrent(&config, &context.db, client, &mam, torrent).await?;
+            }
+        }
+        action @ ("set-category" | "add-tags" | "remove-tags") => {
+            let tags = form
+                .tags
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .collect::<Vec<_>>();
+            // Keep going when a torrent fails so one missing torrent doesn't
+            // leave the rest of the selection half updated
+            let mut failed = vec![];
+            for torrent in &form.torrents {
+                if let Err(err) = update_qbit_torrent(
+                    &config,
+                    &context.db,
+                    action,
+                    torrent,
+                    &form.category,
+                    &tags,
+                )
+                .await
+                {
+                    warn!("Failed to {action} on torrent {torrent}: {err}");
+                    failed.push(format!("{torrent}: {err}"));
+                }
+            }
+            if !failed.is_empty() {
+                return Err(anyhow::Error::msg(format!(
+                    "Failed to update {} of {} torrents: {}",
+                    failed.len(),
+                    form.torrents.len(),
+                    failed.join(", ")
+                ))
+                .into());
+            }
+        }
         "remove" => {
             for torrent in form.torrents {
                 let (_guard, rw) = context.db.rw_async().await?;
@@ -674,26 +967,78 @@ pub async fn torrents_page_post(
     Ok(Redirect::to(&uri.to_string()))
 }
 
+async fn update_qbit_torrent(
+    config: &Config,
+    db: &Database<'_>,
+    action: &str,
+    id: &str,
+    category: &str,
+    tags: &[&str],
+) -> Result<()> {
+    let Some((_torrent, qbit, qbit_conf)) = qbittorrent::get_torrent(config, id).await? else {
+        return Err(anyhow::Error::msg("not in qBittorrent"));
+    };
+    match action {
+        "set-category" => {
+            ensure_category_exists(&qbit, &qbit_conf.url, category).await?;
+            qbit.set_category(Some(vec![id]), category).await?;
+            let (_guard, rw) = db.rw_async().await?;
+            if let Some(mut torrent) = rw.get().primary::<Torrent>(id.to_string())? {
+                torrent.category = if category.is_empty() {
+                    None
+                } else {
+                    Some(category.to_owned())
+                };
+                rw.upsert(torrent)?;
+                rw.commit()?;
+            }
+        }
+        "add-tags" if !tags.is_empty() => {
+            qbit.add_tags(Some(vec![id]), tags.to_vec()).await?;
+        }
+        "remove-tags" if !tags.is_empty() => {
+            qbit.remove_tags(Some(vec![id]), tags.to_vec()).await?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct TorrentsPageForm {
     action: String,
     #[serde(default, rename = "torrent")]
     torrents: Vec<String>,
+    #[serde(default)]
+    category: String,
+    #[serde(default)]
+    tags: String,
 }
 
 #[derive(Template)]
 #[template(path = "pages/torrents.html")]
 struct TorrentsPageTemplate {
+    language: UiLanguage,
+    timezone: Option<&'static Tz>,
     abs_url: Option<String>,
     paging: Pagination,
     sort: SortOn<TorrentsPageSort>,
     show: TorrentsPageColumns,
     cols: RefCell<Vec<Box<dyn tables::Size>>>,
     query: String,
+    csv_url: String,
     torrents: Vec<Torrent>,
 }
 
-impl Page for TorrentsPageTemplate {}
+impl Page for TorrentsPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+
+    fn timezone(&self) -> Option<&'static Tz> {
+        self.timezone
+    }
+}
 
 #[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "lowercase")]
@@ -737,6 +1082,7 @@ pub enum TorrentsPageFilter {
     Abs,
     Query,
     Source,
+    Unmatched,
     Metadata,
     // Workaround sort decode failure
     SortBy,
@@ -766,6 +1112,7 @@ struct TorrentsPageColumns {
     path: bool,
     created_at: bool,
     uploaded_at: bool,
+    description: bool,
 }
 
 #[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -791,6 +1138,7 @@ impl Default for TorrentsPageColumns {
             path: false,
             created_at: true,
             uploaded_at: false,
+            description: false,
         }
     }
 }
@@ -815,6 +1163,7 @@ impl TryFrom<String> for TorrentsPageColumns {
             path: false,
             created_at: false,
             uploaded_at: false,
+            description: false,
         };
         for column in value.split(",") {
             match column {
@@ -833,6 +1182,7 @@ impl TryFrom<String> for TorrentsPageColumns {
                 "path" => columns.path = true,
                 "created_at" => columns.created_at = true,
                 "uploaded_at" => columns.uploaded_at = true,
+                "description" => columns.description = true,
                 "" => {}
                 _ => {
                     return Err(format!("Unknown column {column}"));
@@ -859,3 +1209,116 @@ impl HidableColumns for TorrentsPageTemplate {
         self.cols.borrow()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use mlm_db::{SeriesEntries, Size, TorrentMeta};
+
+    use super::*;
+    use crate::test_helpers::{library_torrent, template_meta};
+
+    fn torrent(title: &str, authors: &[&str], library_path: Option<&str>) -> Torrent {
+        let meta = TorrentMeta {
+            mam_id: 1,
+            main_cat: None,
+            language: Some(Language::English),
+            filetypes: vec!["m4b".to_string(), "mp3".to_string()],
+            num_files: 1,
+            size: Size::from_bytes(1024 * 1024),
+            title: title.to_string(),
+            authors: authors.iter().map(|a| a.to_string()).collect(),
+            series: vec![Series {
+                name: "Tom, Jerry".to_string(),
+                entries: SeriesEntries::new(vec![SeriesEntry::Num(2.0)]),
+            }],
+            ..template_meta()
+        };
+        Torrent {
+            library_path: library_path.map(PathBuf::from),
+            ..library_torrent(title, meta)
+        }
+    }
+
+    #[test]
+    fn test_search_note() {
+        let mut gift = torrent("First", &["Author One"], None);
+        gift.note = Some("gift for Sam".to_string());
+        let other = torrent("Second", &["Author Two"], None);
+        let filter = TorrentsFilter::new(
+            vec![(TorrentsPageFilter::Query, "gift".to_string())],
+            TorrentsPageColumns::default(),
+        );
+        assert!(filter.score(&gift).is_some());
+        assert_eq!(filter.score(&other), None);
+    }
+
+    #[test]
+    fn test_write_torrents_csv() {
+        let torrents = vec![
+            torrent(
+                "First",
+                &["Author One", "Author Two"],
+                Some("/library/First"),
+            ),
+            torrent("Second", &["Author Three"], None),
+            torrent("Third, \"Book\"", &["Author One"], None),
+        ];
+        let filter = TorrentsFilter::new(
+            vec![(TorrentsPageFilter::Author, "Author One".to_string())],
+            TorrentsPageColumns::default(),
+        );
+        let torrents = torrents
+            .into_iter()
+            .filter(|t| filter.score(t).is_some())
+            .map(Ok);
+
+        let mut out = vec![];
+        write_torrents_csv(torrents, None, &mut out).unwrap();
+        let mut reader = csv::Reader::from_reader(out.as_slice());
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec![
+                "title",
+                "authors",
+                "narrators",
+                "series",
+                "size",
+                "language",
+                "filetypes",
+                "path",
+                "created_at"
+            ]
+        );
+        let rows = reader
+            .records()
+            .map(|r| r.unwrap().iter().take(8).map(str::to_string).collect())
+            .collect::<Vec<Vec<_>>>();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    "First",
+                    "Author One, Author Two",
+                    "",
+                    "Tom, Jerry #2",
+                    "1 MiB",
+                    "English",
+                    "m4b, mp3",
+                    "/library/First"
+                ],
+                vec![
+                    "Third, \"Book\"",
+                    "Author One",
+                    "",
+                    "Tom, Jerry #2",
+                    "1 MiB",
+                    "English",
+                    "m4b, mp3",
+                    ""
+                ],
+            ]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use askama::Template;
+use axum::{
+    extract::{OriginalUri, State},
+    response::{Html, Redirect},
+};
+use axum_extra::extract::Form;
+use mlm_db::{DatabaseExt as _, List, ListItem, ListItemKey};
+use serde::Deserialize;
+
+use crate::{
+    config::UiLanguage,
+    lists::pick_candidate,
+    stats::Context,
+    web::{AppError, Page, list_url},
+};
+
+pub async fn unmatched_page(
+    State(context): State<Context>,
+) -> std::result::Result<Html<String>, AppError> {
+    let config = context.config().await;
+    let r = context.db.r_transaction()?;
+    let lists = r
+        .scan()
+        .primary::<List>()?
+        .all()?
+        .collect::<Result<Vec<_>, native_db::db_type::Error>>()?;
+    let items = r
+        .scan()
+        .secondary::<ListItem>(ListItemKey::created_at)?
+        .all()?
+        .rev()
+        .filter(|t| {
+            t.as_ref()
+                .is_ok_and(|t| !t.candidates.is_empty() && (t.want_audio() || t.want_ebook()))
+        })
+        .map(|t| {
+            let t = t?;
+            let list = lists
+                .iter()
+                .find(|list| list.id == t.list_id)
+                .map_or_else(|| t.list_id.clone(), |list| list.title.clone());
+            Ok((list, t))
+        })
+        .collect::<Result<Vec<_>, native_db::db_type::Error>>()?;
+    let template = UnmatchedPageTemplate {
+        language: config.language,
+        items,
+    };
+    Ok::<_, AppError>(Html(template.to_string()))
+}
+
+pub async fn unmatched_page_post(
+    State(context): State<Context>,
+    uri: OriginalUri,
+    Form(form): Form<UnmatchedPageForm>,
+) -> Result<Redirect, AppError> {
+    let guid = (form.list_id, form.item_id);
+    match form.action.as_str() {
+        "pick" => {
+            let Some(mam_id) = form.mam_id else {
+                return Err(anyhow::Error::msg("Missing mam_id").into());
+            };
+            let config = context.config().await;
+            let mam = context.mam()?;
+            pick_candidate(&config, &context.db, &mam, guid, mam_id).await?;
+            context.triggers.downloader_tx.send(())?;
+        }
+        "dismiss" => {
+            let (_guard, rw) = context.db.rw_async().await?;
+            let Some(mut item) = rw.get().primary::<ListItem>(guid)? else {
+                return Err(anyhow::Error::msg("Could not find item").into());
+            };
+            item.candidates.clear();
+            rw.upsert(item)?;
+            rw.commit()?;
+        }
+        action => {
+            eprintln!("unknown action: {action}");
+        }
+    }
+
+    Ok(Redirect::to(&uri.to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnmatchedPageForm {
+    action: String,
+    list_id: String,
+    item_id: String,
+    mam_id: Option<u64>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/unmatched.html")]
+struct UnmatchedPageTemplate {
+    language: UiLanguage,
+    items: Vec<(String, ListItem)>,
+}
+
+impl Page for UnmatchedPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use askama::Template;
+use axum::{
+    extract::{OriginalUri, State},
+    response::{Html, Redirect},
+};
+use axum_extra::extract::Form;
+use mlm_db::{Size, Torrent};
+use serde::Deserialize;
+
+use crate::{
+    audit::{
+        UntrackedFiles, delete_untracked_files, find_untracked_files, import_untracked_files,
+        moved_torrent,
+    },
+    config::UiLanguage,
+    stats::Context,
+    web::{AppError, Page},
+};
+
+pub async fn untracked_files_page(
+    State(context): State<Context>,
+) -> std::result::Result<Html<String>, AppError> {
+    let config = context.config().await;
+    let (untracked, torrents) = find_untracked_files(&config, &context.db)?;
+    let untracked = untracked
+        .into_iter()
+        .map(|found| {
+            let moved = moved_torrent(&found, &torrents).cloned();
+            (found, moved)
+        })
+        .collect();
+    let template = UntrackedFilesPageTemplate {
+        language: config.language,
+        untracked,
+    };
+    Ok::<_, AppError>(Html(template.to_string()))
+}
+
+pub async fn untracked_files_page_post(
+    State(context): State<Context>,
+    uri: OriginalUri,
+    Form(form): Form<UntrackedFilesPageForm>,
+) -> Result<Redirect, AppError> {
+    let config = context.config().await;
+    match form.action.as_str() {
+        "import" => {
+            import_untracked_files(&config, &context.db, &form.dirs).await?;
+        }
+        "delete" => {
+            delete_untracked_files(&config, &context.db, &form.dirs)?;
+        }
+        action => {
+            eprintln!("unknown action: {action}");
+        }
+    }
+
+    Ok(Redirect::to(&uri.to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UntrackedFilesPageForm {
+    action: String,
+    #[serde(default, rename = "dir")]
+    dirs: Vec<PathBuf>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/untracked_files.html")]
+struct UntrackedFilesPageTemplate {
+    language: UiLanguage,
+    untracked: Vec<(UntrackedFiles, Option<Torrent>)>,
+}
+
+impl UntrackedFilesPageTemplate {
+    fn size(&self, found: &UntrackedFiles) -> Size {
+        Size::from_bytes(found.size)
+    }
+}
+
+impl Page for UntrackedFilesPageTemplate {
+    fn language(&self) -> UiLanguage {
+        self.language
+    }
+}
@@ -4,7 +4,7 @@ use anyhow::Result;
 use tracing::error;
 use tray_item::{IconSource, TrayItem};
 
-use crate::config::Config;
+use crate::config::{Config, WebBind};
 
 pub fn start_tray_icon(
     log_dir: Option<PathBuf>,
@@ -14,8 +14,12 @@ pub fn start_tray_icon(
     let mut tray = TrayItem::new("MLM", IconSource::Resource("tray-icon"))?;
     tray.add_label(&format!("MLM v{}", env!("CARGO_PKG_VERSION")))
         .unwrap();
+    let web_port = match config.web_bind() {
+        WebBind::Tcp { port, .. } => port,
+        WebBind::Unix(_) => config.web_port,
+    };
     tray.add_menu_item("Open Web UI", move || {
-        if let Err(err) = open::that(format!("http://localhost:{}", config.web_port)) {
+        if let Err(err) = open::that(format!("http://localhost:{web_port}")) {
             error!("Error opening web ui: {}", err);
         }
     })?;